@@ -0,0 +1,166 @@
+//! Generate the terminfo capability enums from the vendored ncurses `Caps` file.
+//!
+//! The generated code is written to `$OUT_DIR/fields.rs` and included by `src/terminfo/fields.rs`.
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+const CAPS_PATH: &str = "vendor/ncurses/Caps";
+
+struct Capability {
+    variable: String,
+    name: String,
+    termcap: String,
+    description: String,
+}
+
+impl Capability {
+    /// Convert the capability's variable name into a CamelCase enum variant (e.g. "set_a_foreground" -> "SetAForeground")
+    fn variant(&self) -> String {
+        self.variable
+            .split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(c) => c.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Count the number of parameters a string capability takes.
+    ///
+    /// `Caps` doesn't record this explicitly, but every parameter is referenced in the description as `#1`-`#9`,
+    /// ranges of parameters are written as `#1-#9`.
+    fn arity(&self) -> usize {
+        // user strings are numbered "User string #0" - "User string #9", those aren't parameters.
+        if self.description.starts_with("User string") {
+            return 0;
+        }
+
+        let bytes = self.description.as_bytes();
+        let mut arity = 0;
+        for (i, &c) in bytes.iter().enumerate() {
+            if c != b'#' {
+                continue;
+            }
+
+            if let Some(&d) = bytes.get(i + 1) {
+                if d.is_ascii_digit() {
+                    arity = arity.max((d - b'0') as usize);
+                }
+            }
+        }
+        arity
+    }
+}
+
+fn parse_caps(path: &PathBuf) -> (Vec<Capability>, Vec<Capability>, Vec<Capability>) {
+    let file = File::open(path).expect("failed to open the Caps file");
+
+    let mut bools = Vec::new();
+    let mut numbers = Vec::new();
+    let mut strings = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line.expect("failed to read the Caps file");
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let columns: Vec<&str> = line.split('\t').filter(|c| !c.is_empty()).collect();
+        if columns.len() < 8 {
+            panic!("malformed line in Caps file: {:?}", line);
+        }
+
+        let cap = Capability {
+            variable: columns[0].to_string(),
+            name: columns[1].to_string(),
+            termcap: columns[3].to_string(),
+            description: columns[7..].join(" "),
+        };
+
+        match columns[2] {
+            "bool" => bools.push(cap),
+            "num" => numbers.push(cap),
+            "str" => strings.push(cap),
+            t => panic!("unknown capability type {:?} in Caps file", t),
+        }
+    }
+
+    (bools, numbers, strings)
+}
+
+fn write_enum<W: Write>(w: &mut W, name: &str, caps: &[Capability]) {
+    writeln!(w, "#[repr(usize)]").unwrap();
+    writeln!(w, "#[derive(Copy, Clone, Debug, PartialEq, Eq)]").unwrap();
+    writeln!(w, "pub enum {} {{", name).unwrap();
+    for cap in caps {
+        writeln!(w, "    /// {} (`{}`)", cap.description, cap.name).unwrap();
+        writeln!(w, "    {},", cap.variant()).unwrap();
+    }
+    writeln!(w, "}}\n").unwrap();
+}
+
+fn write_table<W: Write, F: Fn(&Capability) -> String>(
+    w: &mut W,
+    name: &str,
+    typ: &str,
+    caps: &[Capability],
+    f: F,
+) {
+    writeln!(w, "const {}: [{}; {}] = [", name, typ, caps.len()).unwrap();
+    for cap in caps {
+        writeln!(w, "    {},", f(cap)).unwrap();
+    }
+    writeln!(w, "];\n").unwrap();
+}
+
+fn write_tables<W: Write>(w: &mut W, prefix: &str, field: &str, caps: &[Capability]) {
+    write_table(w, &format!("{}_FIELDS", prefix), field, caps, |c| {
+        format!("{}::{}", field, c.variant())
+    });
+    write_table(w, &format!("{}_NAMES", prefix), "&str", caps, |c| {
+        format!("{:?}", c.name)
+    });
+    write_table(w, &format!("{}_TERMCAP_NAMES", prefix), "&str", caps, |c| {
+        format!("{:?}", c.termcap)
+    });
+    write_table(w, &format!("{}_VARIABLE_NAMES", prefix), "&str", caps, |c| {
+        format!("{:?}", c.variable)
+    });
+    write_table(w, &format!("{}_DESCRIPTIONS", prefix), "&str", caps, |c| {
+        format!("{:?}", c.description)
+    });
+}
+
+fn main() {
+    let caps_path = PathBuf::from(CAPS_PATH);
+    println!("cargo:rerun-if-changed={}", CAPS_PATH);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let (bools, numbers, strings) = parse_caps(&caps_path);
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("fields.rs");
+    let mut out = File::create(out_path).expect("failed to create fields.rs");
+
+    writeln!(out, "/// Number of booleans expected to be present in the file").unwrap();
+    writeln!(out, "pub const PREDEFINED_BOOLEANS_COUNT: usize = {};\n", bools.len()).unwrap();
+    writeln!(out, "/// Number of numbers expected to be present in the file").unwrap();
+    writeln!(out, "pub const PREDEFINED_NUMERICS_COUNT: usize = {};\n", numbers.len()).unwrap();
+    writeln!(out, "/// Number of strings expected to be present in the file").unwrap();
+    writeln!(out, "pub const PREDEFINED_STRINGS_COUNT: usize = {};\n", strings.len()).unwrap();
+
+    write_enum(&mut out, "BooleanField", &bools);
+    write_enum(&mut out, "NumericField", &numbers);
+    write_enum(&mut out, "StringField", &strings);
+
+    write_tables(&mut out, "BOOLEAN", "BooleanField", &bools);
+    write_tables(&mut out, "NUMERIC", "NumericField", &numbers);
+    write_tables(&mut out, "STRING", "StringField", &strings);
+    write_table(&mut out, "STRING_ARITIES", "usize", &strings, |c| {
+        c.arity().to_string()
+    });
+}
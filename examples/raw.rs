@@ -6,14 +6,13 @@ use nixterm::Term;
 pub fn main() {
     let term = Term::new().unwrap();
 
-    // save the terminal's settings
-    let settings = term.settings();
-
     term.print("Entering raw mode...\n").unwrap();
 
-    // Clone the settings an add the necessary options for raw mode
+    // Enter raw mode, the terminal's settings are restored when `raw` is dropped.
+    let raw = term.raw_mode().unwrap();
+
     // stop & start output can be confusing (usually they are Ctrl-S & Ctrl-Q) so disable them.
-    term.update(settings.clone().raw().stop_output('\0').start_output('\0'))
+    term.update(term.settings().stop_output('\0').start_output('\0'))
         .unwrap();
     term.print("Try pressing a few keys keys (Ctrl-C to quit): ")
         .unwrap();
@@ -35,5 +34,5 @@ pub fn main() {
     term.print("\n\r").unwrap();
 
     // Revert back to the settings saved before entering raw mode
-    term.update(settings).unwrap();
+    raw.restore().unwrap();
 }
@@ -5,9 +5,6 @@ use nixterm::term::Term;
 pub fn main() {
     let term = Term::new().unwrap();
 
-    // save the terminal's settings
-    let settings = term.settings();
-
     term.writer()
         .bold()
         .println("Demo Signup Form")
@@ -24,31 +21,15 @@ pub fn main() {
 
     let name = term.readline().unwrap();
 
-    // Turn echo off to hide the password
-    term.update(settings.clone().echo(false)).unwrap();
+    // the password isn't echoed, a '*' is printed for each character instead
+    term.writer().foreground("green").print("\t--> ").done().unwrap();
+    let password = term.prompt_secret("Password: ", Some('*')).unwrap();
 
-    term.writer()
-        .foreground("green")
-        .print("\t--> ")
-        .print("Password: ")
-        .done()
+    term.writer().foreground("green").print("\t--> ").done().unwrap();
+    let password2 = term
+        .prompt_secret("Password (Confirm): ", Some('*'))
         .unwrap();
-    let password = term.readline().unwrap();
-
-    term.println("");
 
-    term.writer()
-        .foreground("green")
-        .print("\t--> ")
-        .print("Password (Confirm): ")
-        .done()
-        .unwrap();
-    let password2 = term.readline().unwrap();
-
-    // restore the original settings
-    term.update(settings).unwrap();
-
-    term.println("");
     term.println("");
 
     if password != password2 {
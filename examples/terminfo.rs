@@ -5,12 +5,17 @@ use nixterm::terminfo;
 fn main() {
     let info = terminfo::from_env().unwrap();
 
-    print!("Terminal: {}", info.names[0]);
-    if info.names.len() > 1 {
-        print!(" (AKA \"{}\")", info.names[1..].join("\", \""));
+    print!("Terminal: {}", info.primary_name());
+    let aliases: Vec<&str> = info.aliases().collect();
+    if !aliases.is_empty() {
+        print!(" (AKA \"{}\")", aliases.join("\", \""));
     }
     println!();
 
+    if let Some(description) = info.description() {
+        println!("Description: {}", description);
+    }
+
     println!(
         "Does this terminal support automatic margins? {}",
         info.boolean(terminfo::AutoRightMargin)
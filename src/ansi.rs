@@ -1,8 +1,11 @@
 use errors::*;
 use failure::ResultExt;
+use std::fmt;
 use std::io::Write;
+use std::mem;
+use std::str;
 use std::str::{Chars, FromStr};
-use util;
+use text;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Color {
@@ -59,6 +62,243 @@ pub enum GraphicRendition {
     ResetForeground = 39,
 }
 
+/// A control sequence to write, `CSI`, then an optional private marker, parameters, intermediates and a final byte.
+///
+/// The bytes given to the builder are checked, so the sequence is always well-formed and terminated.
+///
+/// # Examples
+/// ```
+/// use nixterm::ansi::Csi;
+///
+/// assert_eq!(Csi::cursor_position(3, 7).to_string(), "\x1b[3;7H");
+/// assert_eq!(Csi::new(b'q').param(2).intermediate(b' ').to_string(), "\x1b[2 q");
+/// assert_eq!(Csi::new(b'c').private(b'>').to_string(), "\x1b[>c");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Csi {
+    private: Option<u8>,
+    params: Vec<u32>,
+    intermediates: Vec<u8>,
+    final_byte: u8,
+}
+
+impl Csi {
+    /// Start a control sequence that ends with `final_byte`.
+    ///
+    /// # Panics
+    /// If `final_byte` isn't between `@` and `~`.
+    pub fn new(final_byte: u8) -> Csi {
+        assert!(
+            (0x40..=0x7e).contains(&final_byte),
+            "invalid final byte {:?} for a control sequence",
+            final_byte as char
+        );
+        Csi {
+            private: None,
+            params: Vec::new(),
+            intermediates: Vec::new(),
+            final_byte,
+        }
+    }
+
+    /// Set the private marker written before the parameters, e.g. `?` for DEC private modes.
+    ///
+    /// # Panics
+    /// If `marker` isn't one of `<`, `=`, `>` or `?`.
+    pub fn private(mut self, marker: u8) -> Csi {
+        assert!(
+            (0x3c..=0x3f).contains(&marker),
+            "invalid private marker {:?} for a control sequence",
+            marker as char
+        );
+        self.private = Some(marker);
+        self
+    }
+
+    pub fn param(mut self, param: u32) -> Csi {
+        self.params.push(param);
+        self
+    }
+
+    pub fn params<I: IntoIterator<Item = u32>>(mut self, params: I) -> Csi {
+        self.params.extend(params);
+        self
+    }
+
+    /// Add an intermediate byte, written between the parameters and the final byte, e.g. `$` in DECRQM.
+    ///
+    /// # Panics
+    /// If `b` isn't between space and `/`.
+    pub fn intermediate(mut self, b: u8) -> Csi {
+        assert!(
+            (0x20..=0x2f).contains(&b),
+            "invalid intermediate byte {:?} for a control sequence",
+            b as char
+        );
+        self.intermediates.push(b);
+        self
+    }
+
+    /// Move the cursor up `n` rows (CUU).
+    pub fn cursor_up(n: u32) -> Csi {
+        Csi::new(b'A').param(n)
+    }
+
+    /// Move the cursor down `n` rows (CUD).
+    pub fn cursor_down(n: u32) -> Csi {
+        Csi::new(b'B').param(n)
+    }
+
+    /// Move the cursor right `n` columns (CUF).
+    pub fn cursor_forward(n: u32) -> Csi {
+        Csi::new(b'C').param(n)
+    }
+
+    /// Move the cursor left `n` columns (CUB).
+    pub fn cursor_back(n: u32) -> Csi {
+        Csi::new(b'D').param(n)
+    }
+
+    /// Move the cursor to `column` on its row (CHA), the first column is 1.
+    pub fn cursor_column(column: u32) -> Csi {
+        Csi::new(b'G').param(column)
+    }
+
+    /// Move the cursor to `row` and `column` (CUP), the top left corner is `(1, 1)`.
+    pub fn cursor_position(row: u32, column: u32) -> Csi {
+        Csi::new(b'H').param(row).param(column)
+    }
+
+    /// Erase part of the screen (ED): 0 is below the cursor, 1 above it, and 2 everything.
+    /// 0 is the default, so it's left out.
+    pub fn erase_display(n: u32) -> Csi {
+        Csi::new(b'J').params(Some(n).filter(|&n| n != 0))
+    }
+
+    /// Erase part of the cursor's row (EL): 0 is right of the cursor, 1 left of it, and 2 the whole row.
+    /// 0 is the default, so it's left out.
+    pub fn erase_line(n: u32) -> Csi {
+        Csi::new(b'K').params(Some(n).filter(|&n| n != 0))
+    }
+
+    /// Select graphic rendition (SGR), e.g. `[1, 31]` for bold and red.
+    pub fn sgr<I: IntoIterator<Item = u32>>(params: I) -> Csi {
+        Csi::new(b'm').params(params)
+    }
+
+    /// Set the cursor's shape (DECSCUSR), see `CursorStyle`.
+    pub fn cursor_style(style: u32) -> Csi {
+        Csi::new(b'q').param(style).intermediate(b' ')
+    }
+
+    /// The sequence's bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(write!(w, "{}", self).context(ErrorKind::CsiFailed)?)
+    }
+}
+
+impl fmt::Display for Csi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\x1b[")?;
+        if let Some(marker) = self.private {
+            write!(f, "{}", marker as char)?;
+        }
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        for &b in &self.intermediates {
+            write!(f, "{}", b as char)?;
+        }
+        write!(f, "{}", self.final_byte as char)
+    }
+}
+
+/// A DEC private mode, turned on and off with DECSET (`CSI ? n h`) and DECRST (`CSI ? n l`).
+///
+/// # Examples
+/// ```
+/// use nixterm::ansi::PrivateMode;
+///
+/// assert_eq!(PrivateMode::CursorVisible.reset().to_string(), "\x1b[?25l");
+/// assert_eq!(PrivateMode::BracketedPaste.set().to_string(), "\x1b[?2004h");
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PrivateMode {
+    CursorVisible = 25,
+
+    /// Report presses, releases and the scroll wheel.
+    MouseClicks = 1000,
+
+    /// Also report moving the mouse while a button is held down.
+    MouseDrag = 1002,
+
+    /// Also report moving the mouse without a button held down.
+    MouseMotion = 1003,
+
+    /// Report focus changes with `CSI I` and `CSI O`.
+    FocusReporting = 1004,
+
+    /// Send mouse reports in the SGR encoding, which isn't limited to 223 columns.
+    SgrMouse = 1006,
+
+    /// Switch to the alternate screen, saving the cursor first.
+    AltScreen = 1049,
+
+    /// Mark pasted text with `CSI 200 ~` and `CSI 201 ~`.
+    BracketedPaste = 2004,
+
+    /// Hold back drawing until the mode is reset, so a half-drawn screen isn't shown.
+    SynchronizedOutput = 2026,
+}
+
+impl PrivateMode {
+    /// The mode's number.
+    pub fn number(self) -> u32 {
+        self as u32
+    }
+
+    /// Turn the mode on (DECSET).
+    pub fn set(self) -> Csi {
+        self.csi(b'h')
+    }
+
+    /// Turn the mode off (DECRST).
+    pub fn reset(self) -> Csi {
+        self.csi(b'l')
+    }
+
+    /// Save whether the mode is on, so it can be put back with `PrivateMode::restore`. This is an xterm extension.
+    pub fn save(self) -> Csi {
+        self.csi(b's')
+    }
+
+    /// Put the mode back to what it was when it was saved. This is an xterm extension.
+    pub fn restore(self) -> Csi {
+        self.csi(b'r')
+    }
+
+    /// Ask the terminal if the mode is on (DECRQM).
+    ///
+    /// The reply is `CSI ? n ; s $ y`, where `s` is 1 if it's on, 2 if it's off, and 0 if the mode isn't recognized.
+    pub fn request(self) -> Csi {
+        Csi::new(b'p')
+            .private(b'?')
+            .param(self.number())
+            .intermediate(b'$')
+    }
+
+    fn csi(self, final_byte: u8) -> Csi {
+        Csi::new(final_byte).private(b'?').param(self.number())
+    }
+}
+
 impl From<u8> for Color {
     fn from(v: u8) -> Color {
         Color::Index(v)
@@ -207,54 +447,607 @@ impl FromStr for Color {
 }
 
 pub fn set_foreground<W: Write>(w: &mut W, c: Color) -> Result<()> {
-    Ok(match c {
-        Color::Index(x @ 0...7) => write!(w, "\x1b[{}m", x + 30),
-        Color::Index(x @ 8...15) => write!(w, "\x1b[{}m", x + 82),
-        Color::Index(x) => write!(w, "\x1b[38;5;{}m", x),
-        Color::Rgb(r, g, b) => write!(w, "\x1b[38;2;{};{};{}m", r, g, b),
-    }.context(ErrorKind::CsiFailed)?)
+    color_sgr(30, c).write(w)
 }
 
 pub fn set_background<W: Write>(w: &mut W, c: Color) -> Result<()> {
-    Ok(match c {
-        Color::Index(x @ 0...7) => write!(w, "\x1b[{}m", x + 40),
-        Color::Index(x @ 8...15) => write!(w, "\x1b[{}m", x + 92),
-        Color::Index(x) => write!(w, "\x1b[48;5;{}m", x),
-        Color::Rgb(r, g, b) => write!(w, "\x1b[98;2;{};{};{}m", r, g, b),
-    }.context(ErrorKind::CsiFailed)?)
+    color_sgr(40, c).write(w)
+}
+
+/// The SGR sequence that sets a color, `base` is 30 for the foreground and 40 for the background.
+fn color_sgr(base: u32, c: Color) -> Csi {
+    match c {
+        Color::Index(x @ 0..=7) => Csi::sgr(vec![base + u32::from(x)]),
+        Color::Index(x @ 8..=15) => Csi::sgr(vec![base + 60 + u32::from(x - 8)]),
+        Color::Index(x) => Csi::sgr(vec![base + 8, 5, u32::from(x)]),
+        Color::Rgb(r, g, b) => Csi::sgr(vec![base + 8, 2, u32::from(r), u32::from(g), u32::from(b)]),
+    }
 }
 
+/// Move the cursor up (positive) or down.
 pub fn cursor_shift_vertical<W: Write>(w: &mut W, shift: isize) -> Result<()> {
     if shift < 0 {
-        Ok(write!(w, "\x1b[{}B", -shift).context(ErrorKind::CsiFailed)?)
+        Csi::cursor_down(shift.unsigned_abs() as u32).write(w)
     } else if shift > 0 {
-        Ok(write!(w, "\x1b[{}A", shift).context(ErrorKind::CsiFailed)?)
+        Csi::cursor_up(shift as u32).write(w)
     } else {
         Ok(())
     }
 }
 
+/// Move the cursor right (positive) or left.
 pub fn cursor_shift_horizontal<W: Write>(w: &mut W, shift: isize) -> Result<()> {
     if shift < 0 {
-        Ok(write!(w, "\x1b[{}D", -shift).context(ErrorKind::CsiFailed)?)
+        Csi::cursor_back(shift.unsigned_abs() as u32).write(w)
     } else if shift > 0 {
-        Ok(write!(w, "\x1b[{}C", shift).context(ErrorKind::CsiFailed)?)
+        Csi::cursor_forward(shift as u32).write(w)
     } else {
         Ok(())
     }
 }
 
+/// Move the cursor to column `x` and row `y`, the top left corner is `(1, 1)`.
 pub fn cursor_move<W: Write>(w: &mut W, x: usize, y: usize) -> Result<()> {
-    Ok(write!(w, "\x1b[{};{}m", x, y).context(ErrorKind::CsiFailed)?)
+    Csi::cursor_position(y as u32, x as u32).write(w)
 }
 
 pub fn cursor_set_column<W: Write>(w: &mut W, x: usize) -> Result<()> {
-    Ok(write!(w, "\x1b[{}G", x).context(ErrorKind::CsiFailed)?)
+    Csi::cursor_column(x as u32).write(w)
 }
 
 #[inline]
 pub fn sgr<W: Write>(w: &mut W, gr: GraphicRendition) -> Result<usize> {
-    Ok(w.write(b"\x1b[").context(ErrorKind::CsiFailed)?
-        + util::write_u8_ansi(w, gr as u8)?
-        + w.write(b"m").context(ErrorKind::CsiFailed)?)
+    let seq = Csi::sgr(vec![gr as u32]).to_bytes();
+    w.write_all(&seq).context(ErrorKind::CsiFailed)?;
+    Ok(seq.len())
+}
+
+/// The most parameters kept for a control sequence, any more are dropped.
+const MAX_PARAMS: usize = 32;
+
+/// The most intermediate bytes kept for a sequence, any more are dropped.
+const MAX_INTERMEDIATES: usize = 8;
+
+/// The most bytes of data kept for a string sequence (e.g. OSC), the rest of the string is dropped.
+const MAX_STRING_LEN: usize = 1 << 20;
+
+/// A part of a byte stream, read by a `Parser`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Sequence {
+    /// A printable character.
+    Print(char),
+
+    /// A C0 control character, e.g. `\r`, or DEL.
+    Control(u8),
+
+    /// A byte that isn't part of a valid UTF-8 character.
+    Invalid(u8),
+
+    /// An escape sequence that isn't one of the others, e.g. `ESC 7` (save the cursor) or `ESC ( B`.
+    Escape { intermediates: Vec<u8>, final_byte: u8 },
+
+    /// A control sequence, e.g. `CSI ? 25 h`.
+    ///
+    /// `private` is the marker some sequences start with (`<`, `=`, `>` or `?`).
+    /// Empty parameters are 0, and `:` separates parameters like `;` does.
+    Csi {
+        private: Option<u8>,
+        params: Vec<u32>,
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+
+    /// `ESC O` followed by a character, e.g. the cursor keys in keypad transmit mode.
+    Ss3(u8),
+
+    /// An operating system command, the bytes between `ESC ]` and BEL or ST.
+    Osc(Vec<u8>),
+
+    /// A device control string, which is introduced like a control sequence and followed by data up to ST.
+    Dcs {
+        private: Option<u8>,
+        params: Vec<u32>,
+        intermediates: Vec<u8>,
+        final_byte: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// Splits a byte stream into characters and escape sequences, following the VT500 parser's state machine.
+///
+/// Input is parsed a chunk at a time, a sequence that's cut off at the end of a chunk is finished by the next one.
+/// Text is decoded as UTF-8, so the 8-bit C1 controls (e.g. `0x9b` for CSI) aren't recognized.
+/// SOS, PM and APC strings are skipped.
+/// Parameters, intermediates and string data past a fixed limit are dropped, so a sequence that never ends
+/// doesn't use more and more memory.
+///
+/// # Examples
+/// ```
+/// use nixterm::ansi::{Parser, Sequence};
+///
+/// let mut parser = Parser::new();
+/// let mut sequences = parser.parse(b"a\x1b[1;");
+/// sequences.extend(parser.parse(b"31m"));
+/// assert_eq!(
+///     sequences,
+///     vec![
+///         Sequence::Print('a'),
+///         Sequence::Csi {
+///             private: None,
+///             params: vec![1, 31],
+///             intermediates: vec![],
+///             final_byte: b'm',
+///         },
+///     ]
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    state: State,
+    private: Option<u8>,
+    params: Vec<u32>,
+
+    /// The parameter being read, `None` until a digit or separator is read.
+    param: Option<u32>,
+    intermediates: Vec<u8>,
+    final_byte: u8,
+
+    /// A string sequence's data.
+    data: Vec<u8>,
+
+    /// The bytes of a UTF-8 character that's only been partly read.
+    utf8: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Ss3,
+
+    /// Reading a control sequence, or the start of a device control string (`dcs`).
+    Params { dcs: bool, phase: Phase },
+
+    /// Reading a string sequence's data, until BEL or ST.
+    String(StringKind),
+
+    /// An escape was read in a string, it's either the start of ST or it ends the string early.
+    StringEscape(StringKind),
+}
+
+/// How much of a control sequence has been read.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Entry,
+    Param,
+    Intermediate,
+
+    /// The sequence is malformed, skip to its final byte.
+    Ignore,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum StringKind {
+    Osc,
+    Dcs,
+
+    /// SOS, PM and APC strings, or a malformed device control string.
+    Ignored,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// Parse a chunk of a byte stream.
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<Sequence> {
+        let mut sequences = Vec::new();
+        for &b in bytes {
+            self.advance(b, &mut sequences);
+        }
+        sequences
+    }
+
+    /// Check if the parser isn't in the middle of a sequence or character.
+    pub fn is_ground(&self) -> bool {
+        self.state == State::Ground && self.utf8.is_empty()
+    }
+
+    /// Check if the parser is in an OSC, DCS or other string, which only ends with ST or BEL.
+    pub fn in_string(&self) -> bool {
+        matches!(self.state, State::String(_) | State::StringEscape(_))
+    }
+
+    fn advance(&mut self, b: u8, out: &mut Vec<Sequence>) {
+        if !self.utf8.is_empty() || (self.state == State::Ground && b >= 0x80) {
+            return self.advance_utf8(b, out);
+        }
+
+        // CAN and SUB cancel a sequence, and escape starts a new one, except in a string
+        match b {
+            0x18 | 0x1a => {
+                self.reset();
+                return out.push(Sequence::Control(b));
+            }
+            0x1b if !self.in_string() => {
+                self.reset();
+                self.state = State::Escape;
+                return;
+            }
+            _ => (),
+        }
+
+        match self.state {
+            State::Ground => match b {
+                0x20..=0x7e => out.push(Sequence::Print(b as char)),
+                _ => out.push(Sequence::Control(b)),
+            },
+            State::Escape => match b {
+                0x00..=0x1f => out.push(Sequence::Control(b)),
+                0x20..=0x2f => {
+                    self.push_intermediate(b);
+                    self.state = State::EscapeIntermediate;
+                }
+                b'[' => self.state = State::Params { dcs: false, phase: Phase::Entry },
+                b'P' => self.state = State::Params { dcs: true, phase: Phase::Entry },
+                b']' => self.state = State::String(StringKind::Osc),
+                b'X' | b'^' | b'_' => self.state = State::String(StringKind::Ignored),
+                b'O' => self.state = State::Ss3,
+                0x30..=0x7e => self.dispatch_escape(b, out),
+                _ => (),
+            },
+            State::EscapeIntermediate => match b {
+                0x00..=0x1f => out.push(Sequence::Control(b)),
+                0x20..=0x2f => self.push_intermediate(b),
+                0x30..=0x7e => self.dispatch_escape(b, out),
+                _ => (),
+            },
+            State::Ss3 => match b {
+                0x00..=0x1f => out.push(Sequence::Control(b)),
+                0x20..=0x7e => {
+                    self.reset();
+                    out.push(Sequence::Ss3(b));
+                }
+                _ => (),
+            },
+            State::Params { dcs, phase } => self.advance_params(dcs, phase, b, out),
+            State::String(kind) => match b {
+                0x07 if kind == StringKind::Osc => self.dispatch_string(kind, out),
+                0x1b => self.state = State::StringEscape(kind),
+                0x00..=0x1f if kind != StringKind::Dcs => (),
+                _ if kind != StringKind::Ignored && self.data.len() < MAX_STRING_LEN => self.data.push(b),
+                _ => (),
+            },
+            State::StringEscape(kind) => {
+                // anything other than `\` ends the string, and is the start of the next escape sequence
+                self.dispatch_string(kind, out);
+                if b != b'\\' {
+                    self.state = State::Escape;
+                    self.advance(b, out);
+                }
+            }
+        }
+    }
+
+    /// Read a byte of a control sequence, or the part of a device control string before its data.
+    fn advance_params(&mut self, dcs: bool, phase: Phase, b: u8, out: &mut Vec<Sequence>) {
+        let next = |phase| State::Params { dcs, phase };
+        match b {
+            // controls are carried out in the middle of a control sequence
+            0x00..=0x1f if !dcs => out.push(Sequence::Control(b)),
+            0x00..=0x1f | 0x7f => (),
+            0x3c..=0x3f if phase == Phase::Entry => {
+                self.private = Some(b);
+                self.state = next(Phase::Param);
+            }
+            0x30..=0x3b if phase == Phase::Entry || phase == Phase::Param => {
+                self.state = next(Phase::Param);
+                match b {
+                    b'0'..=b'9' => {
+                        let digit = u32::from(b - b'0');
+                        let param = self.param.unwrap_or(0);
+                        self.param = Some(param.saturating_mul(10).saturating_add(digit));
+                    }
+                    _ => self.push_param(),
+                }
+            }
+            0x20..=0x2f if phase != Phase::Ignore => {
+                self.push_intermediate(b);
+                self.state = next(Phase::Intermediate);
+            }
+            0x40..=0x7e if phase == Phase::Ignore => {
+                self.reset();
+                if dcs {
+                    self.state = State::String(StringKind::Ignored);
+                }
+            }
+            0x40..=0x7e => {
+                if self.param.is_some() || !self.params.is_empty() {
+                    self.push_param();
+                }
+                if dcs {
+                    self.final_byte = b;
+                    self.state = State::String(StringKind::Dcs);
+                } else {
+                    out.push(Sequence::Csi {
+                        private: self.private,
+                        params: mem::take(&mut self.params),
+                        intermediates: mem::take(&mut self.intermediates),
+                        final_byte: b,
+                    });
+                    self.reset();
+                }
+            }
+            _ => self.state = next(Phase::Ignore),
+        }
+    }
+
+    fn advance_utf8(&mut self, b: u8, out: &mut Vec<Sequence>) {
+        if self.utf8.is_empty() {
+            match utf8_len(b) {
+                1 => out.push(Sequence::Invalid(b)),
+                _ => self.utf8.push(b),
+            }
+            return;
+        }
+
+        if b & 0xc0 != 0x80 {
+            // the character was cut short, the byte that did it is the start of something else
+            out.extend(self.utf8.drain(..).map(Sequence::Invalid));
+            return self.advance(b, out);
+        }
+        self.utf8.push(b);
+        if self.utf8.len() == utf8_len(self.utf8[0]) {
+            match str::from_utf8(&self.utf8) {
+                Ok(s) => out.extend(s.chars().map(Sequence::Print)),
+                Err(_) => out.extend(self.utf8.iter().cloned().map(Sequence::Invalid)),
+            }
+            self.utf8.clear();
+        }
+    }
+
+    fn push_param(&mut self) {
+        let param = self.param.take().unwrap_or(0);
+        if self.params.len() < MAX_PARAMS {
+            self.params.push(param);
+        }
+    }
+
+    fn push_intermediate(&mut self, b: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(b);
+        }
+    }
+
+    fn dispatch_escape(&mut self, b: u8, out: &mut Vec<Sequence>) {
+        out.push(Sequence::Escape {
+            intermediates: mem::take(&mut self.intermediates),
+            final_byte: b,
+        });
+        self.reset();
+    }
+
+    fn dispatch_string(&mut self, kind: StringKind, out: &mut Vec<Sequence>) {
+        match kind {
+            StringKind::Osc => out.push(Sequence::Osc(mem::take(&mut self.data))),
+            StringKind::Dcs => out.push(Sequence::Dcs {
+                private: self.private,
+                params: mem::take(&mut self.params),
+                intermediates: mem::take(&mut self.intermediates),
+                final_byte: self.final_byte,
+                data: mem::take(&mut self.data),
+            }),
+            StringKind::Ignored => (),
+        }
+        self.reset();
+    }
+
+    /// Go back to the ground state, dropping the sequence that was being read.
+    fn reset(&mut self) {
+        self.state = State::Ground;
+        self.private = None;
+        self.params.clear();
+        self.param = None;
+        self.intermediates.clear();
+        self.data.clear();
+    }
+}
+
+/// Get the number of columns `s` takes up on the terminal, not counting escape sequences.
+///
+/// Characters are measured with `text::char_width`, so wide characters take up two columns and combining marks none.
+/// Control characters are zero-width, like escape sequences.
+///
+/// # Examples
+/// ```
+/// use nixterm::ansi::visible_width;
+///
+/// assert_eq!(visible_width("\x1b[1;31merror\x1b[0m: 日本"), 11);
+/// assert_eq!(visible_width("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"), 4);
+/// ```
+pub fn visible_width(s: &str) -> usize {
+    Parser::new()
+        .parse(s.as_bytes())
+        .into_iter()
+        .map(|seq| match seq {
+            Sequence::Print(c) => text::char_width(c),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The length of the UTF-8 character that starts with `b`, 1 if it isn't the start of a multi-byte character.
+fn utf8_len(b: u8) -> usize {
+    match b {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ansi::*;
+
+    fn csi(private: Option<u8>, params: Vec<u32>, intermediates: &[u8], final_byte: u8) -> Sequence {
+        Sequence::Csi {
+            private,
+            params,
+            intermediates: intermediates.to_vec(),
+            final_byte,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.parse("aé\r\x1b[A\x1b[?25h\x1b[;5H\x1b[?2026;2$y\x1bOP\x1b7\x1b(B".as_bytes()),
+            vec![
+                Sequence::Print('a'),
+                Sequence::Print('é'),
+                Sequence::Control(b'\r'),
+                csi(None, vec![], b"", b'A'),
+                csi(Some(b'?'), vec![25], b"", b'h'),
+                csi(None, vec![0, 5], b"", b'H'),
+                csi(Some(b'?'), vec![2026, 2], b"$", b'y'),
+                Sequence::Ss3(b'P'),
+                Sequence::Escape {
+                    intermediates: vec![],
+                    final_byte: b'7',
+                },
+                Sequence::Escape {
+                    intermediates: vec![b'('],
+                    final_byte: b'B',
+                },
+            ]
+        );
+        assert!(parser.is_ground());
+
+        // strings end with BEL or ST, escape in a control sequence starts a new one
+        assert_eq!(
+            parser.parse(b"\x1b]11;?\x07\x1b]0;title\x1b\\\x1b[12\x1b[B\x1bPq#0\x1b\\"),
+            vec![
+                Sequence::Osc(b"11;?".to_vec()),
+                Sequence::Osc(b"0;title".to_vec()),
+                csi(None, vec![], b"", b'B'),
+                Sequence::Dcs {
+                    private: None,
+                    params: vec![],
+                    intermediates: vec![],
+                    final_byte: b'q',
+                    data: b"#0".to_vec(),
+                },
+            ]
+        );
+
+        // controls are carried out in the middle of a sequence, CAN cancels it
+        assert_eq!(
+            parser.parse(b"\x1b[1\n2m\x1b[3\x18x\x1b_apc\x1b\\\x1b[1<m"),
+            vec![
+                Sequence::Control(b'\n'),
+                csi(None, vec![12], b"", b'm'),
+                Sequence::Control(0x18),
+                Sequence::Print('x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn csi_builder() {
+        let seq = Csi::new(b'p').private(b'?').param(2026).intermediate(b'$');
+        assert_eq!(seq.to_string(), "\x1b[?2026$p");
+        assert_eq!(Csi::sgr(vec![]).to_bytes(), b"\x1b[m");
+        assert_eq!(Csi::erase_line(2).to_string(), "\x1b[2K");
+        assert_eq!(Csi::erase_display(0).to_string(), "\x1b[J");
+
+        // what's built is what's parsed
+        let parsed = Parser::new().parse(&seq.to_bytes());
+        assert_eq!(parsed, vec![csi(Some(b'?'), vec![2026], b"$", b'p')]);
+
+        let mut out = Vec::new();
+        set_foreground(&mut out, Color::Index(9)).unwrap();
+        set_background(&mut out, Color::Rgb(1, 2, 3)).unwrap();
+        cursor_shift_vertical(&mut out, -2).unwrap();
+        cursor_move(&mut out, 5, 1).unwrap();
+        sgr(&mut out, GraphicRendition::Reset).unwrap();
+        assert_eq!(out, b"\x1b[91m\x1b[48;2;1;2;3m\x1b[2B\x1b[1;5H\x1b[0m");
+    }
+
+    #[test]
+    fn private_modes() {
+        let mode = PrivateMode::SynchronizedOutput;
+        assert_eq!(mode.set().to_bytes(), b"\x1b[?2026h");
+        assert_eq!(mode.reset().to_bytes(), b"\x1b[?2026l");
+        assert_eq!(mode.save().to_bytes(), b"\x1b[?2026s");
+        assert_eq!(mode.restore().to_bytes(), b"\x1b[?2026r");
+        assert_eq!(mode.request().to_bytes(), b"\x1b[?2026$p");
+        assert_eq!(PrivateMode::AltScreen.number(), 1049);
+    }
+
+    #[test]
+    #[should_panic]
+    fn csi_invalid_final_byte() {
+        Csi::new(b'1');
+    }
+
+    #[test]
+    fn width() {
+        assert_eq!(visible_width(""), 0);
+        assert_eq!(visible_width("plain"), 5);
+        assert_eq!(visible_width("\x1b[38;2;1;2;3m日本\x1b[m語\x1b(B"), 6);
+        assert_eq!(visible_width("e\u{301}\r\x1bOA"), 1);
+        // a sequence that's cut off at the end takes up no room
+        assert_eq!(visible_width("ok\x1b[1;3"), 2);
+    }
+
+    #[test]
+    fn parse_chunks() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b[1;"), vec![]);
+        assert!(!parser.is_ground());
+        assert!(!parser.in_string());
+        assert_eq!(parser.parse(b"2R\xe2\x82"), vec![csi(None, vec![1, 2], b"", b'R')]);
+        assert_eq!(parser.parse(b"\xac\x1b]"), vec![Sequence::Print('€')]);
+        assert!(parser.in_string());
+        assert_eq!(parser.parse(b"x\x1b"), vec![]);
+        assert_eq!(parser.parse(b"\\"), vec![Sequence::Osc(b"x".to_vec())]);
+
+        // a character that's cut short is invalid, but what cut it short isn't lost
+        assert_eq!(
+            parser.parse(b"\xe2\x82a\xff"),
+            vec![
+                Sequence::Invalid(0xe2),
+                Sequence::Invalid(0x82),
+                Sequence::Print('a'),
+                Sequence::Invalid(0xff),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_limits() {
+        let mut parser = Parser::new();
+        let params = "1;".repeat(40).into_bytes();
+        assert_eq!(
+            parser.parse(&[b"\x1b[", &params[..], b"m"].concat()),
+            vec![csi(None, vec![1; MAX_PARAMS], b"", b'm')]
+        );
+        assert_eq!(
+            parser.parse(&[b"\x1b[1", &[b' '; 20][..], b"q"].concat()),
+            vec![csi(None, vec![1], &[b' '; MAX_INTERMEDIATES], b'q')]
+        );
+
+        // a string that doesn't end is cut short, but the parser still waits for its end
+        assert!(parser.parse(b"\x1b]").is_empty());
+        for _ in 0..(MAX_STRING_LEN / 4096 + 1) {
+            assert!(parser.parse(&[b'x'; 4096]).is_empty());
+        }
+        assert!(parser.in_string());
+        assert_eq!(parser.parse(b"\x07"), vec![Sequence::Osc(vec![b'x'; MAX_STRING_LEN])]);
+    }
 }
@@ -0,0 +1,730 @@
+//! Main loops for programs that redraw the terminal as events arrive, see `Term::run_loop` and `Term::run_app`.
+use errors::*;
+use events::Event;
+use failure::ResultExt;
+use nix::sys::signal;
+use std::cell::RefCell;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use term::{take_signal, Rect, SignalGuard, Term, TermWriter};
+
+/// The state of a single frame in `Term::run_loop`.
+pub struct Frame<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
+
+    /// Every event received since the last frame.
+    pub events: Vec<Event>,
+
+    /// True if the terminal was resized since the last frame.
+    pub resized: bool,
+
+    /// True if the program was suspended (e.g. with Ctrl-Z) and resumed since the last frame.
+    /// The terminal's contents may have been overwritten in the meantime, so everything should be redrawn.
+    pub resumed: bool,
+
+    redraw: bool,
+    quit: bool,
+}
+
+/// How `Term::run_app` sets up the terminal.
+///
+/// By default the alternate screen is used, the cursor is hidden and mouse reporting is on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AppOptions {
+    alternate_screen: bool,
+    hide_cursor: bool,
+    mouse: bool,
+    bracketed_paste: bool,
+}
+
+impl AppOptions {
+    pub fn new() -> AppOptions {
+        AppOptions::default()
+    }
+
+    /// Draw on the alternate screen, so the terminal's contents are restored when the application ends.
+    pub fn alternate_screen(mut self, enabled: bool) -> Self {
+        self.alternate_screen = enabled;
+        self
+    }
+
+    pub fn hide_cursor(mut self, enabled: bool) -> Self {
+        self.hide_cursor = enabled;
+        self
+    }
+
+    /// Report mouse events, see `Term::set_mouse_capture`.
+    pub fn mouse(mut self, enabled: bool) -> Self {
+        self.mouse = enabled;
+        self
+    }
+
+    /// Report pastes as `Event::Paste`, see `Term::set_bracketed_paste`.
+    pub fn bracketed_paste(mut self, enabled: bool) -> Self {
+        self.bracketed_paste = enabled;
+        self
+    }
+}
+
+impl Default for AppOptions {
+    fn default() -> AppOptions {
+        AppOptions {
+            alternate_screen: true,
+            hide_cursor: true,
+            mouse: true,
+            bracketed_paste: false,
+        }
+    }
+}
+
+/// What a `Term::run_app` application draws on.
+pub struct Screen<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
+    size: (u16, u16),
+    quit: bool,
+
+    /// The areas drawn on since the last flush.
+    damage: RefCell<Vec<Rect>>,
+}
+
+/// Sets the terminal up for `Term::run_app`, and puts it back when it's dropped.
+struct AppScreen<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
+    options: AppOptions,
+}
+
+impl<I, O> Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    /// Run an application's main loop, calling `f` at most `fps` times per second.
+    ///
+    /// Input is read and decoded between frames, `f` is only called when there's something new to handle:
+    /// input, a resize, the program resuming after it was suspended, a `Waker` (which adds an `Event::Wake`),
+    /// or a call to `Frame::redraw` in the last frame.
+    /// If nothing happened the loop sleeps, and the terminal isn't flushed.
+    /// When bracketed paste is on, pasted text arrives as a single `Event::Paste`.
+    /// `f` is always called once when the loop starts.
+    ///
+    /// The loop ends when `f` calls `Frame::quit`, returns an error, or the terminal's input is closed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::{Event, Key};
+    /// use nixterm::term::Term;
+    ///
+    /// pub fn main() {
+    ///     let term = Term::new().unwrap();
+    ///     term.update(term.settings().raw()).unwrap();
+    ///
+    ///     term.run_loop(30, |frame| {
+    ///         if frame.events.contains(&Event::Key(Key::Char('q'))) {
+    ///             frame.quit();
+    ///         }
+    ///         frame.writer().print("press q to quit\r").done()?;
+    ///         Ok(())
+    ///     }).unwrap();
+    /// }
+    /// ```
+    pub fn run_loop<F>(&self, fps: u32, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut Frame<I, O>) -> Result<()>,
+    {
+        if self.is_dumb() {
+            return Err(ErrorKind::UnsupportedTerminal.into());
+        }
+
+        let frame_time = Duration::from_secs(1) / fps.max(1);
+        let settings = self.settings();
+        self.err()?;
+
+        let _signals = SignalGuard::install(&[
+            signal::Signal::SIGWINCH,
+            signal::Signal::SIGTSTP,
+            signal::Signal::SIGCONT,
+        ])?;
+        let keys = self.read_keys();
+        let mut frame = Frame::new(self);
+        frame.redraw = true;
+
+        let mut last_frame: Option<Instant> = None;
+        let mut buf = [0u8; 256];
+
+        // the start of a paste that hasn't ended yet
+        let mut pending = Vec::new();
+
+        loop {
+            if take_signal(signal::Signal::SIGTSTP) {
+                self.suspend()?;
+                frame.resumed = true;
+            }
+            if take_signal(signal::Signal::SIGCONT) {
+                self.update(settings.clone())?;
+                frame.resumed = true;
+            }
+            frame.resized |= take_signal(signal::Signal::SIGWINCH);
+            if self.take_wake() {
+                frame.events.push(Event::Wake);
+            }
+
+            // wait for input until the next frame is due, or indefinitely if there's nothing new to draw.
+            let timeout = if frame.is_dirty() {
+                let due = last_frame.map(|t| t + frame_time).unwrap_or_else(Instant::now);
+                Some(due.saturating_duration_since(Instant::now()))
+            } else {
+                None
+            };
+
+            if timeout != Some(Duration::from_secs(0)) {
+                // a signal or wake up also ends the wait, they're handled at the top of the loop
+                if self.wait_for_input_or_signal(timeout)? {
+                    let read = self.read(&mut buf);
+                    if read == 0 {
+                        // the input was closed
+                        return self.err();
+                    }
+                    pending.extend_from_slice(&buf[..read]);
+                    let (events, rest) = keys.decode_events(&pending);
+                    frame.events.extend(events);
+                    pending = rest.to_vec();
+                }
+                continue;
+            }
+
+            last_frame = Some(Instant::now());
+            frame.redraw = false;
+            f(&mut frame)?;
+            self.flush();
+            self.err()?;
+
+            if frame.quit {
+                return Ok(());
+            }
+            frame.events.clear();
+            frame.resized = false;
+            frame.resumed = false;
+        }
+    }
+
+    /// Run a full-screen application, calling `f` with every event until it calls `Screen::quit`.
+    ///
+    /// The terminal is put in raw mode and set up according to `options`. `f` is first called with an `Event::Resize`
+    /// for the terminal's current size, so it can draw the initial screen.
+    /// Everything is put back the way it was when the application ends, whether it returns, fails or panics.
+    /// If the program is suspended (e.g. by `SIGTSTP`) the terminal is restored until it's resumed,
+    /// then `f` gets another `Event::Resize` so it can redraw the screen.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::app::AppOptions;
+    /// use nixterm::events::{Event, Key};
+    /// use nixterm::term::Term;
+    ///
+    /// pub fn main() {
+    ///     let term = Term::new().unwrap();
+    ///     term.run_app(&AppOptions::new(), |screen, event| {
+    ///         match event {
+    ///             Event::Key(Key::Char('q')) => screen.quit(),
+    ///             Event::Resize(columns, rows) => {
+    ///                 screen.clear();
+    ///                 screen.writer().move_to((columns / 2).saturating_sub(8), rows / 2).print("press q to quit").done()?;
+    ///             }
+    ///             _ => (),
+    ///         }
+    ///         Ok(())
+    ///     }).unwrap();
+    /// }
+    /// ```
+    pub fn run_app<F>(&self, options: &AppOptions, f: F) -> Result<()>
+    where
+        F: FnMut(&mut Screen<I, O>, Event) -> Result<()>,
+    {
+        if self.is_dumb() {
+            return Err(ErrorKind::UnsupportedTerminal.into());
+        }
+
+        // a panic's message would be printed to the alternate screen and lost, so hold on to it until the terminal is restored
+        let previous_hook = Arc::new(panic::take_hook());
+        let message = Arc::new(Mutex::new(None));
+        {
+            let previous_hook = previous_hook.clone();
+            let message = message.clone();
+            let app_thread = thread::current().id();
+            panic::set_hook(Box::new(move |info| {
+                if thread::current().id() == app_thread {
+                    *message.lock().unwrap() = Some(info.to_string());
+                } else {
+                    previous_hook(info);
+                }
+            }));
+        }
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| self.app_loop(options, f)));
+        panic::set_hook(Box::new(move |info| previous_hook(info)));
+        match result {
+            Ok(result) => result,
+            Err(payload) => {
+                if let Some(message) = message.lock().unwrap().take() {
+                    eprintln!("{}", message);
+                }
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    fn app_loop<F>(&self, options: &AppOptions, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut Screen<I, O>, Event) -> Result<()>,
+    {
+        let raw = self.raw_mode()?;
+        let app_screen = AppScreen {
+            term: self,
+            options: *options,
+        };
+        app_screen.enter()?;
+        let mut events = self
+            .read_events()?
+            .catch_signals(&[signal::Signal::SIGTSTP])?;
+
+        let (columns, rows) = self.size()?;
+        let mut screen = Screen {
+            term: self,
+            size: (columns, rows),
+            quit: false,
+            damage: RefCell::new(Vec::new()),
+        };
+        let mut event = Event::Resize(columns, rows);
+        loop {
+            if let Event::Resize(columns, rows) = event {
+                screen.size = (columns, rows);
+                screen.mark_dirty(Rect::new(0, 0, columns, rows));
+            }
+
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(&mut screen, event)));
+            match result {
+                Ok(result) => result?,
+                Err(payload) => {
+                    // a writer may have been dropped mid-panic, the output is still usable to restore the terminal
+                    self.stdout.clear_poison();
+                    panic::resume_unwind(payload);
+                }
+            }
+            screen.flush()?;
+            if screen.quit {
+                return Ok(());
+            }
+
+            event = match events.next() {
+                Some(event) => event?,
+                None => return Ok(()),
+            };
+            if event == Event::Signal(signal::Signal::SIGTSTP) {
+                app_screen.leave()?;
+                raw.with_cooked(|| signal::raise(signal::Signal::SIGSTOP))?
+                    .context(ErrorKind::FailedToHandleSignals)?;
+                app_screen.enter()?;
+
+                let (columns, rows) = self.size()?;
+                event = Event::Resize(columns, rows);
+            }
+        }
+    }
+}
+
+impl<'a, I, O> Screen<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    /// The terminal being drawn to.
+    pub fn term(&self) -> &'a Term<I, O> {
+        self.term
+    }
+
+    /// Get a writer for the terminal, like `Term::writer`.
+    ///
+    /// Text and lines drawn with the writer are recorded as damage, see `Screen::flush`.
+    pub fn writer(&self) -> TermWriter<'_, O> {
+        let mut writer = self.term.writer();
+        writer.damage = Some(&self.damage);
+        writer
+    }
+
+    /// Get a writer that draws inside `rect`, see `TermWriter::region`.
+    pub fn region(&self, rect: Rect) -> TermWriter<'_, O> {
+        self.writer().region(rect)
+    }
+
+    /// Record `rect` as changed, so it's included in the damage returned by the next `Screen::flush`.
+    pub fn mark_dirty(&self, rect: Rect) {
+        self.damage.borrow_mut().push(rect);
+    }
+
+    /// The areas of the screen drawn on since the last flush.
+    ///
+    /// Areas are clipped to the screen, and overlapping or touching areas on the same rows are merged.
+    /// Text printed before the cursor is moved with `move_to` may have been drawn anywhere, so it damages the whole screen.
+    pub fn damage(&self) -> Vec<Rect> {
+        let screen = Rect::new(0, 0, self.size.0, self.size.1);
+        let mut rects: Vec<Rect> = self
+            .damage
+            .borrow()
+            .iter()
+            .map(|r| r.intersection(screen))
+            .filter(|r| r.width > 0 && r.height > 0)
+            .collect();
+        if rects.contains(&screen) {
+            return vec![screen];
+        }
+
+        rects.sort_by_key(|r| (r.y, r.height, r.x));
+        let mut merged: Vec<Rect> = Vec::with_capacity(rects.len());
+        for rect in rects {
+            match merged.last_mut() {
+                Some(last) if last.y == rect.y && last.height == rect.height && rect.x <= last.x + last.width => {
+                    let right = (last.x + last.width).max(rect.x + rect.width);
+                    last.width = right - last.x;
+                }
+                _ => merged.push(rect),
+            }
+        }
+        merged
+    }
+
+    /// Flush output to the terminal, and return the areas of the screen that changed since the last flush.
+    ///
+    /// This is done after each event, but it can be called to synchronize other output (e.g. images) with what was drawn.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::app::AppOptions;
+    /// use nixterm::term::{Rect, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.run_app(&AppOptions::new(), |screen, _| {
+    ///     screen.region(Rect::new(0, 0, 10, 1)).print("hello").done()?;
+    ///     for rect in screen.flush()? {
+    ///         // redraw anything overlapping rect
+    ///     }
+    ///     screen.quit();
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn flush(&self) -> Result<Vec<Rect>> {
+        let damage = self.damage();
+        self.damage.borrow_mut().clear();
+        self.term.flush();
+        self.term.err()?;
+        Ok(damage)
+    }
+
+    /// The size of the screen as `(columns, rows)`, from the last `Event::Resize`.
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    /// Clear the whole screen.
+    pub fn clear(&self) {
+        self.term.clear_screen();
+        self.mark_dirty(Rect::new(0, 0, self.size.0, self.size.1));
+    }
+
+    /// End the application after this event.
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+}
+
+impl<'a, I, O> AppScreen<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn enter(&self) -> Result<()> {
+        if self.options.alternate_screen {
+            self.term.enter_alternate_screen();
+        }
+        if self.options.hide_cursor {
+            self.term.hide_cursor();
+        }
+        if self.options.mouse {
+            self.term.set_mouse_capture(true)?;
+        }
+        if self.options.bracketed_paste {
+            self.term.set_bracketed_paste(true)?;
+        }
+        self.term.flush();
+        self.term.err()
+    }
+
+    fn leave(&self) -> Result<()> {
+        if self.options.bracketed_paste {
+            self.term.set_bracketed_paste(false)?;
+        }
+        if self.options.mouse {
+            self.term.set_mouse_capture(false)?;
+        }
+        if self.options.hide_cursor {
+            self.term.show_cursor();
+        }
+        if self.options.alternate_screen {
+            self.term.exit_alternate_screen();
+        }
+        self.term.flush();
+        self.term.err()
+    }
+}
+
+impl<'a, I, O> Drop for AppScreen<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn drop(&mut self) {
+        if !self.term.is_gone() {
+            let _ = self.leave();
+        }
+    }
+}
+
+impl<'a, I, O> Frame<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn new(term: &'a Term<I, O>) -> Frame<'a, I, O> {
+        Frame {
+            term,
+            events: Vec::new(),
+            resized: false,
+            resumed: false,
+            redraw: false,
+            quit: false,
+        }
+    }
+
+    /// Check if anything happened that needs to be handled by the next frame.
+    fn is_dirty(&self) -> bool {
+        self.redraw || self.resized || self.resumed || !self.events.is_empty()
+    }
+
+    /// The terminal being drawn to.
+    pub fn term(&self) -> &'a Term<I, O> {
+        self.term
+    }
+
+    /// Get a writer for the terminal, this is the same as calling `Term::writer`.
+    pub fn writer(&self) -> TermWriter<'a, O> {
+        self.term.writer()
+    }
+
+    /// Draw another frame, even if nothing happens in the meantime (e.g. to animate something).
+    pub fn redraw(&mut self) {
+        self.redraw = true;
+    }
+
+    /// End the loop after this frame.
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use app::*;
+    use std::cell::RefCell;
+    use std::panic;
+    use term::{Rect, Term};
+    use terminfo;
+
+    const TERMINFO: &'static [u8] = include_bytes!("../test-data/rxvt");
+
+    #[test]
+    fn run_app() {
+        use events::{Event, Key};
+        use nix::fcntl;
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let winsize = pty::Winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = pty::openpty(&winsize, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let settings = term.settings();
+
+        let mut events = Vec::new();
+        term.run_app(&AppOptions::new().mouse(false), |screen, event| {
+            match event {
+                Event::Resize(..) => master.write_all(b"xq").unwrap(),
+                Event::Key(Key::Char('q')) => screen.quit(),
+                _ => (),
+            }
+            events.push(event);
+            screen.writer().move_to(0, 0).print("hi").done()?;
+            Ok(())
+        }).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::Resize(80, 24),
+                Event::Key(Key::Char('x')),
+                Event::Key(Key::Char('q')),
+            ]
+        );
+        assert_eq!(
+            term.settings().termios.local_flags,
+            settings.termios.local_flags
+        );
+
+        // the terminal is restored even if the application panics while writing
+        let panicked = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            term.run_app(&AppOptions::new(), |screen, _| {
+                let _w = screen.writer().print("...");
+                panic!("oops");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(
+            term.settings().termios.local_flags,
+            settings.termios.local_flags
+        );
+        term.print("done").unwrap();
+
+        fcntl::fcntl(pty.master, fcntl::F_SETFL(fcntl::OFlag::O_NONBLOCK)).unwrap();
+        let mut out = Vec::new();
+        let _ = master.read_to_end(&mut out);
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.starts_with("\x1b7\x1b[?47h\x1b[?25l"));
+        assert!(out.ends_with("\x1b[?25h\x1b[2J\x1b[?47l\x1b8\x1b[0m\x0fdone"));
+        assert_eq!(out.matches("\x1b[?47l").count(), 2);
+        assert_eq!(out.matches("\x1b[?1000h").count(), 1);
+        assert_eq!(out.matches("\x1b[?1000l").count(), 1);
+    }
+
+    #[test]
+    fn screen_damage() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let winsize = pty::Winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = pty::openpty(&winsize, None).unwrap();
+        let _master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let screen = Screen {
+            term: &term,
+            size: (80, 24),
+            quit: false,
+            damage: RefCell::new(Vec::new()),
+        };
+
+        screen.writer().move_to(2, 1).print("ab").print("cd\nxyz").done().unwrap();
+        screen.region(Rect::new(70, 5, 5, 2)).print("clipped\nz").done().unwrap();
+        screen.mark_dirty(Rect::new(78, 23, 10, 10));
+        assert_eq!(
+            screen.flush().unwrap(),
+            vec![
+                Rect::new(2, 1, 4, 1),
+                Rect::new(0, 2, 3, 1),
+                Rect::new(70, 5, 5, 1),
+                Rect::new(70, 6, 1, 1),
+                Rect::new(78, 23, 2, 1),
+            ]
+        );
+        assert_eq!(screen.flush().unwrap(), vec![]);
+
+        // without moving the cursor first text could be anywhere
+        screen.writer().print("?").done().unwrap();
+        assert_eq!(screen.flush().unwrap(), vec![Rect::new(0, 0, 80, 24)]);
+    }
+
+    #[test]
+    fn run_loop() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        let mut frames = Vec::new();
+        term.run_loop(1000, |frame| {
+            if frames.is_empty() {
+                master.write_all(b"a\x1b[Aq").unwrap();
+            }
+            frames.push(frame.events.clone());
+            if frame.events.contains(&Event::Key(Key::Char('q'))) {
+                frame.quit();
+            }
+            Ok(())
+        }).unwrap();
+
+        let events: Vec<Event> = frames.into_iter().flat_map(|f| f).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Char('a')),
+                Event::Key(Key::Up),
+                Event::Key(Key::Char('q')),
+            ]
+        );
+
+        // input read ahead of the loop is still handled
+        master.write_all(b"xy").unwrap();
+        assert_eq!(term.read_keys().next().unwrap().unwrap(), Key::Char('x'));
+        term.run_loop(1000, |frame| {
+            if frame.events.contains(&Event::Key(Key::Char('y'))) {
+                frame.quit();
+            }
+            Ok(())
+        }).unwrap();
+    }
+}
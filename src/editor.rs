@@ -0,0 +1,1480 @@
+//! Line editing for `Term::readline`.
+//!
+//! The editor puts the terminal in raw mode and handles each key itself, redrawing the line with terminfo's cursor movement
+//! capabilities. Text that's wider than the terminal wraps onto the next row, and can have more than one line, see
+//! `Editor::multiline`.
+use ansi::Csi;
+use errors::*;
+use events::{Key, Keys, Modifiers};
+use failure::{Fail, ResultExt};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::str;
+use term::{Align, Term};
+use terminfo;
+use text;
+
+/// The line being edited, and the position of the cursor in it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    /// The index of the first character on the cursor's line.
+    fn line_start(&self) -> usize {
+        self.chars[..self.cursor]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |i| i + 1)
+    }
+
+    /// The index of the newline at the end of the cursor's line, or the length of the text if it's the last line.
+    fn line_end(&self) -> usize {
+        self.chars[self.cursor..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(self.chars.len(), |i| self.cursor + i)
+    }
+
+    /// Move to the same column on the line above, returns false if the cursor is on the first line.
+    fn up(&mut self) -> bool {
+        let start = self.line_start();
+        if start == 0 {
+            return false;
+        }
+        let column = self.cursor - start;
+        self.cursor = start - 1;
+        let above = self.line_start();
+        self.cursor = above + column.min(start - 1 - above);
+        true
+    }
+
+    /// Move to the same column on the line below, returns false if the cursor is on the last line.
+    fn down(&mut self) -> bool {
+        let end = self.line_end();
+        if end == self.chars.len() {
+            return false;
+        }
+        let column = self.cursor - self.line_start();
+        self.cursor = end + 1;
+        let below = self.line_end();
+        self.cursor = (end + 1 + column).min(below);
+        true
+    }
+
+    fn kill_to_start(&mut self) {
+        let start = self.line_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn kill_to_end(&mut self) {
+        let end = self.line_end();
+        self.chars.drain(self.cursor..end);
+    }
+
+    /// The index of the start of the word before the cursor, skipping any whitespace between it and the cursor.
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    /// The index just past the end of the word after the cursor, skipping any whitespace between it and the cursor.
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        while end < self.chars.len() && self.chars[end].is_whitespace() {
+            end += 1;
+        }
+        while end < self.chars.len() && !self.chars[end].is_whitespace() {
+            end += 1;
+        }
+        end
+    }
+
+    /// Delete the word before the cursor, and any whitespace between it and the cursor.
+    fn kill_word(&mut self) {
+        let start = self.word_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// Replace the line with `s`, moving the cursor to `cursor`.
+    fn set(&mut self, s: &str, cursor: usize) {
+        self.chars = s.chars().collect();
+        self.cursor = cursor.min(self.chars.len());
+    }
+}
+
+/// Lines that were entered into an `Editor`, oldest first.
+///
+/// Empty lines aren't kept, and entering a line that's already in the history moves it to the end.
+/// Once there are more than `max_len` entries the oldest are forgotten.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: VecDeque<String>,
+    max_len: usize,
+
+    /// The file the history is saved to, if it was loaded with `History::load`.
+    path: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(max_len: usize) -> History {
+        History {
+            entries: VecDeque::new(),
+            max_len,
+            path: None,
+        }
+    }
+
+    /// Load history from the file at `path`, which has one entry per line.
+    /// Newlines and backslashes in an entry are escaped as `\n` and `\\`.
+    ///
+    /// The file doesn't need to exist, it's created the first time the history is saved.
+    /// A history that's been loaded is saved whenever an entry is added.
+    pub fn load<P: AsRef<Path>>(path: P, max_len: usize) -> Result<History> {
+        let mut history = History::new(max_len);
+        match File::open(path.as_ref()) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    history.push(&unescape(&line.context(ErrorKind::FailedToReadHistory)?));
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.context(ErrorKind::FailedToReadHistory).into()),
+        }
+        history.path = Some(path.as_ref().to_owned());
+        Ok(history)
+    }
+
+    /// Write the history to the file it was loaded from, if there is one.
+    pub fn save(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        // write to a temporary file first, so the history isn't lost if writing fails part way through
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp).context(ErrorKind::FailedToWriteHistory)?;
+        for entry in &self.entries {
+            writeln!(file, "{}", escape(entry)).context(ErrorKind::FailedToWriteHistory)?;
+        }
+        fs::rename(&tmp, path).context(ErrorKind::FailedToWriteHistory)?;
+        Ok(())
+    }
+
+    /// Add a line to the end of the history, then save it if it was loaded from a file.
+    pub fn add(&mut self, line: &str) -> Result<()> {
+        self.push(line);
+        self.save()
+    }
+
+    fn push(&mut self, line: &str) {
+        let line = line.trim_end_matches(&['\n', '\r'][..]);
+        if line.is_empty() || self.max_len == 0 {
+            return;
+        }
+
+        self.entries.retain(|e| e != line);
+        self.entries.push_back(line.to_owned());
+        while self.entries.len() > self.max_len {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.entries.get(i).map(String::as_str)
+    }
+
+    /// Iterate over the entries, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// Find the newest entry before `before` that contains `query`, returning its index.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        (0..before.min(self.len()))
+            .rev()
+            .find(|&i| self.entries[i].contains(query))
+    }
+}
+
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some(&'n')) => '\n',
+            ('\\', Some(&'\\')) => '\\',
+            _ => {
+                entry.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        entry.push(escaped);
+    }
+    entry
+}
+
+/// A function that's given the line and the cursor's byte offset in it, and returns completions for the word before the cursor.
+type Completer = Box<dyn FnMut(&str, usize) -> Vec<String>>;
+
+/// The most rows of completions shown below the line.
+const MAX_MENU_ROWS: usize = 8;
+
+/// The state of a Ctrl-R search.
+#[derive(Debug, Clone, Default)]
+struct Search {
+    query: String,
+
+    /// The entry that matched the query.
+    found: Option<usize>,
+
+    /// The line from before the search started.
+    original: Line,
+}
+
+/// What to do after a key is handled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Action {
+    Continue,
+
+    /// The line is finished.
+    Accept,
+
+    /// The input ended before anything was typed.
+    Eof,
+    Cancel,
+}
+
+/// An editing command, what a key does in the line editor, whichever key it's bound to.
+///
+/// `Bindings` turns keys into these, so editors other than `Editor` can share its key bindings,
+/// see `Term::read_edit_events`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EditEvent {
+    InsertChar(char),
+    MoveLeft,
+    MoveRight,
+    MoveWordBack,
+    MoveWordForward,
+    MoveLineStart,
+    MoveLineEnd,
+
+    /// Delete the character before the cursor.
+    DeleteBack,
+
+    /// Delete the character under the cursor.
+    DeleteForward,
+    DeleteWordBack,
+    DeleteToLineStart,
+    DeleteToLineEnd,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    Complete,
+
+    /// Finish the line, in multi-line mode this starts a new line unless Escape was pressed first.
+    Submit,
+    Cancel,
+
+    /// End the input if the line is empty, otherwise delete the character under the cursor.
+    /// In multi-line mode this submits the text.
+    Eof,
+
+    /// Switch to another set of bindings, this is handled by `Bindings` and isn't passed on.
+    SetMode(EditMode),
+}
+
+/// Which of a `Bindings`' tables keys are looked up in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EditMode {
+    /// Characters that aren't bound are inserted.
+    Insert,
+
+    /// Vi's command mode, keys that aren't bound do nothing.
+    Command,
+}
+
+/// The keys bound to each `EditEvent`, in insert and command mode.
+///
+/// `Bindings::emacs` is the default for an `Editor`, `Bindings::vi` starts in insert mode and switches to command mode
+/// when Escape is pressed.
+///
+/// # Examples
+/// ```no_run
+/// use nixterm::editor::{Bindings, EditEvent, EditMode, Editor};
+/// use nixterm::events::Key;
+/// use nixterm::term::Term;
+///
+/// let bindings = Bindings::vi()
+///     .bind_in(EditMode::Command, Key::Char('q'), vec![EditEvent::Cancel])
+///     .bind(Key::Control('L'), EditEvent::DeleteToLineEnd);
+/// let mut editor = Editor::new().bindings(bindings);
+///
+/// let term = Term::new().unwrap();
+/// let line = editor.read_line(&term).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    insert: Vec<(Key, Vec<EditEvent>)>,
+    command: Vec<(Key, Vec<EditEvent>)>,
+    mode: EditMode,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings::emacs()
+    }
+}
+
+impl Bindings {
+    /// A table with nothing bound, only characters are inserted.
+    pub fn new() -> Bindings {
+        Bindings {
+            insert: Vec::new(),
+            command: Vec::new(),
+            mode: EditMode::Insert,
+        }
+    }
+
+    /// Emacs style bindings, like readline's defaults, see `Editor` for the list of keys.
+    pub fn emacs() -> Bindings {
+        let alt = |c| Key::Char(c).with_modifiers(Modifiers::ALT);
+        Bindings::new()
+            .bind_keys(
+                &[Key::Enter, Key::Control('J'), Key::Control('M')],
+                EditEvent::Submit,
+            )
+            .bind(Key::Control('C'), EditEvent::Cancel)
+            .bind(Key::Control('D'), EditEvent::Eof)
+            .bind_keys(
+                &[Key::Backspace, Key::Delete, Key::Control('H')],
+                EditEvent::DeleteBack,
+            )
+            .bind(Key::DeleteChar, EditEvent::DeleteForward)
+            .bind_keys(&[Key::Left, Key::Control('B')], EditEvent::MoveLeft)
+            .bind_keys(&[Key::Right, Key::Control('F')], EditEvent::MoveRight)
+            .bind(alt('b'), EditEvent::MoveWordBack)
+            .bind(alt('f'), EditEvent::MoveWordForward)
+            .bind_keys(
+                &[Key::Home, Key::Begin, Key::Control('A')],
+                EditEvent::MoveLineStart,
+            )
+            .bind_keys(&[Key::End, Key::Control('E')], EditEvent::MoveLineEnd)
+            .bind(Key::Control('U'), EditEvent::DeleteToLineStart)
+            .bind(Key::Control('K'), EditEvent::DeleteToLineEnd)
+            .bind(Key::Control('W'), EditEvent::DeleteWordBack)
+            .bind_keys(&[Key::Up, Key::Control('P')], EditEvent::HistoryPrev)
+            .bind_keys(&[Key::Down, Key::Control('N')], EditEvent::HistoryNext)
+            .bind(Key::Tab, EditEvent::Complete)
+            .bind(Key::Control('R'), EditEvent::HistorySearch)
+    }
+
+    /// Vi style bindings.
+    ///
+    /// Insert mode has the arrow keys, Backspace, Ctrl-W, Ctrl-U and Tab, Escape switches to command mode. Command mode has:
+    /// - h/l, b/w, 0/$: move left or right a character, a word, or to the start or end of the line
+    /// - x/X: delete the character under, or before the cursor
+    /// - D: delete to the end of the line
+    /// - k/j: go back, or forward through the history
+    /// - i/a, I/A: insert before or after the cursor, or at the start or end of the line
+    pub fn vi() -> Bindings {
+        use self::EditEvent::*;
+
+        let insert = EditMode::Insert;
+        let command = EditMode::Command;
+        let mut bindings = Bindings::new()
+            .bind_in(insert, Key::Escape, vec![SetMode(command), MoveLeft])
+            .bind_keys(&[Key::Backspace, Key::Delete, Key::Control('H')], DeleteBack)
+            .bind(Key::Control('W'), DeleteWordBack)
+            .bind(Key::Control('U'), DeleteToLineStart)
+            .bind(Key::Tab, Complete);
+
+        let both = vec![
+            (Key::Enter, Submit),
+            (Key::Control('J'), Submit),
+            (Key::Control('M'), Submit),
+            (Key::Control('C'), Cancel),
+            (Key::Control('D'), Eof),
+            (Key::DeleteChar, DeleteForward),
+            (Key::Left, MoveLeft),
+            (Key::Right, MoveRight),
+            (Key::Home, MoveLineStart),
+            (Key::End, MoveLineEnd),
+            (Key::Up, HistoryPrev),
+            (Key::Down, HistoryNext),
+        ];
+        for (key, event) in both {
+            bindings = bindings
+                .bind_in(insert, key.clone(), vec![event.clone()])
+                .bind_in(command, key, vec![event]);
+        }
+
+        let commands = vec![
+            ('h', vec![MoveLeft]),
+            ('l', vec![MoveRight]),
+            ('b', vec![MoveWordBack]),
+            ('w', vec![MoveWordForward]),
+            ('0', vec![MoveLineStart]),
+            ('$', vec![MoveLineEnd]),
+            ('x', vec![DeleteForward]),
+            ('X', vec![DeleteBack]),
+            ('D', vec![DeleteToLineEnd]),
+            ('k', vec![HistoryPrev]),
+            ('j', vec![HistoryNext]),
+            ('i', vec![SetMode(insert)]),
+            ('a', vec![MoveRight, SetMode(insert)]),
+            ('I', vec![MoveLineStart, SetMode(insert)]),
+            ('A', vec![MoveLineEnd, SetMode(insert)]),
+        ];
+        for (c, events) in commands {
+            bindings = bindings.bind_in(command, Key::Char(c), events);
+        }
+        bindings
+    }
+
+    /// Bind `key` to `event` in insert mode, replacing what it was bound to.
+    pub fn bind(self, key: Key, event: EditEvent) -> Self {
+        self.bind_in(EditMode::Insert, key, vec![event])
+    }
+
+    /// Bind `key` to `events` in `mode`, they're handled in order.
+    pub fn bind_in(mut self, mode: EditMode, key: Key, events: Vec<EditEvent>) -> Self {
+        let table = match mode {
+            EditMode::Insert => &mut self.insert,
+            EditMode::Command => &mut self.command,
+        };
+        table.retain(|(k, _)| *k != key);
+        table.push((key, events));
+        self
+    }
+
+    fn bind_keys(self, keys: &[Key], event: EditEvent) -> Self {
+        keys.iter()
+            .fold(self, |bindings, key| bindings.bind(key.clone(), event.clone()))
+    }
+
+    pub fn mode(&self) -> EditMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: EditMode) {
+        self.mode = mode;
+    }
+
+    /// Get the events `key` is bound to in the current mode, switching modes if it's bound to `EditEvent::SetMode`.
+    pub fn translate(&mut self, key: &Key) -> Vec<EditEvent> {
+        let table = match self.mode {
+            EditMode::Insert => &self.insert,
+            EditMode::Command => &self.command,
+        };
+        let events = match table.iter().find(|(k, _)| k == key) {
+            Some((_, events)) => events.clone(),
+            None => match *key {
+                Key::Char(c) if self.mode == EditMode::Insert => vec![EditEvent::InsertChar(c)],
+                _ => Vec::new(),
+            },
+        };
+
+        let mut translated = Vec::with_capacity(events.len());
+        for event in events {
+            match event {
+                EditEvent::SetMode(mode) => self.mode = mode,
+                event => translated.push(event),
+            }
+        }
+        translated
+    }
+}
+
+/// Keys read from a terminal as `EditEvent`s, see `Term::read_edit_events`.
+pub struct EditEvents<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    keys: Keys<'a, I, O>,
+    bindings: Bindings,
+
+    /// Events from a key that's bound to more than one.
+    pending: VecDeque<EditEvent>,
+}
+
+impl<'a, I, O> EditEvents<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    pub fn new(keys: Keys<'a, I, O>, bindings: Bindings) -> EditEvents<'a, I, O> {
+        EditEvents {
+            keys,
+            bindings,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The bindings keys are translated with, e.g. to show which mode they're in.
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+}
+
+impl<'a, I, O> Iterator for EditEvents<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    type Item = Result<EditEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            match self.keys.next()? {
+                Ok(key) => self.pending.extend(self.bindings.translate(&key)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// A line editor, see `Term::readline`.
+///
+/// Keys:
+/// - Left/Right, Ctrl-B/Ctrl-F: move the cursor
+/// - Alt-B/Alt-F: move back, or forward a word
+/// - Home/End, Ctrl-A/Ctrl-E: move to the start or end of the line
+/// - Backspace: delete the character before the cursor
+/// - Delete, Ctrl-D: delete the character under the cursor
+/// - Ctrl-U/Ctrl-K: delete everything before, or after the cursor
+/// - Ctrl-W: delete the word before the cursor
+/// - Up/Down, Ctrl-P/Ctrl-N: go back, or forward through the history
+/// - Ctrl-R: search the history, press it again to find an older match. Escape or Ctrl-G cancels the search
+/// - Tab: complete the word before the cursor, see `Editor::completer`
+/// - Ctrl-C: cancel, returning an `ErrorKind::Cancelled` error
+/// - Ctrl-D on an empty line: end the input
+///
+/// These are the default, Emacs style, `Bindings`, `Editor::bindings` can change them or switch to `Bindings::vi`.
+///
+/// In multi-line mode, see `Editor::multiline`, Enter starts a new line and Alt-Enter or Ctrl-D submits the text.
+/// Home/End and Ctrl-U/Ctrl-K work on the cursor's line, and Up/Down move between lines before going through the history.
+///
+/// # Examples
+/// ```no_run
+/// use nixterm::editor::{Editor, History};
+/// use nixterm::term::Term;
+///
+/// let term = Term::new().unwrap();
+/// let mut editor = Editor::new().history(History::load("/tmp/.example_history", 500).unwrap());
+/// loop {
+///     term.print("> ").unwrap();
+///     let line = editor.read_line(&term).unwrap();
+///     if line.is_empty() {
+///         break;
+///     }
+/// }
+/// ```
+pub struct Editor {
+    /// The bytes of a multi-byte character that's only been partly read.
+    partial: Vec<u8>,
+
+    history: History,
+
+    /// The history entry being shown, and the line that was being edited before going back through the history.
+    browsing: Option<(usize, Line)>,
+    search: Option<Search>,
+
+    completer: Option<Completer>,
+
+    /// Completions to show below the line, after the current key is handled.
+    menu: Option<Vec<String>>,
+
+    multiline: bool,
+
+    /// Whether the last key was Escape, which is how Alt-Enter is read.
+    escape: bool,
+
+    bindings: Bindings,
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor {
+            partial: Vec::new(),
+            history: History::new(DEFAULT_HISTORY_LEN),
+            browsing: None,
+            search: None,
+            completer: None,
+            menu: None,
+            multiline: false,
+            escape: false,
+            bindings: Bindings::emacs(),
+        }
+    }
+}
+
+/// The number of entries an editor's history holds, unless it's given a `History`.
+const DEFAULT_HISTORY_LEN: usize = 1000;
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor::default()
+    }
+
+    /// Use `history` for Up/Down and Ctrl-R, lines are added to it as they're entered.
+    pub fn history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn get_history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn get_history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Complete the word before the cursor when Tab is pressed.
+    ///
+    /// `f` is given the line and the cursor's byte offset in it, and returns replacements for the word before the cursor.
+    /// If there's one replacement it's inserted followed by a space, otherwise their longest common prefix is inserted.
+    /// If that doesn't add anything, every replacement is listed below the line until the next key is pressed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::editor::Editor;
+    /// use nixterm::term::Term;
+    ///
+    /// let commands = ["help", "history", "quit"];
+    /// let mut editor = Editor::new().completer(move |line, cursor| {
+    ///     let word = line[..cursor].rsplit(' ').next().unwrap();
+    ///     commands.iter().filter(|c| c.starts_with(word)).map(|c| c.to_string()).collect()
+    /// });
+    ///
+    /// let term = Term::new().unwrap();
+    /// let line = editor.read_line(&term).unwrap();
+    /// ```
+    pub fn completer<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&str, usize) -> Vec<String> + 'static,
+    {
+        self.completer = Some(Box::new(f));
+        self
+    }
+
+    pub fn set_completer<F>(&mut self, f: F)
+    where
+        F: FnMut(&str, usize) -> Vec<String> + 'static,
+    {
+        self.completer = Some(Box::new(f));
+    }
+
+    /// Accept text with more than one line, e.g. for a commit message.
+    ///
+    /// Enter starts a new line, and Alt-Enter or Ctrl-D submits the text.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::editor::Editor;
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let mut editor = Editor::new().multiline(true);
+    /// let message = editor.prompt(&term, "message: ").unwrap();
+    /// ```
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+
+    pub fn set_multiline(&mut self, multiline: bool) {
+        self.multiline = multiline;
+    }
+
+    /// Use `bindings` to decide what each key does, instead of the default Emacs style ones.
+    pub fn bindings(mut self, bindings: Bindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    /// Read a line from `term`, the terminal's settings are restored before returning.
+    ///
+    /// Like `BufRead::read_line` the line ends with a newline, unless the input ended, in which case it's empty.
+    pub fn read_line<I, O>(&mut self, term: &Term<I, O>) -> Result<String>
+    where
+        I: io::Read + AsRawFd,
+        O: io::Write + AsRawFd,
+    {
+        self.read_line_from(term, 0)
+    }
+
+    /// Print `prompt`, then read a line after it.
+    ///
+    /// Unlike printing the prompt before calling `read_line`, the editor knows where the prompt ends,
+    /// so text that wraps onto the next row is redrawn correctly.
+    pub fn prompt<I, O, T>(&mut self, term: &Term<I, O>, prompt: T) -> Result<String>
+    where
+        I: io::Read + AsRawFd,
+        O: io::Write + AsRawFd,
+        T: AsRef<str>,
+    {
+        let prompt = prompt.as_ref();
+        term.writer().print(prompt).done()?;
+        let column = prompt.rsplit('\n').next().map_or(0, text::width);
+        self.read_line_from(term, column)
+    }
+
+    /// Read a line, the cursor starts `column` columns from the left of the terminal.
+    fn read_line_from<I, O>(&mut self, term: &Term<I, O>, column: usize) -> Result<String>
+    where
+        I: io::Read + AsRawFd,
+        O: io::Write + AsRawFd,
+    {
+        let raw = term.raw_mode()?;
+        let line = self.edit(term, column);
+        let restored = raw.restore();
+
+        term.println("")?;
+        let line = line?;
+        restored?;
+        self.history.add(&line)?;
+        Ok(line)
+    }
+
+    fn edit<I, O>(&mut self, term: &Term<I, O>, column: usize) -> Result<String>
+    where
+        I: io::Read + AsRawFd,
+        O: io::Write + AsRawFd,
+    {
+        let mut line = Line::default();
+        self.partial.clear();
+        self.browsing = None;
+        self.search = None;
+        self.escape = false;
+        self.bindings.set_mode(EditMode::Insert);
+
+        let columns = (term.columns() as usize).max(1);
+        let start = column % columns;
+
+        // the row and column of the cursor, relative to the row the text starts on
+        let mut cursor = (0, start);
+        let mut shown = Some((String::new(), 0));
+        let mut menu_shown = false;
+        for key in term.read_keys() {
+            let key = key?;
+            if menu_shown {
+                clear_menu(term)?;
+                menu_shown = false;
+            }
+
+            match self.handle(&mut line, key) {
+                Action::Continue => (),
+                Action::Accept => return Ok(line.text() + "\n"),
+                Action::Eof => return Ok(String::new()),
+                Action::Cancel => return Err(ErrorKind::Cancelled.into()),
+            }
+
+            let display = self.display(&line);
+            if shown.as_ref() != Some(&display) {
+                cursor = redraw(term, cursor, start, &display.0, display.1)?;
+                shown = Some(display);
+            }
+            if let Some(candidates) = self.menu.take() {
+                let columns = term.columns() as usize;
+                show_menu(term, &menu_lines(&candidates, columns))?;
+                menu_shown = true;
+
+                // the menu is drawn over anything below the cursor's row
+                shown = None;
+            }
+        }
+        Ok(line.text() + "\n")
+    }
+
+    /// Get the text to show for `line`, and the number of characters in it before the cursor.
+    fn display(&self, line: &Line) -> (String, usize) {
+        match self.search {
+            Some(ref search) => {
+                let prefix = format!("(reverse-i-search)`{}': ", search.query);
+                let cursor = prefix.chars().count() + line.cursor;
+                (prefix + &line.text(), cursor)
+            }
+            None => (line.text(), line.cursor),
+        }
+    }
+
+    /// Show the history entry `i`, or the line that was being edited if `i` is past the end of the history.
+    fn browse(&mut self, line: &mut Line, i: usize) {
+        let (_, draft) = self.browsing.take().unwrap_or_else(|| (0, line.clone()));
+        match self.history.get(i) {
+            Some(entry) => {
+                line.set(entry, usize::MAX);
+                self.browsing = Some((i, draft));
+            }
+            None => *line = draft,
+        }
+    }
+
+    /// Complete the word before the cursor.
+    fn complete(&mut self, line: &mut Line) {
+        let completer = match self.completer {
+            Some(ref mut f) => f,
+            None => return,
+        };
+
+        let text = line.text();
+        let offset = line.chars[..line.cursor].iter().map(|c| c.len_utf8()).sum();
+        let candidates = completer(&text, offset);
+
+        let mut start = line.cursor;
+        while start > 0 && !line.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        let replace = |line: &mut Line, s: &str| {
+            let len = s.chars().count();
+            line.chars.splice(start..line.cursor, s.chars());
+            line.cursor = start + len;
+        };
+
+        match candidates.len() {
+            0 => (),
+            1 => replace(line, &(candidates[0].clone() + " ")),
+            _ => {
+                let prefix = common_prefix(&candidates);
+                if prefix.chars().count() > line.cursor - start {
+                    replace(line, prefix);
+                } else {
+                    self.menu = Some(candidates);
+                }
+            }
+        }
+    }
+
+    /// Handle a key while searching, returns `None` if the search ended and the key should be handled normally.
+    fn handle_search(&mut self, line: &mut Line, key: &Key) -> Option<Action> {
+        let mut search = self.search.take()?;
+        let before = match *key {
+            Key::Control('R') => search.found.unwrap_or(self.history.len()),
+            Key::Char(c) => {
+                search.query.push(c);
+                search.found.map(|i| i + 1).unwrap_or(self.history.len())
+            }
+            Key::Backspace | Key::Delete | Key::Control('H') => {
+                search.query.pop();
+                self.history.len()
+            }
+            Key::Escape | Key::Control('G') => {
+                *line = search.original;
+                return Some(Action::Continue);
+            }
+            _ => return None,
+        };
+
+        if let Some(i) = self.history.search(&search.query, before) {
+            let entry = self.history.get(i).unwrap();
+            let at = entry.find(search.query.as_str()).unwrap_or(0);
+            line.set(entry, entry[..at].chars().count());
+            search.found = Some(i);
+        }
+        self.search = Some(search);
+        Some(Action::Continue)
+    }
+
+    fn handle(&mut self, line: &mut Line, key: Key) -> Action {
+        let escaped = mem::replace(&mut self.escape, key == Key::Escape && self.search.is_none());
+        if let Some(action) = self.handle_search(line, &key) {
+            return action;
+        }
+
+        if let Key::Invalid(b) = key {
+            self.partial.push(b);
+            match str::from_utf8(&self.partial) {
+                Ok(s) => {
+                    s.chars().for_each(|c| line.insert(c));
+                    self.partial.clear();
+                }
+                Err(ref e) if e.error_len().is_some() => self.partial.clear(),
+                Err(_) => (),
+            }
+            return Action::Continue;
+        }
+
+        for event in self.bindings.translate(&key) {
+            let action = self.apply(line, event, escaped);
+            if action != Action::Continue {
+                return action;
+            }
+        }
+        Action::Continue
+    }
+
+    /// Do what `event` says to `line`, `escaped` is true if the key before it was Escape.
+    fn apply(&mut self, line: &mut Line, event: EditEvent, escaped: bool) -> Action {
+        match event {
+            EditEvent::Submit if self.multiline && !escaped => line.insert('\n'),
+            EditEvent::Submit => return Action::Accept,
+            EditEvent::Cancel => return Action::Cancel,
+            EditEvent::Eof if line.chars.is_empty() => return Action::Eof,
+            EditEvent::Eof if self.multiline => return Action::Accept,
+            EditEvent::InsertChar(c) => line.insert(c),
+            EditEvent::DeleteBack => line.backspace(),
+            EditEvent::DeleteForward | EditEvent::Eof => line.delete(),
+            EditEvent::MoveLeft => line.left(),
+            EditEvent::MoveRight => line.right(),
+            EditEvent::MoveWordBack => line.cursor = line.word_start(),
+            EditEvent::MoveWordForward => line.cursor = line.word_end(),
+            EditEvent::MoveLineStart => line.cursor = line.line_start(),
+            EditEvent::MoveLineEnd => line.cursor = line.line_end(),
+            EditEvent::DeleteToLineStart => line.kill_to_start(),
+            EditEvent::DeleteToLineEnd => line.kill_to_end(),
+            EditEvent::DeleteWordBack => line.kill_word(),
+            EditEvent::HistoryPrev if self.multiline && line.up() => (),
+            EditEvent::HistoryNext if self.multiline && line.down() => (),
+            EditEvent::HistoryPrev => {
+                let i = match self.browsing {
+                    Some((i, _)) => i,
+                    None => self.history.len(),
+                };
+                if i > 0 {
+                    self.browse(line, i - 1);
+                }
+            }
+            EditEvent::HistoryNext => {
+                if let Some((i, _)) = self.browsing {
+                    self.browse(line, i + 1);
+                }
+            }
+            EditEvent::Complete => self.complete(line),
+            EditEvent::HistorySearch => {
+                self.search = Some(Search {
+                    original: line.clone(),
+                    ..Search::default()
+                });
+            }
+            EditEvent::SetMode(mode) => self.bindings.set_mode(mode),
+        }
+        Action::Continue
+    }
+}
+
+/// The longest prefix shared by every string in `strings`.
+fn common_prefix(strings: &[String]) -> &str {
+    let first = match strings.first() {
+        Some(s) => s.as_str(),
+        None => return "",
+    };
+    let mut len = first.len();
+    for s in &strings[1..] {
+        len = first
+            .char_indices()
+            .zip(s.chars())
+            .take_while(|&((_, a), b)| a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Lay out completions in columns, filling each row left to right, in at most `MAX_MENU_ROWS` rows.
+fn menu_lines(candidates: &[String], columns: usize) -> Vec<String> {
+    let width = candidates.iter().map(|c| text::width(c)).max().unwrap_or(0) + 2;
+    let per_row = (columns / width).max(1);
+    let mut lines = candidates
+        .chunks(per_row)
+        .map(|row| {
+            let row = row
+                .iter()
+                .map(|c| text::fit(c, width, Align::Left))
+                .collect::<String>();
+            text::truncate(row.trim_end(), columns).to_owned()
+        })
+        .collect::<Vec<_>>();
+
+    if lines.len() > MAX_MENU_ROWS {
+        let hidden = candidates.len() - (MAX_MENU_ROWS - 1) * per_row;
+        lines.truncate(MAX_MENU_ROWS - 1);
+        lines.push(format!("({} more)", hidden));
+    }
+    lines
+}
+
+/// Get a capability with no parameters, or `fallback` if the terminal doesn't have it.
+fn capability<'a, I, O>(term: &'a Term<I, O>, field: terminfo::StringField, fallback: &'a str) -> &'a [u8]
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    term.info.string(field).unwrap_or(fallback).as_bytes()
+}
+
+/// Show `lines` below the line being edited, leaving the cursor where it is.
+fn show_menu<I, O>(term: &Term<I, O>, lines: &[String]) -> Result<usize>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    // make room first, the screen scrolls if the line is near the bottom, which would move a saved cursor position
+    let mut buf = vec![b'\n'; lines.len()];
+    match term.info.format(terminfo::ParmUpCursor, &[(lines.len() as u64).into()]) {
+        Ok(s) => buf.extend_from_slice(&s),
+        Err(_) => buf.extend_from_slice(&Csi::cursor_up(lines.len() as u32).to_bytes()),
+    }
+
+    buf.extend_from_slice(capability(term, terminfo::SaveCursor, "\x1b7"));
+    for line in lines {
+        buf.extend_from_slice(b"\r\n");
+        buf.extend_from_slice(line.as_bytes());
+    }
+    buf.extend_from_slice(capability(term, terminfo::RestoreCursor, "\x1b8"));
+    term.writer().write_bytes(&buf).done()
+}
+
+/// Clear everything below the line being edited.
+fn clear_menu<I, O>(term: &Term<I, O>) -> Result<usize>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    let mut buf = capability(term, terminfo::SaveCursor, "\x1b7").to_vec();
+    buf.extend_from_slice(b"\r\n");
+    clear_to_end(term, &mut buf);
+    buf.extend_from_slice(capability(term, terminfo::RestoreCursor, "\x1b8"));
+    term.writer().write_bytes(&buf).done()
+}
+
+/// Clear from the cursor to the end of the screen.
+fn clear_to_end<I, O>(term: &Term<I, O>, buf: &mut Vec<u8>)
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    match term.info.string(terminfo::ClrEos) {
+        Some(s) => buf.extend_from_slice(s.as_bytes()),
+        None => buf.extend_from_slice(&Csi::erase_display(0).to_bytes()),
+    }
+}
+
+/// Get a capability that takes a count, e.g. how far to move the cursor, or `fallback` with the count filled in.
+fn parameterized<I, O>(
+    term: &Term<I, O>,
+    buf: &mut Vec<u8>,
+    field: terminfo::StringField,
+    fallback: fn(u32) -> Csi,
+    n: usize,
+) where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    if n == 0 {
+        return;
+    }
+    match term.info.format(field, &[(n as u64).into()]) {
+        Ok(s) => buf.extend_from_slice(&s),
+        Err(_) => buf.extend_from_slice(&fallback(n as u32).to_bytes()),
+    }
+}
+
+/// Move the cursor from one `(row, column)` to another, `to` can't be below `from`.
+fn move_cursor<I, O>(
+    term: &Term<I, O>,
+    buf: &mut Vec<u8>,
+    from: (usize, usize),
+    to: (usize, usize),
+) where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    parameterized(term, buf, terminfo::ParmUpCursor, Csi::cursor_up, from.0.saturating_sub(to.0));
+    if to.1 < from.1 {
+        parameterized(term, buf, terminfo::ParmLeftCursor, Csi::cursor_back, from.1 - to.1);
+    } else {
+        parameterized(term, buf, terminfo::ParmRightCursor, Csi::cursor_forward, to.1 - from.1);
+    }
+}
+
+/// Find where text ends up on a terminal `columns` wide, if it starts at column `start`.
+///
+/// A full row leaves the cursor past the last column until something else is written, so the column can be `columns`.
+fn position(chars: &[char], start: usize, columns: usize) -> (usize, usize) {
+    chars.iter().fold((0, start), |(row, column), &c| {
+        let width = text::char_width(c);
+        if c == '\n' {
+            (row + 1, 0)
+        } else if column + width > columns {
+            (row + 1, width)
+        } else {
+            (row, column + width)
+        }
+    })
+}
+
+/// Replace the text with `display`, which starts `start` columns from the left and is wrapped at the edge of the terminal.
+///
+/// The cursor was at `from`, and is moved to before the `cursor`th character. Returns the cursor's new position.
+fn redraw<I, O>(
+    term: &Term<I, O>,
+    from: (usize, usize),
+    start: usize,
+    display: &str,
+    cursor: usize,
+) -> Result<(usize, usize)>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    let columns = (term.columns() as usize).max(1);
+    let chars = display.chars().collect::<Vec<_>>();
+
+    let mut buf = Vec::new();
+    move_cursor(term, &mut buf, from, (0, start));
+    clear_to_end(term, &mut buf);
+    // the terminal's in raw mode, so a newline doesn't go back to the first column
+    buf.extend_from_slice(display.replace('\n', "\r\n").as_bytes());
+
+    let mut end = position(&chars, start, columns);
+    if end.1 >= columns {
+        buf.extend_from_slice(b"\r\n");
+        end = (end.0 + 1, 0);
+    }
+    let mut to = position(&chars[..cursor], start, columns);
+    if to.1 >= columns {
+        to = (to.0 + 1, 0);
+    }
+    move_cursor(term, &mut buf, end, to);
+    term.writer().write_bytes(&buf).done()?;
+    Ok(to)
+}
+
+#[cfg(test)]
+mod test {
+    use editor::*;
+    use std::{env, process};
+
+    fn type_keys(keys: Vec<Key>) -> (Line, Action) {
+        type_keys_with(Editor::new(), keys)
+    }
+
+    fn type_keys_with(mut editor: Editor, keys: Vec<Key>) -> (Line, Action) {
+        let mut line = Line::default();
+        let mut action = Action::Continue;
+        for key in keys {
+            action = editor.handle(&mut line, key);
+        }
+        (line, action)
+    }
+
+    fn chars(s: &str) -> Vec<Key> {
+        s.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn editing() {
+        let mut keys = chars("hllo");
+        keys.extend(vec![Key::Home, Key::Right]);
+        keys.extend(chars("e"));
+        keys.extend(vec![Key::End, Key::Delete, Key::Left, Key::DeleteChar]);
+        let (line, _) = type_keys(keys);
+        assert_eq!(line.text(), "hel");
+        assert_eq!(line.cursor, 3);
+
+        let mut keys = chars("one two  ");
+        keys.push(Key::Control('W'));
+        assert_eq!(type_keys(keys).0.text(), "one ");
+
+        let alt = |c| Key::Char(c).with_modifiers(Modifiers::ALT);
+        let mut keys = chars("one two  three");
+        keys.extend(vec![alt('b'), alt('b'), Key::Char('_'), alt('f'), alt('f'), Key::Char('!')]);
+        let (line, _) = type_keys(keys);
+        assert_eq!(line.text(), "one _two  three!");
+        assert_eq!(line.cursor, 16);
+
+        let mut keys = chars("abcd");
+        keys.extend(vec![Key::Left, Key::Left, Key::Control('K')]);
+        assert_eq!(type_keys(keys).0.text(), "ab");
+
+        let mut keys = chars("abcd");
+        keys.extend(vec![Key::Left, Key::Control('U')]);
+        let (line, _) = type_keys(keys);
+        assert_eq!(line.text(), "d");
+        assert_eq!(line.cursor, 0);
+
+        // a multi-byte character comes in as separate bytes
+        let (line, _) = type_keys(vec![Key::Invalid(0xc3), Key::Invalid(0xa9), Key::Char('!')]);
+        assert_eq!(line.text(), "é!");
+        assert_eq!(line.cursor, 2);
+    }
+
+    #[test]
+    fn vi_bindings() {
+        let vi = || Editor::new().bindings(Bindings::vi());
+        let mut keys = chars("one two");
+        keys.extend(vec![Key::Escape, Key::Char('b'), Key::Char('x'), Key::Char('i')]);
+        keys.extend(chars("T"));
+        keys.extend(vec![Key::Escape, Key::Char('0'), Key::Char('a')]);
+        keys.extend(chars("N"));
+        keys.extend(vec![Key::Escape, Key::Char('A'), Key::Char('!')]);
+        let (line, action) = type_keys_with(vi(), keys);
+        assert_eq!((line.text().as_str(), action), ("oNne Two!", Action::Continue));
+
+        let mut keys = chars("abc");
+        keys.extend(vec![Key::Escape, Key::Char('h'), Key::Char('D'), Key::Char('q'), Key::Enter]);
+        let (line, action) = type_keys_with(vi(), keys);
+        assert_eq!((line.text().as_str(), action), ("a", Action::Accept));
+    }
+
+    #[test]
+    fn bindings() {
+        let mut bindings = Bindings::emacs()
+            .bind(Key::Control('T'), EditEvent::Cancel)
+            .bind(Key::Control('W'), EditEvent::MoveWordBack);
+        assert_eq!(bindings.translate(&Key::Char('a')), vec![EditEvent::InsertChar('a')]);
+        assert_eq!(bindings.translate(&Key::Control('T')), vec![EditEvent::Cancel]);
+        assert_eq!(bindings.translate(&Key::Control('W')), vec![EditEvent::MoveWordBack]);
+        assert_eq!(bindings.translate(&Key::Fn(1)), vec![]);
+
+        let mut vi = Bindings::vi();
+        assert_eq!(vi.translate(&Key::Escape), vec![EditEvent::MoveLeft]);
+        assert_eq!(vi.mode(), EditMode::Command);
+        assert_eq!(vi.translate(&Key::Char('q')), vec![]);
+        assert_eq!(vi.translate(&Key::Char('A')), vec![EditEvent::MoveLineEnd]);
+        assert_eq!(vi.mode(), EditMode::Insert);
+        assert_eq!(vi.translate(&Key::Enter), vec![EditEvent::Submit]);
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(type_keys(vec![Key::Control('D')]).1, Action::Eof);
+        assert_eq!(type_keys(chars("a")).1, Action::Continue);
+
+        let mut keys = chars("ab");
+        keys.extend(vec![Key::Left, Key::Control('D')]);
+        let (line, action) = type_keys(keys);
+        assert_eq!((line.text().as_str(), action), ("a", Action::Continue));
+
+        keys = chars("a");
+        keys.push(Key::Control('C'));
+        assert_eq!(type_keys(keys).1, Action::Cancel);
+        assert_eq!(type_keys(vec![Key::Enter]).1, Action::Accept);
+    }
+
+    #[test]
+    fn multiline() {
+        let editor = || Editor::new().multiline(true);
+        let mut keys = chars("ab");
+        keys.extend(vec![Key::Enter, Key::Char('c'), Key::Control('M')]);
+        keys.extend(chars("def"));
+        let (line, action) = type_keys_with(editor(), keys.clone());
+        assert_eq!((line.text().as_str(), action), ("ab\nc\ndef", Action::Continue));
+
+        // Home, End and the kill keys stay on the cursor's line
+        let mut more = keys.clone();
+        more.extend(vec![Key::Up, Key::Home, Key::Control('K'), Key::Down, Key::Char('>')]);
+        let (line, _) = type_keys_with(editor(), more);
+        assert_eq!((line.text().as_str(), line.cursor), ("ab\n\n>def", 5));
+
+        let mut more = keys.clone();
+        more.extend(vec![Key::Up, Key::Up, Key::Control('U'), Key::End, Key::Char('!')]);
+        let (line, _) = type_keys_with(editor(), more);
+        assert_eq!(line.text(), "b!\nc\ndef");
+
+        let mut more = keys.clone();
+        more.extend(vec![Key::Escape, Key::Enter]);
+        assert_eq!(type_keys_with(editor(), more).1, Action::Accept);
+        keys.push(Key::Control('D'));
+        assert_eq!(type_keys_with(editor(), keys).1, Action::Accept);
+        assert_eq!(type_keys_with(editor(), vec![Key::Control('D')]).1, Action::Eof);
+    }
+
+    #[test]
+    fn wrapping() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(position(&chars("abc"), 2, 10), (0, 5));
+        assert_eq!(position(&chars("abcd"), 6, 10), (0, 10));
+        assert_eq!(position(&chars("abcde"), 6, 10), (1, 1));
+        assert_eq!(position(&chars("ab\ncd"), 6, 10), (1, 2));
+        assert_eq!(position(&chars("abcd\n"), 6, 10), (1, 0));
+        assert_eq!(position(&chars("abc日"), 6, 10), (1, 2));
+        assert_eq!(position(&chars("abcdefghijklmnopqrstuvw"), 0, 10), (2, 3));
+    }
+
+    #[test]
+    fn history() {
+        let mut history = History::new(3);
+        for line in ["a\n", "", "b", "a", "c\r\n", "d"].iter() {
+            history.add(line).unwrap();
+        }
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec!["a", "c", "d"]);
+        assert_eq!(history.search("a", 3), Some(0));
+        assert_eq!(history.search("a", 0), None);
+
+        let path = env::temp_dir().join(format!("nixterm-history-{}", process::id()));
+        let mut saved = History::load(&path, 10).unwrap();
+        assert!(saved.is_empty());
+        saved.add("one").unwrap();
+        saved.add("two\nlines \\n").unwrap();
+        let loaded = History::load(&path, 10).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), vec!["one", "two\nlines \\n"]);
+    }
+
+    #[test]
+    fn history_keys() {
+        let mut history = History::new(10);
+        for line in ["make", "cargo build", "cargo test"].iter() {
+            history.add(line).unwrap();
+        }
+        let mut editor = Editor::new().history(history);
+        let mut line = Line::default();
+        let mut press = |keys: Vec<Key>, line: &mut Line| {
+            for key in keys {
+                editor.handle(line, key);
+            }
+            (line.text(), editor.display(line))
+        };
+
+        press(chars("ls"), &mut line);
+        assert_eq!(press(vec![Key::Up, Key::Up], &mut line).0, "cargo build");
+        assert_eq!(press(vec![Key::Down], &mut line).0, "cargo test");
+        assert_eq!(press(vec![Key::Down], &mut line).0, "ls");
+
+        let (text, display) = press(vec![Key::Control('R'), Key::Char('b')], &mut line);
+        assert_eq!(text, "cargo build");
+        assert_eq!(
+            display,
+            ("(reverse-i-search)`b': cargo build".to_owned(), 29)
+        );
+
+        let mut keys = vec![Key::Backspace];
+        keys.extend(chars("a"));
+        assert_eq!(press(keys, &mut line).0, "cargo test");
+        assert_eq!(press(vec![Key::Control('R')], &mut line).0, "cargo build");
+        assert_eq!(press(vec![Key::Control('R')], &mut line).0, "make");
+
+        // the search ends on any other key, which is then handled
+        let (text, display) = press(vec![Key::End, Key::Char('!')], &mut line);
+        assert_eq!(text, "make!");
+        assert_eq!(display, ("make!".to_owned(), 5));
+
+        let (text, _) = press(vec![Key::Control('R'), Key::Char('t'), Key::Escape], &mut line);
+        assert_eq!(text, "make!");
+    }
+
+    #[test]
+    fn completion() {
+        let words = ["cargo", "carrot", "cart", "make"];
+        let mut editor = Editor::new().completer(move |line, cursor| {
+            let word = line[..cursor].rsplit(' ').next().unwrap();
+            words
+                .iter()
+                .filter(|w| w.starts_with(word))
+                .map(|w| w.to_string())
+                .collect()
+        });
+        let mut line = Line::default();
+        let mut press = |keys: Vec<Key>, line: &mut Line| {
+            for key in keys {
+                editor.handle(line, key);
+            }
+            (line.text(), line.cursor, editor.menu.take())
+        };
+
+        // the common prefix is inserted first, then the candidates are listed
+        assert_eq!(press(chars("ca"), &mut line).2, None);
+        assert_eq!(press(vec![Key::Tab], &mut line), ("car".to_owned(), 3, None));
+        let (text, _, menu) = press(vec![Key::Tab], &mut line);
+        assert_eq!(text, "car");
+        assert_eq!(menu.unwrap(), vec!["cargo", "carrot", "cart"]);
+
+        let mut keys = chars("g");
+        keys.push(Key::Tab);
+        assert_eq!(press(keys, &mut line), ("cargo ".to_owned(), 6, None));
+
+        // the word before the cursor is completed, not the whole line
+        let mut keys = chars("mk");
+        keys.extend(vec![Key::Left, Key::Tab]);
+        assert_eq!(press(keys, &mut line), ("cargo make k".to_owned(), 11, None));
+        assert_eq!(press(chars(" x\t"), &mut line).2, None);
+    }
+
+    #[test]
+    fn menu_layout() {
+        let candidates = (0..30).map(|i| format!("item{}", i)).collect::<Vec<_>>();
+        assert_eq!(
+            menu_lines(&candidates[..5], 20),
+            vec!["item0  item1", "item2  item3", "item4"]
+        );
+
+        let lines = menu_lines(&candidates, 20);
+        assert_eq!(lines.len(), MAX_MENU_ROWS);
+        assert_eq!(lines[MAX_MENU_ROWS - 1], "(16 more)");
+
+        assert_eq!(common_prefix(&["日本".to_owned(), "日曜".to_owned()]), "日");
+        assert_eq!(common_prefix(&["a".to_owned(), "b".to_owned()]), "");
+    }
+}
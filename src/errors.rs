@@ -78,6 +78,9 @@ pub enum ErrorKind {
     #[fail(display = "Terminal failed to read from the input stream")]
     ReadFailed,
 
+    #[fail(display = "The terminal's input was closed")]
+    EndOfInput,
+
     #[fail(display = "Terminal failed to write to the output stream")]
     WriteFailed,
 
@@ -86,6 +89,66 @@ pub enum ErrorKind {
 
     #[fail(display = "Failed to get termios")]
     FailedToGetTermios,
+
+    #[fail(display = "The terminal is no longer available")]
+    TerminalGone,
+
+    #[fail(display = "Failed to install a signal handler")]
+    FailedToHandleSignals,
+
+    #[fail(display = "Failed to get the size of the terminal")]
+    FailedToGetSize,
+
+    #[fail(display = "Failed to change the cursor style")]
+    FailedToSetCursorStyle,
+
+    #[fail(display = "Failed to turn bracketed paste on or off")]
+    FailedToSetBracketedPaste,
+
+    #[fail(display = "Failed to turn mouse reporting on or off")]
+    FailedToSetMouseCapture,
+
+    #[fail(display = "Failed to turn focus reporting on or off")]
+    FailedToSetFocusReporting,
+
+    #[fail(display = "Failed to change the keypad's transmit mode")]
+    FailedToSetKeypad,
+
+    #[fail(display = "Failed to turn meta mode on or off")]
+    FailedToSetMetaMode,
+
+    #[fail(display = "Failed to start or end a synchronized update")]
+    FailedToSynchronizeOutput,
+
+    #[fail(display = "The terminal can't run full-screen applications")]
+    UnsupportedTerminal,
+
+    #[fail(display = "Failed to start the output thread")]
+    FailedToStartOutputThread,
+
+    #[fail(display = "The output thread stopped")]
+    OutputThreadStopped,
+
+    #[fail(display = "Failed to wake the thread reading events")]
+    FailedToWake,
+
+    #[fail(display = "Invalid directive \"{}\" in a styled format string", _0)]
+    InvalidFormatDirective(String),
+
+    #[fail(display = "Invalid style \"{}\"", _0)]
+    InvalidStyle(String),
+
+    #[fail(display = "There's no style named \"{}\"", _0)]
+    UnknownStyleName(String),
+
+    #[fail(display = "The prompt was cancelled")]
+    Cancelled,
+
+    #[fail(display = "Failed to read the history file")]
+    FailedToReadHistory,
+
+    #[fail(display = "Failed to write the history file")]
+    FailedToWriteHistory,
 }
 
 impl Error {
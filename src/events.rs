@@ -1,10 +1,15 @@
+use ansi;
 use errors::*;
+use nix::sys::signal::Signal;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::io;
 use std::os::unix::io::AsRawFd;
+use std::str;
+use std::time::{Duration, Instant};
 use term;
 use terminfo;
+use util;
 
 const FUNC_KEYS_KEY: [terminfo::StringField; 64] = [
     terminfo::StringField::KeyF0,
@@ -84,10 +89,18 @@ pub enum Key {
     Tab,
     Right,
     Delete,
+
+    /// The delete-character key, which deletes the character under the cursor.
+    /// Most terminals send `Key::Delete` for the backspace key.
+    DeleteChar,
     Backspace,
+    Insert,
+    PageUp,
+    PageDown,
     Escape,
     Enter,
     Begin,
+    Home,
     End,
     Clear,
     Exit,
@@ -99,13 +112,325 @@ pub enum Key {
     KeypadC3,
     Control(char),
     Invalid(u8),
+
+    /// A key pressed while holding modifier keys, e.g. Ctrl-Right is `Key::Modified(Modifiers::CTRL, Box::new(Key::Right))`.
+    ///
+    /// Only navigation, delete and function keys are decoded with modifiers, Ctrl with a letter is still `Key::Control`.
+    Modified(Modifiers, Box<Key>),
+
+    /// A key bound with `KeyMap::bind_custom`, the number is chosen by the application.
+    Custom(usize),
+}
+
+bitflags! {
+    /// Modifier keys held down while another key is pressed, see `Key::Modified`.
+    pub struct Modifiers: u8 {
+        const SHIFT = 1;
+        const ALT = 2;
+        const CTRL = 4;
+        const META = 8;
+    }
 }
 
+impl Modifiers {
+    /// Decode the modifier parameter in xterm's escape sequences, which is one more than the modifier bits.
+    fn from_param(param: u8) -> Modifiers {
+        Modifiers::from_bits_truncate(param.saturating_sub(1))
+    }
+}
+
+impl Key {
+    /// Add modifiers to a key, if `modifiers` is empty the key is returned as-is.
+    pub fn with_modifiers(self, modifiers: Modifiers) -> Key {
+        match self {
+            _ if modifiers.is_empty() => self,
+            Key::Modified(m, key) => Key::Modified(m | modifiers, key),
+            key => Key::Modified(modifiers, Box::new(key)),
+        }
+    }
+
+    /// Get the modifiers held down with the key.
+    pub fn modifiers(&self) -> Modifiers {
+        match *self {
+            Key::Modified(m, _) => m,
+            _ => Modifiers::empty(),
+        }
+    }
+
+    /// Get the key without its modifiers.
+    pub fn unmodified(&self) -> &Key {
+        match *self {
+            Key::Modified(_, ref key) => key,
+            ref key => key,
+        }
+    }
+}
+
+/// Keys that xterm sends with modifiers, as `\x1b[<number>;<modifiers><end>`.
+/// They're decoded from the parsed control sequence when terminfo doesn't list the key,
+/// as the `kUP5` style extended capabilities do.
+const XTERM_MODIFIED_KEYS: [(u8, char, Key); 22] = [
+    (1, 'A', Key::Up),
+    (1, 'B', Key::Down),
+    (1, 'C', Key::Right),
+    (1, 'D', Key::Left),
+    (1, 'H', Key::Home),
+    (1, 'F', Key::End),
+    (1, 'P', Key::Fn(1)),
+    (1, 'Q', Key::Fn(2)),
+    (1, 'R', Key::Fn(3)),
+    (1, 'S', Key::Fn(4)),
+    (2, '~', Key::Insert),
+    (3, '~', Key::DeleteChar),
+    (5, '~', Key::PageUp),
+    (6, '~', Key::PageDown),
+    (15, '~', Key::Fn(5)),
+    (17, '~', Key::Fn(6)),
+    (18, '~', Key::Fn(7)),
+    (19, '~', Key::Fn(8)),
+    (20, '~', Key::Fn(9)),
+    (21, '~', Key::Fn(10)),
+    (23, '~', Key::Fn(11)),
+    (24, '~', Key::Fn(12)),
+];
+
+/// Extended capabilities for modified keys, the name is followed by xterm's modifier parameter, e.g. `kUP5` is Ctrl-Up.
+/// The name on its own is the key with Shift.
+const MODIFIED_KEY_CAPS: [(&str, Key); 10] = [
+    ("kUP", Key::Up),
+    ("kDN", Key::Down),
+    ("kLFT", Key::Left),
+    ("kRIT", Key::Right),
+    ("kHOM", Key::Home),
+    ("kEND", Key::End),
+    ("kDC", Key::DeleteChar),
+    ("kIC", Key::Insert),
+    ("kPRV", Key::PageUp),
+    ("kNXT", Key::PageDown),
+];
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Event {
     ScrollUp(usize),
     ScrollDonw(usize),
     Key(Key),
+
+    /// Text that was pasted while bracketed paste was on, see `Term::set_bracketed_paste`.
+    /// Line endings are converted to `\n`.
+    Paste(String),
+
+    /// The mouse was used while mouse reporting was on, see `Term::set_mouse_mode`.
+    Mouse(Mouse),
+
+    /// The terminal was resized to `(columns, rows)`, only reported by `Term::read_events`.
+    Resize(u16, u16),
+
+    /// The terminal's window gained (`true`) or lost focus, while focus reporting was on, see `Term::set_focus_reporting`.
+    Focus(bool),
+
+    /// A signal was received, only reported by `Term::read_events`.
+    ///
+    /// `SIGTSTP` (e.g. Ctrl-Z when the terminal isn't in raw mode) suspends the program,
+    /// and `SIGCONT` is reported once it's resumed, since the screen may need to be redrawn.
+    /// Other signals, like `SIGINT` and `SIGTERM`, are only reported after `Events::catch_signals`,
+    /// instead of ending the program, so it can clean up first.
+    Signal(Signal),
+
+    /// A `Waker` was used, see `Term::waker`.
+    Wake,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+
+    /// The mouse moved while the button was held down.
+    Drag(MouseButton),
+
+    /// The mouse moved without a button held down, only reported with `MouseMode::Motion`.
+    Moved,
+    ScrollUp,
+    ScrollDown,
+}
+
+/// Something done with the mouse, at a cell on the screen. The top left cell is `(0, 0)`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Mouse {
+    pub action: MouseAction,
+    pub x: u16,
+    pub y: u16,
+
+    /// The modifier keys that were held down, terminals only report Shift, Alt and Ctrl.
+    pub modifiers: Modifiers,
+}
+
+/// Extra escape sequences to decode as keys, for terminals that send keys terminfo doesn't describe.
+///
+/// Bindings are checked before the terminal's own keys, so they can also replace them. See `Term::set_keymap`.
+///
+/// # Examples
+/// ```no_run
+/// use nixterm::events::{Key, KeyMap, Modifiers};
+/// use nixterm::term::Term;
+///
+/// const TOGGLE_SIDEBAR: usize = 1;
+///
+/// let mut term = Term::new().unwrap();
+/// term.set_keymap(
+///     KeyMap::new()
+///         .bind("\x1b[7^", Key::Home.with_modifiers(Modifiers::CTRL))
+///         .bind_capability("kHOM3", Key::Home.with_modifiers(Modifiers::ALT))
+///         .bind_custom("\x1b[24;2~", TOGGLE_SIDEBAR),
+/// );
+/// for key in term.read_keys() {
+///     match key.unwrap() {
+///         Key::Custom(TOGGLE_SIDEBAR) => (),
+///         _ => (),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KeyMap {
+    bindings: Vec<(Binding, Key)>,
+}
+
+/// Where a `KeyMap` binding's escape sequence comes from.
+#[derive(Clone, Debug)]
+enum Binding {
+    Sequence(String),
+
+    /// A terminfo capability, standard or extended, looked up when the keys are read.
+    Capability(String),
+}
+
+impl KeyMap {
+    pub fn new() -> KeyMap {
+        KeyMap::default()
+    }
+
+    /// Decode `seq` as `key`.
+    pub fn bind<S: Into<String>>(mut self, seq: S, key: Key) -> Self {
+        self.bindings.push((Binding::Sequence(seq.into()), key));
+        self
+    }
+
+    /// Decode the sequence in the terminfo capability `name` as `key`, e.g. `kHOM3`.
+    ///
+    /// Both standard and extended capabilities can be used. If the terminal doesn't have the capability it's ignored.
+    pub fn bind_capability<S: Into<String>>(mut self, name: S, key: Key) -> Self {
+        self.bindings.push((Binding::Capability(name.into()), key));
+        self
+    }
+
+    /// Decode `seq` as `Key::Custom(id)`, for keys that don't correspond to any other `Key`.
+    pub fn bind_custom<S: Into<String>>(self, seq: S, id: usize) -> Self {
+        self.bind(seq, Key::Custom(id))
+    }
+
+    /// The escape sequences bound, and their keys, for the terminal described by `info`.
+    fn sequences<'a>(&'a self, info: &'a terminfo::TermInfoBuf) -> impl Iterator<Item = (&'a str, &'a Key)> + 'a {
+        self.bindings.iter().filter_map(move |(binding, key)| {
+            let seq = match binding {
+                Binding::Sequence(seq) => seq.as_str(),
+                Binding::Capability(name) => match terminfo::StringField::from_name(name) {
+                    Some(field) => info.string(field)?,
+                    None => info.ext_string(name)?,
+                },
+            };
+            Some((seq, key)).filter(|&(seq, _)| !seq.is_empty())
+        })
+    }
+}
+
+/// Parse the escape sequence at the start of `bytes`, returning it and the number of bytes it used.
+///
+/// `None` if `bytes` doesn't start with a complete escape sequence, control sequence or SS3:
+/// if it's cut off, it's a string (e.g. OSC), or it's interrupted by a control character or another escape.
+fn parse_sequence(bytes: &[u8]) -> Option<(ansi::Sequence, usize)> {
+    let mut parser = ansi::Parser::new();
+    for (i, &b) in bytes.iter().enumerate() {
+        if i > 0 && b == 27 {
+            return None;
+        }
+        let mut parsed = parser.parse(&[b]);
+        match parsed.pop() {
+            Some(sequence) => {
+                return match sequence {
+                    ansi::Sequence::Escape { .. } | ansi::Sequence::Csi { .. } | ansi::Sequence::Ss3(_)
+                        if parsed.is_empty() =>
+                    {
+                        Some((sequence, i + 1))
+                    }
+                    _ => None,
+                }
+            }
+            None if parser.in_string() => return None,
+            None => (),
+        }
+    }
+    None
+}
+
+/// Decode one of xterm's modified keys, e.g. `CSI 1 ; 5 A` for Ctrl-Up, see `XTERM_MODIFIED_KEYS`.
+fn xterm_modified_key(sequence: &ansi::Sequence) -> Option<Key> {
+    let (params, final_byte) = match *sequence {
+        ansi::Sequence::Csi {
+            private: None,
+            ref params,
+            ref intermediates,
+            final_byte,
+        } if intermediates.is_empty() => (params, final_byte),
+        _ => return None,
+    };
+    let (number, param) = match params[..] {
+        [number, param] if (2..17).contains(&param) => (number, param),
+        _ => return None,
+    };
+    XTERM_MODIFIED_KEYS
+        .iter()
+        .find(|&&(n, end, _)| u32::from(n) == number && end as u8 == final_byte)
+        .map(|(_, _, key)| key.clone().with_modifiers(Modifiers::from_param(param as u8)))
+}
+
+/// The start of a mouse report in xterm's SGR encoding, which is followed by `<button>;<x>;<y>` then `M` or `m`.
+const MOUSE_START: &str = "\x1b[<";
+
+/// Sent when the terminal gains or loses focus.
+const FOCUS_IN: &str = "\x1b[I";
+const FOCUS_OUT: &str = "\x1b[O";
+
+/// How long to wait for the rest of an escape sequence, unless it's changed with `Term::set_escape_timeout`.
+pub const DEFAULT_ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+/// Sent before and after pasted text when bracketed paste is on.
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+/// The longest paste that's returned as one `Event::Paste`, anything after it is read as keys.
+pub const MAX_PASTE_LEN: usize = 1 << 20;
+
+/// How long to wait for more of a paste before returning what arrived, in case the end of the paste is lost.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What an escape sequence in the input stands for.
+#[derive(Clone, Debug)]
+enum Input {
+    Key(Key),
+    PasteStart,
+    PasteEnd,
+
+    /// The start of a mouse report, its length varies, so the rest of it is decoded separately.
+    MouseStart,
+    Mouse(Mouse),
+    Focus(bool),
 }
 
 pub struct Keys<'a, I, O>
@@ -116,7 +441,11 @@ where
     // Keys may need to be buffered if we have to back out of an escape code
     buffer: VecDeque<Key>,
     unread: VecDeque<u8>,
-    map: HashMap<&'a str, Key>,
+
+    /// The bytes read since the last key or event was returned, for `Term::debug_input`.
+    /// `unread` is always at the end of these.
+    read: Vec<u8>,
+    decoder: Decoder,
     tty: &'a term::Term<I, O>,
 }
 
@@ -134,7 +463,10 @@ where
             None => (),
         };
 
-        Some(self.getkey())
+        match self.getkey() {
+            Err(ref e) if *e.kind() == ErrorKind::EndOfInput => None,
+            result => Some(result),
+        }
     }
 }
 
@@ -144,48 +476,13 @@ where
     O: io::Write + AsRawFd + 'a,
 {
     pub fn new(t: &'a term::Term<I, O>) -> Keys<'a, I, O> {
-        let mut keys = Keys {
+        Keys {
             buffer: VecDeque::new(),
             unread: VecDeque::new(),
+            read: Vec::new(),
+            decoder: t.decoder(),
             tty: t,
-            map: HashMap::new(),
-        };
-        keys.make_keymap();
-        if let Some(v) = keys.tty.info.string(terminfo::KeypadXmit) {
-            keys.tty.writer().write_bytes(v.as_bytes());
-            keys.tty.flush();
         }
-        keys
-    }
-
-    fn string_to_key(&mut self, key: Key, field: terminfo::StringField) {
-        self.map
-            .insert(self.tty.info.string(field).unwrap_or(""), key);
-    }
-
-    fn make_keymap(&mut self) {
-        self.string_to_key(Key::Backspace, terminfo::KeyBackspace);
-        self.string_to_key(Key::Backtab, terminfo::BackTab);
-        self.string_to_key(Key::Begin, terminfo::KeyBeg);
-        self.string_to_key(Key::End, terminfo::KeyEnd);
-        self.string_to_key(Key::Clear, terminfo::KeyClear);
-        self.string_to_key(Key::Exit, terminfo::KeyExit);
-        self.string_to_key(Key::KeypadC1, terminfo::KeyC1);
-        self.string_to_key(Key::KeypadC3, terminfo::KeyC3);
-        self.string_to_key(Key::KeypadB2, terminfo::KeyB2);
-        self.string_to_key(Key::KeypadA3, terminfo::KeyA3);
-        self.string_to_key(Key::KeypadA1, terminfo::KeyA1);
-        self.string_to_key(Key::Up, terminfo::KeyUp);
-        self.string_to_key(Key::Down, terminfo::KeyDown);
-        self.string_to_key(Key::Left, terminfo::KeyLeft);
-        self.string_to_key(Key::Right, terminfo::KeyRight);
-        self.string_to_key(Key::Up, terminfo::ScrollForward);
-        self.string_to_key(Key::Down, terminfo::ScrollReverse);
-
-        FUNC_KEYS_KEY.iter().enumerate().for_each(|(i, &x)| {
-            self.map
-                .insert(self.tty.info.string(x).unwrap_or(""), Key::Fn(i));
-        });
     }
 
     fn getch(&mut self) -> Option<u8> {
@@ -194,7 +491,7 @@ where
         }
 
         let mut c: [u8; 1] = [0; 1];
-        let read = self.tty.read(&mut c);
+        let read = self.read_byte(&mut c);
 
         if read == 0 {
             None
@@ -203,66 +500,1031 @@ where
         }
     }
 
-    fn getkey_esc(&mut self) -> Result<Key> {
-        let mut read = 1;
-        let mut possible_keys = self.map.clone();
+    fn read_byte(&mut self, c: &mut [u8; 1]) -> usize {
+        let read = self.tty.read(c);
+        self.read.extend_from_slice(&c[..read]);
+        read
+    }
+
+    /// Pass the bytes `event` was decoded from to the function given to `Term::debug_input`.
+    fn report(&mut self, event: &Event) {
+        let used = self.read.len().saturating_sub(self.unread.len());
+        let bytes: Vec<u8> = self.read.drain(..used).collect();
+        self.tty.report_input(&bytes, event);
+    }
+
+    fn getkey_esc(&mut self) -> Result<Input> {
+        let start = self.unread.len();
+        let mut seq = vec![27];
         let mut c: [u8; 1] = [0; 1];
+        let timeout = self.tty.escape_timeout();
 
-        while possible_keys.len() > 0 {
-            let mut read_len = self.tty.read(&mut c);
+        while self.decoder.wants_more(&seq) {
+            if !self.tty.wait_for_input(timeout)? {
+                // the rest of the sequence didn't arrive in time, so escape was pressed on its own
+                break;
+            }
+            if self.read_byte(&mut c) < 1 {
+                // the input ended, the next read reports it
+                self.tty.err()?;
+                break;
+            }
+            seq.push(c[0]);
+            self.unread.push_back(c[0]);
+        }
+
+        if let Some((len, input)) = self.decoder.match_sequence(&seq) {
+            // anything read past the end of the sequence is read again as keys
+            self.unread.truncate(start);
+            self.unread.extend(seq[len..].iter().cloned());
+            return Ok(input);
+        }
 
-            while read_len < 1 {
-                read_len = self.tty.read(&mut c);
+        // nothing matched, but escape and one more byte may still be an Alt key
+        if self.unread.len() == start + 1 {
+            if let Some(key) = alt_key(self.unread[start]) {
+                self.unread.truncate(start);
+                return Ok(Input::Key(key));
             }
+        }
 
-            possible_keys.retain(|k, _| k.bytes().nth(read) == Some(c[0]));
+        Ok(Input::Key(Key::Escape))
+    }
 
-            read += 1;
-            self.unread.push_back(c[0]);
+    fn getkey(&mut self) -> Result<Key> {
+        loop {
+            if let Some(key) = self.getkey_timeout(None)? {
+                return Ok(key);
+            }
+        }
+    }
 
-            if let Some(k) = possible_keys
-                .iter()
-                .filter(|(k, _)| k.len() == read)
-                .map(|(_, v)| v)
-                .next()
-            {
-                self.unread.clear();
-                return Ok(k.clone());
+    /// Read a key, the start and end of a paste are skipped so pasted text is read as keys.
+    fn getkey_timeout(&mut self, timeout: Option<Duration>) -> Result<Option<Key>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            match self.getinput(left)? {
+                Some(Input::Key(key)) => {
+                    self.report(&Event::Key(key.clone()));
+                    return Ok(Some(key));
+                }
+                Some(_) => (),
+                None => return Ok(None),
             }
         }
+    }
 
-        Ok(Key::Escape)
+    /// Read a byte or escape sequence, waiting for input with `poll` instead of retrying the read.
+    fn getinput(&mut self, timeout: Option<Duration>) -> Result<Option<Input>> {
+        match self.getbyte(timeout)? {
+            Some(27) => match self.getkey_esc()? {
+                Input::MouseStart => match self.getmouse()? {
+                    Some(mouse) => Ok(Some(Input::Mouse(mouse))),
+                    // not a valid report, skip it
+                    None => self.getinput(timeout),
+                },
+                input => Ok(Some(input)),
+            },
+            Some(ch) => {
+                // read the rest of a multibyte character
+                let mut bytes = vec![ch];
+                if !self.decoder.meta_mode {
+                    while bytes.len() < self.decoder.encoding.char_len(ch) {
+                        match self.getbyte(self.tty.escape_timeout())? {
+                            Some(b) if b & 0xc0 == 0x80 => bytes.push(b),
+                            Some(b) => {
+                                self.unread.push_front(b);
+                                break;
+                            }
+                            None => break,
+                        }
+                    }
+                }
+
+                let (key, len) = self.decoder.decode_char(&bytes);
+                for &b in bytes[len..].iter().rev() {
+                    self.unread.push_front(b);
+                }
+                Ok(Some(Input::Key(key)))
+            }
+            None => Ok(None),
+        }
     }
 
-    fn getkey(&mut self) -> Result<Key> {
+    /// Read the rest of a mouse report.
+    fn getmouse(&mut self) -> Result<Option<Mouse>> {
+        let mut params = Vec::new();
+        while let Some(b) = self.getbyte(self.tty.escape_timeout())? {
+            match b {
+                b'M' | b'm' => return Ok(parse_mouse(&params, b)),
+                b'0'..=b'9' | b';' if params.len() < MAX_MOUSE_PARAMS_LEN => params.push(b),
+                _ => break,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check if there's input that's been read, but not returned yet.
+    fn has_pending(&self) -> bool {
+        !self.buffer.is_empty() || !self.unread.is_empty()
+    }
+
+    /// Read a byte, or return `Ok(None)` if nothing arrives within `timeout`.
+    ///
+    /// If there's input to read but nothing can be read the input was closed, and `ErrorKind::EndOfInput` is returned.
+    fn getbyte(&mut self, timeout: Option<Duration>) -> Result<Option<u8>> {
         self.tty.err()?;
+        if self.unread.is_empty() && !self.tty.wait_for_input(timeout)? {
+            return Ok(None);
+        }
+
+        match self.getch() {
+            Some(c) => Ok(Some(c)),
+            None => {
+                self.tty.err()?;
+                Err(ErrorKind::EndOfInput.into())
+            }
+        }
+    }
+
+    /// Read the next event, unlike iterating over `Keys` pasted text is read all at once as an `Event::Paste`.
+    ///
+    /// A paste longer than `MAX_PASTE_LEN`, or one that stops arriving for a second before it ends, is returned as it is so far.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::Event;
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.update(term.settings().raw()).unwrap();
+    /// term.set_bracketed_paste(true).unwrap();
+    ///
+    /// let mut keys = term.read_keys();
+    /// match keys.next_event().unwrap() {
+    ///     Event::Paste(text) => println!("pasted {} lines\r", text.lines().count()),
+    ///     event => println!("{:?}\r", event),
+    /// }
+    /// ```
+    pub fn next_event(&mut self) -> Result<Event> {
+        if let Some(key) = self.buffer.pop_front() {
+            return Ok(Event::Key(key));
+        }
+        let event = self.read_event()?;
+        if event != Event::Wake {
+            self.report(&event);
+        }
+        Ok(event)
+    }
+
+    fn read_event(&mut self) -> Result<Event> {
+        while !self.has_pending() {
+            if self.tty.take_wake() {
+                return Ok(Event::Wake);
+            }
+            if self.tty.wait_for_input_or_signal(None)? {
+                break;
+            }
+        }
+
+        loop {
+            match self.getinput(None)? {
+                Some(Input::Key(key)) => return Ok(Event::Key(key)),
+                Some(Input::Mouse(mouse)) => return Ok(Event::Mouse(mouse)),
+                Some(Input::Focus(focused)) => return Ok(Event::Focus(focused)),
+                Some(Input::PasteStart) => break,
+                _ => (),
+            }
+        }
+
+        let mut pasted = Vec::new();
+        while !pasted.ends_with(PASTE_END.as_bytes()) && pasted.len() < MAX_PASTE_LEN {
+            match self.getbyte(Some(PASTE_TIMEOUT)) {
+                Ok(Some(b)) => pasted.push(b),
+                Ok(None) => break,
+                // return the paste, the end of the input is reported by the next read
+                Err(ref e) if *e.kind() == ErrorKind::EndOfInput => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if pasted.ends_with(PASTE_END.as_bytes()) {
+            pasted.truncate(pasted.len() - PASTE_END.len());
+        }
+        Ok(Event::Paste(pasted_text(&pasted)))
+    }
+
+    /// Get the next key without removing it, so it's returned again by the next call to `next`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::Key;
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let mut keys = term.read_keys();
+    /// if keys.next().unwrap().unwrap() == Key::Char('g') {
+    ///     if *keys.peek().unwrap() == Key::Char('g') {
+    ///         keys.next();
+    ///         // go to the top
+    ///     }
+    /// }
+    /// ```
+    pub fn peek(&mut self) -> Result<&Key> {
+        if self.buffer.is_empty() {
+            let key = self.getkey()?;
+            self.buffer.push_back(key);
+        }
+        Ok(&self.buffer[0])
+    }
+
+    /// Put a key back, so it's the next one returned.
+    pub fn push_back(&mut self, key: Key) {
+        self.buffer.push_front(key);
+    }
+
+    /// Read the next key, or return `Ok(None)` if no key is pressed within `timeout`.
+    /// If the input is closed `ErrorKind::EndOfInput` is returned, when iterating over `Keys` the iterator ends instead.
+    ///
+    /// Once the first byte of an escape sequence arrives the rest of it is waited for, up to `Term::escape_timeout`,
+    /// even if that goes past `timeout`.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Key>> {
+        if let Some(key) = self.buffer.pop_front() {
+            return Ok(Some(key));
+        }
+        self.getkey_timeout(Some(timeout))
+    }
+
+    /// Decode a chunk of input that has already been read from the terminal, see `Decoder::decode`.
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Key> {
+        self.decoder.decode(bytes)
+    }
+
+    /// Decode a chunk of input into events, see `Decoder::decode_events`.
+    pub fn decode_events<'b>(&self, bytes: &'b [u8]) -> (Vec<Event>, &'b [u8]) {
+        self.decoder.decode_events(bytes)
+    }
+}
+
+/// The characters in KOI8-R from 0x80 to 0xff.
+const KOI8_R: [char; 128] = [
+    '\u{2500}', '\u{2502}', '\u{250c}', '\u{2510}', '\u{2514}', '\u{2518}', '\u{251c}', '\u{2524}',
+    '\u{252c}', '\u{2534}', '\u{253c}', '\u{2580}', '\u{2584}', '\u{2588}', '\u{258c}', '\u{2590}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2320}', '\u{25a0}', '\u{2219}', '\u{221a}', '\u{2248}',
+    '\u{2264}', '\u{2265}', '\u{00a0}', '\u{2321}', '\u{00b0}', '\u{00b2}', '\u{00b7}', '\u{00f7}',
+    '\u{2550}', '\u{2551}', '\u{2552}', '\u{0451}', '\u{2553}', '\u{2554}', '\u{2555}', '\u{2556}',
+    '\u{2557}', '\u{2558}', '\u{2559}', '\u{255a}', '\u{255b}', '\u{255c}', '\u{255d}', '\u{255e}',
+    '\u{255f}', '\u{2560}', '\u{2561}', '\u{0401}', '\u{2562}', '\u{2563}', '\u{2564}', '\u{2565}',
+    '\u{2566}', '\u{2567}', '\u{2568}', '\u{2569}', '\u{256a}', '\u{256b}', '\u{256c}', '\u{00a9}',
+    '\u{044e}', '\u{0430}', '\u{0431}', '\u{0446}', '\u{0434}', '\u{0435}', '\u{0444}', '\u{0433}',
+    '\u{0445}', '\u{0438}', '\u{0439}', '\u{043a}', '\u{043b}', '\u{043c}', '\u{043d}', '\u{043e}',
+    '\u{043f}', '\u{044f}', '\u{0440}', '\u{0441}', '\u{0442}', '\u{0443}', '\u{0436}', '\u{0432}',
+    '\u{044c}', '\u{044b}', '\u{0437}', '\u{0448}', '\u{044d}', '\u{0449}', '\u{0447}', '\u{044a}',
+    '\u{042e}', '\u{0410}', '\u{0411}', '\u{0426}', '\u{0414}', '\u{0415}', '\u{0424}', '\u{0413}',
+    '\u{0425}', '\u{0418}', '\u{0419}', '\u{041a}', '\u{041b}', '\u{041c}', '\u{041d}', '\u{041e}',
+    '\u{041f}', '\u{042f}', '\u{0420}', '\u{0421}', '\u{0422}', '\u{0423}', '\u{0416}', '\u{0412}',
+    '\u{042c}', '\u{042b}', '\u{0417}', '\u{0428}', '\u{042d}', '\u{0429}', '\u{0427}', '\u{042a}',
+];
+
+/// How bytes that aren't ASCII are decoded as characters, see `Decoder::encoding`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Encoding {
+    Utf8,
+
+    /// ISO 8859-1, each byte is the character with the same code point.
+    Latin1,
+    Koi8R,
+
+    /// A single byte encoding, the table has the characters for bytes 0x80 to 0xff.
+    Table(&'static [char; 128]),
+
+    /// Bytes that aren't ASCII are decoded as `Key::Invalid`.
+    Ascii,
+}
+
+impl Encoding {
+    /// The encoding used by the locale (`LC_ALL`, `LC_CTYPE` or `LANG`).
+    ///
+    /// If the locale doesn't name an encoding, or it isn't one of the ones above, input is decoded as ASCII.
+    pub fn from_locale() -> Encoding {
+        match util::locale_codeset().as_deref() {
+            Some("utf-8") | Some("utf8") => Encoding::Utf8,
+            Some("iso-8859-1") | Some("iso8859-1") | Some("iso88591") | Some("latin1") => Encoding::Latin1,
+            Some("koi8-r") | Some("koi8r") => Encoding::Koi8R,
+            _ => Encoding::Ascii,
+        }
+    }
+
+    /// The number of bytes in the character that starts with `lead`.
+    fn char_len(self, lead: u8) -> usize {
+        match self {
+            Encoding::Utf8 if lead >= 0xf0 => 4,
+            Encoding::Utf8 if lead >= 0xe0 => 3,
+            Encoding::Utf8 if lead >= 0xc0 => 2,
+            _ => 1,
+        }
+    }
 
-        let mut c = self.getch();
-        while c.is_none() {
-            c = self.getch();
-        }
-        let ch = c.unwrap();
-        Ok(match ch {
-            0...8 | 10...12 | 14...26 | 28...31 => Key::Control((ch + 64) as char),
-            9 => Key::Tab,
-            10 | 13 => Key::Enter,
-            27 => self.getkey_esc()?,
-            127 => Key::Delete,
-            32...126 => Key::Char(ch as char),
-            _ => Key::Invalid(ch),
+    /// Decode the character at the start of `bytes`, which starts with a byte that isn't ASCII.
+    fn decode(self, bytes: &[u8]) -> Option<char> {
+        let lead = bytes[0];
+        match self {
+            Encoding::Utf8 => {
+                let len = self.char_len(lead);
+                let s = str::from_utf8(bytes.get(..len)?).ok()?;
+                s.chars().next()
+            }
+            Encoding::Latin1 => Some(lead as char),
+            Encoding::Koi8R => Some(KOI8_R[lead as usize - 0x80]),
+            Encoding::Table(table) => Some(table[lead as usize - 0x80]),
+            Encoding::Ascii => None,
+        }
+    }
+}
+
+/// Decodes the bytes a terminal sends as keys and events.
+///
+/// `Keys` uses a decoder made by `Term::decoder`, but a decoder can also be made on its own, to decode input
+/// that doesn't come from a `Term`, e.g. in tests.
+///
+/// # Examples
+/// ```
+/// use nixterm::events::{Decoder, Event, Key, KeyMap};
+/// use nixterm::terminfo::TermInfoBuf;
+///
+/// let mut info = TermInfoBuf::new();
+/// info.set_string(nixterm::terminfo::KeyUp, "\x1b[A").unwrap();
+///
+/// let decoder = Decoder::new(&info, &KeyMap::new());
+/// let events = decoder
+///     .events(&b"a\x1b[A"[..])
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(events, vec![Event::Key(Key::Char('a')), Event::Key(Key::Up)]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    map: HashMap<String, Input>,
+    meta_mode: bool,
+    encoding: Encoding,
+}
+
+/// An iterator over the events decoded from a reader, see `Decoder::events`.
+pub struct DecodedEvents<'a, R: io::Read> {
+    decoder: &'a Decoder,
+    input: R,
+
+    /// Input that's been read, but not decoded yet.
+    pending: Vec<u8>,
+    events: VecDeque<Event>,
+    eof: bool,
+}
+
+impl Decoder {
+    /// Make a decoder for the keys listed in `info`, and the extra keys in `keymap`.
+    pub fn new(info: &terminfo::TermInfoBuf, keymap: &KeyMap) -> Decoder {
+        let mut decoder = Decoder {
+            map: HashMap::new(),
+            meta_mode: false,
+            encoding: Encoding::Utf8,
+        };
+        decoder.make_keymap(info, keymap);
+        decoder
+    }
+
+    fn string_to_key(&mut self, info: &terminfo::TermInfoBuf, key: Key, field: terminfo::StringField) {
+        self.map.insert(
+            info.string(field).unwrap_or("").to_owned(),
+            Input::Key(key),
+        );
+    }
+
+    fn make_keymap(&mut self, info: &terminfo::TermInfoBuf, keymap: &KeyMap) {
+        // added first, so the terminal's own capabilities take precedence
+        self.map.insert(MOUSE_START.to_owned(), Input::MouseStart);
+        self.map.insert(FOCUS_IN.to_owned(), Input::Focus(true));
+        self.map.insert(FOCUS_OUT.to_owned(), Input::Focus(false));
+
+        self.string_to_key(info, Key::Backspace, terminfo::KeyBackspace);
+        self.string_to_key(info, Key::Backtab, terminfo::BackTab);
+        self.string_to_key(info, Key::Begin, terminfo::KeyBeg);
+        self.string_to_key(info, Key::Home, terminfo::KeyHome);
+        self.string_to_key(info, Key::End, terminfo::KeyEnd);
+        self.string_to_key(info, Key::DeleteChar, terminfo::KeyDc);
+        self.string_to_key(info, Key::Insert, terminfo::KeyIc);
+        self.string_to_key(info, Key::PageUp, terminfo::KeyPpage);
+        self.string_to_key(info, Key::PageDown, terminfo::KeyNpage);
+        self.string_to_key(info, Key::Clear, terminfo::KeyClear);
+        self.string_to_key(info, Key::Exit, terminfo::KeyExit);
+        self.string_to_key(info, Key::KeypadC1, terminfo::KeyC1);
+        self.string_to_key(info, Key::KeypadC3, terminfo::KeyC3);
+        self.string_to_key(info, Key::KeypadB2, terminfo::KeyB2);
+        self.string_to_key(info, Key::KeypadA3, terminfo::KeyA3);
+        self.string_to_key(info, Key::KeypadA1, terminfo::KeyA1);
+        self.string_to_key(info, Key::Up, terminfo::KeyUp);
+        self.string_to_key(info, Key::Down, terminfo::KeyDown);
+        self.string_to_key(info, Key::Left, terminfo::KeyLeft);
+        self.string_to_key(info, Key::Right, terminfo::KeyRight);
+        self.string_to_key(info, Key::Up, terminfo::ScrollForward);
+        self.string_to_key(info, Key::Down, terminfo::ScrollReverse);
+
+        FUNC_KEYS_KEY.iter().enumerate().for_each(|(i, &x)| {
+            self.map.insert(
+                info.string(x).unwrap_or("").to_owned(),
+                Input::Key(Key::Fn(i)),
+            );
+        });
+
+        for &(name, ref key) in MODIFIED_KEY_CAPS.iter() {
+            // without a number it's the shifted key, some of those are standard capabilities (e.g. `kLFT`)
+            let shifted = match terminfo::StringField::from_name(name) {
+                Some(field) => info.string(field),
+                None => info.ext_string(name),
+            };
+            if let Some(seq) = shifted {
+                let key = key.clone().with_modifiers(Modifiers::SHIFT);
+                self.map.insert(seq.to_owned(), Input::Key(key));
+            }
+
+            for param in 2..17 {
+                if let Some(seq) = info.ext_string(format!("{}{}", name, param)) {
+                    let key = key.clone().with_modifiers(Modifiers::from_param(param));
+                    self.map.insert(seq.to_owned(), Input::Key(key));
+                }
+            }
+        }
+        self.map.insert(PASTE_START.to_owned(), Input::PasteStart);
+        self.map.insert(PASTE_END.to_owned(), Input::PasteEnd);
+
+        // terminfo lists what the cursor keys send in keypad transmit mode, e.g. `ESC O A` for up,
+        // in local mode they send `ESC [ A` instead (and the other way around on some terminals)
+        let cursor_keys = self
+            .map
+            .iter()
+            .filter_map(|(seq, input)| {
+                let other = match seq.as_bytes() {
+                    [0x1b, b'O', c @ b'A'..=b'H'] => format!("\x1b[{}", *c as char),
+                    [0x1b, b'[', c @ b'A'..=b'H'] => format!("\x1bO{}", *c as char),
+                    _ => return None,
+                };
+                Some((other, input.clone()))
+            })
+            .collect::<Vec<_>>();
+        for (seq, input) in cursor_keys {
+            self.map.entry(seq).or_insert(input);
+        }
+
+        for (seq, key) in keymap.sequences(info) {
+            self.map.insert(seq.to_owned(), Input::Key(key.clone()));
+        }
+    }
+
+    /// Decode bytes with the 8th bit set as keys with `Modifiers::ALT`, for terminals in meta mode, see `Term::meta_mode`.
+    pub fn meta_mode(mut self, enabled: bool) -> Self {
+        self.meta_mode = enabled;
+        self
+    }
+
+    /// Decode characters that aren't ASCII with `encoding`, the default is UTF-8.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Decode everything read from `input`, reading it in chunks until it ends.
+    ///
+    /// An escape sequence or paste that's cut off at the end of a chunk is decoded once the rest of it is read.
+    /// Unlike `Keys` there's no escape timeout: input is only split into keys once it's read.
+    pub fn events<R: io::Read>(&self, input: R) -> DecodedEvents<'_, R> {
+        DecodedEvents {
+            decoder: self,
+            input,
+            pending: Vec::new(),
+            events: VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    /// The length of the escape sequence that's cut off at the end of `bytes`, 0 if there isn't one.
+    ///
+    /// That's either the start of an escape or control sequence, or of a key that isn't shaped like one.
+    /// Strings like OSC aren't waited for, since keys don't send them. `ESC P` (the start of a DCS) is held like any
+    /// other cut-off sequence, if the input ends there it's decoded as Alt-P.
+    fn incomplete_len(&self, bytes: &[u8]) -> usize {
+        match bytes.iter().rposition(|&b| b == 27) {
+            Some(start) if self.wants_more(&bytes[start..]) => bytes.len() - start,
+            _ => 0,
+        }
+    }
+
+    /// Check if more input could make `bytes`, which starts with escape, a longer key.
+    ///
+    /// That's true if it's the start of a key in the keymap, or of an escape or control sequence the parser hasn't finished.
+    fn wants_more(&self, bytes: &[u8]) -> bool {
+        if self.is_sequence_start(bytes) {
+            return true;
+        }
+        if self.map.contains_key(str::from_utf8(bytes).unwrap_or("")) {
+            return false;
+        }
+        let mut parser = ansi::Parser::new();
+        let parsed = parser.parse(bytes);
+        parsed.is_empty() && !parser.is_ground() && !parser.in_string() && !bytes[1..].contains(&27)
+    }
+
+    /// Decode a chunk of input.
+    ///
+    /// This never waits for more input, so an escape sequence that's cut off at the end of `bytes` is decoded as
+    /// `Key::Escape`, followed by the rest of the sequence's bytes.
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Key> {
+        let mut keys = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match self.match_sequence(&bytes[i..]) {
+                Some((len, Input::MouseStart)) => {
+                    i += len + mouse_report_len(&bytes[i + len..]).unwrap_or(0);
+                }
+                Some((len, input)) => {
+                    if let Input::Key(key) = input {
+                        keys.push(key);
+                    }
+                    i += len;
+                }
+                None => {
+                    let (key, len) = self.decode_char(&bytes[i..]);
+                    keys.push(key);
+                    i += len;
+                }
+            }
+        }
+
+        keys
+    }
+
+    /// Decode a chunk of input into events, like `Decoder::decode`, except that pasted text becomes an `Event::Paste`.
+    ///
+    /// A paste or mouse report that hasn't ended by the end of `bytes` is returned undecoded,
+    /// it should be passed in again at the start of the next chunk of input.
+    /// Pastes longer than `MAX_PASTE_LEN` are cut short.
+    pub fn decode_events<'b>(&self, bytes: &'b [u8]) -> (Vec<Event>, &'b [u8]) {
+        let mut events = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match self.match_sequence(&bytes[i..]) {
+                Some((len, Input::PasteStart)) => {
+                    let text = &bytes[i + len..];
+                    match util::find(text, PASTE_END.as_bytes()) {
+                        Some(end) => {
+                            events.push(Event::Paste(pasted_text(&text[..end])));
+                            i += len + end + PASTE_END.len();
+                        }
+                        None if text.len() >= MAX_PASTE_LEN => {
+                            events.push(Event::Paste(pasted_text(&text[..MAX_PASTE_LEN])));
+                            i += len + MAX_PASTE_LEN;
+                        }
+                        None => return (events, &bytes[i..]),
+                    }
+                }
+                Some((len, Input::MouseStart)) => {
+                    let report = &bytes[i + len..];
+                    match mouse_report_len(report) {
+                        Some(end) => {
+                            let mouse = parse_mouse(&report[..end - 1], report[end - 1]);
+                            events.extend(mouse.map(Event::Mouse));
+                            i += len + end;
+                        }
+                        None if report.iter().all(is_mouse_param) => return (events, &bytes[i..]),
+                        None => i += len,
+                    }
+                }
+                Some((len, input)) => {
+                    match input {
+                        Input::Key(key) => events.push(Event::Key(key)),
+                        Input::Focus(focused) => events.push(Event::Focus(focused)),
+                        _ => (),
+                    }
+                    i += len;
+                }
+                None if self.is_cut_off(&bytes[i..]) => return (events, &bytes[i..]),
+                None => {
+                    let (key, len) = self.decode_char(&bytes[i..]);
+                    events.push(Event::Key(key));
+                    i += len;
+                }
+            }
+        }
+
+        (events, &[])
+    }
+
+    /// Decode the key at the start of `bytes`, which isn't a known escape sequence, and the number of bytes it used.
+    ///
+    /// In meta mode the 8th bit means Alt was held down, otherwise bytes that aren't ASCII are decoded with the encoding.
+    /// Escape followed by a printable character is that character with Alt held down.
+    fn decode_char(&self, bytes: &[u8]) -> (Key, usize) {
+        if bytes[0] == 27 && bytes.len() > 1 && !self.is_sequence_start(&bytes[..2]) {
+            if let Some(key) = alt_key(bytes[1]) {
+                return (key, 2);
+            }
+        }
+        let ch = bytes[0];
+        if ch < 128 {
+            return (key_from_byte(ch), 1);
+        }
+        if self.meta_mode {
+            return (key_from_byte(ch & 0x7f).with_modifiers(Modifiers::ALT), 1);
+        }
+        match self.encoding.decode(bytes) {
+            Some(c) => (Key::Char(c), self.encoding.char_len(ch)),
+            None => (Key::Invalid(ch), 1),
+        }
+    }
+
+    /// Check if `bytes` is the start of a longer escape sequence, e.g. `ESC [`.
+    fn is_sequence_start(&self, bytes: &[u8]) -> bool {
+        self.map
+            .keys()
+            .any(|k| k.len() > bytes.len() && k.as_bytes().starts_with(bytes))
+    }
+
+    /// Check if `bytes` is the start of a character that's cut off, and could be completed by more input.
+    fn is_cut_off(&self, bytes: &[u8]) -> bool {
+        let len = self.encoding.char_len(bytes[0]);
+        !self.meta_mode
+            && bytes.len() < len
+            && bytes[1..].iter().all(|&b| b & 0xc0 == 0x80)
+    }
+
+    /// Decode the escape sequence `bytes` starts with, and the number of bytes it used.
+    ///
+    /// The sequence is parsed, then looked up in the keymap, or decoded as one of xterm's modified keys.
+    /// Keys in the keymap that aren't shaped like an escape or control sequence (e.g. the Linux console's `ESC [ [ A`)
+    /// are matched by the longest one `bytes` starts with.
+    fn match_sequence(&self, bytes: &[u8]) -> Option<(usize, Input)> {
+        if bytes.first() != Some(&27) {
+            return None;
+        }
+        if let Some((sequence, len)) = parse_sequence(bytes) {
+            if let Some(input) = str::from_utf8(&bytes[..len]).ok().and_then(|k| self.map.get(k)) {
+                return Some((len, input.clone()));
+            }
+            if let Some(key) = xterm_modified_key(&sequence) {
+                return Some((len, Input::Key(key)));
+            }
+        }
+        self.map
+            .iter()
+            .filter(|&(k, _)| !k.is_empty() && bytes.starts_with(k.as_bytes()))
+            .max_by_key(|&(k, _)| k.len())
+            .map(|(k, input)| (k.len(), input.clone()))
+    }
+}
+
+impl<'a, R: io::Read> Iterator for DecodedEvents<'a, R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.eof {
+                if self.pending.is_empty() {
+                    return None;
+                }
+                // whatever's left can't be completed, decode it as keys
+                let keys = self.decoder.decode(&self.pending);
+                self.events.extend(keys.into_iter().map(Event::Key));
+                self.pending.clear();
+                continue;
+            }
+
+            let mut buf = [0; 1024];
+            let read = match self.input.read(&mut buf) {
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(util::io_error(e, ErrorKind::ReadFailed))),
+            };
+            if read == 0 {
+                self.eof = true;
+                continue;
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+
+            let complete = self.pending.len() - self.decoder.incomplete_len(&self.pending);
+            let (events, rest) = self.decoder.decode_events(&self.pending[..complete]);
+            let used = complete - rest.len();
+            self.events.extend(events);
+            self.pending.drain(..used);
+        }
+    }
+}
+
+/// The longest a mouse report's parameters can be, anything longer isn't treated as a report.
+const MAX_MOUSE_PARAMS_LEN: usize = 32;
+
+fn is_mouse_param(b: &u8) -> bool {
+    b.is_ascii_digit() || *b == b';'
+}
+
+/// Get the length of the mouse report at the start of `bytes` including the final `M` or `m`, not counting its prefix.
+/// Returns `None` if the report isn't valid or is cut off.
+fn mouse_report_len(bytes: &[u8]) -> Option<usize> {
+    let params = bytes.iter().take_while(|b| is_mouse_param(b)).count();
+    match bytes.get(params) {
+        Some(&b'M') | Some(&b'm') if params <= MAX_MOUSE_PARAMS_LEN => Some(params + 1),
+        _ => None,
+    }
+}
+
+/// Decode a mouse report in xterm's SGR encoding, `params` is `<button>;<x>;<y>` and `end` is `M` for a press,
+/// or `m` for a release.
+fn parse_mouse(params: &[u8], end: u8) -> Option<Mouse> {
+    let params = str::from_utf8(params).ok()?;
+    let mut numbers = params.split(';').map(|p| p.parse::<u16>().ok());
+    let (code, x, y) = match (numbers.next(), numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Some(code)), Some(Some(x)), Some(Some(y)), None) => (code, x, y),
+        _ => return None,
+    };
+
+    let button = match code & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let action = match (code & 64 != 0, code & 32 != 0, button) {
+        (true, _, Some(MouseButton::Left)) => MouseAction::ScrollUp,
+        (true, _, Some(MouseButton::Middle)) => MouseAction::ScrollDown,
+        (true, _, _) => return None,
+        (false, true, Some(button)) => MouseAction::Drag(button),
+        (false, true, None) => MouseAction::Moved,
+        (false, false, Some(button)) if end == b'm' => MouseAction::Release(button),
+        (false, false, Some(button)) => MouseAction::Press(button),
+        (false, false, None) => return None,
+    };
+
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(Modifiers::SHIFT, code & 4 != 0);
+    modifiers.set(Modifiers::ALT, code & 8 != 0);
+    modifiers.set(Modifiers::CTRL, code & 16 != 0);
+
+    Some(Mouse {
+        action,
+        x: x.saturating_sub(1),
+        y: y.saturating_sub(1),
+        modifiers,
+    })
+}
+
+/// Convert pasted bytes to text, terminals send line endings as `\r`.
+fn pasted_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+/// Map the byte following an escape to a key, many terminals send Alt-b as `ESC b`.
+///
+/// Two escapes are a single `Key::Escape`, since some terminals send escape with Alt held down that way.
+fn alt_key(ch: u8) -> Option<Key> {
+    match ch {
+        27 => Some(Key::Escape),
+        32..=126 => Some(Key::Char(ch as char).with_modifiers(Modifiers::ALT)),
+        _ => None,
+    }
+}
+
+/// Map a single byte of input to a key, escape sequences aren't handled here.
+fn key_from_byte(ch: u8) -> Key {
+    match ch {
+        0...8 | 10...12 | 14...26 | 28...31 => Key::Control((ch + 64) as char),
+        9 => Key::Tab,
+        10 | 13 => Key::Enter,
+        27 => Key::Escape,
+        127 => Key::Delete,
+        32...126 => Key::Char(ch as char),
+        _ => Key::Invalid(ch),
+    }
+}
+
+/// The signals `Events` always handles.
+const EVENT_SIGNALS: [Signal; 3] = [Signal::SIGWINCH, Signal::SIGTSTP, Signal::SIGCONT];
+
+/// An iterator over everything that happens on a terminal, see `Term::read_events`.
+///
+/// The iterator ends when the terminal's input is closed.
+pub struct Events<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    keys: Keys<'a, I, O>,
+
+    /// Events that were peeked at or pushed back.
+    buffer: VecDeque<Event>,
+
+    /// Signals reported as events, from `catch_signals`.
+    caught: Vec<Signal>,
+
+    // the guards are dropped in order, so the handlers installed last are restored first
+    _caught_signals: Vec<term::SignalGuard>,
+    _signals: term::SignalGuard,
+}
+
+impl<'a, I, O> Events<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    pub fn new(t: &'a term::Term<I, O>) -> Result<Events<'a, I, O>> {
+        t.err()?;
+        Ok(Events {
+            _signals: term::SignalGuard::install(&EVENT_SIGNALS)?,
+            keys: Keys::new(t),
+            buffer: VecDeque::new(),
+            caught: Vec::new(),
+            _caught_signals: Vec::new(),
         })
     }
+
+    /// Report `signals` as `Event::Signal`s instead of letting them end (or stop) the program.
+    ///
+    /// This is useful in raw mode with `ISIG` off, where Ctrl-C is read as a key, but `SIGTERM` or `SIGHUP`
+    /// can still arrive from elsewhere.
+    /// If `SIGTSTP` is caught the program isn't suspended, call `Events::suspend` to do that.
+    /// The original handlers are restored when the `Events` is dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// extern crate nix;
+    /// extern crate nixterm;
+    ///
+    /// use nix::sys::signal::Signal;
+    /// use nixterm::events::Event;
+    /// use nixterm::term::Term;
+    ///
+    /// # fn main() {
+    /// let term = Term::new().unwrap();
+    /// let events = term
+    ///     .read_events()
+    ///     .unwrap()
+    ///     .catch_signals(&[Signal::SIGINT, Signal::SIGTERM])
+    ///     .unwrap();
+    ///
+    /// for event in events {
+    ///     match event.unwrap() {
+    ///         Event::Signal(Signal::SIGINT) | Event::Signal(Signal::SIGTERM) => break,
+    ///         event => println!("{:?}", event),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn catch_signals(mut self, signals: &[Signal]) -> Result<Self> {
+        let new = signals
+            .iter()
+            .cloned()
+            .filter(|sig| !EVENT_SIGNALS.contains(sig) && !self.caught.contains(sig))
+            .collect::<Vec<_>>();
+        if !new.is_empty() {
+            self._caught_signals
+                .insert(0, term::SignalGuard::install(&new)?);
+        }
+        self.caught.extend(new);
+        if signals.contains(&Signal::SIGTSTP) && !self.caught.contains(&Signal::SIGTSTP) {
+            self.caught.push(Signal::SIGTSTP);
+        }
+        Ok(self)
+    }
+
+    /// Suspend the program, like the default action for `SIGTSTP`.
+    ///
+    /// The terminal's original (cooked) settings are restored while the program is stopped,
+    /// and the current settings are put back once it's resumed.
+    pub fn suspend(&mut self) -> Result<()> {
+        self.keys.tty.suspend()
+    }
+
+    /// Get the next event without removing it, so it's returned again by the next call to `next`.
+    pub fn peek(&mut self) -> Result<&Event> {
+        if self.buffer.is_empty() {
+            let event = self.read()?;
+            self.buffer.push_back(event);
+        }
+        Ok(&self.buffer[0])
+    }
+
+    /// Put an event back, so it's the next one returned.
+    pub fn push_back(&mut self, event: Event) {
+        self.buffer.push_front(event);
+    }
+
+    /// Get the next event, or `None` if nothing happens before `timeout` passes.
+    ///
+    /// Like `Keys::next_timeout`, once the start of an escape sequence or paste arrives the rest of it is waited for,
+    /// even if that goes past `timeout`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    /// use std::time::Duration;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let mut events = term.read_events().unwrap();
+    /// if events.next_timeout(Duration::from_secs(5)).unwrap().is_none() {
+    ///     term.print("press ? for help").unwrap();
+    /// }
+    /// ```
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        if let Some(event) = self.buffer.pop_front() {
+            return Ok(Some(event));
+        }
+        self.read_timeout(Some(timeout))
+    }
+
+    fn read(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.read_timeout(None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Read the next event that isn't dropped by the `Term`'s event hooks.
+    fn read_timeout(&mut self, timeout: Option<Duration>) -> Result<Option<Event>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            let event = match self.read_unfiltered(left)? {
+                Some(event) => event,
+                None => return Ok(None),
+            };
+            if let Some(event) = self.keys.tty.filter_event(event) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    fn read_unfiltered(&mut self, timeout: Option<Duration>) -> Result<Option<Event>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            if let Some(event) = self.signal()? {
+                return Ok(Some(event));
+            }
+            if self.keys.tty.take_wake() {
+                return Ok(Some(Event::Wake));
+            }
+
+            // if it wasn't input, check which signal or wake up it was
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if self.keys.has_pending() || self.keys.tty.wait_for_input_or_signal(left)? {
+                return self.keys.next_event().map(Some);
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Get the event for a signal that arrived, if there was one.
+    fn signal(&mut self) -> Result<Option<Event>> {
+        let tty = self.keys.tty;
+        if term::take_signal(Signal::SIGTSTP) {
+            if self.caught.contains(&Signal::SIGTSTP) {
+                return Ok(Some(Event::Signal(Signal::SIGTSTP)));
+            }
+            tty.suspend()?;
+            return Ok(Some(Event::Signal(Signal::SIGCONT)));
+        }
+        if term::take_signal(Signal::SIGWINCH) {
+            let (columns, rows) = tty.size()?;
+            return Ok(Some(Event::Resize(columns, rows)));
+        }
+        Ok(Some(Signal::SIGCONT)
+            .iter()
+            .chain(self.caught.iter())
+            .cloned()
+            .find(|&sig| sig != Signal::SIGTSTP && term::take_signal(sig))
+            .map(Event::Signal))
+    }
 }
 
-impl<'a, I, O> Drop for Keys<'a, I, O>
+impl<'a, I, O> Iterator for Events<'a, I, O>
 where
     I: io::Read + AsRawFd + 'a,
     O: io::Write + AsRawFd + 'a,
 {
-    fn drop(&mut self) {
-        if let Some(v) = self.tty.info.string(terminfo::KeypadLocal) {
-            self.tty.writer().write_bytes(v.as_bytes());
-            self.tty.flush();
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.buffer.pop_front() {
+            Some(event) => Some(Ok(event)),
+            None => match self.read() {
+                Err(ref e) if *e.kind() == ErrorKind::EndOfInput => None,
+                result => Some(result),
+            },
         }
     }
 }
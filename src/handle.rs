@@ -0,0 +1,209 @@
+//! Printing to a terminal from other threads, see `Term::handle`.
+use errors::*;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use term::{Style, Term, TermWriter};
+use util;
+
+/// A handle for printing to a terminal from any thread, see `Term::handle`.
+///
+/// Output is queued and written by a single thread. Everything passed to one call is written at once,
+/// so output from different threads never interleaves in the middle of a line or an escape sequence.
+#[derive(Clone)]
+pub struct TermHandle {
+    // the queue is declared first so it's dropped first: once the last handle's sender is gone the thread can finish
+    queue: mpsc::Sender<Queued>,
+    _thread: Arc<OutputThread>,
+}
+
+/// Joins a `TermHandle`'s output thread when the last handle is dropped, so queued output isn't lost.
+struct OutputThread(Option<thread::JoinHandle<()>>);
+
+/// Something sent to a `TermHandle`'s output thread.
+enum Queued {
+    Segments(Vec<(Style, String)>),
+
+    /// Reply once everything queued before this is written, with the first error since the last flush.
+    Flush(mpsc::Sender<Result<()>>),
+}
+
+impl Drop for OutputThread {
+    fn drop(&mut self) {
+        if let Some(thread) = self.0.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<I, O> Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd + Send + 'static,
+{
+    /// Get a handle that can print to the terminal from other threads, e.g. for log messages from workers.
+    ///
+    /// The handle's output is written from its own thread, through the same lock as `Term::writer`,
+    /// so it never lands in the middle of the terminal's own output.
+    /// The thread runs until every clone of the handle is dropped, dropping the last one waits for queued output to be written.
+    /// Don't drop it while holding a `TermWriter`, the thread can't finish until the writer is done.
+    ///
+    /// The handle uses the terminal's current color choice, colors are reduced with `NearestColor`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::{Style, Term};
+    /// use std::thread;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let handle = term.handle().unwrap();
+    /// let workers = (0..4)
+    ///     .map(|i| {
+    ///         let handle = handle.clone();
+    ///         thread::spawn(move || {
+    ///             handle
+    ///                 .print_segments(vec![(Style::new().bold(), format!("worker {}:", i)), (Style::new(), " done\n".into())])
+    ///                 .unwrap();
+    ///         })
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// for worker in workers {
+    ///     worker.join().unwrap();
+    /// }
+    /// handle.flush().unwrap();
+    /// ```
+    pub fn handle(&self) -> Result<TermHandle> {
+        let out = self.stdout.clone();
+        let info = self.info.clone();
+        let monochrome = self.monochrome.clone();
+        let styled = self.colors_enabled();
+        let tty = self.writes_to_tty();
+
+        let (queue, queued) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("nixterm-output".into())
+            .spawn(move || {
+                let mut err = None;
+                for item in queued {
+                    match item {
+                        Queued::Segments(segments) => {
+                            let mut w = TermWriter::new(&info, &monochrome, out.lock().unwrap(), None);
+                            w.styled = styled;
+                            w.tty = tty;
+                            if let Err(e) = w.print_segments(&segments).done() {
+                                err = err.or(Some(e));
+                            }
+                        }
+                        Queued::Flush(reply) => {
+                            let _ = reply.send(err.take().map_or(Ok(()), Err));
+                        }
+                    }
+                }
+            })
+            .map_err(|e| util::io_error(e, ErrorKind::FailedToStartOutputThread))?;
+        Ok(TermHandle {
+            queue,
+            _thread: Arc::new(OutputThread(Some(thread))),
+        })
+    }
+}
+
+impl TermHandle {
+    pub fn print<T: Into<String>>(&self, s: T) -> Result<()> {
+        self.print_segments(vec![(Style::new(), s.into())])
+    }
+
+    pub fn println<T: Into<String>>(&self, s: T) -> Result<()> {
+        self.print_segments(vec![(Style::new(), s.into() + "\n")])
+    }
+
+    /// Print each segment in its own style, see `TermWriter::print_segments`.
+    pub fn print_segments<T, S>(&self, segments: T) -> Result<()>
+    where
+        T: IntoIterator<Item = (Style, S)>,
+        S: Into<String>,
+    {
+        let segments = segments
+            .into_iter()
+            .map(|(style, text)| (style, text.into()))
+            .collect();
+        self.queue
+            .send(Queued::Segments(segments))
+            .map_err(|_| ErrorKind::OutputThreadStopped.into())
+    }
+
+    /// Wait until everything queued so far is written.
+    ///
+    /// Output is written in the background, so errors are only reported here, the first since the last `flush`.
+    pub fn flush(&self) -> Result<()> {
+        let (reply, done) = mpsc::channel();
+        self.queue
+            .send(Queued::Flush(reply))
+            .map_err(|_| Error::from(ErrorKind::OutputThreadStopped))?;
+        done.recv()
+            .map_err(|_| Error::from(ErrorKind::OutputThreadStopped))?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use term::{ColorChoice, Style, Term};
+    use terminfo;
+
+    const TERMINFO: &'static [u8] = include_bytes!("../test-data/rxvt");
+
+    #[test]
+    fn handle() {
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let (read, write) = nix::unistd::pipe().unwrap();
+        let (mut read, write) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            write.try_clone().unwrap(),
+            write,
+        );
+        term.set_color_choice(ColorChoice::Always);
+        term.set_assume_tty(true);
+        let handle = term.handle().unwrap();
+
+        let workers = (0..4)
+            .map(|i| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        let line = format!("thread {} line {}\n", i, j);
+                        handle.print_segments(vec![(Style::new().bold(), line)]).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        handle.flush().unwrap();
+        term.writer().print("frame").done().unwrap();
+        // dropping the last handle waits for the queue to be written
+        handle.println("done").unwrap();
+        drop(handle);
+        drop(term);
+
+        let mut out = String::new();
+        read.read_to_string(&mut out).unwrap();
+        let mut expected = 0;
+        for i in 0..4 {
+            for j in 0..10 {
+                let line = format!("\x1b[0;1m\x0fthread {} line {}\n\x1b[m\x0f", i, j);
+                assert_eq!(out.matches(&line).count(), 1);
+                expected += line.len();
+            }
+        }
+        assert!(out.ends_with("\x1b[0m\x0fframe\x1b[0m\x0fdone\n\x1b[m\x0f"));
+        assert_eq!(out.len(), expected + "\x1b[0m\x0fframe\x1b[0m\x0fdone\n\x1b[m\x0f".len());
+    }
+}
@@ -1,15 +1,24 @@
 #[macro_use]
+extern crate bitflags;
+#[macro_use]
 extern crate failure;
 extern crate memchr;
 extern crate nix;
+extern crate unicode_width;
 
 #[macro_use]
 pub mod ansi;
+pub mod app;
+pub mod editor;
 mod errors;
 pub mod events;
+pub mod handle;
+pub mod prompt;
 pub mod term;
 pub mod terminfo;
+pub mod text;
 mod util;
+pub mod widgets;
 pub mod xterm;
 
 pub use self::errors::*;
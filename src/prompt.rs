@@ -0,0 +1,211 @@
+//! Shell prompt rendering.
+//!
+//! Shells measure the length of a prompt to figure out where the cursor is, so any escape codes
+//! inside a prompt have to be marked as zero-width, otherwise line editing will break as soon as the prompt is styled.
+//! `PromptWriter` wraps an `io::Write` and takes care of inserting those markers (and escaping the shell's special characters).
+use std::io;
+
+/// The shell a prompt is being rendered for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Shell {
+    /// Bash's `PS1`, non-printing sequences are wrapped in `\[` and `\]`.
+    Bash,
+
+    /// Zsh's `PROMPT`, non-printing sequences are wrapped in `%{` and `%}`.
+    Zsh,
+
+    /// Raw readline prompts (e.g. those passed directly to `readline()`), non-printing sequences are wrapped in `\x01` and `\x02`.
+    Readline,
+}
+
+impl Shell {
+    fn start_marker(self) -> &'static [u8] {
+        match self {
+            Shell::Bash => b"\\[",
+            Shell::Zsh => b"%{",
+            Shell::Readline => b"\x01",
+        }
+    }
+
+    fn end_marker(self) -> &'static [u8] {
+        match self {
+            Shell::Bash => b"\\]",
+            Shell::Zsh => b"%}",
+            Shell::Readline => b"\x02",
+        }
+    }
+
+    /// Get the escaped version of `c`, if the shell would otherwise interpret it.
+    fn escape(self, c: u8) -> Option<&'static [u8]> {
+        match (self, c) {
+            (Shell::Bash, b'\\') => Some(b"\\\\"),
+            (Shell::Zsh, b'%') => Some(b"%%"),
+            _ => None,
+        }
+    }
+}
+
+/// The position of the writer within an escape sequence
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    /// Not in an escape sequence
+    Ground,
+
+    /// The last byte was ESC
+    Escape,
+
+    /// In a sequence that ends after one more byte (e.g. `ESC ( B`)
+    Intermediate,
+
+    /// In a control sequence (`ESC [`), ended by a byte in the range 0x40-0x7E
+    Csi,
+
+    /// In an operating system command, or other string (`ESC ]`, `ESC P`, etc.), ended by BEL or `ESC \`
+    String,
+
+    /// The last byte was ESC inside a string
+    StringEscape,
+}
+
+/// An `io::Write` adapter that marks every escape sequence written to it as zero-width.
+///
+/// Adjacent non-printing sequences are grouped into a single pair of markers.
+/// The final marker is only written once `PromptWriter::into_inner` is called.
+pub struct PromptWriter<W: io::Write> {
+    inner: W,
+    shell: Shell,
+    state: State,
+    in_marker: bool,
+}
+
+impl<W: io::Write> PromptWriter<W> {
+    pub fn new(inner: W, shell: Shell) -> PromptWriter<W> {
+        PromptWriter {
+            inner,
+            shell,
+            state: State::Ground,
+            in_marker: false,
+        }
+    }
+
+    /// Close any open marker and get the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.end_marker()?;
+        Ok(self.inner)
+    }
+
+    fn start_marker(&mut self) -> io::Result<()> {
+        if !self.in_marker {
+            self.inner.write_all(self.shell.start_marker())?;
+            self.in_marker = true;
+        }
+        Ok(())
+    }
+
+    fn end_marker(&mut self) -> io::Result<()> {
+        if self.in_marker {
+            self.inner.write_all(self.shell.end_marker())?;
+            self.in_marker = false;
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, c: u8) -> io::Result<()> {
+        match self.shell.escape(c) {
+            Some(escaped) => self.inner.write_all(escaped),
+            None => self.inner.write_all(&[c]),
+        }
+    }
+
+    /// Figure out the state after `c`, returns true if `c` is part of a non-printing sequence.
+    fn advance(&mut self, c: u8) -> bool {
+        let (next, hidden) = match (self.state, c) {
+            (State::Ground, 0x1b) => (State::Escape, true),
+            (State::Ground, b'\n') | (State::Ground, b'\r') | (State::Ground, b'\t') => {
+                (State::Ground, false)
+            }
+            (State::Ground, 0..=0x1f) | (State::Ground, 0x7f) => (State::Ground, true),
+            (State::Ground, _) => (State::Ground, false),
+
+            (State::Escape, b'[') => (State::Csi, true),
+            (State::Escape, b']') | (State::Escape, b'P') | (State::Escape, b'_') => {
+                (State::String, true)
+            }
+            (State::Escape, 0x20..=0x2f) => (State::Intermediate, true),
+            (State::Escape, _) | (State::Intermediate, _) => (State::Ground, true),
+
+            (State::Csi, 0x40..=0x7e) => (State::Ground, true),
+            (State::Csi, _) => (State::Csi, true),
+
+            (State::String, 0x07) => (State::Ground, true),
+            (State::String, 0x1b) => (State::StringEscape, true),
+            (State::String, _) => (State::String, true),
+            (State::StringEscape, b'\\') => (State::Ground, true),
+            (State::StringEscape, _) => (State::String, true),
+        };
+
+        self.state = next;
+        hidden
+    }
+}
+
+impl<W: io::Write> io::Write for PromptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &c in buf {
+            if self.advance(c) {
+                self.start_marker()?;
+            } else {
+                self.end_marker()?;
+            }
+            self.write_byte(c)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Mark every escape sequence in `s` as zero-width, and escape any characters `shell` would interpret.
+pub fn escape<T: AsRef<[u8]>>(s: T, shell: Shell) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut w = PromptWriter::new(Vec::new(), shell);
+    // writing to a vector can't fail
+    w.write_all(s.as_ref()).unwrap();
+    w.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use prompt::*;
+
+    #[test]
+    fn markers() {
+        assert_eq!(
+            escape("\x1b[1m\x1b[31m$\x1b[0m ", Shell::Bash),
+            b"\\[\x1b[1m\x1b[31m\\]$\\[\x1b[0m\\] ".to_vec()
+        );
+        assert_eq!(
+            escape("\x1b[1m%\x1b[m\x0f", Shell::Zsh),
+            b"%{\x1b[1m%}%%%{\x1b[m\x0f%}".to_vec()
+        );
+        assert_eq!(
+            escape("\x1b(Bx", Shell::Readline),
+            b"\x01\x1b(B\x02x".to_vec()
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            escape("\x1b]0;title\x1b\\> ", Shell::Bash),
+            b"\\[\x1b]0;title\x1b\\\\\\]> ".to_vec()
+        );
+        assert_eq!(
+            escape("\x1b]2;a\x07b\\", Shell::Readline),
+            b"\x01\x1b]2;a\x07\x02b\\".to_vec()
+        );
+    }
+}
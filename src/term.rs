@@ -1,18 +1,68 @@
 use ansi;
+use editor;
 use errors::*;
-use events::Keys;
+use events;
+use events::{Events, Key, Keys};
 use failure::Fail;
 use failure::ResultExt;
+use nix;
+use nix::libc;
+use nix::poll;
+use nix::sys::signal;
 use nix::sys::termios;
-use std::cell::RefCell;
+use prompt;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{BufRead, BufReader, Read};
-use std::ops::DerefMut;
+use std::str;
+use std::ops::{Deref, DerefMut};
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::{Mutex, MutexGuard};
+use std::panic;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::time::{Duration, Instant};
 use terminfo;
+use text;
 use util;
 
+/// Print formatted text with inline style directives, to a `Term` or `TermWriter`.
+///
+/// `{}` and `{0}` are replaced with arguments like `format!`, but format specs (e.g. `{:>5}`) aren't supported,
+/// format the argument first instead.
+/// Directives change the style of the text after them: `{bold}`, `{dim}`, `{italics}`, `{underline}`, `{blink}`,
+/// `{invert}`, `{standout}`, `{invisible}`, `{strikethrough}`, `{underline=STYLE}`, `{fg=COLOR}`, `{bg=COLOR}`,
+/// `{ul=COLOR}` and `{reset}`, where `COLOR` is anything `ansi::Color` can be parsed from,
+/// and `STYLE` is `single`, `double`, `curly`, `dotted` or `dashed`.
+/// See `TermWriter::print_styled`.
+///
+/// # Examples
+/// ```no_run
+/// #[macro_use]
+/// extern crate nixterm;
+///
+/// use nixterm::term::Term;
+///
+/// # fn main() {
+/// let term = Term::new().unwrap();
+/// let path = "Cargo.toml";
+/// termwrite!(term, "{bold}{fg=red}error:{reset} couldn't open {}\n", path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! termwrite {
+    ($w:expr, $template:expr) => {
+        $w.print_styled($template, &[])
+    };
+    ($w:expr, $template:expr, $($arg:expr),+ $(,)*) => {
+        $w.print_styled($template, &[$(&$arg),+])
+    };
+}
+
 macro_rules! terminfo_setter {
     (@imp $name:ident($field:ident) -> $enum:ident::$flag:ident) => {
         #[inline]
@@ -22,7 +72,7 @@ macro_rules! terminfo_setter {
             if v {
                 self.termios.$field |= $enum::$flag;
             } else {
-                self.termios.$field ^= $enum::$flag;
+                self.termios.$field &= !$enum::$flag;
             }
             self
         }
@@ -63,972 +113,6279 @@ macro_rules! terminfo_setter {
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Cursor(usize, usize);
 
-pub enum Align {
-    Left,
-    Right,
-    Center,
+/// The shape of the text cursor, see `Term::set_cursor_style`.
+///
+/// The values match the parameter to the DECSCUSR sequence (`CSI Ps SP q`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CursorStyle {
+    /// The terminal's default cursor, usually a blinking block.
+    Default = 0,
+    BlinkingBlock = 1,
+    SteadyBlock = 2,
+    BlinkingUnderline = 3,
+    SteadyUnderline = 4,
+    BlinkingBar = 5,
+    SteadyBar = 6,
 }
 
-#[derive(Clone)]
-pub struct Settings {
-    termios: termios::Termios,
+/// Parse a style from space separated directives, the same ones `termwrite!` uses, e.g. `"bold fg=red"`.
+///
+/// # Examples
+/// ```
+/// use nixterm::term::Style;
+///
+/// let style: Style = "underline fg=blue".parse().unwrap();
+/// assert_eq!(style, Style::new().underline().foreground("blue"));
+/// ```
+impl FromStr for Style {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Style> {
+        s.split_whitespace().try_fold(Style::new(), |style, directive| {
+            style
+                .directive(directive)
+                .ok_or_else(|| ErrorKind::InvalidStyle(s.to_owned()).into())
+        })
+    }
 }
 
-pub struct Term<I, O>
-where
-    I: io::Read + AsRawFd,
-    O: io::Write + AsRawFd,
-{
-    pub info: terminfo::TermInfoBuf,
-    stdin_fd: RawFd,
-    stdin: Mutex<BufReader<I>>,
-    stdout: Mutex<O>,
-    err: RefCell<Option<Error>>,
+/// How `Term::bell` gets the user's attention, see `Term::set_bell_style`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BellStyle {
+    /// Ring the terminal's bell.
+    Audible,
+
+    /// Flash the screen instead, see `Term::flash`.
+    Visual,
+
+    /// Don't do anything.
+    Off,
 }
 
-pub struct TermWriter<'a, O>
-where
-    O: io::Write + AsRawFd + 'a,
-{
-    info: &'a terminfo::TermInfoBuf,
-    err: Option<Error>,
-    written: usize,
-    stdout: MutexGuard<'a, O>,
+/// Which mouse events the terminal reports, see `Term::set_mouse_mode`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MouseMode {
+    Off,
 
-    bold: bool,
-    blink: bool,
-    underline: bool,
-    italics: bool,
-    standout: bool,
-    invert: bool,
-    invisible: bool,
-    dim: bool,
+    /// Presses, releases and the scroll wheel (mode 1000).
+    Clicks,
 
-    foreground: Option<ansi::Color>,
-    background: Option<ansi::Color>,
+    /// Clicks, and moving the mouse while a button is held down (mode 1002).
+    Drag,
+
+    /// Clicks, drags, and moving the mouse without a button held down (mode 1003).
+    Motion,
 }
 
-impl Settings {
-    /// Convenience method to configure the terminal to be in "raw" mode
-    ///
-    /// This method is roughly equivalent to the termios C API's `cfmakeraw()` function. It configures the terminal
-    /// to stop responding to signals, perform no post, or input processing, and turns off echoing and canonical mode.
-    pub fn raw(mut self) -> Self {
-        use nix::sys::termios::ControlFlags;
-        use nix::sys::termios::InputFlags;
-        use nix::sys::termios::LocalFlags;
-        use nix::sys::termios::OutputFlags;
+impl MouseMode {
+    /// The xterm modes that are set for this mode.
+    fn modes(self) -> &'static [ansi::PrivateMode] {
+        use ansi::PrivateMode::*;
 
-        self.termios.input_flags &= !(InputFlags::IGNBRK
-            | InputFlags::BRKINT
-            | InputFlags::PARMRK
-            | InputFlags::ISTRIP
-            | InputFlags::INLCR
-            | InputFlags::IGNCR
-            | InputFlags::ICRNL
-            | InputFlags::IXON);
-        self.termios.output_flags &= !OutputFlags::OPOST;
-        self.termios.local_flags &= !(LocalFlags::ECHO
-            | LocalFlags::ECHONL
-            | LocalFlags::ICANON
-            | LocalFlags::ISIG
-            | LocalFlags::IEXTEN);
-        self.termios.control_flags &= !(ControlFlags::CSIZE | ControlFlags::PARENB);
-        self.termios.control_flags |= ControlFlags::CS8;
-        return self;
+        match self {
+            MouseMode::Off => &[],
+            MouseMode::Clicks => &[MouseClicks],
+            MouseMode::Drag => &[MouseClicks, MouseDrag],
+            MouseMode::Motion => &[MouseClicks, MouseDrag, MouseMotion],
+        }
     }
+}
 
-    /// Set the character size, `x` must be in the range 5-8 otherwise this method will panic
-    pub fn char_size(mut self, x: u8) -> Self {
-        if x < 5 || x > 8 {
-            panic!("terminal character size must be in the range 5-8");
-        }
-        self.termios
-            .control_flags
-            .remove(termios::ControlFlags::CSIZE);
+/// Whether text is written with colors and other attributes, see `Term::set_color_choice`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ColorChoice {
+    /// Follow the user's environment: `NO_COLOR`, `CLICOLOR_FORCE`, `CLICOLOR` and `TERM=dumb`.
+    /// Colors are also turned off if the output isn't a terminal, unless `CLICOLOR_FORCE` is set.
+    Auto,
 
-        self.termios.control_flags.insert(match x {
-            5 => termios::ControlFlags::CS5,
-            6 => termios::ControlFlags::CS6,
-            7 => termios::ControlFlags::CS7,
-            8 => termios::ControlFlags::CS8,
-            _ => unreachable!(),
-        });
-        self
-    }
+    /// Always write colors and attributes.
+    Always,
 
-    /// Turn off canonical mode.
-    /// In non-canonical mode the terminal does perform line editing, the input buffer is 4096 characters long,
-    /// and timeouts may be set for input.
-    terminfo_setter!(local canonical -> ICANON);
+    /// Never write colors or attributes, only the text.
+    Never,
+}
 
-    /// Set the minimum number of characters for the `Term::read` function to return.
+impl ColorChoice {
+    /// Check if colors should be written to an output that is (or isn't) a `tty`,
+    /// `var` looks up environment variables for `ColorChoice::Auto`.
     ///
-    /// __*non-canonical mode only__
-    terminfo_setter!(char characters -> VMIN<u8>);
+    /// A non-empty `NO_COLOR` turns colors off, then a `CLICOLOR_FORCE` other than `0` turns them on.
+    /// Otherwise colors are used if the output is a terminal, unless `CLICOLOR` is `0` or `TERM` is `dumb`.
+    fn enabled<F: Fn(&str) -> Option<String>>(self, tty: bool, var: F) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                if var("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                    false
+                } else if var("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0") {
+                    true
+                } else if !tty {
+                    false
+                } else {
+                    var("CLICOLOR").is_none_or(|v| v != "0") && var("TERM").is_none_or(|v| v != "dumb")
+                }
+            }
+        }
+    }
+}
 
-    /// Set the maximum time the `Term::read` function will wait before returning (time in deciseconds)
-    ///
-    ///
-    /// __*non-canonical mode only__
-    terminfo_setter!(char timeout -> VTIME<u8>);
+/// What a terminal reports about itself, see `Term::probe`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DeviceAttributes {
+    /// The VT level the terminal conforms to, e.g. 1 for a VT100 and 4 for a VT420.
+    pub vt_level: u8,
 
-    /// Set the character that will signal the stdout buffer should be flushed.
-    terminfo_setter!(char flush -> VEOF);
+    /// The extensions listed in the primary device attributes, e.g. 4 for sixel graphics or 22 for ANSI color.
+    pub features: Vec<u16>,
 
-    /// Set an extra end-of-line character
-    terminfo_setter!(char eol -> VEOL);
+    /// The terminal's model from the secondary device attributes, e.g. 1 for a VT220 and 41 for a VT420.
+    /// `None` if the terminal didn't answer.
+    pub terminal_id: Option<u16>,
 
-    /// Set the erase char (typically it will be something like `DEL` by default)
-    terminfo_setter!(char erase -> VERASE);
+    /// The terminal's firmware version, emulators usually report their own version (xterm sends its patch number).
+    /// `None` if the terminal didn't answer.
+    pub firmware_version: Option<u32>,
+}
 
-    /// Set the interrupt char, when this character is read the running process will be sent `SIGINT`.
-    terminfo_setter!(char interrupt -> VINTR);
+impl DeviceAttributes {
+    /// True if the terminal can display sixel graphics.
+    pub fn sixel(&self) -> bool {
+        self.features.contains(&4)
+    }
 
-    /// Set the suspend char, when this character is read the running process will be sent `SIGTSTP`.
-    terminfo_setter!(char suspend -> VSUSP);
+    /// Parse the replies to the primary (`CSI ? ... c`) and secondary (`CSI > ... c`) device attributes queries.
+    /// The primary reply is required, the secondary one is optional.
+    fn parse(reply: &[ansi::Sequence]) -> Option<DeviceAttributes> {
+        // get the parameters from the reply with the private marker `marker`
+        let params = |marker| {
+            reply.iter().find_map(|seq| match *seq {
+                ansi::Sequence::Csi {
+                    private,
+                    ref params,
+                    final_byte: b'c',
+                    ..
+                } if private == Some(marker) => Some(params.clone()),
+                _ => None,
+            })
+        };
 
-    /// Set the kill char, when this character is read the running process will be sent `SIGKILL`.
-    terminfo_setter!(char kill -> VKILL);
+        let primary = params(b'?')?;
+        let secondary = params(b'>').unwrap_or_default();
+        let vt_level = match primary.first().cloned() {
+            Some(1) | Some(6) => 1,
+            Some(level @ 61..=69) => (level - 60) as u8,
+            _ => return None,
+        };
+        Some(DeviceAttributes {
+            vt_level,
+            features: primary[1..].iter().map(|&f| f as u16).collect(),
+            terminal_id: secondary.first().map(|&id| id as u16),
+            firmware_version: secondary.get(1).cloned(),
+        })
+    }
+}
 
-    /// Set the quit char, when this character is read the running process will be sent `SIGQUIT`.
-    terminfo_setter!(char quit -> VQUIT);
+/// A rectangle on the screen, in columns and rows from the top left corner (0, 0).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
 
-    /// stop showing output until the `start_output` char is reached.
-    terminfo_setter!(char start_output -> VSTART);
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
 
-    /// Set the character the will restart  output, ofter the `stop_output` char.
-    terminfo_setter!(char stop_output -> VSTOP);
+    /// The part of this rectangle that's inside `other`, it's empty if they don't overlap.
+    pub fn intersection(&self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (u32::from(self.x) + u32::from(self.width)).min(u32::from(other.x) + u32::from(other.width));
+        let bottom = (u32::from(self.y) + u32::from(self.height)).min(u32::from(other.y) + u32::from(other.height));
+        Rect::new(
+            x,
+            y,
+            right.saturating_sub(u32::from(x)) as u16,
+            bottom.saturating_sub(u32::from(y)) as u16,
+        )
+    }
+}
 
-    terminfo_setter!(local echo -> ECHO);
-    terminfo_setter!(local echo_newline -> ECHONL);
-    terminfo_setter!(local signals -> ISIG);
-    terminfo_setter!(local flush_on_signal -> NOFLSH);
-    terminfo_setter!(local input_processing -> IEXTEN);
+/// A line-drawing character, see `TermWriter::line`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LineChar {
+    Horizontal,
+    Vertical,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+
+    /// A vertical line with a branch to the right (├).
+    TeeRight,
+
+    /// A vertical line with a branch to the left (┤).
+    TeeLeft,
+
+    /// A horizontal line with a branch going down (┬).
+    TeeDown,
+
+    /// A horizontal line with a branch going up (┴).
+    TeeUp,
+    Cross,
+}
 
-    terminfo_setter!(ctrl parity -> PARENB);
-    terminfo_setter!(ctrl odd_parity -> PARODD);
-    terminfo_setter!(ctrl hangup -> HUPCL);
-    terminfo_setter!(ctrl ignore_modem_ctrl_lines -> CLOCAL);
+impl LineChar {
+    /// The character's key in the terminal's `acsc` capability, these are the characters used by the VT100.
+    pub fn acs(self) -> u8 {
+        match self {
+            LineChar::Horizontal => b'q',
+            LineChar::Vertical => b'x',
+            LineChar::TopLeft => b'l',
+            LineChar::TopRight => b'k',
+            LineChar::BottomLeft => b'm',
+            LineChar::BottomRight => b'j',
+            LineChar::TeeRight => b't',
+            LineChar::TeeLeft => b'u',
+            LineChar::TeeDown => b'w',
+            LineChar::TeeUp => b'v',
+            LineChar::Cross => b'n',
+        }
+    }
 
-    terminfo_setter!(out post_processing -> OPOST);
-    terminfo_setter!(out make_output_carriage_return_newline -> OCRNL);
+    pub fn unicode(self) -> char {
+        match self {
+            LineChar::Horizontal => '─',
+            LineChar::Vertical => '│',
+            LineChar::TopLeft => '┌',
+            LineChar::TopRight => '┐',
+            LineChar::BottomLeft => '└',
+            LineChar::BottomRight => '┘',
+            LineChar::TeeRight => '├',
+            LineChar::TeeLeft => '┤',
+            LineChar::TeeDown => '┬',
+            LineChar::TeeUp => '┴',
+            LineChar::Cross => '┼',
+        }
+    }
 
-    terminfo_setter!(inp ignore_break -> IGNBRK);
-    terminfo_setter!(inp interrupt_on_break -> BRKINT);
-    terminfo_setter!(inp ignore_frame_and_parity_errors -> IGNPAR);
-    terminfo_setter!(inp check_input_parity -> INPCK);
-    terminfo_setter!(inp strip_bit8 -> ISTRIP);
-    terminfo_setter!(inp make_input_carriage_return_newline -> ICRNL);
-    terminfo_setter!(inp make_input_newline_carriage_return -> INLCR);
-    terminfo_setter!(inp mark_bad_input -> IGNPAR);
-    terminfo_setter!(inp ignore_input_carriage_return -> IGNCR);
-    terminfo_setter!(inp xon_xoff -> IXON);
-    terminfo_setter!(inp utf8 -> IUTF8);
+    /// The closest ASCII character, for terminals that can't draw lines at all.
+    pub fn ascii(self) -> char {
+        match self {
+            LineChar::Horizontal => '-',
+            LineChar::Vertical => '|',
+            _ => '+',
+        }
+    }
 }
 
-impl Term<io::Stdin, io::Stdout> {
-    pub fn new() -> Result<Term<io::Stdin, io::Stdout>> {
-        Ok(Term::from_streams(
-            terminfo::from_env().context(ErrorKind::FailedToCreateTermInstance)?,
-            io::stdin(),
-            io::stdout(),
-        ))
+/// Where text goes when it's padded to a wider width, see `text::pad`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+
+    /// Centered, with the extra column on the right if the padding is uneven.
+    Center,
+}
+
+/// A text attribute, used in place of colors on terminals that can't display them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Attribute {
+    None,
+    Bold,
+    Dim,
+    Underline,
+    Blink,
+    Standout,
+    Invert,
+}
+
+/// The shape of underlined text, see `TermWriter::underline_style`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl UnderlineStyle {
+    /// The style's parameter for `Smulx` and `CSI 4:n m`.
+    fn param(self) -> u8 {
+        match self {
+            UnderlineStyle::Single => 1,
+            UnderlineStyle::Double => 2,
+            UnderlineStyle::Curly => 3,
+            UnderlineStyle::Dotted => 4,
+            UnderlineStyle::Dashed => 5,
+        }
     }
 }
 
-/// Map a `seta[b/f]` color to a `set[b/f]` color.
-#[inline]
-fn seta_to_set_pallet(x: u8) -> u8 {
-    match x {
-        1 => 4, // red and blue switch places
-        4 => 1,
-        3 => 6, // magenta and yellow switch places
-        6 => 3,
-        _ => x,
+impl FromStr for UnderlineStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<UnderlineStyle> {
+        Ok(match s {
+            "single" => UnderlineStyle::Single,
+            "double" => UnderlineStyle::Double,
+            "curly" => UnderlineStyle::Curly,
+            "dotted" => UnderlineStyle::Dotted,
+            "dashed" => UnderlineStyle::Dashed,
+            _ => return Err(ErrorKind::InvalidStyle(s.to_owned()).into()),
+        })
     }
 }
 
-/// Convert r, g and b values into a 3-bit pallet based color
+/// A set of attributes and colors, built once and applied to any number of writes with `TermWriter::style`.
 ///
-/// Expected Color Pallet:
-/// 0. black
-/// 1. red
-/// 2. green
-/// 3. yellow
-/// 4. blue
-/// 5. magenta
-/// 6. cyan
-/// 7. grey
-fn index_from_rgb3(r: u8, g: u8, b: u8) -> u8 {
-    let ir = r as isize;
-    let ig = g as isize;
-    let ib = b as isize;
+/// # Examples
+/// ```no_run
+/// use nixterm::term::{Style, Term};
+///
+/// let term = Term::new().unwrap();
+/// let error = Style::new().bold().foreground("red");
+///
+/// term.writer().style(&error).print("error:").print(" something went wrong\n").done().unwrap();
+/// term.writer().style(&error).print("error:").print(" again\n").done().unwrap();
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub blink: bool,
+    pub underline: bool,
+    pub italics: bool,
+    pub standout: bool,
+    pub invert: bool,
+    pub invisible: bool,
+    pub dim: bool,
+    pub strikethrough: bool,
+
+    /// The shape of the underline, if `underline` is set.
+    pub underline_style: UnderlineStyle,
+
+    pub foreground: Option<ansi::Color>,
+    pub background: Option<ansi::Color>,
+    pub underline_color: Option<ansi::Color>,
+}
 
-    if ir > 200 && ig > 200 && ib > 200 {
-        7
-    } else if ir > (ig + ib) {
-        1
-    } else if ig > (ir + ib) {
-        2
-    } else if ib > (ig + ir) {
-        4
-    } else if (ir - ig).abs() < ib {
-        3
-    } else if (ib - ig).abs() < ir {
-        6
-    } else if (ib - ir).abs() < ig {
-        5
-    } else {
-        0
+impl Style {
+    /// A style with no attributes, which uses the terminal's default colors.
+    pub fn new() -> Style {
+        Style::default()
     }
-}
 
-/// Same as index_from_rgb3 but with any extra bit to tell if the color should be "bright"
-fn index_from_rgb4(r: u8, g: u8, b: u8) -> u8 {
-    let ir = r as isize;
-    let ig = g as isize;
-    let ib = b as isize;
+    /// Apply a style directive, e.g. `bold` or `fg=red`, see `termwrite!`.
+    fn directive(self, directive: &str) -> Option<Style> {
+        Some(match directive {
+            "bold" => self.bold(),
+            "dim" => self.dim(),
+            "italics" => self.italics(),
+            "underline" => self.underline(),
+            "blink" => self.blink(),
+            "invert" => self.invert(),
+            "standout" => self.standout(),
+            "invisible" => self.invisible(),
+            "strikethrough" => self.strikethrough(),
+            "reset" => Style::new(),
+            d if d.starts_with("underline=") => self.underline_style(d[10..].parse().ok()?),
+            d if d.starts_with("fg=") => self.foreground(d[3..].parse::<ansi::Color>().ok()?),
+            d if d.starts_with("bg=") => self.background(d[3..].parse::<ansi::Color>().ok()?),
+            d if d.starts_with("ul=") => self.underline_color(d[3..].parse::<ansi::Color>().ok()?),
+            _ => return None,
+        })
+    }
 
-    if ir > 200 && ig > 200 && ib > 200 {
-        15
-    } else if ir > 150 && ig > 150 && ib > 150 {
-        8
-    } else if ir > (ig + ib) {
-        if ir / 2 > (ig + ib) {
-            9
-        } else {
-            1
-        }
-    } else if ig > (ir + ib) {
-        if ig > (ir + ib) {
-            10
-        } else {
-            2
-        }
-    } else if ib > (ig + ir) {
-        if ib > (ig + ir) {
-            12
-        } else {
-            4
-        }
-    } else if (ir - ig).abs() < ib {
-        if (ir - ig).abs() < ib / 2 {
-            11
-        } else {
-            3
-        }
-    } else if (ib - ig).abs() < ir {
-        if (ib - ig).abs() < ir / 2 {
-            14
-        } else {
-            6
-        }
-    } else if (ib - ir).abs() < ig {
-        if (ib - ir).abs() < ig / 2 {
-            13
-        } else {
-            5
-        }
-    } else {
-        0
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
     }
-}
 
-impl<'a, O> TermWriter<'a, O>
-where
-    O: io::Write + AsRawFd + 'a,
-{
-    fn exec<'b>(&'b self, field: terminfo::StringField) -> Result<terminfo::lang::Executor<'a>> {
-        match self.info.exec(field) {
-            Some(v) => Ok(v),
-            None => Err(ErrorKind::MissingTermInfoField(field).into()),
-        }
+    pub fn blink(mut self) -> Self {
+        self.blink = true;
+        self
     }
 
-    fn write_info_str(mut self, field: terminfo::StringField, fallback: &[u8]) -> Self {
-        if self.err().is_some() {
-            return self;
-        }
-
-        match self.exec(field) {
-            Ok(mut v) => {
-                self.written += v
-                    .write(self.stdout.deref_mut())
-                    .context(ErrorKind::FailedToRunTerminfo(field))
-                    .unwrap_or_else(|e| {
-                        self.err = Some(e.into());
-                        0
-                    });
-                self
-            }
-            Err(_) => self.write_bytes(fallback),
-        }
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
     }
 
-    /// Try to map the color into its closest equivalent supported by this terminal.
-    fn scrunch_color(&self, color: ansi::Color) -> ansi::Color {
-        match self.info.number(terminfo::MaxColors).unwrap_or(2) {
-            8..=15 => match color {
-                ansi::Color::Index(x @ 0..=7) => x,
-                ansi::Color::Index(x @ 8..=15) => (x - 8),
-                ansi::Color::Index(16) => 0,
-                ansi::Color::Index(x @ 17..=232) => {
-                    index_from_rgb3((x % 6) * 51, ((x / 6) % 6) * 51, (x / 36) * 51)
-                }
-                ansi::Color::Index(x) => {
-                    if x > 233 + (255 - 233) / 2 {
-                        7
-                    } else {
-                        0
-                    }
-                }
-                ansi::Color::Rgb(r, g, b) => index_from_rgb3(r, g, b),
-            }.into(),
-            16..=87 => match color {
-                ansi::Color::Index(x @ 0..=15) => x,
-                ansi::Color::Index(16) => 0,
-                ansi::Color::Index(x @ 17..=232) => {
-                    index_from_rgb4((x % 6) * 51, ((x / 6) % 6) * 51, (x / 36) * 51)
-                }
-                ansi::Color::Index(x) => {
-                    let y = 233 + (255 - 233);
-                    if x > (y / 3) * 2 {
-                        15
-                    } else if x > y / 3 {
-                        0
-                    } else {
-                        7
-                    }
-                }
-                ansi::Color::Rgb(r, g, b) => index_from_rgb4(r, g, b),
-            }.into(),
-            88..=255 => match color.into() {
-                ansi::Color::Index(x @ 0..=15) => x,
-                ansi::Color::Index(x) => (x as f64 * 0.3451171875) as u8,
-                ansi::Color::Rgb(r, g, b) => (r * 4 + g) * 4 + b + 16,
-            }.into(),
-            256 => match color.into() {
-                ansi::Color::Index(x) => x,
-                ansi::Color::Rgb(r, g, b) => (r * 16 + g) * 16 + b + 16,
-            }.into(),
-            _ => unimplemented!(),
-        }
+    pub fn italics(mut self) -> Self {
+        self.italics = true;
+        self
     }
 
-    fn write_u8(mut self, x: u8) -> Self {
-        if self.err().is_some() {
-            return self;
-        }
-
-        match util::write_u8_ansi(self.stdout.deref_mut(), x) {
-            Ok(v) => self.written += v,
-            Err(e) => self.err = Some(e.context(ErrorKind::WriteFailed).into()),
-        };
-
+    pub fn standout(mut self) -> Self {
+        self.standout = true;
         self
     }
 
-    fn wipe_formatting(&mut self) {
-        self.standout = false;
-        self.underline = false;
-        self.invert = false;
-        self.blink = false;
-        self.dim = false;
-        self.bold = false;
-        self.invisible = false;
-        self.background = None;
-        self.foreground = None;
+    pub fn invert(mut self) -> Self {
+        self.invert = true;
+        self
     }
 
-    fn set_sgr(&mut self) {
-        match self
-            .exec(terminfo::SetAttributes)
-            .unwrap()
-            .arg(self.standout)
-            .arg(self.underline)
-            .arg(self.invert)
-            .arg(self.blink)
-            .arg(self.dim)
-            .arg(self.bold)
-            .arg(self.invisible)
-            .write(self.stdout.deref_mut())
-        {
-            Ok(v) => self.written += v,
-            Err(e) => {
-                self.err = Some(
-                    e.context(ErrorKind::FailedToRunTerminfo(terminfo::SetAAttributes))
-                        .into(),
-                );
-            }
-        }
+    pub fn invisible(mut self) -> Self {
+        self.invisible = true;
+        self
     }
 
-    pub fn write_bytes(mut self, buf: &[u8]) -> Self {
-        if self.err().is_some() {
-            return self;
-        }
-
-        self.set_sgr();
-        if let Err(e) = self.write_fg_bg() {
-            self.err = Some(
-                e.context(ErrorKind::FailedToRunTerminfo(terminfo::SetAAttributes))
-                    .into(),
-            );
-            return self;
-        }
-
-        match self.stdout.write(buf) {
-            Ok(v) => self.written += v,
-            Err(e) => self.err = Some(e.context(ErrorKind::WriteFailed).into()),
-        };
-
-        self.wipe_formatting();
+    pub fn dim(mut self) -> Self {
+        self.dim = true;
         self
     }
 
-    pub fn print<T: AsRef<str>>(self, s: T) -> Self {
-        self.write_bytes(s.as_ref().as_bytes())
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = true;
+        self
     }
 
-    pub fn println<T: AsRef<str>>(self, s: T) -> Self {
-        self.print(s).print("\n")
+    /// Underline text with the given shape, see `TermWriter::underline_style`.
+    pub fn underline_style(mut self, style: UnderlineStyle) -> Self {
+        self.underline = true;
+        self.underline_style = style;
+        self
     }
 
-    pub fn bold(mut self) -> Self {
-        self.bold = true;
+    /// Set the underline's color, see `TermWriter::underline_color`.
+    pub fn underline_color<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        self.underline_color = Some(color.into());
         self
     }
 
-    pub fn blink(mut self) -> Self {
-        self.blink = true;
+    /// Set the foreground color, see `TermWriter::foreground` for the accepted formats.
+    pub fn foreground<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        self.foreground = Some(color.into());
         self
     }
 
-    pub fn italics(mut self) -> Self {
-        self.italics = true;
+    /// Set the background color, see `TermWriter::foreground` for the accepted formats.
+    pub fn background<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        self.background = Some(color.into());
         self
     }
+}
 
-    pub fn underline(mut self) -> Self {
-        self.underline = true;
-        self
+/// A mapping from colors to attributes for monochrome terminals (those with fewer than 8 colors).
+///
+/// Colors are first reduced to one of the 8 basic colors, then looked up in the foreground or background table.
+///
+/// # Examples
+/// ```
+/// use nixterm::term::{Attribute, MonochromeMap};
+///
+/// let map = MonochromeMap::new()
+///     .foreground("red", Attribute::Bold)
+///     .background("blue", Attribute::Invert);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MonochromeMap {
+    foreground: [Attribute; 8],
+    background: [Attribute; 8],
+}
+
+impl MonochromeMap {
+    /// Create a map that ignores all colors.
+    pub fn new() -> MonochromeMap {
+        MonochromeMap {
+            foreground: [Attribute::None; 8],
+            background: [Attribute::None; 8],
+        }
     }
 
-    pub fn invisible(mut self) -> Self {
-        self.invisible = true;
+    /// Display text with the foreground `color` using `attr`.
+    pub fn foreground<T: Into<ansi::Color>>(mut self, color: T, attr: Attribute) -> Self {
+        self.foreground[index_from_color3(color.into()) as usize] = attr;
         self
     }
 
-    pub fn standout(mut self) -> Self {
-        self.standout = true;
+    /// Display text with the background `color` using `attr`.
+    pub fn background<T: Into<ansi::Color>>(mut self, color: T, attr: Attribute) -> Self {
+        self.background[index_from_color3(color.into()) as usize] = attr;
         self
     }
 
-    pub fn dim(mut self) -> Self {
-        self.dim = true;
-        self
+    /// Get the attribute used for the foreground `color`.
+    pub fn foreground_attribute(&self, color: ansi::Color) -> Attribute {
+        self.foreground[index_from_color3(color) as usize]
     }
 
-    pub fn clear(self) -> Self {
-        self.write_info_str(terminfo::ExitAttributeMode, ansi::ALL_OFF)
+    /// Get the attribute used for the background `color`.
+    pub fn background_attribute(&self, color: ansi::Color) -> Attribute {
+        self.background[index_from_color3(color) as usize]
     }
+}
 
-    pub fn done(mut self) -> Result<usize> {
-        self.stdout.flush().context(ErrorKind::WriteFailed)?;
-        match self.err {
-            Some(v) => Err(v),
-            None => Ok(self.written),
+impl Default for MonochromeMap {
+    /// Warm colors are bold, cool colors are underlined, and any background other than black is inverted.
+    fn default() -> MonochromeMap {
+        MonochromeMap {
+            foreground: [
+                Attribute::None,
+                Attribute::Bold,
+                Attribute::Underline,
+                Attribute::Bold,
+                Attribute::Underline,
+                Attribute::Bold,
+                Attribute::Underline,
+                Attribute::None,
+            ],
+            background: [
+                Attribute::None,
+                Attribute::Invert,
+                Attribute::Invert,
+                Attribute::Invert,
+                Attribute::Invert,
+                Attribute::Invert,
+                Attribute::Invert,
+                Attribute::Invert,
+            ],
         }
     }
+}
 
-    pub fn err(&self) -> &Option<Error> {
-        &self.err
-    }
+/// Interrupts a blocked `Term::read_events` from another thread, see `Term::waker`.
+#[derive(Clone)]
+pub struct Waker {
+    pipe: Arc<WakePipe>,
+}
 
-    pub fn written(&self) -> usize {
-        self.written
-    }
+/// The pipe a `Waker` writes to, it's polled along with the terminal's input.
+struct WakePipe {
+    read: RawFd,
+    write: RawFd,
+}
 
-    /// Set the terminal's foreground color.
-    ///
-    /// `T` a `ansi::Color` enum, a number (`u8`) or a string.
-    /// strings may name a color, or provide custom r, g, and b values.
-    /// Any of the following are valid:
-    /// - "black"
-    /// - "red"
-    /// - "green"
-    /// - "yellow"
-    /// - "blue"
-    /// - "magenta"
-    /// - "cyan"
-    /// - "grey"
-    /// - "white" (same as "bright grey")
-    /// - "bright*" (`*` may be any of the other named colors)
-    /// - "#rrggbb"
-    /// - "#rgb"
-    /// - "rgb(r, g, b)"
-    ///
-    /// Numbers are also valid, the number must fit inside a `u8` (so it should be in the range 0-255).
-    ///
-    /// # Compatibility
-    /// Everything layed out above will work as far as this library, but the chances of it actually being supported across
-    /// any meaningful number of terminals is closed to 0.
-    ///
-    /// ## 24-bit Colors
-    /// Full RGB (that is, 24-bit color) terminals are pretty rare. When full 24-bit colors are not supported,
-    /// the color will get as close as it can with whatever the terminal provides.
-    ///
-    /// ## 8-bit Colors
-    /// 256 color terminals are fairly common, using depending on indices beyond 15 is still risky though.
-    ///
-    /// ## 4/3-bit Colors
-    /// Basically all terminals will support 3-bits, many will support 4 (that 4th bit gives the option of a "bright" variant).
-    pub fn foreground<T: Into<ansi::Color>>(mut self, color: T) -> Self {
-        if self.err().is_some() {
-            return self;
-        }
+/// Keeps the terminal in raw mode until it's dropped, see `Term::raw_mode`.
+pub struct RawMode<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
 
-        self.foreground = Some(self.scrunch_color(color.into()));
-        self
-    }
+    /// The settings from before raw mode, `None` once they're restored.
+    saved: Option<Settings>,
+}
 
-    pub fn background<T: Into<ansi::Color>>(mut self, color: T) -> Self {
-        if self.err().is_some() {
-            return self;
-        }
+/// Keeps the terminal from echoing input until it's dropped, see `Term::no_echo`.
+pub struct NoEcho<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
 
-        self.background = Some(self.scrunch_color(color.into()));
-        self
-    }
+    /// The settings from before echo was turned off, `None` once they're restored.
+    saved: Option<Settings>,
+}
 
-    fn write_fg_bg(&mut self) -> Result<()> {
-        if self.err().is_some() {
-            return Ok(());
+/// Keeps the keypad in transmit mode until it's dropped, see `Term::keypad_transmit`.
+pub struct KeypadTransmit<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
+
+    /// Whether transmit mode was on before, `None` once it's restored.
+    saved: Option<bool>,
+}
+
+/// Keeps the cursor's shape until it's dropped, see `Term::set_cursor_style`.
+pub struct CursorStyleGuard<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    term: &'a Term<I, O>,
+
+    /// The cursor's shape from before, `None` once it's restored.
+    saved: Option<CursorStyle>,
+}
+
+/// The signals received since they were last checked, bit `n` is set when signal `n` arrives.
+/// Set by the signal handlers installed by `SignalGuard`.
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+/// A pipe that a byte is written to whenever a signal arrives, so `poll` can wait for input and signals at once,
+/// without missing a signal that arrives just before it's called.
+static SIGNAL_PIPE_READ: AtomicIsize = AtomicIsize::new(-1);
+static SIGNAL_PIPE_WRITE: AtomicIsize = AtomicIsize::new(-1);
+static SIGNAL_PIPE_INIT: Once = Once::new();
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    SIGNALS.fetch_or(1 << sig, Ordering::SeqCst);
+
+    // the pipe is non-blocking, if it's full there's already a wake up waiting to be read
+    let fd = SIGNAL_PIPE_WRITE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd as RawFd, b"\0".as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Get the read end of the signal pipe, creating it the first time.
+pub(crate) fn signal_pipe() -> Result<RawFd> {
+    SIGNAL_PIPE_INIT.call_once(|| {
+        let flags = nix::fcntl::OFlag::O_NONBLOCK | nix::fcntl::OFlag::O_CLOEXEC;
+        if let Ok((read, write)) = nix::unistd::pipe2(flags) {
+            SIGNAL_PIPE_READ.store(read as isize, Ordering::SeqCst);
+            SIGNAL_PIPE_WRITE.store(write as isize, Ordering::SeqCst);
+        }
+    });
+    match SIGNAL_PIPE_READ.load(Ordering::SeqCst) {
+        fd if fd >= 0 => Ok(fd as RawFd),
+        _ => Err(ErrorKind::FailedToHandleSignals.into()),
+    }
+}
+
+/// Check if `sig` arrived since it was last checked.
+pub(crate) fn take_signal(sig: signal::Signal) -> bool {
+    let bit = 1 << sig as usize;
+    SIGNALS.fetch_and(!bit, Ordering::SeqCst) & bit != 0
+}
+
+/// Installs the signal handlers used by `Term::run_loop` and `Term::read_events`,
+/// restoring the old handlers when it's dropped.
+pub(crate) struct SignalGuard {
+    old: Vec<(signal::Signal, signal::SigAction)>,
+}
+
+impl SignalGuard {
+    pub(crate) fn install(signals: &[signal::Signal]) -> Result<SignalGuard> {
+        signal_pipe()?;
+        let action = signal::SigAction::new(
+            signal::SigHandler::Handler(on_signal),
+            signal::SaFlags::SA_RESTART,
+            signal::SigSet::empty(),
+        );
+
+        let mut guard = SignalGuard { old: Vec::new() };
+        for &sig in signals {
+            // forget signals from before the handler was installed
+            take_signal(sig);
+            let old = unsafe { signal::sigaction(sig, &action) }
+                .context(ErrorKind::FailedToHandleSignals)?;
+            guard.old.push((sig, old));
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        for &(sig, ref old) in self.old.iter() {
+            unsafe {
+                let _ = signal::sigaction(sig, old);
+            }
+        }
+    }
+}
+
+/// How long to wait for the terminal to answer a query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A function that reopens a terminal's input and output streams, see `Term::on_reconnect`.
+type ReconnectFn<I, O> = Box<dyn FnMut() -> io::Result<(I, O)>>;
+
+/// A function that's given the bytes each key or event was decoded from, see `Term::debug_input`.
+type InputDebugger = Box<dyn FnMut(&[u8], &events::Event)>;
+
+/// A function that changes or drops events as they're read, see `Term::add_event_hook`.
+type EventHook = Box<dyn FnMut(events::Event) -> Option<events::Event>>;
+
+/// Maps colors into ones a terminal can display.
+///
+/// Colors are mapped before they're written by `TermWriter::foreground` and `TermWriter::background`.
+/// Terminals with fewer than 8 colors use `MonochromeMap` instead.
+///
+/// # Examples
+/// ```
+/// use nixterm::ansi::Color;
+/// use nixterm::term::{ColorMapper, NearestColor};
+/// use nixterm::terminfo::TermInfoBuf;
+///
+/// /// Use the brand's red for any red-ish color, and leave everything else to `NearestColor`.
+/// struct Brand;
+///
+/// impl ColorMapper for Brand {
+///     fn map(&self, color: Color, info: &TermInfoBuf) -> Color {
+///         match color {
+///             Color::Rgb(r, g, b) if r > 128 && g < 64 && b < 64 => Color::Index(1),
+///             _ => NearestColor.map(color, info),
+///         }
+///     }
+/// }
+/// ```
+pub trait ColorMapper {
+    /// Map `color` into a color supported by the terminal described by `info`.
+    fn map(&self, color: ansi::Color, info: &terminfo::TermInfoBuf) -> ansi::Color;
+}
+
+/// The default `ColorMapper`, it picks the closest color supported by the terminal.
+///
+/// 8 and 16 color terminals get the closest basic color, 88 and 256 color terminals get the closest color in xterm's
+/// palette. Terminals with more than 256 colors are assumed to support direct color.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct NearestColor;
+
+impl ColorMapper for NearestColor {
+    fn map(&self, color: ansi::Color, info: &terminfo::TermInfoBuf) -> ansi::Color {
+        match info.number(terminfo::MaxColors).unwrap_or(2) {
+            8..=15 => index_from_color3(color).into(),
+            16..=87 => match color {
+                ansi::Color::Index(x @ 0..=15) => x,
+                ansi::Color::Index(x) => {
+                    let (r, g, b) = palette_rgb(x, 256);
+                    index_from_rgb4(r, g, b)
+                }
+                ansi::Color::Rgb(r, g, b) => index_from_rgb4(r, g, b),
+            }.into(),
+            88..=255 => match color {
+                ansi::Color::Index(x @ 0..=15) => x,
+                ansi::Color::Index(x) => nearest_palette_index(palette_rgb(x, 256), 88),
+                ansi::Color::Rgb(r, g, b) => nearest_palette_index((r, g, b), 88),
+            }.into(),
+            256 => match color {
+                ansi::Color::Index(x) => x,
+                ansi::Color::Rgb(r, g, b) => nearest_palette_index((r, g, b), 256),
+            }.into(),
+            // monochrome terminals are handled by `MonochromeMap`, anything larger than 256 supports direct color.
+            _ => color,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub(crate) termios: termios::Termios,
+}
+
+pub struct Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    pub info: terminfo::TermInfoBuf,
+
+    /// How colors are displayed if the terminal supports fewer than 8.
+    pub monochrome: MonochromeMap,
+
+    /// How colors are reduced to the ones the terminal supports, see `Term::set_color_mapper`.
+    color_mapper: Box<dyn ColorMapper>,
+
+    /// Extra escape sequences decoded as keys, see `Term::set_keymap`.
+    keymap: events::KeyMap,
+    stdin_fd: Cell<RawFd>,
+    stdin: Mutex<BufReader<I>>,
+    pub(crate) stdout: Arc<Mutex<O>>,
+
+    /// The settings the terminal had when it was opened, restored while the program is suspended.
+    cooked: RefCell<Option<Settings>>,
+    err: RefCell<Option<Error>>,
+    reconnect: RefCell<Option<ReconnectFn<I, O>>>,
+    event_hooks: RefCell<Vec<EventHook>>,
+    input_debugger: RefCell<Option<InputDebugger>>,
+    wake_pipe: RefCell<Option<Arc<WakePipe>>>,
+
+    /// The cursor style last set with `Term::set_cursor_style`.
+    cursor_style: Cell<CursorStyle>,
+
+    /// True if bracketed paste is on, and should be turned off when the `Term` is dropped.
+    bracketed_paste: Cell<bool>,
+    mouse_mode: Cell<MouseMode>,
+    focus_reporting: Cell<bool>,
+
+    /// True if the keypad is in transmit mode, and should be put back in local mode when the `Term` is dropped.
+    keypad: Cell<bool>,
+
+    /// True if meta mode is on, and should be turned off when the `Term` is dropped.
+    meta_mode: Cell<bool>,
+
+    /// How input that isn't ASCII is decoded, see `Term::set_input_encoding`.
+    input_encoding: Cell<events::Encoding>,
+
+    /// How long to wait after an escape for the rest of an escape sequence.
+    escape_timeout: Cell<Option<Duration>>,
+
+    bell_style: Cell<BellStyle>,
+    color_choice: Cell<ColorChoice>,
+
+    /// If true escape sequences are written even if the output isn't a terminal, see `Term::set_assume_tty`.
+    assume_tty: Cell<bool>,
+
+    /// Styles that can be used by name, see `Term::define_style`.
+    styles: RefCell<HashMap<String, Style>>,
+
+    /// Whether the terminal supports synchronized output, `None` until it's checked.
+    sync_update: Cell<Option<bool>>,
+
+    /// The editor used by `readline`, kept so its history lasts between lines.
+    editor: RefCell<editor::Editor>,
+}
+
+pub struct TermWriter<'a, O>
+where
+    O: io::Write + 'a,
+{
+    info: &'a terminfo::TermInfoBuf,
+    monochrome: &'a MonochromeMap,
+    color_mapper: &'a dyn ColorMapper,
+    err: Option<Error>,
+    written: usize,
+    stdout: MutexGuard<'a, O>,
+
+    /// Output that hasn't been written to `stdout` yet.
+    /// Everything is written at once by `done` (or when the writer is dropped), so a whole frame is drawn in one write.
+    buffer: Vec<u8>,
+
+    bold: bool,
+    blink: bool,
+    underline: bool,
+    italics: bool,
+    standout: bool,
+    invert: bool,
+    invisible: bool,
+    dim: bool,
+    strikethrough: bool,
+    underline_style: UnderlineStyle,
+
+    foreground: Option<ansi::Color>,
+    background: Option<ansi::Color>,
+    underline_color: Option<ansi::Color>,
+
+    /// If true attributes aren't reset after each write, see `TermWriter::keep_style`.
+    keep_style: bool,
+
+    /// The style last written to the terminal while `keep_style` is set, `None` if it isn't known.
+    active: Option<Style>,
+
+    /// The width `print_wrapped` wraps text to, `None` if it isn't known.
+    columns: Option<usize>,
+
+    /// The cursor's column, counted from the start of this writer's first line, or from the left edge of the region.
+    column: usize,
+
+    /// The part of the screen output is drawn in, see `TermWriter::region`.
+    region: Option<Rect>,
+
+    /// The cursor's row in the region.
+    row: u16,
+
+    /// The cursor's row on the screen, `None` until the cursor is moved with `move_to`.
+    line: Option<u16>,
+
+    /// Where the areas of the screen drawn on are recorded, see `Screen::flush`.
+    pub(crate) damage: Option<&'a RefCell<Vec<Rect>>>,
+
+    /// The styles defined with `Term::define_style`, `None` if the writer wasn't made by a `Term`.
+    styles: Option<&'a RefCell<HashMap<String, Style>>>,
+
+    /// If false colors and attributes aren't written, see `Term::set_color_choice`.
+    pub(crate) styled: bool,
+
+    /// If false the output isn't a terminal, and the cursor isn't moved.
+    pub(crate) tty: bool,
+}
+
+impl Settings {
+    /// Convenience method to configure the terminal to be in "raw" mode
+    ///
+    /// This method is roughly equivalent to the termios C API's `cfmakeraw()` function. It configures the terminal
+    /// to stop responding to signals, perform no post, or input processing, and turns off echoing and canonical mode.
+    pub fn raw(mut self) -> Self {
+        use nix::sys::termios::ControlFlags;
+        use nix::sys::termios::InputFlags;
+        use nix::sys::termios::LocalFlags;
+        use nix::sys::termios::OutputFlags;
+
+        self.termios.input_flags &= !(InputFlags::IGNBRK
+            | InputFlags::BRKINT
+            | InputFlags::PARMRK
+            | InputFlags::ISTRIP
+            | InputFlags::INLCR
+            | InputFlags::IGNCR
+            | InputFlags::ICRNL
+            | InputFlags::IXON);
+        self.termios.output_flags &= !OutputFlags::OPOST;
+        self.termios.local_flags &= !(LocalFlags::ECHO
+            | LocalFlags::ECHONL
+            | LocalFlags::ICANON
+            | LocalFlags::ISIG
+            | LocalFlags::IEXTEN);
+        self.termios.control_flags &= !(ControlFlags::CSIZE | ControlFlags::PARENB);
+        self.termios.control_flags |= ControlFlags::CS8;
+        return self;
+    }
+
+    /// Set the character size, `x` must be in the range 5-8 otherwise this method will panic
+    pub fn char_size(mut self, x: u8) -> Self {
+        if x < 5 || x > 8 {
+            panic!("terminal character size must be in the range 5-8");
+        }
+        self.termios
+            .control_flags
+            .remove(termios::ControlFlags::CSIZE);
+
+        self.termios.control_flags.insert(match x {
+            5 => termios::ControlFlags::CS5,
+            6 => termios::ControlFlags::CS6,
+            7 => termios::ControlFlags::CS7,
+            8 => termios::ControlFlags::CS8,
+            _ => unreachable!(),
+        });
+        self
+    }
+
+    /// Turn off canonical mode.
+    /// In non-canonical mode the terminal does perform line editing, the input buffer is 4096 characters long,
+    /// and timeouts may be set for input.
+    terminfo_setter!(local canonical -> ICANON);
+
+    /// Set the minimum number of characters for the `Term::read` function to return.
+    ///
+    /// __*non-canonical mode only__
+    terminfo_setter!(char characters -> VMIN<u8>);
+
+    /// Set the maximum time the `Term::read` function will wait before returning (time in deciseconds)
+    ///
+    ///
+    /// __*non-canonical mode only__
+    terminfo_setter!(char timeout -> VTIME<u8>);
+
+    /// Set the character that will signal the stdout buffer should be flushed.
+    terminfo_setter!(char flush -> VEOF);
+
+    /// Set an extra end-of-line character
+    terminfo_setter!(char eol -> VEOL);
+
+    /// Set the erase char (typically it will be something like `DEL` by default)
+    terminfo_setter!(char erase -> VERASE);
+
+    /// Set the interrupt char, when this character is read the running process will be sent `SIGINT`.
+    terminfo_setter!(char interrupt -> VINTR);
+
+    /// Set the suspend char, when this character is read the running process will be sent `SIGTSTP`.
+    terminfo_setter!(char suspend -> VSUSP);
+
+    /// Set the kill char, when this character is read the running process will be sent `SIGKILL`.
+    terminfo_setter!(char kill -> VKILL);
+
+    /// Set the quit char, when this character is read the running process will be sent `SIGQUIT`.
+    terminfo_setter!(char quit -> VQUIT);
+
+    /// stop showing output until the `start_output` char is reached.
+    terminfo_setter!(char start_output -> VSTART);
+
+    /// Set the character the will restart  output, ofter the `stop_output` char.
+    terminfo_setter!(char stop_output -> VSTOP);
+
+    terminfo_setter!(local echo -> ECHO);
+    terminfo_setter!(local echo_newline -> ECHONL);
+    terminfo_setter!(local signals -> ISIG);
+    terminfo_setter!(local flush_on_signal -> NOFLSH);
+    terminfo_setter!(local input_processing -> IEXTEN);
+
+    terminfo_setter!(ctrl parity -> PARENB);
+    terminfo_setter!(ctrl odd_parity -> PARODD);
+    terminfo_setter!(ctrl hangup -> HUPCL);
+    terminfo_setter!(ctrl ignore_modem_ctrl_lines -> CLOCAL);
+
+    terminfo_setter!(out post_processing -> OPOST);
+    terminfo_setter!(out make_output_carriage_return_newline -> OCRNL);
+
+    terminfo_setter!(inp ignore_break -> IGNBRK);
+    terminfo_setter!(inp interrupt_on_break -> BRKINT);
+    terminfo_setter!(inp ignore_frame_and_parity_errors -> IGNPAR);
+    terminfo_setter!(inp check_input_parity -> INPCK);
+    terminfo_setter!(inp strip_bit8 -> ISTRIP);
+    terminfo_setter!(inp make_input_carriage_return_newline -> ICRNL);
+    terminfo_setter!(inp make_input_newline_carriage_return -> INLCR);
+    terminfo_setter!(inp mark_bad_input -> IGNPAR);
+    terminfo_setter!(inp ignore_input_carriage_return -> IGNCR);
+    terminfo_setter!(inp xon_xoff -> IXON);
+    terminfo_setter!(inp utf8 -> IUTF8);
+}
+
+impl Term<io::Stdin, io::Stdout> {
+    pub fn new() -> Result<Term<io::Stdin, io::Stdout>> {
+        Ok(Term::from_streams(
+            terminfo::from_env().context(ErrorKind::FailedToCreateTermInstance)?,
+            io::stdin(),
+            io::stdout(),
+        ))
+    }
+}
+
+impl Term<File, File> {
+    /// Open the controlling terminal (`/dev/tty`) for both input and output.
+    ///
+    /// Unlike `Term::new` this stays interactive when stdin or stdout are redirected, e.g. in `cmd | program > out`.
+    /// If the terminal disappears, `Term::reconnect` opens `/dev/tty` again.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    /// use std::io::{self, Read};
+    ///
+    /// let mut input = String::new();
+    /// io::stdin().read_to_string(&mut input).unwrap();
+    ///
+    /// let term = Term::from_tty().unwrap();
+    /// let answer = term.prompt("Keep going? ").unwrap();
+    /// ```
+    pub fn from_tty() -> Result<Term<File, File>> {
+        let (stdin, stdout) =
+            open_tty().map_err(|e| util::io_error(e, ErrorKind::FailedToCreateTermInstance))?;
+        let term = Term::from_streams(
+            terminfo::from_env().context(ErrorKind::FailedToCreateTermInstance)?,
+            stdin,
+            stdout,
+        );
+        term.on_reconnect(open_tty);
+        Ok(term)
+    }
+}
+
+/// Open `/dev/tty`, returning separate handles for input and output.
+fn open_tty() -> io::Result<(File, File)> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    Ok((tty.try_clone()?, tty))
+}
+
+/// Parse the color in an OSC color reply, e.g. `ESC ] 11 ; rgb:ffff/ffff/ffff BEL`.
+///
+/// Each component has one to four hex digits, they're scaled down to 8 bits.
+fn parse_color_reply(reply: &[ansi::Sequence]) -> Option<ansi::Color> {
+    let data = reply.iter().find_map(|seq| match *seq {
+        ansi::Sequence::Osc(ref data) => Some(data),
+        _ => None,
+    })?;
+    let start = util::find(data, b"rgb:")? + 4;
+    let components = str::from_utf8(&data[start..])
+        .ok()?
+        .split('/')
+        .map(|c| match c.len() {
+            1..=4 => u32::from_str_radix(c, 16)
+                .ok()
+                .map(|v| (v * 255 / ((1 << (4 * c.len())) - 1)) as u8),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    match components[..] {
+        [r, g, b] => Some(ansi::Color::Rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+/// Check if `seq` is the reply to the primary device attributes query, which every terminal answers.
+fn is_primary_attributes(seq: &ansi::Sequence) -> bool {
+    matches!(
+        *seq,
+        ansi::Sequence::Csi {
+            private: Some(b'?'),
+            final_byte: b'c',
+            ..
+        }
+    )
+}
+
+/// Split a `termwrite!` template into styled segments, starting with `style`.
+fn styled_segments(
+    mut style: Style,
+    template: &str,
+    args: &[&dyn fmt::Display],
+) -> Result<Vec<(Style, String)>> {
+    let mut segments = vec![(style.clone(), String::new())];
+    let mut next_arg = 0;
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        segments.last_mut().unwrap().1.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+
+        // `{{` and `}}` are literal braces, a lone `}` is kept as-is
+        if rest.starts_with(brace) || brace == "}" {
+            segments.last_mut().unwrap().1.push_str(brace);
+            if rest.starts_with(brace) {
+                rest = &rest[1..];
+            }
+            continue;
+        }
+
+        let end = rest
+            .find('}')
+            .ok_or_else(|| ErrorKind::InvalidFormatDirective(format!("{{{}", rest)))?;
+        let directive = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let invalid = || ErrorKind::InvalidFormatDirective(format!("{{{}}}", directive));
+        let arg = match directive {
+            "" => {
+                next_arg += 1;
+                Some(next_arg - 1)
+            }
+            d => d.parse::<usize>().ok(),
+        };
+        if let Some(i) = arg {
+            let arg = args.get(i).ok_or_else(invalid)?;
+            segments.last_mut().unwrap().1.push_str(&arg.to_string());
+            continue;
+        }
+
+        style = style.directive(directive).ok_or_else(invalid)?;
+        segments.push((style.clone(), String::new()));
+    }
+    segments.last_mut().unwrap().1.push_str(rest);
+    segments.retain(|(_, text)| !text.is_empty());
+    Ok(segments)
+}
+
+/// Map a `seta[b/f]` color to a `set[b/f]` color.
+#[inline]
+fn seta_to_set_pallet(x: u8) -> u8 {
+    match x {
+        1 => 4, // red and blue switch places
+        4 => 1,
+        3 => 6, // magenta and yellow switch places
+        6 => 3,
+        _ => x,
+    }
+}
+
+/// Convert r, g and b values into a 3-bit pallet based color
+///
+/// Expected Color Pallet:
+/// 0. black
+/// 1. red
+/// 2. green
+/// 3. yellow
+/// 4. blue
+/// 5. magenta
+/// 6. cyan
+/// 7. grey
+fn index_from_rgb3(r: u8, g: u8, b: u8) -> u8 {
+    let ir = r as isize;
+    let ig = g as isize;
+    let ib = b as isize;
+
+    if ir > 200 && ig > 200 && ib > 200 {
+        7
+    } else if ir > (ig + ib) {
+        1
+    } else if ig > (ir + ib) {
+        2
+    } else if ib > (ig + ir) {
+        4
+    } else if (ir - ig).abs() < ib {
+        3
+    } else if (ib - ig).abs() < ir {
+        6
+    } else if (ib - ir).abs() < ig {
+        5
+    } else {
+        0
+    }
+}
+
+/// Same as index_from_rgb3 but with any extra bit to tell if the color should be "bright"
+fn index_from_rgb4(r: u8, g: u8, b: u8) -> u8 {
+    let ir = r as isize;
+    let ig = g as isize;
+    let ib = b as isize;
+
+    if ir > 200 && ig > 200 && ib > 200 {
+        15
+    } else if ir > 150 && ig > 150 && ib > 150 {
+        8
+    } else if ir > (ig + ib) {
+        if ir / 2 > (ig + ib) {
+            9
+        } else {
+            1
+        }
+    } else if ig > (ir + ib) {
+        if ig > (ir + ib) {
+            10
+        } else {
+            2
+        }
+    } else if ib > (ig + ir) {
+        if ib > (ig + ir) {
+            12
+        } else {
+            4
+        }
+    } else if (ir - ig).abs() < ib {
+        if (ir - ig).abs() < ib / 2 {
+            11
+        } else {
+            3
+        }
+    } else if (ib - ig).abs() < ir {
+        if (ib - ig).abs() < ir / 2 {
+            14
+        } else {
+            6
+        }
+    } else if (ib - ir).abs() < ig {
+        if (ib - ir).abs() < ig / 2 {
+            13
+        } else {
+            5
+        }
+    } else {
+        0
+    }
+}
+
+/// The level of each component along the axes of the 256 color palette's 6x6x6 color cube.
+const CUBE_LEVELS_256: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The level of each component along the axes of the 88 color palette's 4x4x4 color cube.
+const CUBE_LEVELS_88: [u8; 4] = [0, 139, 205, 255];
+
+/// The 8 steps of the 88 color palette's grayscale ramp.
+const GRAY_LEVELS_88: [u8; 8] = [46, 92, 115, 139, 162, 185, 208, 231];
+
+/// xterm's default values for the 16 basic colors.
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Get the RGB value of `index` in xterm's 88 or 256 color palette.
+///
+/// Indexes past the end of the 88 color palette are clamped to the last gray.
+fn palette_rgb(index: u8, colors: usize) -> (u8, u8, u8) {
+    if index < 16 {
+        return BASIC_COLORS[index as usize];
+    }
+
+    let i = index as usize - 16;
+    let cube: &[u8] = if colors == 88 {
+        &CUBE_LEVELS_88
+    } else {
+        &CUBE_LEVELS_256
+    };
+    let n = cube.len();
+
+    if i < n * n * n {
+        return (cube[i / (n * n)], cube[(i / n) % n], cube[i % n]);
+    }
+
+    let gray = i - n * n * n;
+    let level = if colors == 88 {
+        GRAY_LEVELS_88[gray.min(GRAY_LEVELS_88.len() - 1)]
+    } else {
+        8 + 10 * gray as u8
+    };
+    (level, level, level)
+}
+
+/// A cheap perceptual distance between two colors (the "redmean" approximation).
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> i64 {
+    let rmean = (a.0 as i64 + b.0 as i64) / 2;
+    let dr = a.0 as i64 - b.0 as i64;
+    let dg = a.1 as i64 - b.1 as i64;
+    let db = a.2 as i64 - b.2 as i64;
+
+    (((512 + rmean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - rmean) * db * db) >> 8)
+}
+
+/// Find the entry in the color cube or grayscale ramp of an 88 or 256 color palette closest to `rgb`.
+///
+/// The 16 basic colors are skipped, since their values vary between terminals.
+fn nearest_palette_index(rgb: (u8, u8, u8), colors: usize) -> u8 {
+    (16..colors)
+        .min_by_key(|&i| color_distance(rgb, palette_rgb(i as u8, colors)))
+        .unwrap_or(0) as u8
+}
+
+/// Reduce any color to one of the 8 basic colors.
+fn index_from_color3(color: ansi::Color) -> u8 {
+    match color {
+        ansi::Color::Index(x @ 0..=7) => x,
+        ansi::Color::Index(x @ 8..=15) => x - 8,
+        ansi::Color::Index(16) => 0,
+        ansi::Color::Index(x @ 17..=232) => {
+            index_from_rgb3((x % 6) * 51, ((x / 6) % 6) * 51, (x / 36) * 51)
+        }
+        ansi::Color::Index(x) => {
+            if x > 233 + (255 - 233) / 2 {
+                7
+            } else {
+                0
+            }
+        }
+        ansi::Color::Rgb(r, g, b) => index_from_rgb3(r, g, b),
+    }
+}
+
+impl<'a, O> TermWriter<'a, O>
+where
+    O: io::Write + 'a,
+{
+    pub(crate) fn new(
+        info: &'a terminfo::TermInfoBuf,
+        monochrome: &'a MonochromeMap,
+        stdout: MutexGuard<'a, O>,
+        columns: Option<usize>,
+    ) -> TermWriter<'a, O> {
+        TermWriter {
+            info,
+            monochrome,
+            color_mapper: &NearestColor,
+            stdout,
+            buffer: Vec::new(),
+            written: 0,
+            err: None,
+
+            bold: false,
+            dim: false,
+            standout: false,
+            italics: false,
+            invert: false,
+            blink: false,
+            invisible: false,
+            underline: false,
+            strikethrough: false,
+            underline_style: UnderlineStyle::Single,
+
+            foreground: None,
+            background: None,
+            underline_color: None,
+
+            keep_style: false,
+            active: None,
+
+            columns,
+            column: 0,
+            region: None,
+            row: 0,
+            line: None,
+            damage: None,
+            styles: None,
+            styled: true,
+            tty: true,
+        }
+    }
+
+    fn exec<'b>(&'b self, field: terminfo::StringField) -> Result<terminfo::lang::Executor<'a>> {
+        match self.info.exec(field) {
+            Some(v) => Ok(v),
+            None => Err(ErrorKind::MissingTermInfoField(field).into()),
+        }
+    }
+
+    fn write_info_str(mut self, field: terminfo::StringField, fallback: &[u8]) -> Self {
+        if self.err().is_some() {
+            return self;
+        }
+
+        match self.exec(field) {
+            Ok(mut v) => {
+                self.written += v
+                    .write(&mut self.buffer)
+                    .context(ErrorKind::FailedToRunTerminfo(field))
+                    .unwrap_or_else(|e| {
+                        self.err = Some(e.into());
+                        0
+                    });
+                self
+            }
+            Err(_) => self.write_bytes(fallback),
+        }
+    }
+
+    fn write_u8(mut self, x: u8) -> Self {
+        if self.err().is_some() {
+            return self;
+        }
+
+        match util::write_u8_ansi(&mut self.buffer, x) {
+            Ok(v) => self.written += v,
+            Err(e) => self.err = Some(e.context(ErrorKind::WriteFailed).into()),
+        };
+
+        self
+    }
+
+    fn wipe_formatting(&mut self) {
+        self.standout = false;
+        self.underline = false;
+        self.invert = false;
+        self.blink = false;
+        self.dim = false;
+        self.bold = false;
+        self.invisible = false;
+        self.strikethrough = false;
+        self.underline_style = UnderlineStyle::Single;
+        self.background = None;
+        self.foreground = None;
+        self.underline_color = None;
+    }
+
+    /// The attributes and colors that will be used for the next write.
+    fn current_style(&self) -> Style {
+        Style {
+            bold: self.bold,
+            blink: self.blink,
+            underline: self.underline,
+            italics: self.italics,
+            standout: self.standout,
+            invert: self.invert,
+            invisible: self.invisible,
+            dim: self.dim,
+            strikethrough: self.strikethrough,
+            underline_style: self.underline_style,
+            foreground: self.foreground,
+            background: self.background,
+            underline_color: self.underline_color,
+        }
+    }
+
+    fn set_sgr(&mut self) {
+        if !self.styled {
+            return;
+        }
+
+        match self
+            .exec(terminfo::SetAttributes)
+            .unwrap()
+            .arg(self.standout)
+            .arg(self.underline)
+            .arg(self.invert)
+            .arg(self.blink)
+            .arg(self.dim)
+            .arg(self.bold)
+            .arg(self.invisible)
+            .write(&mut self.buffer)
+        {
+            Ok(v) => self.written += v,
+            Err(e) => {
+                self.err = Some(
+                    e.context(ErrorKind::FailedToRunTerminfo(terminfo::SetAAttributes))
+                        .into(),
+                );
+            }
+        }
+    }
+
+    pub fn write_bytes(mut self, buf: &[u8]) -> Self {
+        if self.err().is_some() {
+            return self;
+        }
+
+        let style = self.current_style();
+        let written = match self.active.clone() {
+            _ if !self.styled => Ok(()),
+            Some(ref active) if self.keep_style && *active == style => Ok(()),
+            Some(ref active) if self.keep_style => self.write_style_change(active, &style),
+            _ => self.write_style(),
+        };
+        if let Err(e) = written {
+            self.err = Some(
+                e.context(ErrorKind::FailedToRunTerminfo(terminfo::SetAAttributes))
+                    .into(),
+            );
+            return self;
+        }
+
+        self.buffer.extend_from_slice(buf);
+        self.written += buf.len();
+
+        match buf.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            Some(i) => self.column = text::width(&String::from_utf8_lossy(&buf[i + 1..])),
+            None => self.column += text::width(&String::from_utf8_lossy(buf)),
+        }
+
+        if self.keep_style {
+            self.active = Some(style);
+        } else {
+            self.wipe_formatting();
+        }
+        self
+    }
+
+    pub fn print<T: AsRef<str>>(mut self, s: T) -> Self {
+        match self.region {
+            Some(region) => self.print_in_region(s.as_ref(), region),
+            None => {
+                if self.damage.is_some() {
+                    let mut column = self.column;
+                    for (i, line) in s.as_ref().split('\n').enumerate() {
+                        if i > 0 {
+                            self.line = self.line.map(|y| y.saturating_add(1));
+                            column = 0;
+                        }
+                        self.mark_damage(column, text::width(line));
+                    }
+                }
+                self.write_bytes(s.as_ref().as_bytes())
+            }
+        }
+    }
+
+    /// Record that `width` columns were drawn from column `x` of the cursor's row.
+    ///
+    /// If the row isn't known the whole screen is recorded.
+    fn mark_damage(&self, x: usize, width: usize) {
+        let damage = match self.damage {
+            Some(damage) if width > 0 => damage,
+            _ => return,
+        };
+        let rect = match self.line {
+            Some(y) => Rect::new(x.min(u16::MAX as usize) as u16, y, width.min(u16::MAX as usize) as u16, 1),
+            None => Rect::new(0, 0, u16::MAX, u16::MAX),
+        };
+        damage.borrow_mut().push(rect);
+    }
+
+    /// Print `s` clipped to `region`, each newline moves the cursor to the start of the region's next row.
+    fn print_in_region(mut self, s: &str, region: Rect) -> Self {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                let row = self.row.saturating_add(1);
+                self = self.move_to(0, row);
+            }
+            if self.row >= region.height {
+                continue;
+            }
+
+            let visible = text::truncate(line, (region.width as usize).saturating_sub(self.column));
+            if !visible.is_empty() {
+                self.mark_damage(region.x as usize + self.column, text::width(visible));
+                self = self.write_bytes(visible.as_bytes());
+            }
+        }
+        self
+    }
+
+    pub fn println<T: AsRef<str>>(self, s: T) -> Self {
+        self.print(s).print("\n")
+    }
+
+    /// Print `s`, soft-wrapping it on spaces so words aren't split across lines at the edge of the terminal.
+    ///
+    /// Text is wrapped to the width of the terminal when the writer was created, or the width set with `wrap_at`.
+    /// The column is counted from the start of this writer, so the cursor should be at the start of a line when it's created.
+    /// If the width isn't known `s` is printed as-is.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.writer()
+    ///     .bold()
+    ///     .print("usage: ")
+    ///     .print_wrapped("a long description, which is wrapped between words instead of being cut off halfway through one\n")
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn print_wrapped<T: AsRef<str>>(self, s: T) -> Self {
+        let width = match self.columns {
+            Some(width) => width,
+            None => return self.print(s),
+        };
+
+        // with `am`, but not `xenl`, the cursor moves to the next line as soon as the last column is written
+        let auto_wrap = self.info.boolean(terminfo::AutoRightMargin)
+            && !self.info.boolean(terminfo::EatNewlineGlitch);
+        let wrapped = util::wrap(s.as_ref(), self.column, width, auto_wrap);
+        self.print(wrapped)
+    }
+
+    /// Move the cursor to column `x` and row `y`, counted from 0.
+    ///
+    /// Inside a region the position is relative to the region's top left corner, see `TermWriter::region`.
+    pub fn move_to(mut self, x: u16, y: u16) -> Self {
+        if self.err().is_some() || !self.tty {
+            return self;
+        }
+
+        let (x, y) = match self.region {
+            // below the region anything printed is clipped anyway, so the cursor isn't moved
+            Some(region) if y >= region.height => {
+                self.row = y;
+                return self;
+            }
+            Some(region) => {
+                self.row = y;
+                self.column = x as usize;
+                (region.x.saturating_add(x), region.y.saturating_add(y))
+            }
+            None => (x, y),
+        };
+
+        let args = [u32::from(y).into(), u32::from(x).into()];
+        match self
+            .info
+            .write_to(terminfo::CursorAddress, &args, &mut self.buffer)
+        {
+            Ok(v) => {
+                self.written += v;
+                self.line = Some(y);
+                if self.region.is_none() {
+                    self.column = x as usize;
+                }
+            }
+            Err(e) => {
+                self.err = Some(
+                    e.context(ErrorKind::FailedToRunTerminfo(terminfo::CursorAddress))
+                        .into(),
+                )
+            }
+        }
+        self
+    }
+
+    /// Draw a run of line-drawing characters.
+    ///
+    /// Unicode box-drawing characters are used if the locale uses UTF-8, otherwise the terminal's alternate character set
+    /// is used (the `acsc`, `smacs` and `rmacs` capabilities). If neither is available the characters are drawn in ASCII.
+    pub fn line(mut self, chars: &[LineChar]) -> Self {
+        let chars = match self.region {
+            Some(region) if self.row >= region.height => &[][..],
+            Some(region) => {
+                let visible = (region.width as usize).saturating_sub(self.column);
+                &chars[..chars.len().min(visible)]
+            }
+            None => chars,
+        };
+        if chars.is_empty() {
+            return self;
+        }
+
+        let column = self.column;
+        let utf8 = util::is_utf8_locale();
+        let acs = match (
+            self.info.string(terminfo::AcsChars),
+            self.info.string(terminfo::EnterAltCharsetMode),
+            self.info.string(terminfo::ExitAltCharsetMode),
+        ) {
+            (Some(acsc), Some(enter), Some(exit)) if !utf8 => {
+                Some((acsc.as_bytes(), enter.as_bytes(), exit.as_bytes()))
+            }
+            _ => None,
+        };
+
+        // `acsc` is a list of pairs, the VT100's character followed by the character this terminal uses
+        let mapped = acs.and_then(|(acsc, enter, exit)| {
+            let mut buf = enter.to_vec();
+            for c in chars {
+                let pair = acsc.chunks(2).find(|p| p.len() == 2 && p[0] == c.acs())?;
+                buf.push(pair[1]);
+            }
+            buf.extend_from_slice(exit);
+            Some(buf)
+        });
+
+        self = match mapped {
+            Some(buf) => {
+                let x = self.region.map_or(0, |r| r.x as usize) + column;
+                self.mark_damage(x, chars.len());
+                self.write_bytes(&buf)
+            }
+            None if utf8 => {
+                self.print(chars.iter().map(|c| c.unicode()).collect::<String>())
+            }
+            None => self.print(chars.iter().map(|c| c.ascii()).collect::<String>()),
+        };
+        self.column = column + chars.len();
+        self
+    }
+
+    /// Draw the outline of `rect` using line-drawing characters, see `TermWriter::line`.
+    ///
+    /// The cursor is left at the bottom right corner. Nothing is drawn if the rectangle is less than 2x2.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::{Rect, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.writer()
+    ///     .box_outline(Rect::new(2, 1, 20, 5))
+    ///     .move_to(4, 3)
+    ///     .print("in a box")
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn box_outline(mut self, rect: Rect) -> Self {
+        if rect.width < 2 || rect.height < 2 {
+            return self;
+        }
+
+        let right = rect.x + rect.width - 1;
+        let bottom = rect.y + rect.height - 1;
+        let mut edge = vec![LineChar::Horizontal; rect.width as usize];
+
+        edge[0] = LineChar::TopLeft;
+        edge[rect.width as usize - 1] = LineChar::TopRight;
+        self = self.move_to(rect.x, rect.y).line(&edge);
+        for y in rect.y + 1..bottom {
+            self = self
+                .move_to(rect.x, y)
+                .line(&[LineChar::Vertical])
+                .move_to(right, y)
+                .line(&[LineChar::Vertical]);
+        }
+
+        edge[0] = LineChar::BottomLeft;
+        edge[rect.width as usize - 1] = LineChar::BottomRight;
+        self.move_to(rect.x, bottom).line(&edge)
+    }
+
+    /// Draw inside `rect`, the cursor is moved to its top left corner.
+    ///
+    /// Positions given to `move_to` are relative to the region, text is cut off at its edges,
+    /// and a newline moves the cursor to the start of the region's next row.
+    /// Inside another region `rect` is relative to it, and clipped to it.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::{Rect, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.writer()
+    ///     .region(Rect::new(10, 2, 20, 3))
+    ///     .box_outline(Rect::new(0, 0, 20, 3))
+    ///     .move_to(2, 1)
+    ///     .print("text that's too long for the box is cut off")
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn region(mut self, rect: Rect) -> Self {
+        let rect = match self.region {
+            Some(outer) => Rect::new(
+                outer.x.saturating_add(rect.x),
+                outer.y.saturating_add(rect.y),
+                rect.width,
+                rect.height,
+            ).intersection(outer),
+            None => rect,
+        };
+        self.region = Some(rect);
+        self.columns = Some(rect.width as usize);
+        self.move_to(0, 0)
+    }
+
+    /// Set the width `print_wrapped` wraps text to.
+    pub fn wrap_at(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// The width of the terminal when the writer was created, or the width set with `wrap_at`.
+    pub(crate) fn columns(&self) -> Option<usize> {
+        self.columns
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub fn blink(mut self) -> Self {
+        self.blink = true;
+        self
+    }
+
+    pub fn italics(mut self) -> Self {
+        self.italics = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    pub fn invisible(mut self) -> Self {
+        self.invisible = true;
+        self
+    }
+
+    pub fn standout(mut self) -> Self {
+        self.standout = true;
+        self
+    }
+
+    pub fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    /// Cross text out, if the terminal supports it (it has the extended `smxx` capability).
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = true;
+        self
+    }
+
+    /// Underline text with a double, curly, dotted or dashed line.
+    ///
+    /// Terminals that don't support styled underlines (they don't have the extended `Smulx` capability or `Su` flag)
+    /// show a plain underline instead.
+    pub fn underline_style(mut self, style: UnderlineStyle) -> Self {
+        self.underline = true;
+        self.underline_style = style;
+        self
+    }
+
+    /// Set the underline's color, it's ignored if the terminal doesn't support colored underlines
+    /// (it doesn't have the extended `Setulc` capability or `Su` flag).
+    pub fn underline_color<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        self.underline_color = Some(color.into());
+        self
+    }
+
+    /// Turn off all attributes and colors.
+    ///
+    /// When `keep_style` is set this also forgets every attribute set so far.
+    pub fn clear(mut self) -> Self {
+        if self.keep_style {
+            self.wipe_formatting();
+            self.active = Some(Style::default());
+        }
+        if !self.styled {
+            return self;
+        }
+        self.write_info_str(terminfo::ExitAttributeMode, ansi::ALL_OFF)
+    }
+
+    /// Keep attributes and colors between writes, instead of resetting them after each one.
+    ///
+    /// With `keep_style` set attributes build up until `clear` is called,
+    /// and they're only written again when they change, so printing in chunks doesn't repeat the same sequences.
+    /// Attributes are left on when the writer is done, call `clear` first to turn them off.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.writer()
+    ///     .keep_style(true)
+    ///     .bold()
+    ///     .print("all of ")
+    ///     .print("this is bold")
+    ///     .clear()
+    ///     .print(", but not this\n")
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn keep_style(mut self, keep: bool) -> Self {
+        self.keep_style = keep;
+        self
+    }
+
+    /// Print each segment in its own style, only writing the attributes and colors that change between segments.
+    ///
+    /// Each segment's style replaces the writer's, instead of being added to it.
+    /// Unless `keep_style` is set, attributes and colors are turned off after the last segment.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::{Style, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// let error = Style::new().bold().foreground("red");
+    /// term.writer()
+    ///     .print_segments(&[(error, "error"), (Style::new().bold(), ": "), (Style::new(), "file not found\n")])
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn print_segments<T: AsRef<str>>(mut self, segments: &[(Style, T)]) -> Self {
+        let keep_style = self.keep_style;
+        self.keep_style = true;
+        for (style, text) in segments {
+            self.wipe_formatting();
+            self = self.style(style).print(text);
+        }
+
+        self.keep_style = keep_style;
+        if !keep_style {
+            self.active = None;
+            self = self.clear();
+        }
+        self
+    }
+
+    /// Print `template`, replacing `{}` with `args` and applying inline style directives, see `termwrite!`.
+    ///
+    /// Text starts in the writer's current style, and unless `keep_style` is set attributes and colors are turned off
+    /// at the end. If the template is invalid nothing is printed, and the error is returned by `done`.
+    pub fn print_styled(mut self, template: &str, args: &[&dyn fmt::Display]) -> Self {
+        match styled_segments(self.current_style(), template, args) {
+            Ok(segments) => self.print_segments(&segments),
+            Err(e) => {
+                self.err = Some(e);
+                self
+            }
+        }
+    }
+
+    /// Add the style named `name` to the next write, see `Term::define_style`.
+    ///
+    /// If there's no style with that name nothing is added, and an error is returned by `done`.
+    pub fn named_style(mut self, name: &str) -> Self {
+        let style = self
+            .styles
+            .and_then(|styles| styles.borrow().get(name).cloned());
+        match style {
+            Some(style) => self.style(&style),
+            None => {
+                self.err = Some(ErrorKind::UnknownStyleName(name.to_owned()).into());
+                self
+            }
+        }
+    }
+
+    /// Add the attributes and colors in `style` to the next write.
+    ///
+    /// Attributes are added to any that are already set, and the style's colors replace the current colors.
+    pub fn style(mut self, style: &Style) -> Self {
+        self.bold |= style.bold;
+        self.blink |= style.blink;
+        self.underline |= style.underline;
+        self.italics |= style.italics;
+        self.standout |= style.standout;
+        self.invert |= style.invert;
+        self.invisible |= style.invisible;
+        self.dim |= style.dim;
+        self.strikethrough |= style.strikethrough;
+        if style.underline {
+            self.underline_style = style.underline_style;
+        }
+        if style.underline_color.is_some() {
+            self.underline_color = style.underline_color;
+        }
+
+        if let Some(color) = style.foreground {
+            self = self.foreground(color);
+        }
+        if let Some(color) = style.background {
+            self = self.background(color);
+        }
+        self
+    }
+
+    /// Write everything buffered so far to the terminal in one go, and flush it.
+    pub fn done(mut self) -> Result<usize> {
+        self.write_buffer()
+            .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+        match self.err.take() {
+            Some(v) => Err(v),
+            None => Ok(self.written),
+        }
+    }
+
+    /// Return the first error so far, instead of carrying on with a writer that ignores everything after it.
+    ///
+    /// Most methods record errors and turn the rest of the chain into no-ops, so they're only reported by `done`.
+    /// `check` (and the `try_` methods, which call it) report an error where it happens, so it can be handled with `?`.
+    /// Output is still buffered until the writer's done, use `try_flush` to write it and check for I/O errors as well.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// fn header(term: &Term<std::io::Stdin, std::io::Stdout>) -> nixterm::Result<usize> {
+    ///     let w = term.writer().try_named_style("header")?;
+    ///     let w = w.try_print("Results")?.check()?;
+    ///     w.try_flush()?.try_println("")?.done()
+    /// }
+    /// ```
+    pub fn check(mut self) -> Result<Self> {
+        match self.err.take() {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+
+    /// Write everything buffered so far to the terminal and flush it, then `check` for errors.
+    pub fn try_flush(mut self) -> Result<Self> {
+        self.write_buffer()
+            .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+        self.check()
+    }
+
+    /// Like `print`, but returns an error as soon as it happens, see `check`.
+    pub fn try_print<T: AsRef<str>>(self, s: T) -> Result<Self> {
+        self.print(s).check()
+    }
+
+    /// Like `println`, but returns an error as soon as it happens, see `check`.
+    pub fn try_println<T: AsRef<str>>(self, s: T) -> Result<Self> {
+        self.println(s).check()
+    }
+
+    /// Like `write_bytes`, but returns an error as soon as it happens, see `check`.
+    pub fn try_write_bytes(self, buf: &[u8]) -> Result<Self> {
+        self.write_bytes(buf).check()
+    }
+
+    /// Like `move_to`, but returns an error as soon as it happens, see `check`.
+    pub fn try_move_to(self, x: u16, y: u16) -> Result<Self> {
+        self.move_to(x, y).check()
+    }
+
+    /// Like `named_style`, but returns an error as soon as it happens, see `check`.
+    pub fn try_named_style(self, name: &str) -> Result<Self> {
+        self.named_style(name).check()
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let result = self.stdout.write_all(&self.buffer);
+            self.buffer.clear();
+            result?;
+        }
+        self.stdout.flush()
+    }
+
+    pub fn err(&self) -> &Option<Error> {
+        &self.err
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Set the terminal's foreground color.
+    ///
+    /// `T` a `ansi::Color` enum, a number (`u8`) or a string.
+    /// strings may name a color, or provide custom r, g, and b values.
+    /// Any of the following are valid:
+    /// - "black"
+    /// - "red"
+    /// - "green"
+    /// - "yellow"
+    /// - "blue"
+    /// - "magenta"
+    /// - "cyan"
+    /// - "grey"
+    /// - "white" (same as "bright grey")
+    /// - "bright*" (`*` may be any of the other named colors)
+    /// - "#rrggbb"
+    /// - "#rgb"
+    /// - "rgb(r, g, b)"
+    ///
+    /// Numbers are also valid, the number must fit inside a `u8` (so it should be in the range 0-255).
+    ///
+    /// # Compatibility
+    /// Everything layed out above will work as far as this library, but the chances of it actually being supported across
+    /// any meaningful number of terminals is closed to 0.
+    ///
+    /// ## 24-bit Colors
+    /// Full RGB (that is, 24-bit color) terminals are pretty rare. When full 24-bit colors are not supported,
+    /// the color will get as close as it can with whatever the terminal provides.
+    ///
+    /// ## 8-bit Colors
+    /// 256 color terminals are fairly common, using depending on indices beyond 15 is still risky though.
+    ///
+    /// ## 4/3-bit Colors
+    /// Basically all terminals will support 3-bits, many will support 4 (that 4th bit gives the option of a "bright" variant).
+    pub fn foreground<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        if self.err().is_some() {
+            return self;
+        }
+
+        let color = color.into();
+        if self.is_monochrome() {
+            let attr = self.monochrome.foreground_attribute(color);
+            self.set_attribute(attr);
+        } else {
+            self.foreground = Some(self.color_mapper.map(color, self.info));
+        }
+        self
+    }
+
+    /// Set the terminal's background color.
+    ///
+    /// See `TermWriter::foreground` for the accepted color formats.
+    pub fn background<T: Into<ansi::Color>>(mut self, color: T) -> Self {
+        if self.err().is_some() {
+            return self;
+        }
+
+        let color = color.into();
+        if self.is_monochrome() {
+            let attr = self.monochrome.background_attribute(color);
+            self.set_attribute(attr);
+        } else {
+            self.background = Some(self.color_mapper.map(color, self.info));
+        }
+        self
+    }
+
+    /// Map colors with `mapper` instead of the terminal's color mapper.
+    pub fn color_mapper(mut self, mapper: &'a dyn ColorMapper) -> Self {
+        self.color_mapper = mapper;
+        self
+    }
+
+    /// Returns true if the terminal supports fewer than 8 colors, in which case colors are replaced by attributes.
+    fn is_monochrome(&self) -> bool {
+        self.info.number(terminfo::MaxColors).unwrap_or(2) < 8
+    }
+
+    fn set_attribute(&mut self, attr: Attribute) {
+        match attr {
+            Attribute::None => (),
+            Attribute::Bold => self.bold = true,
+            Attribute::Dim => self.dim = true,
+            Attribute::Underline => self.underline = true,
+            Attribute::Blink => self.blink = true,
+            Attribute::Standout => self.standout = true,
+            Attribute::Invert => self.invert = true,
+        }
+    }
+
+    /// Switch from the `active` style to `style`, only writing the colors that changed if the attributes are the same.
+    /// Attributes can't be turned off one at a time, so if they change everything is written again.
+    fn write_style_change(&mut self, active: &Style, style: &Style) -> Result<()> {
+        let attributes = |s: &Style| {
+            (
+                (s.bold, s.blink, s.underline, s.standout, s.invert, s.invisible, s.dim),
+                (s.strikethrough, s.underline_style, s.underline_color),
+            )
+        };
+        let colors_kept = (active.foreground.is_none() || style.foreground.is_some())
+            && (active.background.is_none() || style.background.is_some());
+        if attributes(active) != attributes(style) || !colors_kept {
+            return self.write_style();
+        }
+
+        if style.background != active.background {
+            self.write_color(
+                style.background,
+                b"48;2;5",
+                terminfo::SetABackground,
+                terminfo::SetBackground,
+            )?;
+        }
+        if style.foreground != active.foreground {
+            self.write_color(
+                style.foreground,
+                b"38;2;5",
+                terminfo::SetAForeground,
+                terminfo::SetForeground,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write every attribute and color, replacing whatever was set before.
+    fn write_style(&mut self) -> Result<()> {
+        self.set_sgr();
+        self.write_fg_bg()?;
+        self.write_extra_attributes()
+    }
+
+    /// Write the attributes `sgr` doesn't cover: strikethrough, the underline's style and its color.
+    ///
+    /// Strikethrough needs the extended `smxx` capability. Underline styles and colors use the extended `Smulx`
+    /// and `Setulc` capabilities, or the sequences kitty introduced if the terminal has the `Su` flag.
+    /// Otherwise they're left out, so styled underlines fall back to a plain underline.
+    fn write_extra_attributes(&mut self) -> Result<()> {
+        if self.err().is_some() {
+            return Ok(());
+        }
+
+        let su = self.info.ext_boolean("Su");
+        let mut seqs: Vec<(&str, Option<usize>)> = Vec::new();
+        if self.strikethrough {
+            if let Some(smxx) = self.info.ext_string("smxx") {
+                seqs.push((smxx, None));
+            }
+        }
+        if self.underline && self.underline_style != UnderlineStyle::Single {
+            let param = self.underline_style.param() as usize;
+            match self.info.ext_string("Smulx") {
+                Some(smulx) => seqs.push((smulx, Some(param))),
+                None if su => seqs.push(("\x1b[4:%p1%dm", Some(param))),
+                None => (),
+            }
+        }
+        if let Some(color) = self.underline_color {
+            let (r, g, b) = match color {
+                ansi::Color::Index(x) => palette_rgb(x, 256),
+                ansi::Color::Rgb(r, g, b) => (r, g, b),
+            };
+            let rgb = (r as usize) << 16 | (g as usize) << 8 | b as usize;
+            match (self.info.ext_string("Setulc"), color) {
+                (Some(setulc), _) => seqs.push((setulc, Some(rgb))),
+                (None, ansi::Color::Index(x)) if su => seqs.push(("\x1b[58:5:%p1%dm", Some(x as usize))),
+                (None, _) if su => seqs.push((
+                    "\x1b[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%dm",
+                    Some(rgb),
+                )),
+                (None, _) => (),
+            }
+        }
+
+        for (seq, param) in seqs {
+            let mut exe = terminfo::lang::Executor::new(seq.as_bytes());
+            if let Some(param) = param {
+                exe = exe.arg(param);
+            }
+            self.written += exe
+                .write(&mut self.buffer)
+                .context(ErrorKind::WriteFailed)?;
+        }
+        Ok(())
+    }
+
+    fn write_fg_bg(&mut self) -> Result<()> {
+        if self.err().is_some() {
+            return Ok(());
+        }
+        let bg = self.background.clone();
+        let fg = self.foreground.clone();
+
+        self.write_color(
+            bg,
+            b"48;2;5",
+            terminfo::SetABackground,
+            terminfo::SetBackground,
+        )?;
+        self.write_color(
+            fg,
+            b"38;2;5",
+            terminfo::SetAForeground,
+            terminfo::SetForeground,
+        )
+    }
+
+    fn write_color(
+        &mut self,
+        color: Option<ansi::Color>,
+        rgb_prefix: &[u8],
+        seta: terminfo::StringField,
+        set: terminfo::StringField,
+    ) -> Result<()> {
+        match color {
+            Some(ansi::Color::Index(x)) => {
+                if let Some(program) = self.info.program(seta) {
+                    self.written += program
+                        .exec(&[(x as usize).into()], &mut self.buffer)
+                        .context(ErrorKind::FailedToRunTerminfo(seta))?;
+                    return Ok(());
+                }
+
+                self.written += match self.exec(seta) {
+                    Ok(e) => e
+                        .arg(x as usize)
+                        .write(&mut self.buffer)
+                        .context(ErrorKind::FailedToRunTerminfo(set))
+                        .map_err(|e| e.into()),
+                    Err(_) => self.exec(set).map(|exe| {
+                        exe.arg(seta_to_set_pallet(x) as usize)
+                            .write(&mut self.buffer)
+                            .unwrap_or_else(|e| {
+                                self.err =
+                                    Some(e.context(ErrorKind::FailedToRunTerminfo(set)).into());
+                                0
+                            })
+                    }),
+                }.context(ErrorKind::WriteFailed)?
+            }
+            Some(ansi::Color::Rgb(r, g, b)) => {
+                use std::io::Write;
+
+                self.written += self.write(rgb_prefix).context(ErrorKind::WriteFailed)?
+                    + util::write_u8_ansi(self, r).context(ErrorKind::WriteFailed)?
+                    + self.write(b";").context(ErrorKind::WriteFailed)?
+                    + util::write_u8_ansi(self, g).context(ErrorKind::WriteFailed)?
+                    + self.write(b";").context(ErrorKind::WriteFailed)?
+                    + util::write_u8_ansi(self, b).context(ErrorKind::WriteFailed)?
+                    + self.write(b"m").context(ErrorKind::WriteFailed)?;
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    pub fn shift_cursor(mut self, x: isize, y: isize) -> Self {
+        if self.err.is_some() || !self.tty {
+            return self;
+        }
+
+        if (x > 0) {
+            self.exec(terminfo::ParmRightCursor)
+                .map(|ctx| ctx.arg(x).write(&mut self))
+                .map_err(|e| self.err = Some(e));
+        } else if (x < 0) {
+            self.exec(terminfo::ParmLeftCursor)
+                .map(|ctx| ctx.arg(-x).write(&mut self))
+                .map_err(|e| self.err = Some(e));
+        }
+
+        if self.err.is_some() {
+            return self;
+        }
+
+        if (y > 0) {
+            self.exec(terminfo::ParmUpCursor)
+                .map(|ctx| ctx.arg(y).write(&mut self))
+                .map_err(|e| self.err = Some(e));
+        } else if (y < 0) {
+            self.exec(terminfo::ParmDownCursor)
+                .map(|ctx| ctx.arg(-y).write(&mut self))
+                .map_err(|e| self.err = Some(e));
+        }
+
+        return self;
+    }
+
+    pub fn default_background(mut self) -> Self {
+        if !self.styled {
+            return self;
+        }
+        self.write_bytes(ansi::RESET_BACKGROUND)
+    }
+
+    pub fn default_foreground(mut self) -> Self {
+        if !self.styled {
+            return self;
+        }
+        self.write_bytes(ansi::RESET_FOREGROUND)
+    }
+}
+
+impl<'a, O> io::Write for TermWriter<'a, O>
+where
+    O: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(_) = self.err() {
+            return Ok(0);
+        }
+
+        self.set_sgr();
+
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()
+    }
+}
+
+impl<'a, O> Drop for TermWriter<'a, O>
+where
+    O: io::Write,
+{
+    fn drop(&mut self) {
+        let _ = self.write_buffer();
+    }
+}
+
+/// Term represents the user's terminal.
+/// It has two channels, `I` (input), and `O` (output).
+/// Each terminal is accompanied by a "terminfo" file, (represented by the `TermInfoBuf` struct).
+impl<I, O> Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    pub fn from_streams(tib: terminfo::TermInfoBuf, stdin: I, stdout: O) -> Term<I, O> {
+        let cooked = termios::tcgetattr(stdin.as_raw_fd())
+            .ok()
+            .map(|termios| Settings { termios });
+        Term {
+            info: tib,
+            monochrome: MonochromeMap::default(),
+            color_mapper: Box::new(NearestColor),
+            keymap: events::KeyMap::new(),
+            stdin_fd: Cell::new(stdin.as_raw_fd()),
+            stdin: Mutex::new(BufReader::new(stdin)),
+            stdout: Arc::new(Mutex::new(stdout)),
+            cooked: RefCell::new(cooked),
+            err: RefCell::new(None),
+            reconnect: RefCell::new(None),
+            event_hooks: RefCell::new(Vec::new()),
+            input_debugger: RefCell::new(None),
+            wake_pipe: RefCell::new(None),
+            cursor_style: Cell::new(CursorStyle::Default),
+            bracketed_paste: Cell::new(false),
+            mouse_mode: Cell::new(MouseMode::Off),
+            focus_reporting: Cell::new(false),
+            keypad: Cell::new(false),
+            meta_mode: Cell::new(false),
+            input_encoding: Cell::new(events::Encoding::from_locale()),
+            escape_timeout: Cell::new(Some(events::DEFAULT_ESCAPE_TIMEOUT)),
+            bell_style: Cell::new(BellStyle::Audible),
+            color_choice: Cell::new(ColorChoice::Auto),
+            assume_tty: Cell::new(false),
+            styles: RefCell::new(HashMap::new()),
+            sync_update: Cell::new(None),
+            editor: RefCell::new(editor::Editor::new()),
+        }
+    }
+
+    /// Write to the terminal's stdout, it returns the number of bytes written.
+    /// `write` does not need a mutable reference to `self`, meaning it can be used while self is being borrowed,
+    /// however `write` blocks if it's being called from another thread.
+    ///
+    /// # Examples
+    /// ```
+    /// use nixterm::term::Term;
+    ///
+    /// pub fn main() {
+    ///     let term = Term::new().unwrap();
+    ///     term.writer()
+    ///         .foreground("rgb(64, 64, 128)")
+    ///         .write(b" Look look look! I'm kinda blue now!\n")
+    ///         .flush()
+    ///         .err()
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn writer<'a>(&'a self) -> TermWriter<'a, O> {
+        let columns = self.size().ok().map(|(columns, _)| columns as usize);
+        let styled = self.colors_enabled();
+        let tty = self.writes_to_tty();
+        let mut w = TermWriter::new(
+            &self.info,
+            &self.monochrome,
+            self.stdout.lock().unwrap(),
+            columns,
+        );
+        w.styles = Some(&self.styles);
+        w.color_mapper = &*self.color_mapper;
+        w.styled = styled;
+        w.tty = tty;
+        w
+    }
+
+    /// Get a handle that other threads can use to interrupt a blocked `Term::read_events`, so it returns `Event::Wake`.
+    ///
+    /// Wakes that happen before the event is read are combined into one.
+    /// `Keys::next_event` also returns `Event::Wake`, reading keys with `Term::read_keys` ignores it.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::Event;
+    /// use nixterm::term::Term;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let waker = term.waker().unwrap();
+    /// thread::spawn(move || loop {
+    ///     thread::sleep(Duration::from_secs(1));
+    ///     waker.wake().unwrap();
+    /// });
+    ///
+    /// for event in term.read_events().unwrap() {
+    ///     if let Event::Wake = event.unwrap() {
+    ///         term.print("tick\n").unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn waker(&self) -> Result<Waker> {
+        let mut pipe = self.wake_pipe.borrow_mut();
+        if pipe.is_none() {
+            let flags = nix::fcntl::OFlag::O_NONBLOCK | nix::fcntl::OFlag::O_CLOEXEC;
+            let (read, write) = nix::unistd::pipe2(flags).context(ErrorKind::FailedToWake)?;
+            *pipe = Some(Arc::new(WakePipe { read, write }));
+        }
+        Ok(Waker {
+            pipe: pipe.as_ref().unwrap().clone(),
+        })
+    }
+
+    /// Check if a `Waker` was used since this was last called.
+    pub(crate) fn take_wake(&self) -> bool {
+        let pipe = match *self.wake_pipe.borrow() {
+            Some(ref pipe) => pipe.read,
+            None => return false,
+        };
+        let mut woken = false;
+        let mut buf = [0u8; 64];
+        while let Ok(n) = nix::unistd::read(pipe, &mut buf) {
+            if n == 0 {
+                break;
+            }
+            woken = true;
+        }
+        woken
+    }
+
+    /// Change how colors are reduced to the ones the terminal supports, the default is `NearestColor`.
+    pub fn set_color_mapper<M: ColorMapper + 'static>(&mut self, mapper: M) {
+        self.color_mapper = Box::new(mapper);
+    }
+
+    /// Decode the escape sequences in `keymap` as keys, in addition to the ones terminfo lists.
+    pub fn set_keymap(&mut self, keymap: events::KeyMap) {
+        self.keymap = keymap;
+    }
+
+    /// The extra keys set with `Term::set_keymap`.
+    pub fn keymap(&self) -> &events::KeyMap {
+        &self.keymap
+    }
+
+    /// Make a decoder for this terminal's input, with the keys from terminfo and `Term::set_keymap`,
+    /// the input encoding, and in meta mode if it's on.
+    pub fn decoder(&self) -> events::Decoder {
+        events::Decoder::new(&self.info, &self.keymap)
+            .meta_mode(self.meta_mode_enabled())
+            .encoding(self.input_encoding.get())
+    }
+
+    /// Change how input that isn't ASCII is decoded, the default is the locale's encoding, see `Encoding::from_locale`.
+    ///
+    /// This only affects `Keys` made afterwards.
+    pub fn set_input_encoding(&self, encoding: events::Encoding) {
+        self.input_encoding.set(encoding);
+    }
+
+    /// How input that isn't ASCII is decoded.
+    pub fn input_encoding(&self) -> events::Encoding {
+        self.input_encoding.get()
+    }
+
+    /// Render a shell prompt using this terminal's capabilities.
+    ///
+    /// `f` is given a writer, which has the same interface as `Term::writer`, but instead of writing to the terminal
+    /// all output is collected, and every escape sequence is marked as zero-width for `shell`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::prompt::Shell;
+    /// use nixterm::term::Term;
+    ///
+    /// pub fn main() {
+    ///     let term = Term::new().unwrap();
+    ///     let ps1 = term
+    ///         .render_prompt(Shell::Bash, |w| w.foreground("green").bold().print("$ ").done())
+    ///         .unwrap();
+    ///     println!("PS1='{}'", ps1);
+    /// }
+    /// ```
+    pub fn render_prompt<F>(&self, shell: prompt::Shell, f: F) -> Result<String>
+    where
+        F: FnOnce(TermWriter<prompt::PromptWriter<Vec<u8>>>) -> Result<usize>,
+    {
+        let out = Mutex::new(prompt::PromptWriter::new(Vec::new(), shell));
+        let mut w = TermWriter::new(&self.info, &self.monochrome, out.lock().unwrap(), None);
+        w.styles = Some(&self.styles);
+        w.color_mapper = &*self.color_mapper;
+        w.styled = self.colors_enabled();
+        f(w)?;
+
+        let buf = out
+            .into_inner()
+            .unwrap()
+            .into_inner()
+            .context(ErrorKind::WriteFailed)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Print `template` with inline style directives, see `termwrite!`.
+    pub fn print_styled(&self, template: &str, args: &[&dyn fmt::Display]) -> Result<usize> {
+        self.writer().print_styled(template, args).done()
+    }
+
+    /// Print each segment in its own style, see `TermWriter::print_segments`.
+    pub fn print_segments<T: AsRef<str>>(&self, segments: &[(Style, T)]) -> Result<usize> {
+        self.writer().print_segments(segments).done()
+    }
+
+    pub fn print<T: AsRef<str>>(&self, s: T) -> Result<usize> {
+        self.writer().print(s).done()
+    }
+
+    pub fn println<T: AsRef<str>>(&self, s: T) -> Result<usize> {
+        self.writer().println(s).done()
+    }
+
+    /// Read from the terminal's standard input. Read into a fixed length buffer and return the number of characters read.
+    /// Similar to `Term::write`, `read` does not need `Term` to be mutable, however only one thread may be reading at a time.
+    ///
+    /// # Examples
+    /// ```
+    /// use nixterm::term::Term;
+    ///
+    /// pub fn main() {
+    ///     let term = Term::new().unwrap();
+    ///     let mut buffer : [u8; 12] = [0; 12];
+    ///     
+    ///     // There's nothing to read! so read does nothing and returns 0.
+    ///     assert_eq!(term.read(&mut buffer), 0);
+    ///     assert_eq!(buffer, [0; 12]);
+    /// }
+    /// ```
+    ///
+    /// Transient errors are retried, if the terminal has disappeared `Term::reconnect` is tried once before giving up.
+    pub fn read(&self, buffer: &mut [u8]) -> usize {
+        if self.has_err() {
+            return 0;
+        }
+
+        let result = match self.read_retry(buffer) {
+            Err(ref e) if util::is_terminal_gone(e) && self.reconnect().is_ok() => {
+                self.read_retry(buffer)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(v) => v,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+            Err(e) => {
+                self.set_err(util::io_error(e, ErrorKind::ReadFailed));
+                0
+            }
+        }
+    }
+
+    fn read_retry(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        util::retry(|| self.stdin.lock().unwrap().read(buffer))
+    }
+
+    /// Read a line from the terminal, the line ends with a newline unless the input ended.
+    ///
+    /// If the input is a terminal the line can be edited, see `editor::Editor` for the keys it understands.
+    /// Otherwise the line is read as-is.
+    /// Lines read from a terminal are added to the editor's history, which can be replaced with `Term::set_history`.
+    pub fn readline(&self) -> Result<String> {
+        if nix::unistd::isatty(self.as_raw_fd()).unwrap_or(false) {
+            return self.editor.borrow_mut().read_line(self);
+        }
+
+        let mut buf = String::new();
+        self.stdin
+            .lock()
+            .unwrap()
+            .read_line(&mut buf)
+            .map_err(|e| util::io_error(e, ErrorKind::ReadFailed))?;
+        Ok(buf)
+    }
+
+    /// Replace the history used by `readline`.
+    pub fn set_history(&self, history: editor::History) {
+        *self.editor.borrow_mut().get_history_mut() = history;
+    }
+
+    /// Complete words in `readline` when Tab is pressed, see `editor::Editor::completer`.
+    pub fn set_completer<F>(&self, f: F)
+    where
+        F: FnMut(&str, usize) -> Vec<String> + 'static,
+    {
+        self.editor.borrow_mut().set_completer(f);
+    }
+
+    /// Print `prompt` and read a line without echoing it, e.g. for passwords.
+    ///
+    /// If `mask` is set it's printed for each character typed. Backspace deletes the last character, and Ctrl-U the whole line.
+    /// Ctrl-C returns an `ErrorKind::Cancelled` error. The terminal's settings are always restored before returning.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let password = term.prompt_secret("Password: ", Some('*')).unwrap();
+    /// ```
+    pub fn prompt_secret<T: AsRef<str>>(&self, prompt: T, mask: Option<char>) -> Result<String> {
+        self.writer().print(prompt).done()?;
+
+        let raw = self.raw_mode()?;
+        let secret = self.read_secret(mask);
+        let restored = raw.restore();
+
+        self.println("")?;
+        let secret = secret?;
+        restored?;
+        Ok(secret)
+    }
+
+    fn read_secret(&self, mask: Option<char>) -> Result<String> {
+        let mut buf = Vec::new();
+        let mut typed = 0;
+
+        // erase `n` characters before the cursor
+        let erase = |n: usize| -> Result<usize> {
+            match mask {
+                Some(_) => self.print("\x08 \x08".repeat(n)),
+                None => Ok(0),
+            }
+        };
+
+        for key in self.read_keys() {
+            match key? {
+                Key::Enter | Key::Control('J') | Key::Control('M') => break,
+                Key::Control('C') => return Err(ErrorKind::Cancelled.into()),
+                Key::Control('D') if buf.is_empty() => break,
+                Key::Backspace | Key::Delete | Key::Control('H') => {
+                    // remove the whole UTF-8 sequence
+                    while let Some(b) = buf.pop() {
+                        if b & 0xc0 != 0x80 {
+                            typed -= 1;
+                            erase(1)?;
+                            break;
+                        }
+                    }
+                }
+                Key::Control('U') => {
+                    buf.clear();
+                    erase(typed)?;
+                    typed = 0;
+                }
+                Key::Char(c) => {
+                    buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    typed += 1;
+                    if let Some(m) = mask {
+                        self.print(m.to_string())?;
+                    }
+                }
+                // bytes of a multi-byte character
+                Key::Invalid(b) => {
+                    buf.push(b);
+                    if b & 0xc0 != 0x80 {
+                        typed += 1;
+                        if let Some(m) = mask {
+                            self.print(m.to_string())?;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Set a function to reopen the terminal if it disappears.
+    ///
+    /// `f` should return new input and output streams, for example by reopening `/dev/tty`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    /// use nixterm::terminfo;
+    /// use std::fs::{File, OpenOptions};
+    ///
+    /// pub fn main() {
+    ///     let open = || {
+    ///         let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    ///         Ok((tty.try_clone()?, tty))
+    ///     };
+    ///
+    ///     let (stdin, stdout): (File, File) = open().unwrap();
+    ///     let term = Term::from_streams(terminfo::from_env().unwrap(), stdin, stdout);
+    ///     term.on_reconnect(open);
+    /// }
+    /// ```
+    pub fn on_reconnect<F>(&self, f: F)
+    where
+        F: FnMut() -> io::Result<(I, O)> + 'static,
+    {
+        self.reconnect.replace(Some(Box::new(f)));
+    }
+
+    /// Pass every event read with `Term::read_events` through `f` first.
+    ///
+    /// `f` can return the event unchanged, a different event, or `None` to drop it.
+    /// Hooks run in the order they were added, each one is given what the one before it returned.
+    /// Events put back with `Events::push_back` don't go through the hooks again.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::{Event, Key};
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// // Ctrl-Q quits anywhere in the program
+    /// term.add_event_hook(|event| match event {
+    ///     Event::Key(Key::Control('Q')) => std::process::exit(0),
+    ///     event => Some(event),
+    /// });
+    /// ```
+    pub fn add_event_hook<F>(&self, f: F)
+    where
+        F: FnMut(events::Event) -> Option<events::Event> + 'static,
+    {
+        self.event_hooks.borrow_mut().push(Box::new(f));
+    }
+
+    /// Remove the hooks added with `Term::add_event_hook`.
+    pub fn clear_event_hooks(&self) {
+        self.event_hooks.borrow_mut().clear();
+    }
+
+    /// Pass `event` through the hooks added with `Term::add_event_hook`, returns `None` if one of them dropped it.
+    ///
+    /// This is done for `Term::read_events`, it's only needed for events decoded some other way.
+    pub fn filter_event(&self, event: events::Event) -> Option<events::Event> {
+        self.event_hooks
+            .borrow_mut()
+            .iter_mut()
+            .try_fold(event, |event, hook| hook(event))
+    }
+
+    /// Call `f` with the exact bytes of each key or event read from the terminal, and what they were decoded as.
+    ///
+    /// This is for finding out why a key isn't recognized, it's given the event before any `Term::add_event_hook`
+    /// hooks. Bytes that couldn't be decoded are given along with the next key.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    /// use std::env;
+    /// use std::fs::File;
+    /// use std::io::Write;
+    ///
+    /// let term = Term::new().unwrap();
+    /// if let Some(path) = env::var_os("MYAPP_DEBUG_INPUT") {
+    ///     let mut log = File::create(path).unwrap();
+    ///     term.debug_input(move |bytes, event| {
+    ///         let _ = writeln!(log, "{:?} -> {:?}", String::from_utf8_lossy(bytes), event);
+    ///     });
+    /// }
+    /// ```
+    pub fn debug_input<F>(&self, f: F)
+    where
+        F: FnMut(&[u8], &events::Event) + 'static,
+    {
+        self.input_debugger.replace(Some(Box::new(f)));
+    }
+
+    /// Stop passing input to the function given to `Term::debug_input`.
+    pub fn stop_debugging_input(&self) {
+        self.input_debugger.replace(None);
+    }
+
+    pub(crate) fn report_input(&self, bytes: &[u8], event: &events::Event) {
+        if let Some(ref mut f) = *self.input_debugger.borrow_mut() {
+            f(bytes, event);
+        }
+    }
+
+    /// Reopen the terminal using the function given to `Term::on_reconnect`, and clear any pending error.
+    ///
+    /// If no function was set, or it fails, then an `ErrorKind::TerminalGone` error is returned.
+    /// This method blocks while a `TermWriter` is alive.
+    pub fn reconnect(&self) -> Result<()> {
+        let (stdin, stdout) = match self.reconnect.borrow_mut().as_mut() {
+            Some(f) => f().context(ErrorKind::TerminalGone)?,
+            None => return Err(ErrorKind::TerminalGone.into()),
+        };
+
+        self.stdin_fd.set(stdin.as_raw_fd());
+        *self.cooked.borrow_mut() = termios::tcgetattr(stdin.as_raw_fd())
+            .ok()
+            .map(|termios| Settings { termios });
+        *self.stdin.lock().unwrap() = BufReader::new(stdin);
+        *self.stdout.lock().unwrap() = stdout;
+        self.err.replace(None);
+        Ok(())
+    }
+
+    /// Check if the last error was caused by the terminal disappearing.
+    pub fn is_gone(&self) -> bool {
+        match *self.err.borrow() {
+            Some(ref e) => e.kind() == &ErrorKind::TerminalGone,
+            None => false,
+        }
+    }
+
+    pub(crate) fn set_err<T: Into<Error>>(&self, e: T) {
+        self.err.replace(Some(e.into()));
+    }
+
+    pub(crate) fn take_err(&self) -> Error {
+        self.err.replace(None).unwrap()
+    }
+
+    pub(crate) fn has_err(&self) -> bool {
+        self.err.borrow().is_some()
+    }
+
+    pub fn err(&self) -> Result<()> {
+        if self.has_err() {
+            Err(self.take_err())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Execute a string field
+    fn exec<'a>(&'a self, field: terminfo::StringField) -> Result<terminfo::lang::Executor<'a>> {
+        match self.info.exec(field) {
+            Some(v) => Ok(v),
+            None => Err(ErrorKind::MissingTermInfoField(field).into()),
+        }
+    }
+
+    /// Wrapper around exec, which immediately runs the string with no args and writes it to `O`.
+    fn write_info_str(&self, field: terminfo::StringField) -> usize {
+        match self.exec(field) {
+            Ok(mut v) => v
+                .write(self.stdout.lock().unwrap().deref_mut())
+                .context(ErrorKind::FailedToRunTerminfo(field))
+                .unwrap_or_else(|e| {
+                    self.set_err(e);
+                    0
+                }),
+            Err(e) => {
+                self.err.replace(Some(
+                    e.context(ErrorKind::FailedToRunTerminfo(field)).into(),
+                ));
+                0
+            }
+        }
+    }
+
+    /// Write a capability, or `fallback` if the terminal doesn't have it.
+    fn write_info_or(&self, field: terminfo::StringField, fallback: &[u8]) -> usize {
+        match self.info.string(field) {
+            Some(_) => self.write_info_str(field),
+            None if self.is_dumb() => 0,
+            None => self.write_raw(fallback),
+        }
+    }
+
+    /// Write a sequence that doesn't have a capability as-is.
+    fn write_raw(&self, seq: &[u8]) -> usize {
+        match self.stdout.lock().unwrap().write_all(seq) {
+            Ok(()) => seq.len(),
+            Err(e) => {
+                self.set_err(util::io_error(e, ErrorKind::WriteFailed));
+                0
+            }
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        Settings {
+            termios: match termios::tcgetattr(self.as_raw_fd()) {
+                Ok(v) => v,
+                Err(e) => {
+                    // This should be caught on the next `update`;
+                    self.set_err(e.context(ErrorKind::FailedToSetTermios));
+                    unsafe { termios::Termios::default_uninit() }
+                }
+            },
+        }
+    }
+
+    /// Put the terminal in raw mode (see `Settings::raw`) until the returned guard is dropped,
+    /// then restore the settings it had before, even if the program panics.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// {
+    ///     let raw = term.raw_mode().unwrap();
+    ///     let key = raw.read_keys().next();
+    ///     raw.print(format!("{:?}\r\n", key)).unwrap();
+    /// }
+    /// // back in cooked mode
+    /// ```
+    pub fn raw_mode<'a>(&'a self) -> Result<RawMode<'a, I, O>> {
+        let saved = self.settings();
+        self.update(saved.clone().raw())?;
+        Ok(RawMode {
+            term: self,
+            saved: Some(saved),
+        })
+    }
+
+    /// Stop the terminal from echoing input until the returned guard is dropped,
+    /// then restore the settings it had before, even if the program panics.
+    ///
+    /// Unlike `Term::raw_mode` input is still read a line at a time.
+    /// Anything typed before echo was turned off is discarded, since it's already been shown.
+    pub fn no_echo<'a>(&'a self) -> Result<NoEcho<'a, I, O>> {
+        let saved = self.settings();
+        self.update(saved.clone().echo(false))?;
+        Ok(NoEcho {
+            term: self,
+            saved: Some(saved),
+        })
+    }
+
+    /// Run `f` with echo turned off, see `Term::no_echo`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.print("Token: ").unwrap();
+    /// let token = term.read_no_echo(|term| term.readline()).unwrap().unwrap();
+    /// ```
+    pub fn read_no_echo<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Self) -> T,
+    {
+        let no_echo = self.no_echo()?;
+        let value = f(self);
+        no_echo.restore()?;
+        Ok(value)
+    }
+
+    pub fn update(&self, settings: Settings) -> Result<()> {
+        self.err()?;
+
+        termios::tcsetattr(
+            self.as_raw_fd(),
+            termios::SetArg::TCSAFLUSH,
+            &settings.termios,
+        ).context(ErrorKind::FailedToSetTermios)?;
+        Ok(())
+    }
+
+    pub fn flush(&self) {
+        match self.stdout.lock().unwrap().flush() {
+            Ok(_) => (),
+            Err(e) => self.set_err(util::io_error(e, ErrorKind::WriteFailed)),
+        }
+    }
+
+    pub fn read_keys<'a>(&'a self) -> Keys<'a, I, O> {
+        Keys::new(self)
+    }
+
+    /// Read keys as editing commands, e.g. for an editor that draws its text differently than `Editor`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::editor::{Bindings, EditEvent};
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.update(term.settings().raw()).unwrap();
+    ///
+    /// let mut text = String::new();
+    /// for event in term.read_edit_events(Bindings::vi()) {
+    ///     match event.unwrap() {
+    ///         EditEvent::InsertChar(c) => text.push(c),
+    ///         EditEvent::DeleteBack => {
+    ///             text.pop();
+    ///         }
+    ///         EditEvent::Submit | EditEvent::Cancel => break,
+    ///         _ => (),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_edit_events(&self, bindings: editor::Bindings) -> editor::EditEvents<'_, I, O> {
+        editor::EditEvents::new(self.read_keys(), bindings)
+    }
+
+    /// Wait for a key to be pressed, returning `Ok(None)` if none is pressed within `timeout`.
+    ///
+    /// This is a shortcut for `term.read_keys().next_timeout(timeout)`. Each call decodes keys with a new `Keys`,
+    /// so the rest of an escape sequence that isn't recognized is lost between calls, keep a `Keys` around to avoid that.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::Key;
+    /// use nixterm::term::Term;
+    /// use std::time::Duration;
+    ///
+    /// let term = Term::new().unwrap();
+    /// loop {
+    ///     match term.read_key_timeout(Duration::from_millis(100)).unwrap() {
+    ///         Some(Key::Char('q')) => break,
+    ///         Some(_) => (),
+    ///         None => {
+    ///             // nothing was pressed, draw the next frame of an animation
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn read_key_timeout(&self, timeout: Duration) -> Result<Option<Key>> {
+        self.read_keys().next_timeout(timeout)
+    }
+
+    /// Set how long to wait for the rest of an escape sequence after an escape is read, before deciding it was the
+    /// escape key on its own. `None` waits until another byte arrives, so a lone escape isn't seen until the next key.
+    ///
+    /// The default is `events::DEFAULT_ESCAPE_TIMEOUT`, a slow connection may need longer.
+    pub fn set_escape_timeout(&self, timeout: Option<Duration>) {
+        self.escape_timeout.set(timeout);
+    }
+
+    pub fn escape_timeout(&self) -> Option<Duration> {
+        self.escape_timeout.get()
+    }
+
+    /// Wait until there's input to read, or `timeout` passes. Returns `false` if it timed out.
+    ///
+    /// If `timeout` is `None` this waits until there's input, without a timeout.
+    pub fn wait_for_input(&self, timeout: Option<Duration>) -> Result<bool> {
+        // input that's already been read from the terminal won't show up in poll
+        if !self.stdin.lock().unwrap().buffer().is_empty() {
+            return Ok(true);
+        }
+
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    // round up, so the timeout doesn't end early
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    let millis = left.as_micros().div_ceil(1000);
+                    millis.min(libc::c_int::MAX as u128) as libc::c_int
+                }
+                None => -1,
+            };
+
+            let mut fds = [poll::PollFd::new(self.as_raw_fd(), poll::EventFlags::POLLIN)];
+            match poll::poll(&mut fds, timeout) {
+                Ok(0) => return Ok(false),
+                Ok(_) => return Ok(true),
+                // interrupted by a signal, keep waiting until the deadline
+                Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => (),
+                Err(e) => return Err(e.context(ErrorKind::ReadFailed).into()),
+            }
+        }
+    }
+
+    /// Wait until there's input to read, a signal arrives, or `timeout` passes. Returns `true` if there's input.
+    ///
+    /// Signals are only noticed if a `SignalGuard` installed a handler for them.
+    pub(crate) fn wait_for_input_or_signal(&self, timeout: Option<Duration>) -> Result<bool> {
+        if !self.stdin.lock().unwrap().buffer().is_empty() {
+            return Ok(true);
+        }
+
+        let timeout = match timeout {
+            // round up, so the timeout doesn't end early
+            Some(left) => left.as_micros().div_ceil(1000).min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+        let pipe = signal_pipe()?;
+        let mut fds = vec![
+            poll::PollFd::new(self.as_raw_fd(), poll::EventFlags::POLLIN),
+            poll::PollFd::new(pipe, poll::EventFlags::POLLIN),
+        ];
+        // a wake up is left in its pipe, for `take_wake`
+        if let Some(ref wake) = *self.wake_pipe.borrow() {
+            fds.push(poll::PollFd::new(wake.read, poll::EventFlags::POLLIN));
+        }
+        match poll::poll(&mut fds, timeout) {
+            Ok(_) => (),
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => return Ok(false),
+            Err(e) => return Err(e.context(ErrorKind::ReadFailed).into()),
+        }
+
+        // empty the pipe, the signals themselves are checked with `take_signal`
+        let mut buf = [0u8; 64];
+        while let Ok(n) = nix::unistd::read(pipe, &mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+        Ok(fds[0]
+            .revents()
+            .is_some_and(|r| r.contains(poll::EventFlags::POLLIN)))
+    }
+
+    /// Read everything that happens on the terminal as `Event`s: keys, pastes, the mouse, focus changes,
+    /// resizes and signals.
+    ///
+    /// Signal handlers are installed until the `Events` is dropped, see `Event::Signal` for the signals it reports.
+    /// Pastes, the mouse and focus changes are only reported once they're turned on, with `Term::set_bracketed_paste`,
+    /// `Term::set_mouse_capture` and `Term::set_focus_reporting`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::events::{Event, Key};
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.update(term.settings().raw()).unwrap();
+    /// term.set_mouse_capture(true).unwrap();
+    ///
+    /// for event in term.read_events().unwrap() {
+    ///     match event.unwrap() {
+    ///         Event::Key(Key::Char('q')) => break,
+    ///         Event::Mouse(mouse) => println!("{:?} at {}, {}\r", mouse.action, mouse.x, mouse.y),
+    ///         Event::Resize(columns, rows) => println!("resized to {}x{}\r", columns, rows),
+    ///         event => println!("{:?}\r", event),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_events<'a>(&'a self) -> Result<Events<'a, I, O>> {
+        Events::new(self)
+    }
+
+    /// Give `style` a name, so it can be used with `TermWriter::named_style`.
+    ///
+    /// This keeps an application's colors in one place, instead of repeating them wherever they're used.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::{Style, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.define_style("error", Style::new().bold().foreground("red"));
+    /// term.writer()
+    ///     .named_style("error")
+    ///     .print("error:")
+    ///     .print(" file not found\n")
+    ///     .done()
+    ///     .unwrap();
+    /// ```
+    pub fn define_style<T: Into<String>>(&self, name: T, style: Style) {
+        self.styles.borrow_mut().insert(name.into(), style);
+    }
+
+    /// Get the style named `name`, see `Term::define_style`.
+    pub fn named_style(&self, name: &str) -> Option<Style> {
+        self.styles.borrow().get(name).cloned()
+    }
+
+    /// Define a style for each name in `theme`, the styles are parsed from strings like `"bold fg=red"`.
+    ///
+    /// If any style is invalid none of them are defined.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.load_theme(vec![("error", "bold fg=red"), ("warning", "fg=yellow"), ("path", "underline")])
+    ///     .unwrap();
+    /// ```
+    pub fn load_theme<T, K, V>(&self, theme: T) -> Result<()>
+    where
+        T: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: AsRef<str>,
+    {
+        let theme = theme
+            .into_iter()
+            .map(|(name, style)| Ok((name.into(), style.as_ref().parse()?)))
+            .collect::<Result<Vec<(String, Style)>>>()?;
+        self.styles.borrow_mut().extend(theme);
+        Ok(())
+    }
+
+    /// Ring the bell, or flash the screen if the bell style is `BellStyle::Visual`.
+    pub fn bell(&self) {
+        match self.bell_style.get() {
+            BellStyle::Audible => self.write_info_or(terminfo::Bell, b"\x07"),
+            BellStyle::Visual => return self.flash(),
+            BellStyle::Off => return,
+        };
+        self.flush();
+    }
+
+    /// Flash the screen (the `flash` capability), terminals that can't flash ring the bell instead.
+    pub fn flash(&self) {
+        match self.info.string(terminfo::FlashScreen) {
+            Some(_) => self.write_info_str(terminfo::FlashScreen),
+            None => self.write_info_or(terminfo::Bell, b"\x07"),
+        };
+        self.flush();
+    }
+
+    /// Choose what `Term::bell` does, e.g. to replace the audible bell with a visual one.
+    pub fn set_bell_style(&self, style: BellStyle) {
+        self.bell_style.set(style);
+    }
+
+    pub fn bell_style(&self) -> BellStyle {
+        self.bell_style.get()
+    }
+
+    /// Choose whether writers use colors and attributes, by default the user's environment decides.
+    /// When they're turned off writers only print text.
+    pub fn set_color_choice(&self, choice: ColorChoice) {
+        self.color_choice.set(choice);
+    }
+
+    /// Get the color choice set with `Term::set_color_choice`, use `Term::colors_enabled` to check what it decided.
+    pub fn color_choice(&self) -> ColorChoice {
+        self.color_choice.get()
+    }
+
+    /// Check if writers will use colors and attributes, see `ColorChoice`.
+    pub fn colors_enabled(&self) -> bool {
+        match self.color_choice.get() {
+            _ if self.is_dumb() => false,
+            choice => choice.enabled(self.assume_tty.get() || self.output_is_tty(), |var| env::var(var).ok()),
+        }
+    }
+
+    /// Check if both the input and output are terminals.
+    ///
+    /// If they aren't the terminal isn't queried. Writers only need the output to be a terminal:
+    /// if it isn't (e.g. it's piped to another program) they don't write colors, attributes or cursor movement.
+    pub fn is_tty(&self) -> bool {
+        nix::unistd::isatty(self.as_raw_fd()).unwrap_or(false) && self.output_is_tty()
+    }
+
+    /// Check if the output is a terminal.
+    fn output_is_tty(&self) -> bool {
+        let stdout_fd = self.stdout.lock().unwrap().as_raw_fd();
+        nix::unistd::isatty(stdout_fd).unwrap_or(false)
+    }
+
+    /// Check if writers should move the cursor, see `Term::set_assume_tty`.
+    pub(crate) fn writes_to_tty(&self) -> bool {
+        !self.is_dumb() && (self.assume_tty.get() || self.output_is_tty())
+    }
+
+    /// Check if this is a dumb terminal: one named `dumb`, or one that can't move the cursor (it has no `cup` capability).
+    ///
+    /// Dumb terminals only get plain text. Writers ignore colors, attributes and cursor movement,
+    /// `Term::prompt` reads whole lines without an editor, and full-screen APIs like `Term::run_loop` fail with
+    /// `ErrorKind::UnsupportedTerminal`.
+    pub fn is_dumb(&self) -> bool {
+        self.info.primary_name() == "dumb" || self.info.string(terminfo::CursorAddress).is_none()
+    }
+
+    /// Write colors, attributes and cursor movement even if the output isn't a terminal.
+    ///
+    /// The terminal still isn't queried, since there's nothing to answer.
+    pub fn set_assume_tty(&self, assume: bool) {
+        self.assume_tty.set(assume);
+    }
+
+    /// Clear the whole screen, and move the cursor to the top left corner.
+    pub fn clear_screen(&self) {
+        let fallback = ansi::Csi::cursor_position(1, 1).to_string() + &ansi::Csi::erase_display(2).to_string();
+        self.write_info_or(terminfo::ClearScreen, fallback.as_bytes());
+    }
+
+    /// Clear the screen from the cursor to the end.
+    pub fn clear_below(&self) {
+        self.write_info_or(terminfo::ClrEos, &ansi::Csi::erase_display(0).to_bytes());
+    }
+
+    /// Clear the screen from the start up to and including the cursor.
+    pub fn clear_above(&self) {
+        // there's no capability for this
+        self.write_raw(&ansi::Csi::erase_display(1).to_bytes());
+    }
+
+    /// Clear the cursor's line, the cursor doesn't move.
+    pub fn clear_line(&self) {
+        // there's no capability for this
+        self.write_raw(&ansi::Csi::erase_line(2).to_bytes());
+    }
+
+    /// Clear the cursor's line, from the start up to and including the cursor.
+    pub fn clear_line_before_cursor(&self) {
+        self.write_info_or(terminfo::ClrBol, &ansi::Csi::erase_line(1).to_bytes());
+    }
+
+    pub fn clear_line_after_cursor(&self) {
+        self.write_info_or(terminfo::ClrEol, &ansi::Csi::erase_line(0).to_bytes());
+    }
+
+    /// Switch to the alternate screen, which has no scrollback, so full-screen programs don't overwrite the shell's output.
+    pub fn enter_alternate_screen(&self) {
+        self.write_info_or(terminfo::EnterCaMode, &ansi::PrivateMode::AltScreen.set().to_bytes());
+    }
+
+    /// Switch back from the alternate screen, restoring what was on the screen before.
+    pub fn exit_alternate_screen(&self) {
+        self.write_info_or(terminfo::ExitCaMode, &ansi::PrivateMode::AltScreen.reset().to_bytes());
+    }
+
+    pub fn hide_cursor(&self) {
+        self.write_info_or(terminfo::CursorInvisible, &ansi::PrivateMode::CursorVisible.reset().to_bytes());
+    }
+
+    pub fn show_cursor(&self) {
+        self.write_info_or(terminfo::CursorNormal, &ansi::PrivateMode::CursorVisible.set().to_bytes());
+    }
+
+    pub fn save_cursor(&self) {
+        self.write_info_str(terminfo::SaveCursor);
+    }
+
+    pub fn restore_cursor(&self) {
+        self.write_info_str(terminfo::RestoreCursor);
+    }
+
+    /// Change the shape of the cursor.
+    ///
+    /// The extended `Ss` and `Se` capabilities are used if the terminal has them, otherwise the DECSCUSR sequence is written,
+    /// which most modern terminals understand. The cursor goes back to its previous shape when the returned guard is dropped.
+    ///
+    /// ```rust,no_run
+    /// use nixterm::term::{CursorStyle, Term};
+    ///
+    /// let term = Term::new().unwrap();
+    /// {
+    ///     let _bar = term.set_cursor_style(CursorStyle::SteadyBar).unwrap();
+    ///     // ...
+    /// }
+    /// // back to the terminal's default cursor
+    /// ```
+    pub fn set_cursor_style<'a>(&'a self, style: CursorStyle) -> Result<CursorStyleGuard<'a, I, O>> {
+        let saved = self.cursor_style.get();
+        self.write_cursor_style(style)?;
+        Ok(CursorStyleGuard {
+            term: self,
+            saved: Some(saved),
+        })
+    }
+
+    fn write_cursor_style(&self, style: CursorStyle) -> Result<()> {
+        self.err()?;
+
+        let mut stdout = self.stdout.lock().unwrap();
+        let reset = match style {
+            CursorStyle::Default => self.info.ext_string("Se"),
+            _ => None,
+        };
+        match (reset, self.info.ext_string("Ss")) {
+            (Some(se), _) => terminfo::lang::Executor::new(se.as_bytes())
+                .write(stdout.deref_mut())
+                .context(ErrorKind::FailedToSetCursorStyle)?,
+            (None, Some(ss)) => terminfo::lang::Executor::new(ss.as_bytes())
+                .arg(style as usize)
+                .write(stdout.deref_mut())
+                .context(ErrorKind::FailedToSetCursorStyle)?,
+            (None, None) => {
+                ansi::Csi::cursor_style(style as u32)
+                    .write(stdout.deref_mut())
+                    .context(ErrorKind::FailedToSetCursorStyle)?;
+                0
+            }
+        };
+        stdout
+            .flush()
+            .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+
+        self.cursor_style.set(style);
+        Ok(())
+    }
+
+    /// Turn bracketed paste mode on or off.
+    ///
+    /// While it's on the terminal marks the start and end of pasted text, which `Keys::next_event` and `Term::run_loop`
+    /// deliver as a single `Event::Paste`, instead of a key for each character.
+    /// The extended `BE` and `BD` capabilities are used if the terminal has them, otherwise xterm's mode 2004 is set.
+    /// Bracketed paste is turned off when the `Term` is dropped.
+    pub fn set_bracketed_paste(&self, enabled: bool) -> Result<()> {
+        let mode = ansi::PrivateMode::BracketedPaste;
+        let (field, fallback) = if enabled {
+            ("BE", mode.set())
+        } else {
+            ("BD", mode.reset())
+        };
+        let seq = match self.info.ext_string(field) {
+            Some(seq) => seq.to_owned(),
+            None => fallback.to_string(),
+        };
+        self.write_mode(&seq, ErrorKind::FailedToSetBracketedPaste)?;
+        self.bracketed_paste.set(enabled);
+        Ok(())
+    }
+
+    /// Turn mouse reporting on or off.
+    ///
+    /// While it's on, clicks, drags and the scroll wheel are reported as `Event::Mouse`, this is the same as
+    /// `Term::set_mouse_mode` with `MouseMode::Drag` or `MouseMode::Off`.
+    pub fn set_mouse_capture(&self, enabled: bool) -> Result<()> {
+        self.set_mouse_mode(if enabled {
+            MouseMode::Drag
+        } else {
+            MouseMode::Off
+        })
+    }
+
+    /// Choose which mouse events are reported as `Event::Mouse`.
+    ///
+    /// Reports use xterm's SGR encoding (mode 1006), which works past column 223, unlike the original encoding.
+    /// Most terminals stop handling text selection themselves while mouse reporting is on.
+    /// Mouse reporting is turned off when the `Term` is dropped.
+    pub fn set_mouse_mode(&self, mode: MouseMode) -> Result<()> {
+        let old = self.mouse_mode.get();
+        let mut seq = String::new();
+        if mode == MouseMode::Off && old != MouseMode::Off {
+            seq += &ansi::PrivateMode::SgrMouse.reset().to_string();
+        }
+        for m in old.modes().iter().rev().filter(|m| !mode.modes().contains(m)) {
+            seq += &m.reset().to_string();
+        }
+        for m in mode.modes().iter().filter(|m| !old.modes().contains(m)) {
+            seq += &m.set().to_string();
+        }
+        if old == MouseMode::Off && mode != MouseMode::Off {
+            seq += &ansi::PrivateMode::SgrMouse.set().to_string();
+        }
+
+        if !seq.is_empty() {
+            self.write_mode(&seq, ErrorKind::FailedToSetMouseCapture)?;
+        }
+        self.mouse_mode.set(mode);
+        Ok(())
+    }
+
+    /// Turn focus reporting on or off (mode 1004).
+    ///
+    /// While it's on, the terminal sends an `Event::Focus` whenever its window gains or loses focus.
+    /// Focus reporting is turned off when the `Term` is dropped.
+    pub fn set_focus_reporting(&self, enabled: bool) -> Result<()> {
+        let mode = ansi::PrivateMode::FocusReporting;
+        let seq = if enabled { mode.set() } else { mode.reset() };
+        self.write_mode(&seq.to_string(), ErrorKind::FailedToSetFocusReporting)?;
+        self.focus_reporting.set(enabled);
+        Ok(())
+    }
+
+    /// Turn the keypad's transmit mode (also called application mode) on or off, with terminfo's `smkx` and `rmkx`.
+    ///
+    /// The sequences the cursor and keypad keys send in transmit mode are the ones listed in terminfo,
+    /// outside of it many terminals send ANSI sequences instead. `Keys` decodes both, so this is only needed by
+    /// applications that care which mode the terminal is in. The keypad is put back in local mode when the `Term` is dropped.
+    pub fn keypad(&self, enabled: bool) -> Result<()> {
+        let field = if enabled {
+            terminfo::KeypadXmit
+        } else {
+            terminfo::KeypadLocal
+        };
+        if let Some(seq) = self.info.string(field) {
+            self.write_mode(seq, ErrorKind::FailedToSetKeypad)?;
+        }
+        self.keypad.set(enabled);
+        Ok(())
+    }
+
+    /// Put the keypad in transmit mode until the returned guard is dropped, then restore the mode it was in before.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let raw = term.raw_mode().unwrap();
+    /// {
+    ///     let _keypad = raw.keypad_transmit().unwrap();
+    ///     let key = raw.read_keys().next();
+    /// }
+    /// // back in local mode
+    /// ```
+    pub fn keypad_transmit<'a>(&'a self) -> Result<KeypadTransmit<'a, I, O>> {
+        let saved = self.keypad.get();
+        self.keypad(true)?;
+        Ok(KeypadTransmit {
+            term: self,
+            saved: Some(saved),
+        })
+    }
+
+    /// Turn meta mode on or off, with terminfo's `smm` and `rmm`.
+    ///
+    /// While it's on, keys pressed with Alt are sent with the 8th bit set, and `Keys` made afterwards decode bytes
+    /// with the 8th bit set as keys with `Modifiers::ALT`. While it's off most terminals send an escape before the key instead.
+    /// If the terminal doesn't have `smm` and `rmm` only the way input is decoded changes.
+    /// Meta mode is turned off when the `Term` is dropped.
+    pub fn meta_mode(&self, enabled: bool) -> Result<()> {
+        let field = if enabled {
+            terminfo::MetaOn
+        } else {
+            terminfo::MetaOff
+        };
+        if let Some(seq) = self.info.string(field) {
+            self.write_mode(seq, ErrorKind::FailedToSetMetaMode)?;
+        }
+        self.meta_mode.set(enabled);
+        Ok(())
+    }
+
+    /// Check if meta mode is on, see `Term::meta_mode`.
+    pub fn meta_mode_enabled(&self) -> bool {
+        self.meta_mode.get()
+    }
+
+    /// Ask the terminal what it is and what it supports, with the primary and secondary device attributes queries.
+    ///
+    /// terminfo entries are often out of date, or shared by many emulators, so this is more reliable for deciding
+    /// whether to use newer features.
+    /// `None` is returned if the input isn't a terminal, or it didn't answer.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// if let Some(attributes) = term.probe().unwrap() {
+    ///     println!("VT level {}, sixel: {}", attributes.vt_level, attributes.sixel());
+    /// }
+    /// ```
+    pub fn probe(&self) -> Result<Option<DeviceAttributes>> {
+        // the secondary query is sent first since not every terminal answers it,
+        // once the answer to the primary one arrives there's nothing left to wait for.
+        let mut query = ansi::Csi::new(b'c').private(b'>').to_bytes();
+        query.extend(ansi::Csi::new(b'c').to_bytes());
+        let reply = self.query(&query, QUERY_TIMEOUT, is_primary_attributes)?;
+        Ok(reply.and_then(|r| DeviceAttributes::parse(&r)))
+    }
+
+    /// Ask the terminal for its background color (OSC 11).
+    ///
+    /// `None` is returned if the input isn't a terminal, or it didn't answer.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// if let Some(color) = term.background_color().unwrap() {
+    ///     println!("the background is {:?}", color);
+    /// }
+    /// ```
+    pub fn background_color(&self) -> Result<Option<ansi::Color>> {
+        let reply = self.query(b"\x1b]11;?\x1b\\", QUERY_TIMEOUT, |seq| {
+            matches!(*seq, ansi::Sequence::Osc(_))
+        })?;
+        Ok(reply.and_then(|r| parse_color_reply(&r)))
+    }
+
+    /// Guess if the terminal has a dark background, using `Term::background_color`.
+    ///
+    /// `None` is returned if the background color isn't known.
+    pub fn is_dark_theme(&self) -> Result<Option<bool>> {
+        match self.background_color()? {
+            Some(ansi::Color::Rgb(r, g, b)) => {
+                // relative luminance, from ITU-R BT.709
+                let luminance =
+                    0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b);
+                Ok(Some(luminance < 128.0))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if the terminal supports synchronized output (mode 2026), see `Term::sync_update`.
+    ///
+    /// The extended `Sync` capability is used if the terminal has it, otherwise the terminal is asked with DECRQM.
+    /// The answer is remembered, so the terminal is only asked once.
+    pub fn supports_sync_update(&self) -> Result<bool> {
+        if let Some(supported) = self.sync_update.get() {
+            return Ok(supported);
+        }
+
+        let supported = self.info.ext_string("Sync").is_some() || {
+            // the device attributes query is answered by every terminal, so there's no need to wait for a timeout
+            // if the terminal ignores DECRQM.
+            let mut query = ansi::PrivateMode::SynchronizedOutput.request().to_bytes();
+            query.extend(ansi::Csi::new(b'c').to_bytes());
+            let reply = self.query(&query, QUERY_TIMEOUT, is_primary_attributes)?;
+            reply.unwrap_or_default().iter().any(|seq| match *seq {
+                ansi::Sequence::Csi {
+                    private: Some(b'?'),
+                    ref params,
+                    ref intermediates,
+                    final_byte: b'y',
+                } => intermediates[..] == b"$"[..] && (params[..] == [2026, 1] || params[..] == [2026, 2]),
+                _ => false,
+            })
+        };
+        self.sync_update.set(Some(supported));
+        Ok(supported)
+    }
+
+    /// Draw everything written by `f` at once, without the terminal showing a half-drawn screen.
+    ///
+    /// The output is wrapped in the begin and end synchronized update sequences if the terminal supports them,
+    /// see `Term::supports_sync_update`, otherwise `f` is just given a writer.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    ///
+    /// let term = Term::new().unwrap();
+    /// term.sync_update(|w| {
+    ///     w.move_to(0, 0)
+    ///         .print("a whole screen of text")
+    ///         .done()
+    /// }).unwrap();
+    /// ```
+    pub fn sync_update<'a, F>(&'a self, f: F) -> Result<usize>
+    where
+        F: FnOnce(TermWriter<'a, O>) -> Result<usize>,
+    {
+        if !self.supports_sync_update()? {
+            return f(self.writer());
+        }
+
+        self.write_sync(true)?;
+        let written = f(self.writer());
+        let ended = self.write_sync(false);
+        let written = written?;
+        ended?;
+        Ok(written)
+    }
+
+    /// Write the sequence that starts (`true`) or ends a synchronized update, and flush it.
+    fn write_sync(&self, begin: bool) -> Result<()> {
+        let mut stdout = self.stdout.lock().unwrap();
+        match self.info.ext_string("Sync") {
+            Some(seq) => {
+                terminfo::lang::Executor::new(seq.as_bytes())
+                    .arg(begin)
+                    .write(stdout.deref_mut())
+                    .context(ErrorKind::FailedToSynchronizeOutput)?;
+            }
+            None => {
+                let mode = ansi::PrivateMode::SynchronizedOutput;
+                let seq = if begin { mode.set() } else { mode.reset() };
+                seq.write(stdout.deref_mut())
+                    .context(ErrorKind::FailedToSynchronizeOutput)?;
+            }
+        }
+        stdout
+            .flush()
+            .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+        Ok(())
+    }
+
+    /// Send `query` to the terminal, and read the escape sequences it replies with until `end` accepts one.
+    ///
+    /// The terminal is put in raw mode while waiting, so the reply isn't echoed or held back until a newline.
+    /// Text and control characters read while waiting, e.g. keys typed before the query, are dropped.
+    /// `None` is returned if the input or output isn't a terminal, or the reply doesn't arrive within `timeout`.
+    pub(crate) fn query<F>(&self, query: &[u8], timeout: Duration, end: F) -> Result<Option<Vec<ansi::Sequence>>>
+    where
+        F: Fn(&ansi::Sequence) -> bool,
+    {
+        if !self.is_tty() {
+            return Ok(None);
+        }
+
+        let _raw = self.raw_mode()?;
+        {
+            let mut stdout = self.stdout.lock().unwrap();
+            stdout
+                .write_all(query)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut parser = ansi::Parser::new();
+        let mut reply = Vec::new();
+        let mut c = [0; 1];
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if !self.wait_for_input(Some(left))? || self.read(&mut c) == 0 {
+                self.err()?;
+                return Ok(None);
+            }
+
+            for seq in parser.parse(&c) {
+                match seq {
+                    ansi::Sequence::Print(_) | ansi::Sequence::Control(_) | ansi::Sequence::Invalid(_) => (),
+                    seq => {
+                        let done = end(&seq);
+                        reply.push(seq);
+                        if done {
+                            return Ok(Some(reply));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write a capability that turns a mode on or off, and flush it.
+    fn write_mode(&self, seq: &str, kind: ErrorKind) -> Result<()> {
+        self.err()?;
+
+        let mut stdout = self.stdout.lock().unwrap();
+        terminfo::lang::Executor::new(seq.as_bytes())
+            .write(stdout.deref_mut())
+            .context(kind)?;
+        stdout
+            .flush()
+            .map_err(|e| util::io_error(e, ErrorKind::WriteFailed))?;
+        Ok(())
+    }
+
+    pub fn prompt<T: AsRef<str>>(&self, prompt: T) -> Result<String> {
+        if nix::unistd::isatty(self.as_raw_fd()).unwrap_or(false) && !self.is_dumb() {
+            return self.editor.borrow_mut().prompt(self, prompt);
+        }
+
+        self.writer().print(prompt).done()?;
+        self.readline()
+    }
+
+    #[inline]
+    pub fn colors(&self) -> usize {
+        // There has to be at least two colors... right???
+        self.info.number(terminfo::MaxColors).unwrap_or(2) as usize
+    }
+
+    /// Get the size of the terminal as `(columns, rows)`.
+    ///
+    /// The size is asked for with the `TIOCGWINSZ` ioctl, first on the output then the input.
+    /// If neither is a terminal `$COLUMNS` and `$LINES` are used, then terminfo's `cols` and `lines`.
+    pub fn size(&self) -> Result<(u16, u16)> {
+        if let Some(size) = self.window_size() {
+            return Ok(size);
+        }
+
+        match (
+            self.dimension("COLUMNS", terminfo::Columns, |var| env::var(var).ok()),
+            self.dimension("LINES", terminfo::Lines, |var| env::var(var).ok()),
+        ) {
+            (Some(columns), Some(rows)) => Ok((columns, rows)),
+            _ => Err(ErrorKind::FailedToGetSize.into()),
+        }
+    }
+
+    /// Get the width of the terminal.
+    ///
+    /// The width comes from the same places as `Term::size`, in the same order, but each is checked on its own,
+    /// so a `$COLUMNS` without a `$LINES` is still used. If none of them are set the width is 80.
+    /// The window size is asked for on each call, so the result is up to date after the terminal is resized.
+    pub fn columns(&self) -> u16 {
+        self.window_size()
+            .map(|(columns, _)| columns)
+            .or_else(|| self.dimension("COLUMNS", terminfo::Columns, |var| env::var(var).ok()))
+            .unwrap_or(80)
+    }
+
+    /// Get the height of the terminal, see `Term::columns`.
+    ///
+    /// If the height isn't known it's 24.
+    pub fn lines(&self) -> u16 {
+        self.window_size()
+            .map(|(_, rows)| rows)
+            .or_else(|| self.dimension("LINES", terminfo::Lines, |var| env::var(var).ok()))
+            .unwrap_or(24)
+    }
+
+    /// The size reported by `TIOCGWINSZ`, from the output or the input.
+    fn window_size(&self) -> Option<(u16, u16)> {
+        let stdout_fd = self.stdout.lock().unwrap().as_raw_fd();
+        [stdout_fd, self.as_raw_fd()]
+            .iter()
+            .filter_map(|&fd| util::window_size(fd))
+            .next()
+    }
+
+    /// A dimension from the environment variable `name`, or terminfo's `field`. `var` looks up environment variables.
+    fn dimension<F>(&self, name: &str, field: terminfo::NumericField, var: F) -> Option<u16>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        var(name)
+            .and_then(|v| v.trim().parse::<u16>().ok())
+            .filter(|&n| n > 0)
+            .or_else(|| self.info.number(field).map(|n| n.min(u16::MAX as u32) as u16))
+            .filter(|&n| n > 0)
+    }
+
+    /// Restore the terminal and stop the process, like the default action for `SIGTSTP`.
+    ///
+    /// The settings the terminal had when it was opened are put back while the process is stopped, as `RawMode::with_cooked` does.
+    /// Once it's resumed the current settings and modes are reapplied.
+    pub(crate) fn suspend(&self) -> Result<()> {
+        let stop = || signal::raise(signal::Signal::SIGSTOP);
+        let cooked = self.cooked.borrow().clone();
+        match cooked {
+            Some(cooked) => self.with_settings(cooked, stop)?,
+            None => stop(),
+        }.context(ErrorKind::FailedToHandleSignals)?;
+        take_signal(signal::Signal::SIGCONT);
+        Ok(())
+    }
+
+    /// Flush the output, then run `f` with `saved` settings and with the modes that need raw input
+    /// (mouse capture, bracketed paste, focus reporting, keypad transmit mode and meta mode) turned off.
+    /// Once `f` returns the current settings and the modes that were on are put back.
+    fn with_settings<F, T>(&self, saved: Settings, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T,
+    {
+        let raw = self.settings();
+        let mouse_mode = self.mouse_mode.get();
+        let bracketed_paste = self.bracketed_paste.get();
+        let focus_reporting = self.focus_reporting.get();
+        let keypad = self.keypad.get();
+        let meta_mode = self.meta_mode.get();
+
+        self.flush();
+        self.set_mouse_mode(MouseMode::Off)?;
+        if bracketed_paste {
+            self.set_bracketed_paste(false)?;
+        }
+        if focus_reporting {
+            self.set_focus_reporting(false)?;
+        }
+        if keypad {
+            self.keypad(false)?;
+        }
+        if meta_mode {
+            self.meta_mode(false)?;
+        }
+        self.update(saved)?;
+
+        let value = f();
+
+        self.update(raw)?;
+        if keypad {
+            self.keypad(true)?;
+        }
+        if meta_mode {
+            self.meta_mode(true)?;
+        }
+        self.set_mouse_mode(mouse_mode)?;
+        if bracketed_paste {
+            self.set_bracketed_paste(true)?;
+        }
+        if focus_reporting {
+            self.set_focus_reporting(true)?;
+        }
+        Ok(value)
+    }
+}
+
+impl Waker {
+    /// Make the terminal's blocked, or next, `Term::read_events` return `Event::Wake`.
+    pub fn wake(&self) -> Result<()> {
+        match nix::unistd::write(self.pipe.write, b"\0") {
+            // the pipe is full, so there's already a wake up waiting to be read
+            Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => Ok(()),
+            Err(e) => Err(e.context(ErrorKind::FailedToWake).into()),
+        }
+    }
+}
+
+impl Drop for WakePipe {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.read);
+        let _ = nix::unistd::close(self.write);
+    }
+}
+
+impl<'a, I, O> RawMode<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    /// Restore the settings from before raw mode now, unlike dropping the guard this reports if it failed.
+    pub fn restore(mut self) -> Result<()> {
+        match self.saved.take() {
+            Some(saved) => self.term.update(saved),
+            None => Ok(()),
+        }
+    }
+
+    /// Leave raw mode while `f` runs, e.g. to start a subprocess like `$EDITOR` that uses the terminal itself.
+    ///
+    /// Output is flushed, then the settings from before raw mode are restored and mouse capture, bracketed paste,
+    /// focus reporting, keypad transmit mode and meta mode are turned off.
+    /// Once `f` returns the raw settings and the modes that were on are put back, but the screen isn't redrawn.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use nixterm::term::Term;
+    /// use std::process::Command;
+    ///
+    /// let term = Term::new().unwrap();
+    /// let raw = term.raw_mode().unwrap();
+    /// let status = raw
+    ///     .with_cooked(|| Command::new("vi").arg("notes.txt").status())
+    ///     .unwrap();
+    /// ```
+    pub fn with_cooked<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T,
+    {
+        match self.saved {
+            Some(ref saved) => self.term.with_settings(saved.clone(), f),
+            None => Ok(f()),
+        }
+    }
+}
+
+impl<'a, I, O> NoEcho<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    /// Turn echo back on now, unlike dropping the guard this reports if it failed.
+    pub fn restore(mut self) -> Result<()> {
+        match self.saved.take() {
+            Some(saved) => self.term.update(saved),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, I, O> Deref for NoEcho<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    type Target = Term<I, O>;
+
+    fn deref(&self) -> &Term<I, O> {
+        self.term
+    }
+}
+
+impl<'a, I, O> Drop for NoEcho<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            let _ = self.term.update(saved);
+        }
+    }
+}
+
+impl<'a, I, O> KeypadTransmit<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    /// Restore the keypad's mode now, unlike dropping the guard this reports if it failed.
+    pub fn restore(mut self) -> Result<()> {
+        match self.saved.take() {
+            Some(saved) => self.term.keypad(saved),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, I, O> Deref for KeypadTransmit<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    type Target = Term<I, O>;
+
+    fn deref(&self) -> &Term<I, O> {
+        self.term
+    }
+}
+
+impl<'a, I, O> Drop for KeypadTransmit<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            let _ = self.term.keypad(saved);
+        }
+    }
+}
+
+impl<'a, I, O> CursorStyleGuard<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    /// Restore the cursor's shape now, unlike dropping the guard this reports if it failed.
+    pub fn restore(mut self) -> Result<()> {
+        match self.saved.take() {
+            Some(saved) => self.term.write_cursor_style(saved),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, I, O> Deref for CursorStyleGuard<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    type Target = Term<I, O>;
+
+    fn deref(&self) -> &Term<I, O> {
+        self.term
+    }
+}
+
+impl<'a, I, O> Drop for CursorStyleGuard<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            let _ = self.term.write_cursor_style(saved);
+        }
+    }
+}
+
+impl<'a, I, O> Deref for RawMode<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    type Target = Term<I, O>;
+
+    fn deref(&self) -> &Term<I, O> {
+        self.term
+    }
+}
+
+impl<'a, I, O> Drop for RawMode<'a, I, O>
+where
+    I: io::Read + AsRawFd + 'a,
+    O: io::Write + AsRawFd + 'a,
+{
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            let _ = self.term.update(saved);
+        }
+    }
+}
+
+impl<I, O> Drop for Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    fn drop(&mut self) {
+        if self.bracketed_paste.get() {
+            let _ = self.set_bracketed_paste(false);
+        }
+        let _ = self.set_mouse_mode(MouseMode::Off);
+        if self.focus_reporting.get() {
+            let _ = self.set_focus_reporting(false);
+        }
+        if self.keypad.get() {
+            let _ = self.keypad(false);
+        }
+        if self.meta_mode.get() {
+            let _ = self.meta_mode(false);
         }
-        let bg = self.background.clone();
-        let fg = self.foreground.clone();
+    }
+}
 
-        self.write_color(
-            bg,
-            b"48;2;5",
-            terminfo::SetABackground,
-            terminfo::SetBackground,
-        )?;
-        self.write_color(
-            fg,
-            b"38;2;5",
-            terminfo::SetAForeground,
-            terminfo::SetForeground,
-        )
+impl<I, O> AsRawFd for Term<I, O>
+where
+    I: io::Read + AsRawFd,
+    O: io::Write + AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.stdin_fd.get()
     }
+}
 
-    fn write_color(
-        &mut self,
-        color: Option<ansi::Color>,
-        rgb_prefix: &[u8],
-        seta: terminfo::StringField,
-        set: terminfo::StringField,
-    ) -> Result<()> {
-        match color {
-            Some(ansi::Color::Index(x)) => {
-                self.written += match self.exec(seta) {
-                    Ok(e) => e
-                        .arg(x as usize)
-                        .write(self.stdout.deref_mut())
-                        .context(ErrorKind::FailedToRunTerminfo(set))
-                        .map_err(|e| e.into()),
-                    Err(_) => self.exec(set).map(|exe| {
-                        exe.arg(seta_to_set_pallet(x) as usize)
-                            .write(self.stdout.deref_mut())
-                            .unwrap_or_else(|e| {
-                                self.err =
-                                    Some(e.context(ErrorKind::FailedToRunTerminfo(set)).into());
-                                0
-                            })
-                    }),
-                }.context(ErrorKind::WriteFailed)?
+#[cfg(test)]
+mod test {
+    use std::io;
+    use term::*;
+    use terminfo;
+
+    const TERMINFO: &'static [u8] = include_bytes!("../test-data/rxvt");
+
+    struct FakeStdin {
+        buffer: Vec<u8>,
+    }
+
+    struct FakeStdout {
+        buffer: Vec<u8>,
+    }
+
+    impl FakeStdin {
+        fn new() -> FakeStdin {
+            FakeStdin { buffer: Vec::new() }
+        }
+    }
+
+    impl<'a> AsRawFd for &'a mut FakeStdin {
+        fn as_raw_fd(&self) -> RawFd {
+            0
+        }
+    }
+
+    impl FakeStdout {
+        fn new() -> FakeStdout {
+            FakeStdout { buffer: Vec::new() }
+        }
+    }
+
+    impl<'a> AsRawFd for &'a mut FakeStdout {
+        fn as_raw_fd(&self) -> RawFd {
+            1
+        }
+    }
+
+    impl<'a> io::Read for &'a mut FakeStdin {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = if buf.len() > self.buffer.len() {
+                self.buffer.len()
+            } else {
+                buf.len()
+            };
+
+            self.buffer
+                .drain(..len)
+                .enumerate()
+                .for_each(|(i, c)| buf[i] = c);
+            Ok(len)
+        }
+    }
+
+    impl<'a> io::Write for &'a mut FakeStdout {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn term() {
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        {
+            let term = Term::from_streams(
+                terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+                &mut stdin,
+                &mut stdout,
+            );
+            term.set_color_choice(ColorChoice::Always);
+            term.set_assume_tty(true);
+            term.writer().bold().print("Hello World?").done().unwrap();
+        }
+        assert_eq!(&stdout.buffer, b"\x1b[0;1m\x0fHello World?");
+    }
+
+    #[test]
+    fn monochrome() {
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        {
+            let mut info: terminfo::TermInfoBuf =
+                terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+            info.set_number(terminfo::MaxColors, 2).unwrap();
+
+            let mut term = Term::from_streams(info, &mut stdin, &mut stdout);
+            term.set_color_choice(ColorChoice::Always);
+            term.writer().foreground("red").print("Hello").done().unwrap();
+
+            term.monochrome = MonochromeMap::new().foreground("red", Attribute::Underline);
+            term.writer().foreground("brightred").print("World").done().unwrap();
+            term.writer().foreground("blue").print("?").done().unwrap();
+        }
+        assert_eq!(
+            &stdout.buffer,
+            b"\x1b[0;1m\x0fHello\x1b[0;4m\x0fWorld\x1b[0m\x0f?"
+        );
+    }
+
+    #[test]
+    fn color_mapper() {
+        struct Green;
+
+        impl ColorMapper for Green {
+            fn map(&self, _: ansi::Color, _: &terminfo::TermInfoBuf) -> ansi::Color {
+                ansi::Color::Index(2)
             }
-            Some(ansi::Color::Rgb(r, g, b)) => {
-                use std::io::Write;
+        }
 
-                self.written += self.write(rgb_prefix).context(ErrorKind::WriteFailed)?
-                    + util::write_u8_ansi(self, r).context(ErrorKind::WriteFailed)?
-                    + self.write(b";").context(ErrorKind::WriteFailed)?
-                    + util::write_u8_ansi(self, g).context(ErrorKind::WriteFailed)?
-                    + self.write(b";").context(ErrorKind::WriteFailed)?
-                    + util::write_u8_ansi(self, b).context(ErrorKind::WriteFailed)?
-                    + self.write(b"m").context(ErrorKind::WriteFailed)?;
+        let mut stdin = FakeStdin::new();
+        let mut expected = FakeStdout::new();
+        {
+            let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+            let term = Term::from_streams(info, &mut stdin, &mut expected);
+            term.set_color_choice(ColorChoice::Always);
+            term.writer().foreground("green").print("a").done().unwrap();
+            term.writer().foreground("red").print("b").done().unwrap();
+        }
+
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        {
+            let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+            let mut term = Term::from_streams(info, &mut stdin, &mut stdout);
+            term.set_color_choice(ColorChoice::Always);
+            term.set_color_mapper(Green);
+            term.writer().foreground("blue").print("a").done().unwrap();
+            term.writer()
+                .color_mapper(&NearestColor)
+                .foreground("red")
+                .print("b")
+                .done()
+                .unwrap();
+        }
+        assert_eq!(stdout.buffer, expected.buffer);
+    }
+
+    #[test]
+    fn color_choice() {
+        assert!(ColorChoice::Auto.enabled(true, fake_env(&[("TERM", "xterm")])));
+        assert!(ColorChoice::Auto.enabled(true, fake_env(&[("NO_COLOR", "")])));
+        assert!(!ColorChoice::Auto.enabled(true, fake_env(&[("NO_COLOR", "1")])));
+        assert!(!ColorChoice::Auto.enabled(true, fake_env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")])));
+        assert!(!ColorChoice::Auto.enabled(true, fake_env(&[("CLICOLOR", "0")])));
+        assert!(!ColorChoice::Auto.enabled(true, fake_env(&[("TERM", "dumb")])));
+        assert!(ColorChoice::Auto.enabled(true, fake_env(&[("TERM", "dumb"), ("CLICOLOR_FORCE", "1")])));
+        assert!(!ColorChoice::Auto.enabled(true, fake_env(&[("CLICOLOR", "0"), ("CLICOLOR_FORCE", "0")])));
+        assert!(ColorChoice::Always.enabled(true, fake_env(&[("NO_COLOR", "1")])));
+        assert!(!ColorChoice::Never.enabled(true, fake_env(&[])));
+        assert!(!ColorChoice::Auto.enabled(false, fake_env(&[])));
+        assert!(ColorChoice::Auto.enabled(false, fake_env(&[("CLICOLOR_FORCE", "1")])));
+        assert!(!ColorChoice::Auto.enabled(false, fake_env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")])));
+        assert!(ColorChoice::Always.enabled(false, fake_env(&[])));
+
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        {
+            let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+            let term = Term::from_streams(info, &mut stdin, &mut stdout);
+            term.set_color_choice(ColorChoice::Never);
+            term.writer()
+                .bold()
+                .foreground("red")
+                .print("Hello")
+                .clear()
+                .default_foreground()
+                .done()
+                .unwrap();
+            term.writer()
+                .keep_style(true)
+                .underline()
+                .print(", ")
+                .background("blue")
+                .print("World")
+                .done()
+                .unwrap();
+        }
+        assert_eq!(&stdout.buffer, b"Hello, World");
+    }
+
+    #[test]
+    fn piped() {
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let (read, write) = nix::unistd::pipe().unwrap();
+        let (mut read, write) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            write.try_clone().unwrap(),
+            write,
+        );
+        assert!(!term.is_tty());
+        assert!(!term.colors_enabled());
+        assert_eq!(term.background_color().unwrap(), None);
+
+        term.writer()
+            .bold()
+            .move_to(4, 2)
+            .print("a")
+            .shift_cursor(1, 0)
+            .print("b")
+            .done()
+            .unwrap();
+
+        term.set_assume_tty(true);
+        term.set_color_choice(ColorChoice::Never);
+        term.writer().move_to(0, 0).bold().print("c").done().unwrap();
+        drop(term);
+
+        let mut out = Vec::new();
+        read.read_to_end(&mut out).unwrap();
+        assert_eq!(&out, b"ab\x1b[1;1Hc");
+    }
+
+    #[test]
+    fn piped_input() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let (read, _write) = nix::unistd::pipe().unwrap();
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            unsafe { File::from_raw_fd(read) },
+            slave,
+        );
+        assert!(!term.is_tty());
+
+        // only the output needs to be a terminal for cursor movement
+        term.writer().move_to(4, 2).print("a").done().unwrap();
+        let mut out = [0u8; 64];
+        let n = master.read(&mut out).unwrap();
+        assert!(out[..n].starts_with(b"\x1b[3;5H") && out[..n].ends_with(b"a"));
+    }
+
+    #[test]
+    fn dumb() {
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        {
+            let mut info = terminfo::TermInfoBuf::new();
+            info.set_string(terminfo::Bell, "\x07").unwrap();
+
+            let term = Term::from_streams(info, &mut stdin, &mut stdout);
+            term.set_assume_tty(true);
+            term.set_color_choice(ColorChoice::Always);
+            assert!(term.is_dumb());
+            assert!(!term.colors_enabled());
+
+            term.writer()
+                .bold()
+                .foreground("red")
+                .move_to(3, 4)
+                .print("Hello")
+                .clear()
+                .done()
+                .unwrap();
+            term.clear_screen();
+            term.bell();
+            assert_eq!(
+                term.run_loop(30, |_| Ok(())).unwrap_err().kind(),
+                &ErrorKind::UnsupportedTerminal
+            );
+        }
+        assert_eq!(&stdout.buffer, b"Hello\x07");
+
+        let mut stdin = FakeStdin::new();
+        let mut stdout = FakeStdout::new();
+        let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        assert!(!Term::from_streams(info, &mut stdin, &mut stdout).is_dumb());
+    }
+
+    #[test]
+    fn keypad() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+
+        // reading keys doesn't change the keypad's mode, both modes' cursor keys are decoded
+        let keys = term.read_keys();
+        let (events, _) = keys.decode_events(b"\x1b[A\x1bOA\x1bOD");
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Up),
+                Event::Key(Key::Up),
+                Event::Key(Key::Left),
+            ]
+        );
+        drop(keys);
+
+        {
+            let keypad = term.keypad_transmit().unwrap();
+            assert!(keypad.keypad.get());
+            term.keypad_transmit().unwrap().restore().unwrap();
+            assert!(keypad.keypad.get());
+        }
+        assert!(!term.keypad.get());
+
+        // the inner guard puts back the mode it found, which was transmit
+        let mut buf = [0; 8];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b=\x1b=\x1b=\x1b>");
+    }
+
+    #[test]
+    fn meta_mode() {
+        use events::{Key, Modifiers};
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        assert_eq!(term.read_keys().decode(b"\xe2"), vec![Key::Invalid(0xe2)]);
+
+        term.meta_mode(true).unwrap();
+        assert!(term.meta_mode_enabled());
+        assert_eq!(
+            term.read_keys().decode(b"\xe2a"),
+            vec![
+                Key::Char('b').with_modifiers(Modifiers::ALT),
+                Key::Char('a'),
+            ]
+        );
+        term.meta_mode(false).unwrap();
+        assert!(!term.meta_mode_enabled());
+    }
+
+    #[test]
+    fn keymap() {
+        use events::{Key, KeyMap, Modifiers};
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let mut term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        assert_eq!(
+            term.read_keys().decode(b"\x1b[99~"),
+            vec![
+                Key::Escape,
+                Key::Char('['),
+                Key::Char('9'),
+                Key::Char('9'),
+                Key::Char('~'),
+            ]
+        );
+
+        term.set_keymap(
+            KeyMap::new()
+                .bind("\x1b[99~", Key::Fn(24).with_modifiers(Modifiers::SHIFT))
+                .bind_capability("kcuu1", Key::Custom(1))
+                .bind_capability("kNOPE", Key::Custom(2))
+                .bind_custom("\x1bx", 3),
+        );
+        assert_eq!(
+            term.read_keys().decode(b"\x1b[99~\x1b[A\x1bx\x1b[B"),
+            vec![
+                Key::Fn(24).with_modifiers(Modifiers::SHIFT),
+                Key::Custom(1),
+                Key::Custom(3),
+                Key::Down,
+            ]
+        );
+    }
+
+    #[test]
+    fn decoder() {
+        use events::{Decoder, Event, Key, KeyMap};
+
+        /// Reads one byte at a time, so every escape sequence is split between reads.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl<'a> io::Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.split_first() {
+                    Some((&b, rest)) if !buf.is_empty() => {
+                        buf[0] = b;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    _ => Ok(0),
+                }
             }
-            None => (),
         }
 
-        Ok(())
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let decoder = Decoder::new(&info, &KeyMap::new());
+        let events = decoder
+            .events(Trickle(b"a\x1b[A\x1b[200~hi\x1b[201~\x1b"))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Char('a')),
+                Event::Key(Key::Up),
+                Event::Paste("hi".into()),
+                Event::Key(Key::Escape),
+            ]
+        );
+        assert_eq!(decoder.decode(b"\x1b[B"), vec![Key::Down]);
+
+        // `ESC P` could start a DCS, so it's held until the input ends
+        let events = decoder
+            .events(Trickle(b"\x1bP"))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(events, vec![Event::Key(Key::Char('P').with_modifiers(::events::Modifiers::ALT))]);
+
+        // a control sequence interrupted by a control character isn't a key
+        let events = decoder
+            .events(Trickle(b"\x1b[1\r"))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Escape),
+                Event::Key(Key::Char('[')),
+                Event::Key(Key::Char('1')),
+                Event::Key(Key::Enter),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_keys() {
+        use events::{Decoder, Event, Key, KeyMap, Modifiers};
+
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let decoder = Decoder::new(&info, &KeyMap::new());
+        let alt = |c| Key::Char(c).with_modifiers(Modifiers::ALT);
+        assert_eq!(
+            decoder.decode(b"\x1bb\x1bF\x1b\x1b\x1b[A\x1b\t"),
+            vec![alt('b'), alt('F'), Key::Escape, Key::Up, Key::Escape, Key::Tab]
+        );
+        // the start of a known sequence is more likely to be cut off than typed with Alt
+        assert_eq!(decoder.decode(b"\x1b["), vec![Key::Escape, Key::Char('[')]);
+        assert_eq!(decoder.decode_events(b"\x1bf").0, vec![Event::Key(alt('f'))]);
+    }
+
+    #[test]
+    fn input_encoding() {
+        use events::{Decoder, Encoding, Event, Key, KeyMap};
+
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let decoder = Decoder::new(&info, &KeyMap::new());
+        assert_eq!(
+            decoder.decode("é€\x1b[A".as_bytes()),
+            vec![Key::Char('é'), Key::Char('€'), Key::Up]
+        );
+        assert_eq!(decoder.decode(b"\xe2\x82"), vec![Key::Invalid(0xe2), Key::Invalid(0x82)]);
+
+        // a character cut off at the end of a chunk is left for the next one
+        let (events, rest) = decoder.decode_events(b"a\xe2\x82");
+        assert_eq!(events, vec![Event::Key(Key::Char('a'))]);
+        assert_eq!(rest, b"\xe2\x82");
+
+        let latin1 = decoder.clone().encoding(Encoding::Latin1);
+        assert_eq!(latin1.decode(b"\xe9"), vec![Key::Char('é')]);
+        let koi8 = decoder.clone().encoding(Encoding::Koi8R);
+        assert_eq!(koi8.decode(b"\xc4\xc1"), vec![Key::Char('д'), Key::Char('а')]);
+        static STARS: [char; 128] = ['*'; 128];
+        let table = decoder.clone().encoding(Encoding::Table(&STARS));
+        assert_eq!(table.decode(b"\xff"), vec![Key::Char('*')]);
+        let ascii = decoder.encoding(Encoding::Ascii);
+        assert_eq!(ascii.decode(b"\xe9"), vec![Key::Invalid(0xe9)]);
+    }
+
+    /// Look up environment variables in `vars`, instead of the process's environment.
+    fn fake_env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name: &str| {
+            vars.iter()
+                .find(|&&(k, _)| k == name)
+                .map(|&(_, v)| v.to_string())
+        }
+    }
+
+    /// A stream that fails with EIO once the terminal is "gone".
+    struct Tty {
+        input: Vec<u8>,
+        gone: bool,
     }
 
-    pub fn shift_cursor(mut self, x: isize, y: isize) -> Self {
-        if self.err.is_some() {
-            return self;
+    impl AsRawFd for Tty {
+        fn as_raw_fd(&self) -> RawFd {
+            0
         }
+    }
 
-        if (x > 0) {
-            self.exec(terminfo::ParmRightCursor)
-                .map(|ctx| ctx.arg(x).write(&mut self))
-                .map_err(|e| self.err = Some(e));
-        } else if (x < 0) {
-            self.exec(terminfo::ParmLeftCursor)
-                .map(|ctx| ctx.arg(-x).write(&mut self))
-                .map_err(|e| self.err = Some(e));
+    impl io::Read for Tty {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.gone {
+                return Err(io::Error::from_raw_os_error(::nix::libc::EIO));
+            }
+            let len = buf.len().min(self.input.len());
+            buf[..len].copy_from_slice(&self.input[..len]);
+            self.input.drain(..len);
+            Ok(len)
         }
+    }
 
-        if self.err.is_some() {
-            return self;
+    impl io::Write for Tty {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.gone {
+                return Err(io::Error::from_raw_os_error(::nix::libc::EIO));
+            }
+            Ok(buf.len())
         }
 
-        if (y > 0) {
-            self.exec(terminfo::ParmUpCursor)
-                .map(|ctx| ctx.arg(y).write(&mut self))
-                .map_err(|e| self.err = Some(e));
-        } else if (y < 0) {
-            self.exec(terminfo::ParmDownCursor)
-                .map(|ctx| ctx.arg(-y).write(&mut self))
-                .map_err(|e| self.err = Some(e));
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
+    }
 
-        return self;
+    #[test]
+    fn terminal_gone() {
+        let gone = || Tty {
+            input: Vec::new(),
+            gone: true,
+        };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            gone(),
+            gone(),
+        );
+        let mut buf = [0u8; 4];
+
+        assert_eq!(term.read(&mut buf), 0);
+        assert!(term.is_gone());
+        assert_eq!(term.err().unwrap_err().kind(), &ErrorKind::TerminalGone);
+        assert_eq!(term.print("?").unwrap_err().kind(), &ErrorKind::TerminalGone);
+
+        term.on_reconnect(|| {
+            let stdin = Tty {
+                input: b"ok".to_vec(),
+                gone: false,
+            };
+            let stdout = Tty {
+                input: Vec::new(),
+                gone: false,
+            };
+            Ok((stdin, stdout))
+        });
+        assert_eq!(term.read(&mut buf), 2);
+        assert_eq!(&buf[..2], b"ok");
+        assert!(!term.is_gone());
     }
 
-    pub fn default_background(mut self) -> Self {
-        self.write_bytes(ansi::RESET_BACKGROUND)
+    #[test]
+    fn size() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let winsize = pty::Winsize {
+            ws_row: 40,
+            ws_col: 100,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = pty::openpty(&winsize, None).unwrap();
+        let _master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        assert_eq!(term.size().unwrap(), (100, 40));
+        assert_eq!((term.columns(), term.lines()), (100, 40));
+
+        // neither stream is a terminal, so the environment is used, then terminfo's size
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        assert_eq!(term.window_size(), None);
+        let dimensions = |vars| {
+            (
+                term.dimension("COLUMNS", terminfo::Columns, fake_env(vars)),
+                term.dimension("LINES", terminfo::Lines, fake_env(vars)),
+            )
+        };
+        assert_eq!(dimensions(&[]), (Some(80), Some(24)));
+
+        // each dimension falls back on its own
+        assert_eq!(dimensions(&[("COLUMNS", "132")]), (Some(132), Some(24)));
+        assert_eq!(dimensions(&[("COLUMNS", "0"), ("LINES", "x")]), (Some(80), Some(24)));
     }
 
-    pub fn default_foreground(mut self) -> Self {
-        self.write_bytes(ansi::RESET_FOREGROUND)
+    #[test]
+    fn style() {
+        use prompt::Shell;
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        let style = Style::new().bold().underline().foreground("red").background("blue");
+
+        let styled = term
+            .render_prompt(Shell::Readline, |w| w.style(&style).print("$ ").done())
+            .unwrap();
+        let chained = term
+            .render_prompt(Shell::Readline, |w| {
+                w.bold()
+                    .underline()
+                    .foreground("red")
+                    .background("blue")
+                    .print("$ ")
+                    .done()
+            })
+            .unwrap();
+        assert_eq!(styled, chained);
+
+        // the style's attributes are added to those already set
+        let added = term
+            .render_prompt(Shell::Readline, |w| w.dim().style(&Style::new()).print("$ ").done())
+            .unwrap();
+        let dim = term
+            .render_prompt(Shell::Readline, |w| w.dim().print("$ ").done())
+            .unwrap();
+        assert_eq!(added, dim);
     }
-}
 
-impl<'a, O> io::Write for TermWriter<'a, O>
-where
-    O: io::Write + AsRawFd,
-{
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if let Some(_) = self.err() {
-            return Ok(0);
-        }
+    #[test]
+    fn named_styles() {
+        use prompt::Shell;
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        term.define_style("error", Style::new().bold().foreground("red"));
+        term.load_theme(vec![("path", "underline fg=blue")]).unwrap();
+        assert_eq!(term.named_style("path"), Some(Style::new().underline().foreground("blue")));
+        assert!(term.load_theme(vec![("ok", "bold"), ("bad", "bold fg=nope")]).is_err());
+        assert_eq!(term.named_style("ok"), None);
+
+        let named = term
+            .render_prompt(Shell::Readline, |w| w.named_style("error").print("!").done())
+            .unwrap();
+        let chained = term
+            .render_prompt(Shell::Readline, |w| w.bold().foreground("red").print("!").done())
+            .unwrap();
+        assert_eq!(named, chained);
+
+        assert!(term
+            .render_prompt(Shell::Readline, |w| w.named_style("missing").print("!").done())
+            .is_err());
+    }
 
-        self.set_sgr();
+    #[test]
+    fn keep_style() {
+        use prompt::Shell;
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        let render = |f: &dyn Fn(TermWriter<prompt::PromptWriter<Vec<u8>>>) -> Result<usize>| {
+            term.render_prompt(Shell::Readline, f).unwrap()
+        };
+
+        // the style is only written once
+        assert_eq!(
+            render(&|w| w.keep_style(true).bold().print("a").print("b").done()),
+            render(&|w| w.bold().print("ab").done())
+        );
+
+        // and only written again when it changes
+        assert_eq!(
+            render(&|w| {
+                w.keep_style(true)
+                    .bold()
+                    .print("a")
+                    .underline()
+                    .print("b")
+                    .print("c")
+                    .done()
+            }),
+            render(&|w| w.bold().print("a").bold().underline().print("bc").done())
+        );
 
-        self.stdout.write(buf)
+        // until it's cleared
+        let cleared = render(&|w| w.bold().print("a").clear().done());
+        assert_eq!(
+            render(&|w| w.keep_style(true).bold().print("a").clear().print("b").done()),
+            cleared + "b"
+        );
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.stdout.flush()
+    #[test]
+    fn print_wrapped() {
+        use prompt::Shell;
+        use std::fs::File;
+        use terminfo::BooleanField;
+
+        let null = File::open("/dev/null").unwrap();
+        let mut term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        term.info
+            .set_boolean(BooleanField::EatNewlineGlitch, true)
+            .unwrap();
+
+        let wrap = |term: &Term<File, File>, s: &str| {
+            term.render_prompt(Shell::Readline, |w| {
+                w.wrap_at(10).print("> ").print_wrapped(s).done()
+            }).unwrap()
+        };
+        let plain = |term: &Term<File, File>, s: &str| {
+            term.render_prompt(Shell::Readline, |w| w.print("> ").print(s).done())
+                .unwrap()
+        };
+
+        assert_eq!(
+            wrap(&term, "the quick brown fox\njumps over"),
+            plain(&term, "the\nquick\nbrown fox\njumps over")
+        );
+        assert_eq!(
+            wrap(&term, "0123456789abcd  x"),
+            plain(&term, "\n0123456789\nabcd  x")
+        );
+
+        // wide characters aren't split across lines
+        assert_eq!(
+            wrap(&term, "abcdefghi日本"),
+            plain(&term, "\nabcdefghi\n日本")
+        );
+
+        // with `am`, but not `xenl`, full lines wrap by themselves
+        term.info
+            .set_boolean(BooleanField::EatNewlineGlitch, false)
+            .unwrap();
+        assert_eq!(
+            wrap(&term, "12345678 abc defghij"),
+            plain(&term, "12345678abc\ndefghij")
+        );
     }
-}
 
-/// Term represents the user's terminal.
-/// It has two channels, `I` (input), and `O` (output).
-/// Each terminal is accompanied by a "terminfo" file, (represented by the `TermInfoBuf` struct).
-impl<I, O> Term<I, O>
-where
-    I: io::Read + AsRawFd,
-    O: io::Write + AsRawFd,
-{
-    pub fn from_streams(tib: terminfo::TermInfoBuf, stdin: I, stdout: O) -> Term<I, O> {
-        Term {
-            info: tib,
-            stdin_fd: stdin.as_raw_fd(),
-            stdin: Mutex::new(BufReader::new(stdin)),
-            stdout: Mutex::new(stdout),
-            err: RefCell::new(None),
-        }
+    #[test]
+    fn box_outline() {
+        use prompt::Shell;
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let mut term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        let draw = |term: &Term<File, File>| {
+            term.render_prompt(Shell::Readline, |w| {
+                w.box_outline(Rect::new(1, 2, 3, 3)).done()
+            }).unwrap()
+        };
+
+        // only the text, without the escape sequences
+        let text = |s: String| {
+            s.split('\x01')
+                .map(|part| part.splitn(2, '\x02').last().unwrap())
+                .collect::<String>()
+        };
+
+        env::set_var("LC_ALL", "en_US.UTF-8");
+        let unicode = draw(&term);
+        assert!(unicode.starts_with("\x01\x1b[3;2H"));
+        assert_eq!(text(unicode), "┌─┐││└─┘");
+
+        // the alternate character set is entered after the attributes are set
+        env::set_var("LC_ALL", "C");
+        let acs = draw(&term);
+        assert!(acs.contains("\x0e\x02lqk\x01\x0f"));
+        assert_eq!(text(acs), "lqkxxmqj");
+
+        term.info.set_string(terminfo::AcsChars, "").unwrap();
+        assert_eq!(text(draw(&term)), "+-+||+-+");
+        env::remove_var("LC_ALL");
     }
 
-    /// Write to the terminal's stdout, it returns the number of bytes written.
-    /// `write` does not need a mutable reference to `self`, meaning it can be used while self is being borrowed,
-    /// however `write` blocks if it's being called from another thread.
-    ///
-    /// # Examples
-    /// ```
-    /// use nixterm::term::Term;
-    ///
-    /// pub fn main() {
-    ///     let term = Term::new().unwrap();
-    ///     term.writer()
-    ///         .foreground("rgb(64, 64, 128)")
-    ///         .write(b" Look look look! I'm kinda blue now!\n")
-    ///         .flush()
-    ///         .err()
-    ///         .unwrap();
-    /// }
-    /// ```
-    pub fn writer<'a>(&'a self) -> TermWriter<'a, O> {
-        TermWriter {
-            info: &self.info,
-            stdout: self.stdout.lock().unwrap(),
-            written: 0,
-            err: None,
+    #[test]
+    fn closed_input() {
+        use std::fs::{File, OpenOptions};
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            File::open("/dev/null").unwrap(),
+            OpenOptions::new().write(true).open("/dev/null").unwrap(),
+        );
+        let mut keys = term.read_keys();
+        assert_eq!(
+            keys.next_timeout(Duration::from_millis(200)).unwrap_err().kind(),
+            &ErrorKind::EndOfInput
+        );
+        assert!(keys.next().is_none());
+        assert!(term.read_events().unwrap().next().is_none());
+
+        // an escape sequence cut short by the end of the input
+        let (read, write) = nix::unistd::pipe().unwrap();
+        let (read, mut write) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+        write.write_all(b"a\x1b[1").unwrap();
+        drop(write);
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            read,
+            OpenOptions::new().write(true).open("/dev/null").unwrap(),
+        );
+        let keys: Vec<Key> = term.read_keys().map(|key| key.unwrap()).collect();
+        assert_eq!(keys, vec![Key::Char('a'), Key::Escape, Key::Char('['), Key::Char('1')]);
+    }
 
-            bold: false,
-            dim: false,
-            standout: false,
-            italics: false,
-            invert: false,
-            blink: false,
-            invisible: false,
-            underline: false,
+    #[test]
+    fn read_key_timeout() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        let started = Instant::now();
+        assert_eq!(term.read_key_timeout(Duration::from_millis(50)).unwrap(), None);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        master.write_all(b"ab").unwrap();
+        let mut keys = term.read_keys();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(keys.next_timeout(timeout).unwrap(), Some(Key::Char('a')));
+        assert_eq!(keys.next_timeout(timeout).unwrap(), Some(Key::Char('b')));
+        assert_eq!(keys.next_timeout(Duration::from_millis(10)).unwrap(), None);
+
+        term.set_input_encoding(::events::Encoding::Utf8);
+        let mut keys = term.read_keys();
+        master.write_all("é!".as_bytes()).unwrap();
+        assert_eq!(keys.next_timeout(timeout).unwrap(), Some(Key::Char('é')));
+        assert_eq!(keys.next_timeout(timeout).unwrap(), Some(Key::Char('!')));
+
+        // escape and a character that arrive together are an Alt key, even if they start a longer sequence
+        let alt = |c| Some(Key::Char(c).with_modifiers(::events::Modifiers::ALT));
+        master.write_all(b"\x1bb").unwrap();
+        assert_eq!(keys.next_timeout(timeout).unwrap(), alt('b'));
+        master.write_all(b"\x1bO").unwrap();
+        assert_eq!(keys.next_timeout(timeout).unwrap(), alt('O'));
+        master.write_all(b"\x1b\x1b").unwrap();
+        assert_eq!(keys.next_timeout(timeout).unwrap(), Some(Key::Escape));
+        assert_eq!(keys.next_timeout(Duration::from_millis(10)).unwrap(), None);
+    }
 
-            foreground: None,
-            background: None,
+    #[test]
+    fn read_event_timeout() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        let mut events = term.read_events().unwrap();
+        let started = Instant::now();
+        assert_eq!(events.next_timeout(Duration::from_millis(50)).unwrap(), None);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        master.write_all(b"a").unwrap();
+        assert_eq!(
+            events.next_timeout(Duration::from_secs(5)).unwrap(),
+            Some(Event::Key(Key::Char('a')))
+        );
+        events.push_back(Event::Focus(true));
+        assert_eq!(
+            events.next_timeout(Duration::from_millis(0)).unwrap(),
+            Some(Event::Focus(true))
+        );
+    }
+
+    #[test]
+    fn waker() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        let waker = term.waker().unwrap();
+        let background = waker.clone();
+        let woken = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            background.wake().unwrap();
+        });
+        let mut events = term.read_events().unwrap();
+        assert_eq!(events.next().unwrap().unwrap(), Event::Wake);
+        woken.join().unwrap();
+
+        // wakes before the next read are combined
+        waker.wake().unwrap();
+        waker.wake().unwrap();
+        let timeout = Duration::from_millis(20);
+        assert_eq!(events.next_timeout(timeout).unwrap(), Some(Event::Wake));
+        assert_eq!(events.next_timeout(timeout).unwrap(), None);
+        drop(events);
+
+        waker.wake().unwrap();
+        master.write_all(b"a").unwrap();
+        let mut keys = term.read_keys();
+        assert_eq!(keys.next_event().unwrap(), Event::Wake);
+        assert_eq!(keys.next_event().unwrap(), Event::Key(Key::Char('a')));
+    }
+
+    #[test]
+    fn debug_input() {
+        use events::{Event, Key, Modifiers};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+        use std::rc::Rc;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+        term.set_input_encoding(::events::Encoding::Utf8);
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        term.debug_input(move |bytes, event| log.borrow_mut().push((bytes.to_vec(), event.clone())));
+
+        master.write_all("a\x1b[A\x1bxé".as_bytes()).unwrap();
+        let mut keys = term.read_keys();
+        let timeout = Duration::from_secs(5);
+        for _ in 0..4 {
+            keys.next_timeout(timeout).unwrap().unwrap();
         }
+        master.write_all(b"\x1b[B").unwrap();
+        keys.next_event().unwrap();
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (b"a".to_vec(), Event::Key(Key::Char('a'))),
+                (b"\x1b[A".to_vec(), Event::Key(Key::Up)),
+                (b"\x1bx".to_vec(), Event::Key(Key::Char('x').with_modifiers(Modifiers::ALT))),
+                ("é".as_bytes().to_vec(), Event::Key(Key::Char('é'))),
+                (b"\x1b[B".to_vec(), Event::Key(Key::Down)),
+            ]
+        );
+
+        term.stop_debugging_input();
+        master.write_all(b"b").unwrap();
+        keys.next_timeout(timeout).unwrap();
+        assert_eq!(seen.borrow().len(), 5);
     }
 
-    pub fn print<T: AsRef<str>>(&self, s: T) -> Result<usize> {
-        self.writer().print(s).done()
+    #[test]
+    fn event_hooks() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+        use std::rc::Rc;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        term.add_event_hook(|event| match event {
+            Event::Key(Key::Char('x')) => None,
+            Event::Key(Key::Char('a')) => Some(Event::Key(Key::Char('b'))),
+            event => Some(event),
+        });
+        term.add_event_hook(move |event| {
+            log.borrow_mut().push(event.clone());
+            Some(event)
+        });
+
+        master.write_all(b"xac").unwrap();
+        let mut events = term.read_events().unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(events.next_timeout(timeout).unwrap(), Some(Event::Key(Key::Char('b'))));
+        assert_eq!(events.next_timeout(timeout).unwrap(), Some(Event::Key(Key::Char('c'))));
+        events.push_back(Event::Key(Key::Char('a')));
+        assert_eq!(events.next().unwrap().unwrap(), Event::Key(Key::Char('a')));
+        assert_eq!(
+            *seen.borrow(),
+            vec![Event::Key(Key::Char('b')), Event::Key(Key::Char('c'))]
+        );
+
+        // a dropped event doesn't end the timeout early
+        master.write_all(b"x").unwrap();
+        assert_eq!(events.next_timeout(Duration::from_millis(50)).unwrap(), None);
+
+        term.clear_event_hooks();
+        assert_eq!(term.filter_event(Event::Key(Key::Char('x'))), Some(Event::Key(Key::Char('x'))));
     }
 
-    pub fn println<T: AsRef<str>>(&self, s: T) -> Result<usize> {
-        self.writer().println(s).done()
+    #[test]
+    fn bracketed_paste() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+        term.set_bracketed_paste(true).unwrap();
+        let mut buf = [0; 8];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b[?2004h");
+
+        let mut keys = term.read_keys();
+        master.write_all(b"a\x1b[200~one\r\ntwo\rq\x1b[201~b").unwrap();
+        assert_eq!(keys.next_event().unwrap(), Event::Key(Key::Char('a')));
+        assert_eq!(keys.next_event().unwrap(), Event::Paste("one\ntwo\nq".into()));
+        assert_eq!(keys.next_event().unwrap(), Event::Key(Key::Char('b')));
+
+        let (events, rest) = keys.decode_events(b"x\x1b[200~hi\x1b[201~\x1b[200~y");
+        assert_eq!(events, vec![Event::Key(Key::Char('x')), Event::Paste("hi".into())]);
+        assert_eq!(rest, b"\x1b[200~y");
+
+        // pasted text is read as keys when iterating
+        master.write_all(b"\x1b[200~hi\x1b[201~").unwrap();
+        let pasted = keys.by_ref().take(2).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(pasted, vec![Key::Char('h'), Key::Char('i')]);
+        assert_eq!(keys.decode(b"\x1b[200~!\x1b[201~"), vec![Key::Char('!')]);
+
+        // a paste that doesn't end is returned once it stops arriving, or gets too long
+        master.write_all(b"\x1b[200~cut").unwrap();
+        assert_eq!(keys.next_event().unwrap(), Event::Paste("cut".into()));
+        let mut long = b"\x1b[200~".to_vec();
+        long.resize(long.len() + ::events::MAX_PASTE_LEN + 1, b'a');
+        let (events, rest) = keys.decode_events(&long);
+        assert_eq!(
+            events,
+            vec![
+                Event::Paste("a".repeat(::events::MAX_PASTE_LEN)),
+                Event::Key(Key::Char('a')),
+            ]
+        );
+        assert_eq!(rest, b"");
     }
 
-    /// Read from the terminal's standard input. Read into a fixed length buffer and return the number of characters read.
-    /// Similar to `Term::write`, `read` does not need `Term` to be mutable, however only one thread may be reading at a time.
-    ///
-    /// # Examples
-    /// ```
-    /// use nixterm::term::Term;
-    ///
-    /// pub fn main() {
-    ///     let term = Term::new().unwrap();
-    ///     let mut buffer : [u8; 12] = [0; 12];
-    ///     
-    ///     // There's nothing to read! so read does nothing and returns 0.
-    ///     assert_eq!(term.read(&mut buffer), 0);
-    ///     assert_eq!(buffer, [0; 12]);
-    /// }
-    /// ```
-    pub fn read(&self, buffer: &mut [u8]) -> usize {
-        if self.err.borrow().is_none() {
-            self.stdin
-                .lock()
-                .unwrap()
-                .read(buffer)
-                .context(ErrorKind::ReadFailed)
-                .unwrap_or_else(|e| {
-                    self.set_err(e);
-                    0
-                })
-        } else {
-            0
-        }
+    #[test]
+    fn modified_keys() {
+        use events::{Key, Modifiers};
+        use std::fs::File;
+
+        let null = File::open("/dev/null").unwrap();
+        let mut term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        term.info
+            .set_ext_string("kUP5".into(), "\x1bOa".into())
+            .unwrap();
+
+        let keys = term.read_keys();
+        assert_eq!(
+            keys.decode(b"\x1b[1;5C\x1b[3;2~\x1b[1;4P\x1bOa"),
+            vec![
+                Key::Modified(Modifiers::CTRL, Box::new(Key::Right)),
+                Key::Modified(Modifiers::SHIFT, Box::new(Key::DeleteChar)),
+                Key::Modified(Modifiers::SHIFT | Modifiers::ALT, Box::new(Key::Fn(1))),
+                Key::Modified(Modifiers::CTRL, Box::new(Key::Up)),
+            ]
+        );
+
+        // xterm's entry lists the modified keys it sends
+        let xterm: terminfo::TermInfoBuf = terminfo::TermInfo::parse(include_bytes!("../test-data/xterm"))
+            .unwrap()
+            .into();
+        let decoder = ::events::Decoder::new(&xterm, &::events::KeyMap::new());
+        let caps = ["kUP3", "kDN5", "kLFT7", "kRIT4", "kHOM6", "kEND3", "kDC5", "kLFT", "kUP"];
+        let input = caps
+            .iter()
+            .map(|&cap| {
+                terminfo::StringField::from_name(cap)
+                    .and_then(|field| xterm.string(field))
+                    .or_else(|| xterm.ext_string(cap))
+                    .unwrap()
+            })
+            .collect::<String>();
+        assert_eq!(
+            decoder.decode(input.as_bytes()),
+            vec![
+                Key::Up.with_modifiers(Modifiers::ALT),
+                Key::Down.with_modifiers(Modifiers::CTRL),
+                Key::Left.with_modifiers(Modifiers::ALT | Modifiers::CTRL),
+                Key::Right.with_modifiers(Modifiers::ALT | Modifiers::SHIFT),
+                Key::Home.with_modifiers(Modifiers::CTRL | Modifiers::SHIFT),
+                Key::End.with_modifiers(Modifiers::ALT),
+                Key::DeleteChar.with_modifiers(Modifiers::CTRL),
+                Key::Left.with_modifiers(Modifiers::SHIFT),
+                Key::Up.with_modifiers(Modifiers::SHIFT),
+            ]
+        );
+
+        assert_eq!(
+            keys.decode(b"\x1b[5~\x1b[6~\x1b[2~\x1b[5;5~"),
+            vec![
+                Key::PageUp,
+                Key::PageDown,
+                Key::Insert,
+                Key::PageUp.with_modifiers(Modifiers::CTRL),
+            ]
+        );
+
+        let key = Key::Left.with_modifiers(Modifiers::ALT);
+        assert_eq!(key.modifiers(), Modifiers::ALT);
+        assert_eq!(key.unmodified(), &Key::Left);
+        assert_eq!(Key::Left.with_modifiers(Modifiers::empty()), Key::Left);
+    }
+
+    #[test]
+    fn escape_timeout() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+        term.set_escape_timeout(Some(Duration::from_millis(20)));
+        let mut keys = term.read_keys();
+
+        master.write_all(b"\x1b").unwrap();
+        let started = Instant::now();
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Escape);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        master.write_all(b"\x1b[Ax").unwrap();
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Up);
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('x'));
     }
 
-    pub fn readline(&self) -> Result<String> {
-        let mut buf = String::new();
-        self.stdin
-            .lock()
-            .unwrap()
-            .read_line(&mut buf)
-            .context(ErrorKind::ReadFailed)?;
-        Ok(buf)
+    #[test]
+    fn read_events() {
+        use events::{Event, Key, Modifiers, Mouse, MouseAction, MouseButton};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+        term.set_mouse_capture(true).unwrap();
+        let mut buf = [0; 24];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b[?1000h\x1b[?1002h\x1b[?1006h");
+
+        let mouse = |action, x, y| {
+            Event::Mouse(Mouse {
+                action,
+                x,
+                y,
+                modifiers: Modifiers::empty(),
+            })
+        };
+        let mut events = term
+            .read_events()
+            .unwrap()
+            .catch_signals(&[signal::Signal::SIGTERM])
+            .unwrap();
+        master.write_all(b"\x1b[<0;3;4Ma\x1b[<2;3;4m\x1b[I").unwrap();
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            mouse(MouseAction::Press(MouseButton::Left), 2, 3)
+        );
+        assert_eq!(events.next().unwrap().unwrap(), Event::Key(Key::Char('a')));
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            mouse(MouseAction::Release(MouseButton::Right), 2, 3)
+        );
+        assert_eq!(events.next().unwrap().unwrap(), Event::Focus(true));
+
+        signal::raise(signal::Signal::SIGTERM).unwrap();
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            Event::Signal(signal::Signal::SIGTERM)
+        );
+
+        // a signal that arrives while waiting for input
+        let signaller = ::std::thread::spawn(|| {
+            ::std::thread::sleep(Duration::from_millis(50));
+            signal::kill(::nix::unistd::getpid(), signal::Signal::SIGTERM).unwrap();
+        });
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            Event::Signal(signal::Signal::SIGTERM)
+        );
+        signaller.join().unwrap();
+        drop(events);
+
+        let keys = term.read_keys();
+        let (decoded, rest) = keys.decode_events(b"\x1b[<64;1;1M\x1b[<48;2;2M\x1b[O\x1b[<0;1");
+        assert_eq!(
+            decoded,
+            vec![
+                mouse(MouseAction::ScrollUp, 0, 0),
+                Event::Mouse(Mouse {
+                    action: MouseAction::Drag(MouseButton::Left),
+                    x: 1,
+                    y: 1,
+                    modifiers: Modifiers::CTRL,
+                }),
+                Event::Focus(false),
+            ]
+        );
+        assert_eq!(rest, b"\x1b[<0;1");
+        assert_eq!(keys.decode(b"\x1b[<0;1;1Mx"), vec![Key::Char('x')]);
+
+        // motion without a button, past where the original encoding stops
+        let (decoded, _) = keys.decode_events(b"\x1b[<35;300;2M\x1b[<65;1;1M");
+        assert_eq!(
+            decoded,
+            vec![
+                mouse(MouseAction::Moved, 299, 1),
+                mouse(MouseAction::ScrollDown, 0, 0),
+            ]
+        );
+
+        // only the modes that change are written
+        term.set_mouse_mode(MouseMode::Motion).unwrap();
+        term.set_mouse_mode(MouseMode::Clicks).unwrap();
+        term.set_mouse_mode(MouseMode::Off).unwrap();
+        term.set_mouse_mode(MouseMode::Off).unwrap();
+        let mut buf = [0; 40];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..],
+            &b"\x1b[?1003h\x1b[?1003l\x1b[?1002l\x1b[?1006l\x1b[?1000l"[..]
+        );
     }
 
-    pub(crate) fn set_err<T: Into<Error>>(&self, e: T) {
-        self.err.replace(Some(e.into()));
+    #[test]
+    fn peek() {
+        use events::{Event, Key};
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        master.write_all(b"gh").unwrap();
+        let mut keys = term.read_keys();
+        assert_eq!(keys.peek().unwrap(), &Key::Char('g'));
+        assert_eq!(keys.peek().unwrap(), &Key::Char('g'));
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('g'));
+        keys.push_back(Key::Char('x'));
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('x'));
+        assert_eq!(keys.next().unwrap().unwrap(), Key::Char('h'));
+
+        master.write_all(b"i").unwrap();
+        let mut events = term.read_events().unwrap();
+        assert_eq!(events.peek().unwrap(), &Event::Key(Key::Char('i')));
+        events.push_back(Event::Focus(true));
+        assert_eq!(events.next().unwrap().unwrap(), Event::Focus(true));
+        assert_eq!(events.next().unwrap().unwrap(), Event::Key(Key::Char('i')));
     }
 
-    pub(crate) fn take_err(&self) -> Error {
-        self.err.replace(None).unwrap()
+    #[test]
+    fn raw_mode() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let _master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let canonical = |term: &Term<_, _>| {
+            term.settings()
+                .termios
+                .local_flags
+                .contains(termios::LocalFlags::ICANON)
+        };
+        assert!(canonical(&term));
+
+        {
+            let raw = term.raw_mode().unwrap();
+            assert!(!canonical(&raw));
+        }
+        assert!(canonical(&term));
+
+        let raw = term.raw_mode().unwrap();
+        assert!(!canonical(&term));
+        raw.restore().unwrap();
+        assert!(canonical(&term));
     }
 
-    pub(crate) fn has_err(&self) -> bool {
-        self.err.borrow().is_some()
+    #[test]
+    fn suspend_settings() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let _master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let canonical = |term: &Term<_, _>| {
+            term.settings()
+                .termios
+                .local_flags
+                .contains(termios::LocalFlags::ICANON)
+        };
+        term.update(term.settings().raw()).unwrap();
+        term.keypad(true).unwrap();
+
+        // what's done around stopping the process in `Term::suspend`
+        let cooked = term.cooked.borrow().clone().unwrap();
+        let stopped = term
+            .with_settings(cooked, || (canonical(&term), term.keypad.get()))
+            .unwrap();
+        assert_eq!(stopped, (true, false));
+        assert!(!canonical(&term));
+        assert!(term.keypad.get());
     }
 
-    pub fn err(&self) -> Result<()> {
-        if self.has_err() {
-            Err(self.take_err())
-        } else {
-            Ok(())
-        }
+    #[test]
+    fn no_echo() {
+        use nix::pty;
+        use std::fs::File;
+        use std::os::unix::io::FromRawFd;
+        use std::panic;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let _master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let echo = |term: &Term<_, _>| {
+            let flags = term.settings().termios.local_flags;
+            (
+                flags.contains(termios::LocalFlags::ECHO),
+                flags.contains(termios::LocalFlags::ICANON),
+            )
+        };
+        assert_eq!(echo(&term), (true, true));
+
+        // turning echo off when it's already off leaves it off
+        term.update(term.settings().echo(false)).unwrap();
+        assert_eq!(term.read_no_echo(|term| echo(term)).unwrap(), (false, true));
+        term.update(term.settings().echo(true)).unwrap();
+
+        assert_eq!(term.read_no_echo(|term| echo(term)).unwrap(), (false, true));
+        assert_eq!(echo(&term), (true, true));
+
+        let panicked = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            term.read_no_echo(|_| panic!("reading failed")).unwrap();
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(echo(&term), (true, true));
     }
 
-    /// Execute a string field
-    fn exec<'a>(&'a self, field: terminfo::StringField) -> Result<terminfo::lang::Executor<'a>> {
-        match self.info.exec(field) {
-            Some(v) => Ok(v),
-            None => Err(ErrorKind::MissingTermInfoField(field).into()),
-        }
+    #[test]
+    fn with_cooked() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let canonical = |term: &Term<_, _>| {
+            term.settings()
+                .termios
+                .local_flags
+                .contains(termios::LocalFlags::ICANON)
+        };
+
+        let raw = term.raw_mode().unwrap();
+        raw.set_focus_reporting(true).unwrap();
+        let mut buf = [0; 8];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b[?1004h");
+        raw.keypad(true).unwrap();
+        let mut buf = [0; 2];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b=");
+
+        let cooked = raw.with_cooked(|| canonical(&term)).unwrap();
+        assert!(cooked);
+        assert!(!canonical(&raw));
+        assert!(raw.focus_reporting.get());
+
+        // focus reporting off, keypad local, then keypad transmit and focus reporting back on
+        let mut buf = [0; 20];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &b"\x1b[?1004l\x1b>\x1b=\x1b[?1004h"[..]);
     }
 
-    /// Wrapper around exec, which immediately runs the string with no args and writes it to `O`.
-    fn write_info_str(&self, field: terminfo::StringField) -> usize {
-        match self.exec(field) {
-            Ok(mut v) => v
-                .write(self.stdout.lock().unwrap().deref_mut())
-                .context(ErrorKind::FailedToRunTerminfo(field))
-                .unwrap_or_else(|e| {
-                    self.set_err(e);
-                    0
-                }),
-            Err(e) => {
-                self.err.replace(Some(
-                    e.context(ErrorKind::FailedToRunTerminfo(field)).into(),
-                ));
-                0
+    #[test]
+    fn buffered_writes() {
+        // counts the writes made to it
+        struct Writes(Vec<Vec<u8>>);
+
+        impl io::Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(buf.to_vec());
+                Ok(buf.len())
             }
-        }
-    }
 
-    pub fn settings(&self) -> Settings {
-        Settings {
-            termios: match termios::tcgetattr(self.as_raw_fd()) {
-                Ok(v) => v,
-                Err(e) => {
-                    // This should be caught on the next `update`;
-                    self.set_err(e.context(ErrorKind::FailedToSetTermios));
-                    unsafe { termios::Termios::default_uninit() }
-                }
-            },
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
         }
-    }
 
-    pub fn update(&self, settings: Settings) -> Result<()> {
-        self.err()?;
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let monochrome = MonochromeMap::default();
+        let out = Mutex::new(Writes(Vec::new()));
 
-        termios::tcsetattr(
-            self.as_raw_fd(),
-            termios::SetArg::TCSAFLUSH,
-            &settings.termios,
-        ).context(ErrorKind::FailedToSetTermios)?;
-        Ok(())
-    }
+        let w = TermWriter::new(&info, &monochrome, out.lock().unwrap(), None)
+            .bold()
+            .print("a")
+            .foreground("red")
+            .print("b")
+            .move_to(0, 0);
+        assert!(out.try_lock().is_err());
+        let written = w.done().unwrap();
 
-    pub fn flush(&self) {
-        match self.stdout.lock().unwrap().flush() {
-            Ok(_) => (),
-            Err(e) => self.set_err(e.context(ErrorKind::WriteFailed)),
+        {
+            let writes = &out.lock().unwrap().0;
+            assert_eq!(writes.len(), 1);
+            assert_eq!(writes[0].len(), written);
         }
-    }
 
-    pub fn read_keys<'a>(&'a self) -> Keys<'a, I, O> {
-        Keys::new(self)
+        TermWriter::new(&info, &monochrome, out.lock().unwrap(), None).print("c");
+        assert_eq!(out.lock().unwrap().0.len(), 2);
     }
 
-    pub fn clear_line_after_cursor(&self) {
-        self.write_info_str(terminfo::ClrEol);
-    }
+    #[test]
+    fn sync_update() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.set_color_choice(ColorChoice::Always);
+
+        let terminal = thread::spawn(move || {
+            let mut buf = [0; 12];
+            master.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"\x1b[?2026$p\x1b[c");
+            master.write_all(b"\x1b[?2026;2$y\x1b[?62;22c").unwrap();
+            master
+        });
+        assert!(term.supports_sync_update().unwrap());
+        let mut master = terminal.join().unwrap();
 
-    pub fn save_cursor(&self) {
-        self.write_info_str(terminfo::SaveCursor);
+        term.sync_update(|w| w.write_bytes(b"x").done()).unwrap();
+        let mut buf = [0; 22];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b[?2026h\x1b[0m\x0fx\x1b[?2026l");
     }
 
-    pub fn restore_cursor(&self) {
-        self.write_info_str(terminfo::RestoreCursor);
-    }
+    #[test]
+    fn probe() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
 
-    pub fn prompt<T: AsRef<str>>(&self, prompt: T) -> Result<String> {
-        self.writer().print(prompt).done()?;
-        self.readline()
+        let terminal = thread::spawn(move || {
+            let mut buf = [0; 7];
+            master.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"\x1b[>c\x1b[c");
+            master.write_all(b"\x1b[>41;353;0c\x1b[?64;1;4;22c").unwrap();
+            master
+        });
+        let attributes = term.probe().unwrap().unwrap();
+        let _master = terminal.join().unwrap();
+        assert_eq!(
+            attributes,
+            DeviceAttributes {
+                vt_level: 4,
+                features: vec![1, 4, 22],
+                terminal_id: Some(41),
+                firmware_version: Some(353),
+            }
+        );
+        assert!(attributes.sixel());
+
+        let parse = |reply: &[u8]| DeviceAttributes::parse(&ansi::Parser::new().parse(reply));
+        let vt100 = parse(b"\x1b[?1;2c").unwrap();
+        assert_eq!(vt100.vt_level, 1);
+        assert!(!vt100.sixel());
+        assert_eq!(vt100.terminal_id, None);
+        assert_eq!(parse(b"\x1b[?x"), None);
     }
 
-    #[inline]
-    pub fn colors(&self) -> usize {
-        // There has to be at least two colors... right???
-        self.info.number(terminfo::MaxColors).unwrap_or(2) as usize
-    }
-}
+    #[test]
+    fn background_color() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
 
-impl<I, O> AsRawFd for Term<I, O>
-where
-    I: io::Read + AsRawFd,
-    O: io::Write + AsRawFd,
-{
-    fn as_raw_fd(&self) -> RawFd {
-        self.stdin_fd
+        let terminal = thread::spawn(move || {
+            let mut buf = [0; 8];
+            master.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"\x1b]11;?\x1b\\");
+            master.write_all(b"\x1b]11;rgb:2020/2828/3030\x1b\\").unwrap();
+            master
+        });
+        assert_eq!(term.is_dark_theme().unwrap(), Some(true));
+        let _master = terminal.join().unwrap();
+
+        let parse = |reply: &[u8]| parse_color_reply(&ansi::Parser::new().parse(reply));
+        assert_eq!(parse(b"\x1b]11;rgb:ffff/8/00\x07"), Some(ansi::Color::Rgb(255, 136, 0)));
+        assert_eq!(parse(b"\x1b]11;rgb:ff/ff\x07"), None);
+        assert_eq!(parse(b"\x1b]11;rgb:fffff/0/0\x07"), None);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::io;
-    use term::*;
-    use terminfo;
+    #[test]
+    fn clearing() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        term.clear_screen();
+        term.clear_below();
+        term.clear_above();
+        term.clear_line();
+        term.clear_line_before_cursor();
+        term.clear_line_after_cursor();
+        term.flush();
+        term.err().unwrap();
+
+        let mut buf = [0; 25];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x1b[H\x1b[2J\x1b[J\x1b[1J\x1b[2K\x1b[1K\x1b[K");
+    }
 
-    const TERMINFO: &'static [u8] = include_bytes!("../test-data/rxvt");
+    #[test]
+    fn bell() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+
+        term.bell();
+        term.set_bell_style(BellStyle::Off);
+        term.bell();
+        term.set_bell_style(BellStyle::Visual);
+        term.bell();
+        term.err().unwrap();
+
+        let flash = term.info.string(terminfo::FlashScreen).unwrap();
+        let mut buf = vec![0; 1 + flash.len()];
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, format!("\x07{}", flash).into_bytes());
+    }
 
-    struct FakeStdin {
-        buffer: Vec<u8>,
+    #[test]
+    fn print_segments() {
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let monochrome = MonochromeMap::default();
+        let out = Mutex::new(Vec::new());
+
+        let bold = Style::new().bold();
+        TermWriter::new(&info, &monochrome, out.lock().unwrap(), None)
+            .print_segments(&[
+                (bold.clone().foreground(1), "a"),
+                (bold.clone().foreground(2), "b"),
+                (bold, "c"),
+                (Style::new(), "d"),
+            ])
+            .done()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.into_inner().unwrap()).unwrap(),
+            "\x1b[0;1m\x0f\x1b[31ma\x1b[32mb\x1b[0;1m\x0fc\x1b[0m\x0fd\x1b[m\x0f"
+        );
     }
 
-    struct FakeStdout {
-        buffer: Vec<u8>,
+    #[test]
+    fn print_styled() {
+        let info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let monochrome = MonochromeMap::default();
+        let out = Mutex::new(Vec::new());
+
+        let w = TermWriter::new(&info, &monochrome, out.lock().unwrap(), None);
+        termwrite!(w, "{bold}{fg=red}{}:{reset} {{{1}}}", "error", 2)
+            .done()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.lock().unwrap().clone()).unwrap(),
+            "\x1b[0;1m\x0f\x1b[31merror:\x1b[0m\x0f {2}\x1b[m\x0f"
+        );
+
+        let segments = styled_segments(Style::new(), "a{underline}b{bg=blue}c", &[]).unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                (Style::new(), "a".to_string()),
+                (Style::new().underline(), "b".to_string()),
+                (
+                    Style::new().underline().background(4),
+                    "c".to_string()
+                ),
+            ]
+        );
+        for invalid in &["{shiny}", "{fg=nope}", "{}", "{bold"] {
+            assert!(styled_segments(Style::new(), invalid, &[]).is_err());
+        }
     }
 
-    impl FakeStdin {
-        fn new() -> FakeStdin {
-            FakeStdin { buffer: Vec::new() }
+    #[test]
+    fn palette_quantization() {
+        for i in 16..=255u8 {
+            assert_eq!(nearest_palette_index(palette_rgb(i, 256), 256), i);
         }
+
+        assert_eq!(nearest_palette_index((255, 0, 0), 256), 196);
+        assert_eq!(nearest_palette_index((128, 128, 128), 256), 244);
+        assert_eq!(nearest_palette_index((100, 149, 237), 256), 69);
+        assert_eq!(nearest_palette_index((255, 0, 0), 88), 64);
+        assert_eq!(nearest_palette_index(palette_rgb(196, 256), 88), 64);
+        assert_eq!(nearest_palette_index((231, 231, 231), 88), 87);
     }
 
-    impl<'a> AsRawFd for &'a mut FakeStdin {
-        fn as_raw_fd(&self) -> RawFd {
+    #[test]
+    fn region() {
+        let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let monochrome = MonochromeMap::default();
+        let out = Mutex::new(Vec::new());
+        TermWriter::new(&info, &monochrome, out.lock().unwrap(), None)
+            .region(Rect::new(10, 5, 4, 2))
+            .print("abcdef\nxy\nzzz")
+            .region(Rect::new(1, 1, 8, 8))
+            .move_to(1, 0)
+            .print("qq")
+            .done()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.into_inner().unwrap()).unwrap(),
+            "\x1b[6;11H\x1b[0m\x0fabcd\x1b[7;11H\x1b[0m\x0fxy\x1b[7;12H\x1b[7;13H\x1b[0m\x0fqq"
+        );
+
+        assert_eq!(
+            Rect::new(2, 2, 4, 4).intersection(Rect::new(4, 0, 10, 3)),
+            Rect::new(4, 2, 2, 1)
+        );
+        assert_eq!(
+            Rect::new(0, 0, 2, 2).intersection(Rect::new(5, 5, 1, 1)).width,
             0
-        }
+        );
     }
 
-    impl FakeStdout {
-        fn new() -> FakeStdout {
-            FakeStdout { buffer: Vec::new() }
-        }
+    #[test]
+    fn extra_attributes() {
+        let monochrome = MonochromeMap::default();
+        let write = |info: &terminfo::TermInfoBuf| {
+            let out = Mutex::new(Vec::new());
+            TermWriter::new(info, &monochrome, out.lock().unwrap(), None)
+                .strikethrough()
+                .underline_style(UnderlineStyle::Curly)
+                .underline_color(ansi::Color::Rgb(255, 0, 128))
+                .print("a")
+                .style(&"underline=double ul=1".parse().unwrap())
+                .print("b")
+                .done()
+                .unwrap();
+            String::from_utf8(out.into_inner().unwrap()).unwrap()
+        };
+
+        // rxvt doesn't support any of them, so only the underline is written
+        let mut info: terminfo::TermInfoBuf = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        assert_eq!(write(&info), "\x1b[0;4m\x0fa\x1b[0;4m\x0fb");
+
+        info.set_ext_boolean("Su".into(), true).unwrap();
+        assert_eq!(
+            write(&info),
+            "\x1b[0;4m\x0f\x1b[4:3m\x1b[58:2::255:0:128ma\x1b[0;4m\x0f\x1b[4:2m\x1b[58:5:1mb"
+        );
+
+        info.set_ext_string("smxx".into(), "\x1b[9m".into()).unwrap();
+        info.set_ext_string("Smulx".into(), "\x1b[4:%p1%dm".into()).unwrap();
+        info.set_ext_string("Setulc".into(), "\x1b[58:2:%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%dm".into())
+            .unwrap();
+        assert_eq!(
+            write(&info),
+            "\x1b[0;4m\x0f\x1b[9m\x1b[4:3m\x1b[58:2:255:0:128ma\x1b[0;4m\x0f\x1b[4:2m\x1b[58:2:205:0:0mb"
+        );
     }
 
-    impl<'a> AsRawFd for &'a mut FakeStdout {
-        fn as_raw_fd(&self) -> RawFd {
-            1
+    #[test]
+    fn check() {
+        struct Broken;
+
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
         }
+
+        let info = terminfo::TermInfo::parse(TERMINFO).unwrap().into();
+        let monochrome = MonochromeMap::default();
+        let out = Mutex::new(Vec::new());
+        let styles = RefCell::new(HashMap::new());
+        styles.borrow_mut().insert("header".to_string(), Style::new().bold());
+        let mut w = TermWriter::new(&info, &monochrome, out.lock().unwrap(), None);
+        w.styles = Some(&styles);
+
+        let w = w.try_named_style("header").unwrap().try_print("a").unwrap();
+        let err = w.check().unwrap().try_named_style("footer").err().unwrap();
+        assert_eq!(err.kind(), &ErrorKind::UnknownStyleName("footer".into()));
+        assert_eq!(&out.lock().unwrap()[..], b"\x1b[0;1m\x0fa");
+
+        let broken = Mutex::new(Broken);
+        let w = TermWriter::new(&info, &monochrome, broken.lock().unwrap(), None)
+            .try_print("a")
+            .unwrap();
+        assert_eq!(w.try_flush().err().unwrap().kind(), &ErrorKind::WriteFailed);
     }
 
-    impl<'a> io::Read for &'a mut FakeStdin {
-        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-            let len = if buf.len() > self.buffer.len() {
-                self.buffer.len()
-            } else {
-                buf.len()
-            };
+    #[test]
+    fn prompt_secret() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        let echo = |term: &Term<File, File>| {
+            term.settings()
+                .termios
+                .local_flags
+                .contains(termios::LocalFlags::ECHO)
+        };
+        let before = echo(&term);
+
+        // type once the terminal is in raw mode, since switching to it throws away any pending input
+        let type_keys = |master: &File, keys: &'static [u8]| {
+            let mut master = master.try_clone().unwrap();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                master.write_all(keys).unwrap();
+            })
+        };
 
-            self.buffer
-                .drain(..len)
-                .enumerate()
-                .for_each(|(i, c)| buf[i] = c);
-            Ok(len)
+        let typing = type_keys(&master, b"ab\x7fc\xc3\xa9\r");
+        assert_eq!(term.prompt_secret("pw: ", Some('*')).unwrap(), "acé");
+        typing.join().unwrap();
+        assert_eq!(echo(&term), before);
+
+        // characters decoded from UTF-8 input are kept whole
+        let encoding = term.input_encoding();
+        term.set_input_encoding(::events::Encoding::Utf8);
+        let typing = type_keys(&master, "pässwörd\u{20ac}\x7f\r".as_bytes());
+        assert_eq!(term.prompt_secret("", None).unwrap(), "pässwörd");
+        typing.join().unwrap();
+        term.set_input_encoding(encoding);
+
+        let mut out = Vec::new();
+        while !out.ends_with(b"\n") {
+            let mut buf = [0; 256];
+            let n = master.read(&mut buf).unwrap();
+            out.extend_from_slice(&buf[..n]);
         }
+        // without the attributes that are reset before each write
+        let out = String::from_utf8_lossy(&out).replace("\x1b[0m\x0f", "");
+        assert!(out.starts_with("pw: "), "{:?}", out);
+        assert!(out.contains("**\x08 \x08**"), "{:?}", out);
+
+        let typing = type_keys(&master, b"abc\x03");
+        assert_eq!(
+            term.prompt_secret("", None).unwrap_err().kind(),
+            &ErrorKind::Cancelled
+        );
+        typing.join().unwrap();
+        assert_eq!(echo(&term), before);
     }
 
-    impl<'a> io::Write for &'a mut FakeStdout {
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            self.buffer.extend(buf);
-            Ok(buf.len())
-        }
+    #[test]
+    fn readline_editing() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::unix::io::FromRawFd;
+        use std::thread;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
 
-        fn flush(&mut self) -> io::Result<()> {
-            Ok(())
+        let mut keys = b"hllo\x01".to_vec();
+        keys.extend_from_slice(term.info.string(terminfo::KeyRight).unwrap().as_bytes());
+        keys.extend_from_slice(b"e\x05 world\x17\r");
+
+        // type once the terminal is in raw mode, since switching to it throws away any pending input
+        let mut writer = master.try_clone().unwrap();
+        let typing = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.write_all(&keys).unwrap();
+        });
+        assert_eq!(term.readline().unwrap(), "hello \n");
+        typing.join().unwrap();
+
+        // the completions are listed, then cleared on the next key
+        term.set_completer(|_, _| vec!["ab".to_owned(), "ac".to_owned()]);
+        let mut writer = master.try_clone().unwrap();
+        let typing = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.write_all(b"x\t\x17done\r").unwrap();
+        });
+        assert_eq!(term.readline().unwrap(), "done\n");
+        typing.join().unwrap();
+
+        // the output from the first line hasn't been read yet either
+        let mut out = Vec::new();
+        while !(out.ends_with(b"\n") && String::from_utf8_lossy(&out).contains("done")) {
+            let mut buf = [0; 1024];
+            let n = (&master).read(&mut buf).unwrap();
+            out.extend_from_slice(&buf[..n]);
         }
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.contains("\x1b7\r\nab  ac\x1b8"), "{:?}", out);
+        assert!(out.contains("\x1b7\r\n\x1b[J\x1b8"), "{:?}", out);
     }
 
     #[test]
-    fn term() {
-        let mut stdin = FakeStdin::new();
-        let mut stdout = FakeStdout::new();
+    fn cursor_style() {
+        use nix::pty;
+        use std::fs::File;
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let pty = pty::openpty(None, None).unwrap();
+        let mut master = unsafe { File::from_raw_fd(pty.master) };
+        let slave = unsafe { File::from_raw_fd(pty.slave) };
+        let mut read = |n: usize| {
+            let mut buf = vec![0; n];
+            master.read_exact(&mut buf).unwrap();
+            buf
+        };
+
+        let mut term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            slave.try_clone().unwrap(),
+            slave,
+        );
+        term.update(term.settings().raw()).unwrap();
+        term.set_cursor_style(CursorStyle::SteadyBar)
+            .unwrap()
+            .restore()
+            .unwrap();
+        assert_eq!(read(10), b"\x1b[6 q\x1b[0 q");
+
+        term.info
+            .set_ext_string("Ss".into(), "<%p1%d>".into())
+            .unwrap();
+        term.info.set_ext_string("Se".into(), "<>".into()).unwrap();
         {
-            let term = Term::from_streams(
-                terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
-                &mut stdin,
-                &mut stdout,
-            );
-            term.writer().bold().print("Hello World?").done().unwrap();
+            let underline = term.set_cursor_style(CursorStyle::BlinkingUnderline)
+                .unwrap();
+            assert_eq!(read(3), b"<3>");
+
+            // dropping the guard puts back the shape from before it
+            let block = underline.set_cursor_style(CursorStyle::SteadyBlock).unwrap();
+            assert_eq!(read(3), b"<2>");
+            drop(block);
+            assert_eq!(read(3), b"<3>");
         }
-        assert_eq!(&stdout.buffer, b"\x1b[0;1mHello World?\x1b[m\x0F");
+        assert_eq!(read(2), b"<>");
+
+        // the terminal doesn't touch the cursor when it's dropped
+        drop(term);
+        let mut rest = Vec::new();
+        master.read_to_end(&mut rest).ok();
+        assert_eq!(rest, b"");
     }
 
     #[test]
@@ -1043,6 +6400,8 @@ mod test {
                 &mut stdin,
                 &mut stdout,
             );
+            term.set_color_choice(ColorChoice::Always);
+            term.set_assume_tty(true);
             term.writer()
                 .bold()
                 .print("Hello")
@@ -1054,7 +6413,7 @@ mod test {
         }
         assert_eq!(
             String::from_utf8(stdout.buffer.clone()).unwrap(),
-            "\x1b[0;1mHello\x1b[0m \x1b[31mWorld\x1b[0m?"
+            "\x1b[0;1m\x0fHello\x1b[0m\x0f\x1b[31mWorld\x1b[0m\x0f?"
         );
         stdout.buffer.clear();
 
@@ -1064,6 +6423,8 @@ mod test {
                 &mut stdin,
                 &mut stdout,
             );
+            term.set_color_choice(ColorChoice::Always);
+            term.set_assume_tty(true);
             term.writer()
                 .bold()
                 .print("Hello")
@@ -1076,7 +6437,7 @@ mod test {
         }
         assert_eq!(
             String::from_utf8(stdout.buffer).unwrap(),
-            "\x1b[1m\x1b[31mHi\x1b[39m\x1b[22m?"
+            "\x1b[0;1m\x0fHello\x1b[0;1m\x0f\x1b[31mWorld\x1b[0m\x0f?"
         );
     }
 }
@@ -1,5 +1,8 @@
 use failure;
-use std::{fmt, result};
+use std::path::PathBuf;
+use terminfo::lang::Limit;
+use terminfo::StringField;
+use std::{ascii, fmt, result};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -11,6 +14,42 @@ pub type Result<T> = result::Result<T, Error>;
 #[derive(Debug)]
 pub struct Error {
     inner: failure::Context<ErrorKind>,
+    location: Option<Location>,
+}
+
+/// Where in a capability string an error happened.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Location {
+    /// The offset of the byte the error happened at.
+    pub offset: usize,
+
+    /// The source around `offset`, with non-printable characters escaped.
+    pub excerpt: String,
+}
+
+impl Location {
+    /// The number of bytes on either side of the offset included in the excerpt.
+    const CONTEXT: usize = 8;
+
+    /// Describe the position `offset` in `src`.
+    pub fn new(src: &[u8], offset: usize) -> Location {
+        let start = offset.saturating_sub(Location::CONTEXT);
+        let end = (offset + Location::CONTEXT).min(src.len());
+        Location {
+            offset,
+            excerpt: src[start.min(end)..end]
+                .iter()
+                .flat_map(|&c| ascii::escape_default(c))
+                .map(char::from)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at byte {}, near \"{}\"", self.offset, self.excerpt)
+    }
 }
 
 /// Outlines the various points where TermInfo routines may fail.
@@ -22,6 +61,15 @@ pub enum ErrorKind {
     #[fail(display = "could not find a valid path to the terminfo file.")]
     FailedToFindTermInfo,
 
+    #[fail(display = "\"{}\" is not a valid terminal name", _0)]
+    InvalidTerminalName(String),
+
+    #[fail(display = "no terminfo entry for \"{}\" (tried {:?})", _0, _1)]
+    TermInfoNotFound(String, Vec<PathBuf>),
+
+    #[fail(display = "failed to read a terminfo database directory")]
+    FailedToReadDatabase,
+
     #[fail(display = "failed to parse the terminfo file.")]
     FailedToParseFile,
 
@@ -73,12 +121,42 @@ pub enum ErrorKind {
     #[fail(display = "invalid argument identifier")]
     InvalidArgumentIdentifier,
 
+    #[fail(display = "invalid variable name, variables must be a single letter")]
+    InvalidVariableName,
+
     #[fail(display = "unexpected argument type, expected a {}, got a(n) {}", _0, _1)]
     UnexpectedArgumentType(&'static str, &'static str),
 
     #[fail(display = "failed to write string literal")]
     FailedToWriteStringLiteral,
 
+    #[fail(display = "failed to write the expanded string")]
+    FailedToWriteOutput,
+
+    #[fail(display = "the expanded string isn't valid UTF-8")]
+    InvalidUtf8Output,
+
+    #[fail(display = "the terminal doesn't have the {:?} capability", _0)]
+    MissingCapability(StringField),
+
+    #[fail(display = "failed to expand the {:?} capability", _0)]
+    FailedToExpandCapability(StringField),
+
+    #[fail(display = "parameter %p{} wasn't given", _0)]
+    MissingArgument(usize),
+
+    #[fail(display = "division by zero")]
+    DivisionByZero,
+
+    #[fail(display = "the capability string went over the limit of {}", _0)]
+    LimitExceeded(Limit),
+
+    #[fail(display = "the ops can't be written as a capability string")]
+    Unrenderable,
+
+    #[fail(display = "%t, %e or %; without a matching %?")]
+    UnbalancedConditional,
+
     #[fail(display = "unexpected EOF")]
     UnexpectedEof,
 
@@ -94,6 +172,19 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Get where in the capability string this error happened, if it happened while parsing or running one.
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// Record that this error happened at `offset` in `src`, if a location hasn't already been set.
+    pub(crate) fn at(mut self, src: &[u8], offset: usize) -> Error {
+        if self.location.is_none() {
+            self.location = Some(Location::new(src, offset));
+        }
+        self
+    }
 }
 
 impl failure::Fail for Error {
@@ -108,7 +199,11 @@ impl failure::Fail for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.inner, f)
+        fmt::Display::fmt(&self.inner, f)?;
+        if let Some(ref location) = self.location {
+            write!(f, " ({})", location)?;
+        }
+        Ok(())
     }
 }
 
@@ -116,12 +211,16 @@ impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
         Error {
             inner: failure::Context::new(kind),
+            location: None,
         }
     }
 }
 
 impl From<failure::Context<ErrorKind>> for Error {
     fn from(inner: failure::Context<ErrorKind>) -> Error {
-        Error { inner: inner }
+        Error {
+            inner,
+            location: None,
+        }
     }
 }
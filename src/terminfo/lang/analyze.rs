@@ -0,0 +1,137 @@
+use terminfo::errors::*;
+use terminfo::lang::parser::{Op, Parser, Variable};
+
+/// How a capability string uses one of its parameters.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParameterType {
+    /// The parameter is never pushed.
+    Unused,
+
+    /// The parameter is used in arithmetic, or printed as a number or character.
+    Integer,
+
+    /// The parameter is printed with `%s`, or its length is taken with `%l`.
+    String,
+
+    /// The parameter is used, but only in a way that accepts any type (e.g. as a condition).
+    Any,
+}
+
+/// The parameters a capability string expects, as found by `analyze`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Analysis {
+    /// The type of each parameter, up to the highest parameter the string uses.
+    pub parameters: Vec<ParameterType>,
+
+    /// True if the string uses `%i` to increment the first two parameters.
+    pub increments: bool,
+}
+
+impl Analysis {
+    /// The number of parameters the string consumes.
+    #[inline]
+    pub fn arity(&self) -> usize {
+        self.parameters.len()
+    }
+}
+
+/// Tracks which parameter, if any, each value on the stack came from.
+struct State {
+    stack: Vec<Option<usize>>,
+    dynamic_vars: [Option<usize>; 26],
+    static_vars: [Option<usize>; 26],
+    parameters: Vec<ParameterType>,
+}
+
+impl State {
+    fn variable(&mut self, var: Variable) -> &mut Option<usize> {
+        match var {
+            Variable::Dynamic(i) => &mut self.dynamic_vars[i as usize],
+            Variable::Static(i) => &mut self.static_vars[i as usize],
+        }
+    }
+
+    fn pop(&mut self, ty: ParameterType) {
+        if let Some(Some(i)) = self.stack.pop() {
+            if self.parameters[i] == ParameterType::Any {
+                self.parameters[i] = ty;
+            }
+        }
+    }
+
+    fn map(&mut self, argc: usize, ty: ParameterType) {
+        for _ in 0..argc {
+            self.pop(ty);
+        }
+        self.stack.push(None);
+    }
+}
+
+/// Find the number and types of parameters used by the capability string `src`.
+///
+/// Both sides of every conditional are considered, and a parameter's type is decided by the first operation that uses it.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::{analyze, ParameterType};
+///
+/// let analysis = analyze(b"\x1b[%i%p1%d;%p3%dH").unwrap();
+/// assert_eq!(analysis.arity(), 3);
+/// assert_eq!(analysis.parameters[1], ParameterType::Unused);
+/// assert!(analysis.increments);
+/// ```
+pub fn analyze(src: &[u8]) -> Result<Analysis> {
+    let mut state = State {
+        stack: Vec::new(),
+        dynamic_vars: [None; 26],
+        static_vars: [None; 26],
+        parameters: Vec::new(),
+    };
+    let mut increments = false;
+
+    for op in Parser::new(src) {
+        match op? {
+            Op::PushUserArg(i) => {
+                if state.parameters.len() <= i {
+                    state.parameters.resize(i + 1, ParameterType::Unused);
+                }
+                if state.parameters[i] == ParameterType::Unused {
+                    state.parameters[i] = ParameterType::Any;
+                }
+                state.stack.push(Some(i));
+            }
+            Op::Push(_) => state.stack.push(None),
+            Op::SetVariable(var) => {
+                let val = state.stack.pop().and_then(|v| v);
+                *state.variable(var) = val;
+            }
+            Op::PushVariable(var) => {
+                let val = *state.variable(var);
+                state.stack.push(val);
+            }
+            Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Mod
+            | Op::BitAnd
+            | Op::BitOr
+            | Op::BitXor
+            | Op::Less
+            | Op::Greater
+            | Op::Equal => state.map(2, ParameterType::Integer),
+            Op::Invert | Op::Not => state.map(1, ParameterType::Integer),
+            Op::StrLen => state.map(1, ParameterType::String),
+            Op::Print(ref p) if p.character == 's' => state.pop(ParameterType::String),
+            Op::Print(_) => state.pop(ParameterType::Integer),
+            Op::BranchTrue(_) | Op::BranchFalse(_) => state.pop(ParameterType::Any),
+            Op::IncrementArgs => increments = true,
+            Op::Jump(_) | Op::NoOp | Op::PrintSlice(_) => (),
+        }
+    }
+
+    Ok(Analysis {
+        parameters: state.parameters,
+        increments,
+    })
+}
@@ -1,18 +1,118 @@
 use failure::ResultExt;
-use std::collections::VecDeque;
+use std::fmt;
 use std::io;
+use std::str;
 use terminfo::errors::*;
-use terminfo::lang::parser::{Op, Parser};
-use terminfo::lang::Argument;
+use terminfo::lang::parser::{Op, Parser, Variable};
+use terminfo::lang::stack::Stack;
+use terminfo::lang::{Argument, Program};
+
+/// What happens when a capability string divides by zero with `%/` or `%m`.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum ZeroDivision {
+    /// The result is 0, this is what ncurses does.
+    #[default]
+    Zero,
+
+    /// Execution stops with a `DivisionByZero` error.
+    Fail,
+}
+
+/// A limit set with `Limits`, reported by a `LimitExceeded` error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Limit {
+    /// The maximum number of ops executed.
+    Ops(usize),
+
+    /// The maximum number of bytes written.
+    Output(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Ops(n) => write!(f, "{} ops", n),
+            Limit::Output(n) => write!(f, "{} bytes of output", n),
+        }
+    }
+}
+
+/// Bounds on how much work running a capability string can do, by default there are none.
+///
+/// Strings from untrusted terminfo files can ask for enormous amounts of output (e.g. `%999999999d`),
+/// running them with limits turns that into a `LimitExceeded` error.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Limits {
+    /// The maximum number of ops executed in a single run, ops skipped by a conditional don't count.
+    pub max_ops: Option<usize>,
+
+    /// The maximum number of bytes written in a single run.
+    ///
+    /// Padding is checked before it's written, but a single string parameter may go over the limit before the error is returned.
+    pub max_output: Option<usize>,
+}
+
+/// A record of a single op, passed to the callback given to `Executor::trace`.
+#[derive(Debug)]
+pub struct Trace<'t, 'a: 't> {
+    /// The op that was executed.
+    pub op: &'t Op<'a>,
+
+    /// The stack after the op was executed, the top of the stack is the last item.
+    pub stack: &'t [Argument],
+
+    /// The bytes written by the op.
+    pub output: &'t [u8],
+}
+
+impl<'t, 'a> fmt::Display for Trace<'t, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} stack={:?}", self.op, self.stack)?;
+        if !self.output.is_empty() {
+            write!(f, " output={:?}", String::from_utf8_lossy(self.output))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a capability string.
+///
+/// An executor can be reused, its stack and output buffer are kept between runs.
+/// Use `clear_args` or `reset` before running it with new parameters.
 pub struct Executor<'a> {
     src: &'a [u8],
     env: ExecutionEnvironment,
     argc: usize,
+    buf: Vec<u8>,
 }
 
+/// The state capability strings run in: the parameters, the stack, and the variables.
+///
+/// Static variables (`%PA`-`%PZ`) are kept between runs, like they are between calls to curses' `tparm`,
+/// so one environment can be shared by every string that expects them to persist.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::{ExecutionEnvironment, Program};
+///
+/// let save = Program::compile(b"%p1%PA").unwrap();
+/// let restore = Program::compile(b"%gA%d").unwrap();
+///
+/// let mut env = ExecutionEnvironment::new();
+/// let mut out = Vec::new();
+/// env.run(&save, &[7.into()], &mut out).unwrap();
+/// env.run(&restore, &[], &mut out).unwrap();
+/// assert_eq!(out, b"7");
+/// ```
 pub struct ExecutionEnvironment {
-    stack: VecDeque<Argument>,
+    stack: Stack,
     arguments: [Option<Argument>; 9],
+    static_vars: [Option<Argument>; 26],
+    dynamic_vars: [Option<Argument>; 26],
+    zero_division: ZeroDivision,
+    strict: bool,
+    limits: Limits,
+    steps: usize,
 }
 
 impl<'a> Executor<'a> {
@@ -21,6 +121,7 @@ impl<'a> Executor<'a> {
             env: ExecutionEnvironment::new(),
             src: src,
             argc: 0,
+            buf: Vec::new(),
         }
     }
 
@@ -43,26 +144,187 @@ impl<'a> Executor<'a> {
         self
     }
 
+    /// Replace the arguments with the first 9 items of `args`.
+    pub fn set_args(&mut self, args: &[Argument]) -> &mut Executor<'a> {
+        self.env.set_args(args);
+        self.argc = args.len().min(9);
+        self
+    }
+
+    /// Unset every argument, the next call to `arg` sets the first argument again.
+    pub fn clear_args(&mut self) {
+        self.env.clear_args();
+        self.argc = 0;
+    }
+
+    /// Unset every argument and variable, leaving the executor as it was when it was created.
+    pub fn reset(&mut self) {
+        self.env.reset();
+        self.argc = 0;
+    }
+
+    /// Choose what happens when the string divides by zero, by default the result is 0.
+    #[inline]
+    pub fn on_zero_division(mut self, z: ZeroDivision) -> Executor<'a> {
+        self.env.set_zero_division(z);
+        self
+    }
+
+    /// In strict mode using a parameter that wasn't set is a `MissingArgument` error, instead of using 0,
+    /// and chars aren't used as integers (or integers as chars) the way ncurses' `tparm` allows.
+    #[inline]
+    pub fn strict(mut self, strict: bool) -> Executor<'a> {
+        self.env.set_strict(strict);
+        self
+    }
+
+    /// Stop with a `LimitExceeded` error if the string runs too many ops or writes too much.
+    #[inline]
+    pub fn limits(mut self, limits: Limits) -> Executor<'a> {
+        self.env.set_limits(limits);
+        self
+    }
+
+    /// Run the string, and collect the output in a `String`.
+    ///
+    /// If the output isn't valid UTF-8 an `InvalidUtf8Output` error is returned.
+    pub fn to_string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.vec()?).context(ErrorKind::InvalidUtf8Output)?)
+    }
+
+    /// An alias for `to_string`.
+    #[inline]
     pub fn string(&mut self) -> Result<String> {
-        Ok(String::from_utf8(self.vec()?).unwrap())
+        self.to_string()
+    }
+
+    /// Run the string, writing the output to a `fmt::Write`, like a `String` or `fmt::Formatter`.
+    ///
+    /// # Examples
+    /// ```
+    /// use nixterm::terminfo::lang::Executor;
+    ///
+    /// let mut status = String::from("cursor: ");
+    /// Executor::new(b"%p1%d,%p2%d")
+    ///     .arg(3)
+    ///     .arg(4)
+    ///     .write_fmt(&mut status)
+    ///     .unwrap();
+    /// assert_eq!(status, "cursor: 3,4");
+    /// ```
+    pub fn write_fmt<W: fmt::Write>(&mut self, w: &mut W) -> Result<usize> {
+        self.buf.clear();
+        self.env.write(&mut Parser::new(self.src), &mut self.buf)?;
+        let s = str::from_utf8(&self.buf).context(ErrorKind::InvalidUtf8Output)?;
+        w.write_str(s).context(ErrorKind::FailedToWriteOutput)?;
+        Ok(s.len())
     }
 
     pub fn vec(&mut self) -> Result<Vec<u8>> {
         let mut w = Vec::new();
-        self.env.write(&mut Parser::new(self.src), &mut w);
+        self.env.write(&mut Parser::new(self.src), &mut w)?;
         Ok(w)
     }
 
+    /// Run the string like `write`, calling `f` after every op that's executed.
+    ///
+    /// # Examples
+    /// ```
+    /// use nixterm::terminfo::lang::Executor;
+    ///
+    /// let mut out = Vec::new();
+    /// Executor::new(b"%p1%{8}%-%d")
+    ///     .arg(10)
+    ///     .trace(&mut out, |t| eprintln!("{}", t))
+    ///     .unwrap();
+    /// assert_eq!(out, b"2");
+    /// ```
+    pub fn trace<W: io::Write, F: FnMut(&Trace)>(&mut self, w: &mut W, f: F) -> Result<usize> {
+        self.buf.clear();
+        self.env
+            .trace(&mut Parser::new(self.src), &mut self.buf, f)?;
+        w.write_all(&self.buf)
+            .context(ErrorKind::FailedToWriteOutput)?;
+        Ok(self.buf.len())
+    }
+
     pub fn write<W: io::Write>(&mut self, w: &mut W) -> Result<usize> {
-        Ok(w.write(&self.vec()?).unwrap())
+        self.buf.clear();
+        self.env.write(&mut Parser::new(self.src), &mut self.buf)?;
+        w.write_all(&self.buf)
+            .context(ErrorKind::FailedToWriteOutput)?;
+        Ok(self.buf.len())
     }
 }
 
 impl ExecutionEnvironment {
     pub fn new() -> ExecutionEnvironment {
         ExecutionEnvironment {
-            stack: VecDeque::new(),
+            stack: Stack::new(),
             arguments: [None, None, None, None, None, None, None, None, None],
+            static_vars: Default::default(),
+            dynamic_vars: Default::default(),
+            zero_division: ZeroDivision::default(),
+            strict: false,
+            limits: Limits::default(),
+            steps: 0,
+        }
+    }
+
+    /// Fail when a parameter that wasn't set is used, instead of using 0,
+    /// and when a char is used as an integer, or an integer as a char.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Limit the number of ops a single run executes and the number of bytes it writes.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Choose what happens when a string divides by zero.
+    pub fn set_zero_division(&mut self, z: ZeroDivision) {
+        self.zero_division = z;
+    }
+
+    /// Create an environment with the first 9 `args` set as the parameters.
+    pub(crate) fn with_args(args: &[Argument]) -> ExecutionEnvironment {
+        let mut env = ExecutionEnvironment::new();
+        env.set_args(args);
+        env
+    }
+
+    /// Replace the parameters with the first 9 items of `args`.
+    pub fn set_args(&mut self, args: &[Argument]) {
+        self.clear_args();
+        for (slot, arg) in self.arguments.iter_mut().zip(args) {
+            *slot = Some(arg.clone());
+        }
+    }
+
+    /// Unset every parameter.
+    pub fn clear_args(&mut self) {
+        for arg in self.arguments.iter_mut() {
+            *arg = None;
+        }
+    }
+
+    /// Unset every parameter and variable, and empty the stack.
+    ///
+    /// The stack's memory is kept, so resetting an environment doesn't allocate.
+    pub fn reset(&mut self) {
+        self.clear_args();
+        self.clear_dynamic_vars();
+        for var in self.static_vars.iter_mut() {
+            *var = None;
+        }
+        self.stack.clear();
+    }
+
+    fn variable(&mut self, var: Variable) -> &mut Option<Argument> {
+        match var {
+            Variable::Dynamic(i) => &mut self.dynamic_vars[i as usize],
+            Variable::Static(i) => &mut self.static_vars[i as usize],
         }
     }
 
@@ -85,6 +347,7 @@ impl ExecutionEnvironment {
             Some(Argument::String(_)) => {
                 Err(ErrorKind::UnexpectedArgumentType("integer", "string").into())
             }
+            Some(Argument::Char(c)) if !self.strict => Ok(i64::from(c)),
             Some(Argument::Char(_)) => {
                 Err(ErrorKind::UnexpectedArgumentType("integer", "char").into())
             }
@@ -94,6 +357,7 @@ impl ExecutionEnvironment {
 
     pub fn pop_char(&mut self) -> Result<u8> {
         match self.pop() {
+            Some(Argument::Integer(x)) if !self.strict => Ok(x as u8),
             Some(Argument::Integer(_)) => {
                 Err(ErrorKind::UnexpectedArgumentType("char", "integer").into())
             }
@@ -106,11 +370,11 @@ impl ExecutionEnvironment {
     }
 
     pub fn pop(&mut self) -> Option<Argument> {
-        self.stack.pop_back()
+        self.stack.pop()
     }
 
     pub fn push<U: Into<Argument>>(&mut self, t: U) {
-        self.stack.push_back(t.into())
+        self.stack.push(t.into())
     }
 
     fn map_integer2<U: Into<Argument>, F: FnOnce(i64, i64) -> U>(&mut self, f: F) -> Result<()> {
@@ -121,6 +385,21 @@ impl ExecutionEnvironment {
         Ok(())
     }
 
+    /// Pop a dividend and divisor, and push `f(dividend, divisor)`.
+    fn divide<F: FnOnce(i64, i64) -> i64>(&mut self, f: F) -> Result<()> {
+        let divisor = self.pop_integer()?;
+        let dividend = self.pop_integer()?;
+
+        if divisor != 0 {
+            self.push(f(dividend, divisor));
+        } else if self.zero_division == ZeroDivision::Zero {
+            self.push(0);
+        } else {
+            return Err(ErrorKind::DivisionByZero.into());
+        }
+        Ok(())
+    }
+
     fn map_integer<U: Into<Argument>, F: FnOnce(i64) -> U>(&mut self, f: F) -> Result<()> {
         let x = self.pop_integer()?;
 
@@ -137,86 +416,218 @@ impl ExecutionEnvironment {
         }
     }
 
+    /// Run `program` with the parameters `args`, writing the output to `w`.
+    ///
+    /// Static variables set by earlier runs are kept, everything else is cleared first.
+    pub fn run<W: io::Write>(
+        &mut self,
+        program: &Program,
+        args: &[Argument],
+        w: &mut W,
+    ) -> Result<usize> {
+        self.set_args(args);
+        program.exec_in(self, w)
+    }
+
     pub fn write<'a, W: io::Write>(
         &mut self,
         parser: &'a mut Parser<'a>,
         w: &mut W,
+    ) -> Result<usize> {
+        self.interpret(parser, w, None::<fn(&Trace)>)
+    }
+
+    /// Execute the ops in `parser` like `write`, calling `f` after every op.
+    pub fn trace<'a, W: io::Write, F: FnMut(&Trace)>(
+        &mut self,
+        parser: &'a mut Parser<'a>,
+        w: &mut W,
+        f: F,
+    ) -> Result<usize> {
+        self.interpret(parser, w, Some(f))
+    }
+
+    fn interpret<'a, W: io::Write, F: FnMut(&Trace)>(
+        &mut self,
+        parser: &'a mut Parser<'a>,
+        w: &mut W,
+        mut tracer: Option<F>,
     ) -> Result<usize> {
         let mut written = 0;
+        let mut output = Vec::new();
+        self.begin();
+
         'exe: loop {
-            let op = match parser.next() {
+            let (op, span) = match parser.next_spanned() {
                 Some(v) => v?,
                 None => break,
             };
 
-            match op {
-                Op::NoOp => (),
-                Op::Push(arg) => self.push(arg),
-                Op::PushUserArg(arg) => {
-                    let val = self.arguments[arg].clone().unwrap_or(Argument::Integer(0));
-                    self.push(val)
-                }
-                Op::Jump(ip) => for _ in 0..ip {
+            let jump = self
+                .traced_step(&op, w, &mut written, &mut output, tracer.as_mut())
+                .map_err(|e| e.at(parser.source(), span.start))?;
+
+            if let Some(skip) = jump {
+                for _ in 0..skip {
                     match parser.next() {
                         Some(Err(e)) => return Err(e),
                         Some(Ok(_)) => (),
                         None => break 'exe,
                     }
-                },
-                Op::BranchFalse(ip) => if !self.pop_bool() {
-                    for _ in 0..ip {
-                        match parser.next() {
-                            Some(Err(e)) => return Err(e),
-                            Some(Ok(_)) => (),
-                            None => break 'exe,
-                        }
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Execute a single op like `step`, if there's a tracer the op's output is buffered so it can be passed to the tracer.
+    fn traced_step<W: io::Write, F: FnMut(&Trace)>(
+        &mut self,
+        op: &Op,
+        w: &mut W,
+        written: &mut usize,
+        output: &mut Vec<u8>,
+        tracer: Option<&mut F>,
+    ) -> Result<Option<usize>> {
+        let f = match tracer {
+            Some(f) => f,
+            None => return self.step(op, w, written),
+        };
+
+        output.clear();
+        let jump = self.step(op, output, written)?;
+        w.write_all(output)
+            .context(ErrorKind::FailedToWriteOutput)?;
+        f(&Trace {
+            op,
+            stack: &self.stack,
+            output,
+        });
+        Ok(jump)
+    }
+
+    pub(crate) fn clear_dynamic_vars(&mut self) {
+        for var in self.dynamic_vars.iter_mut() {
+            *var = None;
+        }
+    }
+
+    /// Prepare to run a string, dynamic variables and the stack are cleared.
+    pub(crate) fn begin(&mut self) {
+        self.clear_dynamic_vars();
+        self.stack.clear();
+        self.steps = 0;
+    }
+
+    /// Execute a single op, adding the number of bytes written to `written`.
+    ///
+    /// If the op is a jump, and the jump is taken, the number of ops to skip is returned.
+    pub(crate) fn step<W: io::Write>(
+        &mut self,
+        op: &Op,
+        w: &mut W,
+        written: &mut usize,
+    ) -> Result<Option<usize>> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_ops {
+            if self.steps > max {
+                return Err(ErrorKind::LimitExceeded(Limit::Ops(max)).into());
+            }
+        }
+
+        let jump = self.run_op(op, w, written)?;
+
+        if let Some(max) = self.limits.max_output {
+            if *written > max {
+                return Err(ErrorKind::LimitExceeded(Limit::Output(max)).into());
+            }
+        }
+        Ok(jump)
+    }
+
+    fn run_op<W: io::Write>(
+        &mut self,
+        op: &Op,
+        w: &mut W,
+        written: &mut usize,
+    ) -> Result<Option<usize>> {
+        match *op {
+            Op::NoOp => (),
+            Op::Push(ref arg) => self.push(arg.clone()),
+            Op::SetVariable(var) => {
+                let val = self.pop().unwrap_or(Argument::Integer(0));
+                *self.variable(var) = Some(val);
+            }
+            Op::PushVariable(var) => {
+                let val = self.variable(var).clone().unwrap_or(Argument::Integer(0));
+                self.push(val)
+            }
+            Op::PushUserArg(arg) => {
+                let val = match self.arguments[arg] {
+                    Some(ref val) => val.clone(),
+                    None if self.strict => return Err(ErrorKind::MissingArgument(arg + 1).into()),
+                    None => Argument::Integer(0),
+                };
+                self.push(val)
+            }
+            Op::Jump(ip) => return Ok(Some(ip)),
+            Op::BranchFalse(ip) => if !self.pop_bool() {
+                return Ok(Some(ip));
+            },
+            Op::BranchTrue(ip) => if self.pop_bool() {
+                return Ok(Some(ip));
+            },
+            Op::Add => self.map_integer2(|x, y| y.wrapping_add(x))?,
+            Op::Sub => self.map_integer2(|x, y| y.wrapping_sub(x))?,
+            Op::Div => self.divide(i64::wrapping_div)?,
+            Op::Mul => self.map_integer2(|x, y| y.wrapping_mul(x))?,
+            Op::Mod => self.divide(i64::wrapping_rem)?,
+            Op::BitAnd => self.map_integer2(|x, y| x & y)?,
+            Op::BitOr => self.map_integer2(|x, y| x | y)?,
+            Op::BitXor => self.map_integer2(|x, y| x ^ y)?,
+            Op::Equal => self.map_integer2(|x, y| x == y)?,
+            Op::Greater => self.map_integer2(|x, y| y > x)?,
+            Op::Less => self.map_integer2(|x, y| y < x)?,
+            Op::Invert => self.map_integer(|x| !x)?,
+            Op::Not => self.map_integer(|x| if x != 0 { x == 0 } else { x == 1 })?,
+            Op::IncrementArgs => {
+                for arg in self.arguments.iter_mut().take(2) {
+                    if let Some(Argument::Integer(ref mut x)) = *arg {
+                        *x = x.wrapping_add(1);
                     }
-                },
-                Op::BranchTrue(ip) => if self.pop_bool() {
-                    for _ in 0..ip {
-                        match parser.next() {
-                            Some(Err(e)) => return Err(e),
-                            Some(Ok(_)) => (),
-                            None => break 'exe,
-                        }
+                }
+            }
+            Op::StrLen => {
+                let x = self.pop_string()?.len();
+                self.push(x);
+            }
+            Op::Print(ref p) => {
+                let arg = match self.pop() {
+                    // like ncurses, %d, %o, %x and %X print a char's value
+                    Some(Argument::Char(c)) if !self.strict && p.character != 'c' && p.character != 's' => {
+                        Some(Argument::Integer(i64::from(c)))
                     }
-                },
-                Op::Add => self.map_integer2(|x, y| x + y)?,
-                Op::Sub => self.map_integer2(|x, y| x - y)?,
-                Op::Div => self.map_integer2(|x, y| x / y)?,
-                Op::Mul => self.map_integer2(|x, y| x * y)?,
-                Op::Mod => self.map_integer2(|x, y| x % y)?,
-                Op::BitAnd => self.map_integer2(|x, y| x & y)?,
-                Op::BitOr => self.map_integer2(|x, y| x | y)?,
-                Op::BitXor => self.map_integer2(|x, y| x ^ y)?,
-                Op::Equal => self.map_integer2(|x, y| x == y)?,
-                Op::Greater => self.map_integer2(|x, y| y > x)?,
-                Op::Less => self.map_integer2(|x, y| y < x)?,
-                Op::Invert => self.map_integer(|x| !x)?,
-                Op::Not => self.map_integer(|x| if x != 0 { x == 0 } else { x == 1 })?,
-                Op::IncrementArgs => {
-                    match self.arguments[0] {
-                        Some(Argument::Integer(ref mut x)) => *x += 1,
-                        _ => (),
-                    };
-                    match self.arguments[1] {
-                        Some(Argument::Integer(ref mut x)) => *x += 1,
-                        _ => (),
+                    arg => arg,
+                };
+
+                // check the padding before it's written, it could be huge
+                if let Some(max) = self.limits.max_output {
+                    let digits = match p.character {
+                        'c' | 's' => 0,
+                        _ => p.prec.unwrap_or(0),
                     };
+                    if p.width.unwrap_or(0).max(digits) > max.saturating_sub(*written) {
+                        return Err(ErrorKind::LimitExceeded(Limit::Output(max)).into());
+                    }
                 }
-                Op::StrLen => {
-                    let x = self.pop_string()?.len();
-                    self.push(x);
-                }
-                Op::Print(p) => {
-                    written += p.print(w, self.pop())?;
-                }
-                Op::PrintSlice(slice) => {
-                    written += w.write(slice).context(ErrorKind::FailedToWriteArgument)?;
-                }
+                *written += p.print(w, arg)?;
+            }
+            Op::PrintSlice(slice) => {
+                *written += w.write(slice).context(ErrorKind::FailedToWriteArgument)?;
             }
         }
 
-        Ok(written)
+        Ok(None)
     }
 }
@@ -0,0 +1,307 @@
+use std::fmt;
+use std::ops::Range;
+use terminfo::errors::*;
+use terminfo::lang::parser::{Op, Parser};
+use terminfo::lang::Argument;
+
+/// The maximum number of paths through a string `lint` follows, strings with more paths are only partially checked.
+const MAX_PATHS: usize = 4096;
+
+/// A problem found by `lint`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum LintKind {
+    /// A value is pushed, but never printed or used.
+    UnusedValue,
+
+    /// An op pops a value, but the stack is empty.
+    StackUnderflow,
+
+    /// A parameter isn't used, even though a later parameter is (e.g. `%p1` and `%p3`, but no `%p2`).
+    /// The parameter number starts at 1, like `%p1`.
+    UnusedParameter(usize),
+
+    /// Part of the string can never run, because the condition before it is constant.
+    UnreachableBranch,
+
+    /// Arithmetic that overflows a C `int`, or multiplies two values that aren't known ahead of time.
+    Overflow,
+
+    /// A division, or `%m`, by a constant zero.
+    DivisionByZero,
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LintKind::UnusedValue => write!(f, "value is pushed, but never used"),
+            LintKind::StackUnderflow => write!(f, "the stack is empty"),
+            LintKind::UnusedParameter(i) => write!(f, "parameter %p{} is never used", i),
+            LintKind::UnreachableBranch => write!(f, "this branch can never run"),
+            LintKind::Overflow => write!(f, "arithmetic may overflow"),
+            LintKind::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+/// A problem found by `lint`, and where in the string it is.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lint {
+    pub kind: LintKind,
+
+    /// The bytes of the string the problem was found in.
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}: {}", self.span.start, self.span.end, self.kind)
+    }
+}
+
+/// A value on the stack, while linting.
+#[derive(Debug, Clone)]
+struct Value {
+    /// The op that pushed the value.
+    origin: usize,
+
+    /// The value, if it's known ahead of time.
+    constant: Option<i64>,
+}
+
+struct Linter<'a> {
+    ops: Vec<(Op<'a>, Range<usize>)>,
+    visited: Vec<bool>,
+    lints: Vec<Lint>,
+    paths: usize,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, kind: LintKind, op: usize) {
+        let span = self.ops[op].1.clone();
+        if !self.lints.iter().any(|l| l.kind == kind && l.span == span) {
+            self.lints.push(Lint { kind, span });
+        }
+    }
+
+    fn pop(&mut self, stack: &mut Vec<Value>, op: usize) -> Option<i64> {
+        match stack.pop() {
+            Some(v) => v.constant,
+            None => {
+                self.report(LintKind::StackUnderflow, op);
+                None
+            }
+        }
+    }
+
+    /// Run a binary op, returning the result if both values are known.
+    fn binary(&mut self, stack: &mut Vec<Value>, ip: usize) -> Option<i64> {
+        let b = self.pop(stack, ip);
+        let a = self.pop(stack, ip);
+
+        let op = self.ops[ip].0.clone();
+        if (op == Op::Div || op == Op::Mod) && b == Some(0) {
+            self.report(LintKind::DivisionByZero, ip);
+            return None;
+        }
+
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) if op == Op::Mul => {
+                self.report(LintKind::Overflow, ip);
+                return None;
+            }
+            _ => return None,
+        };
+
+        // parameters are C ints, so anything that doesn't fit in an i32 is an overflow
+        let int = |x: i64| {
+            if x as i32 as i64 == x {
+                Some(x as i32)
+            } else {
+                None
+            }
+        };
+        let result = match (int(a), int(b)) {
+            (Some(a), Some(b)) => match op {
+                Op::Add => a.checked_add(b),
+                Op::Sub => a.checked_sub(b),
+                Op::Mul => a.checked_mul(b),
+                Op::Div => a.checked_div(b),
+                Op::Mod => a.checked_rem(b),
+                Op::BitAnd => Some(a & b),
+                Op::BitOr => Some(a | b),
+                Op::BitXor => Some(a ^ b),
+                Op::Less => Some((a < b) as i32),
+                Op::Greater => Some((a > b) as i32),
+                Op::Equal => Some((a == b) as i32),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if result.is_none() {
+            self.report(LintKind::Overflow, ip);
+        }
+        result.map(i64::from)
+    }
+
+    /// Follow every path starting at `ip`.
+    fn run(&mut self, mut ip: usize, mut stack: Vec<Value>) {
+        while ip < self.ops.len() {
+            self.visited[ip] = true;
+            let next = ip + 1;
+
+            // the value of the condition, for branches
+            let condition = match self.ops[ip].0 {
+                Op::PushUserArg(_) | Op::PushVariable(_) => {
+                    stack.push(Value {
+                        origin: ip,
+                        constant: None,
+                    });
+                    None
+                }
+                Op::Push(ref arg) => {
+                    let constant = match *arg {
+                        Argument::Integer(x) => Some(x),
+                        _ => None,
+                    };
+                    stack.push(Value {
+                        origin: ip,
+                        constant,
+                    });
+                    None
+                }
+                Op::Add
+                | Op::Sub
+                | Op::Mul
+                | Op::Div
+                | Op::Mod
+                | Op::BitAnd
+                | Op::BitOr
+                | Op::BitXor
+                | Op::Less
+                | Op::Greater
+                | Op::Equal => {
+                    let constant = self.binary(&mut stack, ip);
+                    stack.push(Value {
+                        origin: ip,
+                        constant,
+                    });
+                    None
+                }
+                Op::Invert | Op::Not | Op::StrLen => {
+                    let x = self.pop(&mut stack, ip);
+                    let constant = match self.ops[ip].0 {
+                        Op::Invert => x.map(|x| !x),
+                        Op::Not => x.map(|x| (x == 0) as i64),
+                        _ => None,
+                    };
+                    stack.push(Value {
+                        origin: ip,
+                        constant,
+                    });
+                    None
+                }
+                Op::SetVariable(_) | Op::Print(_) => {
+                    self.pop(&mut stack, ip);
+                    None
+                }
+                Op::BranchFalse(_) | Op::BranchTrue(_) => Some(self.pop(&mut stack, ip)),
+                Op::NoOp | Op::IncrementArgs | Op::PrintSlice(_) | Op::Jump(_) => None,
+            };
+
+            ip = match (&self.ops[ip].0, condition) {
+                (&Op::Jump(n), _) => next + n,
+                (&Op::BranchFalse(n), Some(Some(0))) => next + n,
+                (&Op::BranchTrue(n), Some(Some(x))) if x != 0 => next + n,
+                (&Op::BranchFalse(_), Some(Some(_))) | (&Op::BranchTrue(_), Some(Some(_))) => next,
+                (&Op::BranchFalse(n), _) | (&Op::BranchTrue(n), _) => {
+                    // the condition isn't known, follow both paths
+                    self.paths += 1;
+                    if self.paths < MAX_PATHS {
+                        self.run(next + n, stack.clone());
+                    }
+                    next
+                }
+                _ => next,
+            };
+        }
+
+        for value in stack {
+            self.report(LintKind::UnusedValue, value.origin);
+        }
+    }
+}
+
+/// Check the capability string `src` for common mistakes.
+///
+/// The string is only parsed, not run, so parameters are treated as unknown values.
+/// An error is returned if the string can't be parsed.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::{lint, LintKind};
+///
+/// let lints = lint(b"\x1b[%p1%p2%dm").unwrap();
+/// assert_eq!(lints.len(), 1);
+/// assert_eq!(lints[0].kind, LintKind::UnusedValue);
+/// assert_eq!(lints[0].span, 2..5);
+/// ```
+pub fn lint(src: &[u8]) -> Result<Vec<Lint>> {
+    let ops = Parser::new(src).spanned().collect::<Result<Vec<_>>>()?;
+
+    let mut linter = Linter {
+        visited: vec![false; ops.len()],
+        ops,
+        lints: Vec::new(),
+        paths: 0,
+    };
+    linter.run(0, Vec::new());
+
+    // parameters that are skipped over
+    let mut params: [Option<Range<usize>>; 9] = Default::default();
+    for (op, span) in linter.ops.iter() {
+        if let Op::PushUserArg(i) = *op {
+            if params[i].is_none() {
+                params[i] = Some(span.clone());
+            }
+        }
+    }
+    if let Some(last) = params.iter().rposition(|p| p.is_some()) {
+        for i in (0..last).filter(|&i| params[i].is_none()) {
+            linter.lints.push(Lint {
+                kind: LintKind::UnusedParameter(i + 1),
+                span: params[last].clone().unwrap(),
+            });
+        }
+    }
+
+    // runs of ops that are never visited, if every path was followed
+    let mut unreachable: Option<Range<usize>> = None;
+    let complete = linter.paths < MAX_PATHS;
+    for ((op, span), &visited) in linter.ops.iter().zip(linter.visited.iter()) {
+        if visited || !complete {
+            if let Some(span) = unreachable.take() {
+                linter.lints.push(Lint {
+                    kind: LintKind::UnreachableBranch,
+                    span,
+                });
+            }
+        } else if *op != Op::NoOp {
+            unreachable = Some(match unreachable {
+                Some(r) => r.start..span.end,
+                None => span.clone(),
+            });
+        }
+    }
+    if let Some(span) = unreachable {
+        linter.lints.push(Lint {
+            kind: LintKind::UnreachableBranch,
+            span,
+        });
+    }
+
+    let mut lints = linter.lints;
+    lints.sort_by_key(|l| (l.span.start, l.span.end, l.kind));
+    Ok(lints)
+}
@@ -1,15 +1,26 @@
+mod analyze;
 mod argument;
+mod lint;
 pub mod executor;
+mod native;
 pub mod parser;
 pub mod printf;
+mod program;
+mod render;
+mod stack;
 
+pub use self::analyze::*;
 pub use self::argument::*;
-pub use self::executor::Executor;
+pub use self::lint::*;
+pub use self::executor::{ExecutionEnvironment, Executor, Limit, Limits, Trace, ZeroDivision};
+pub use self::program::Program;
+pub use self::render::{normalize, render};
 
 #[cfg(test)]
 mod tests {
     use terminfo::lang::printf::*;
     use terminfo::lang::*;
+    use terminfo::{ErrorKind, Location};
 
     #[test]
     fn printf() {
@@ -53,7 +64,7 @@ mod tests {
             .unwrap()
             .print(&mut buffer, Some(21))
             .unwrap();
-        assert_eq!(&buffer, b"2");
+        assert_eq!(&buffer, b"21");
         buffer.clear();
 
         PrintfArgs::parse(b".4s")
@@ -74,14 +85,661 @@ mod tests {
             .unwrap()
             .print(&mut buffer, Some(99))
             .unwrap();
-        assert_eq!(&buffer, b"99       ");
+        assert_eq!(&buffer, b"0099     ");
         buffer.clear();
 
         PrintfArgs::parse(b":-9.4d")
             .unwrap()
             .print(&mut buffer, Some(99999))
             .unwrap();
-        assert_eq!(&buffer, b"9999     ");
+        assert_eq!(&buffer, b"99999    ");
+    }
+
+    #[test]
+    fn printf_flags() {
+        // the expected output is what printf(3) prints, terminfo(5) describes the flags, width and precision as the same
+        let cases: [(&[u8], i64, &[u8]); 18] = [
+            (b"03d", 7, b"007"),
+            (b"03d", -7, b"-07"),
+            (b"02x", 10, b"0a"),
+            (b"12d", 5, b"           5"),
+            (b":-012d", 5, b"5           "),
+            (b"05.3d", 5, b"  005"),
+            (b".0d", 0, b""),
+            (b":+d", 5, b"+5"),
+            (b": +d", 5, b"+5"),
+            (b":+ d", -5, b"-5"),
+            (b":#x", 255, b"0xff"),
+            (b":#X", 255, b"0XFF"),
+            (b":#x", 0, b"0"),
+            (b":#o", 8, b"010"),
+            (b":#o", 0, b"0"),
+            (b":#08x", 255, b"0x0000ff"),
+            (b"x", -1, b"ffffffff"),
+            (b"3c", 65, b"  A"),
+        ];
+
+        for &(spec, n, expected) in cases.iter() {
+            let mut buffer = Vec::new();
+            let written = PrintfArgs::parse(spec)
+                .unwrap()
+                .print(&mut buffer, Some(n))
+                .unwrap();
+            assert_eq!(
+                (String::from_utf8_lossy(&buffer), written),
+                (String::from_utf8_lossy(expected), expected.len()),
+                "%{}",
+                String::from_utf8_lossy(spec)
+            );
+        }
+
+        let mut buffer = Vec::new();
+        Executor::new(b"\x1b[38;5;%p1%03dm%p2%:-4s|")
+            .arg(9)
+            .arg("ab")
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"\x1b[38;5;009mab  |");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let exe = |src: &[u8], a: i64, b: i64| Executor::new(src).arg(a).arg(b).string().unwrap();
+        assert_eq!(exe(b"%p1%p2%-%d", 20, 8), "12");
+        assert_eq!(exe(b"%p1%p2%/%d", 20, 8), "2");
+        assert_eq!(exe(b"%p1%p2%m%d", 20, 8), "4");
+        assert_eq!(exe(b"%p1%p2%/%d", 20, 0), "0");
+        assert_eq!(exe(b"%p1%p2%m%d", 20, 0), "0");
+        assert_eq!(exe(b"%p1%p2%/%d", i64::MIN, -1), i64::MIN.to_string());
+        assert_eq!(exe(b"%p1%p2%*%d", i64::MAX, 2), "-2");
+
+        assert_eq!(
+            Executor::new(b"%{1}%{0}%/%d")
+                .on_zero_division(ZeroDivision::Fail)
+                .vec()
+                .unwrap_err()
+                .kind(),
+            &ErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            Program::compile(b"%{1}%{0}%m%d").unwrap().vec(&[]).unwrap(),
+            b"0"
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let mut steps = Vec::new();
+        let mut buffer = Vec::new();
+        let written = Executor::new(b"%?%p1%t<%p1%d>%;")
+            .arg(5)
+            .trace(&mut buffer, |t| steps.push(t.to_string()))
+            .unwrap();
+        assert_eq!(written, 3);
+        assert_eq!(&buffer, b"<5>");
+        assert_eq!(steps.len(), 6);
+        assert_eq!(steps[0], "PushUserArg(0) stack=[Integer(5)]");
+        assert_eq!(steps[1], "BranchFalse(4) stack=[]");
+        assert_eq!(steps[2], "PrintSlice([60]) stack=[] output=\"<\"");
+        assert!(steps[4].ends_with("stack=[] output=\"5\""));
+
+        steps.clear();
+        Executor::new(b"%?%p1%t<%p1%d>%;")
+            .arg(0)
+            .trace(&mut buffer, |t| steps.push(t.to_string()))
+            .unwrap();
+        assert_eq!(steps, vec!["PushUserArg(0) stack=[Integer(0)]", "BranchFalse(4) stack=[]"]);
+    }
+
+    #[test]
+    fn strict() {
+        let mut exe = Executor::new(b"%p1%d;%p2%d").arg(1).strict(true);
+        assert_eq!(
+            exe.vec().unwrap_err().kind(),
+            &ErrorKind::MissingArgument(2)
+        );
+        exe.set_args(&[1.into(), 2.into()]);
+        assert_eq!(exe.string().unwrap(), "1;2");
+
+        assert_eq!(
+            Executor::new(b"%p1%d;%p2%d").arg(1).string().unwrap(),
+            "1;0"
+        );
+    }
+
+    #[test]
+    fn coercion() {
+        let run = |src: &[u8], arg: Argument, strict: bool| {
+            Executor::new(src).arg(arg).strict(strict).string()
+        };
+
+        assert_eq!(run(b"%p1%d,%p1%x", 'a'.into(), false).unwrap(), "97,61");
+        assert_eq!(run(b"%p1%{1}%+%c", 'a'.into(), false).unwrap(), "b");
+        assert_eq!(run(b"%?%p1%'a'%=%tyes%;", 97.into(), false).unwrap(), "yes");
+        assert_eq!(run(b"%p1%c", 65.into(), false).unwrap(), "A");
+
+        assert_eq!(
+            run(b"%p1%d", 'a'.into(), true).unwrap_err().kind(),
+            &ErrorKind::UnexpectedArgumentType("integer", "char")
+        );
+        assert_eq!(
+            run(b"%p1%{1}%+%d", 'a'.into(), true).unwrap_err().kind(),
+            &ErrorKind::UnexpectedArgumentType("integer", "char")
+        );
+        assert!(run(b"%p1%{1}%+%d", "a".into(), false).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits {
+            max_ops: Some(6),
+            max_output: Some(16),
+        };
+        let run = |src: &[u8]| Executor::new(src).arg(1).limits(limits).string();
+
+        assert_eq!(run(b"%p1%d%p1%d%p1%d").unwrap(), "111");
+        assert_eq!(
+            run(b"%p1%d%p1%d%p1%d%p1%d").unwrap_err().kind(),
+            &ErrorKind::LimitExceeded(Limit::Ops(6))
+        );
+        // skipped ops aren't counted
+        assert_eq!(run(b"%?%{0}%t%p1%p1%p1%p1%p1%;%p1%d").unwrap(), "1");
+
+        assert_eq!(run(b"%p1%16d").unwrap().len(), 16);
+        assert_eq!(
+            run(b"%p1%999999999d").unwrap_err().kind(),
+            &ErrorKind::LimitExceeded(Limit::Output(16))
+        );
+        assert_eq!(
+            run(b"%p1%.20d").unwrap_err().kind(),
+            &ErrorKind::LimitExceeded(Limit::Output(16))
+        );
+        assert_eq!(
+            run(b"0123456789abcdefg").unwrap_err().kind(),
+            &ErrorKind::LimitExceeded(Limit::Output(16))
+        );
+
+        assert_eq!(
+            run(b"%p1%99999999999999999999999d").unwrap_err().kind(),
+            &ErrorKind::BadPrecisionSpecified
+        );
+        assert_eq!(
+            run(b"%p1%.99999999999999999999999d").unwrap_err().kind(),
+            &ErrorKind::BadPrecisionSpecified
+        );
+        assert_eq!(
+            Executor::new(b"%i%p1%d")
+                .arg(i64::max_value())
+                .string()
+                .unwrap(),
+            i64::min_value().to_string()
+        );
+
+        let program = Program::compile(b"%p1%999999999d").unwrap();
+        let mut env = ExecutionEnvironment::new();
+        env.set_limits(limits);
+        env.set_args(&[1.into()]);
+        assert_eq!(
+            program.exec_in(&mut env, &mut Vec::new()).unwrap_err().kind(),
+            &ErrorKind::LimitExceeded(Limit::Output(16))
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let cases: [(&[u8], [&str; 4]); 6] = [
+            (b"%?%p1%t%?%p2%tA%eB%;%eC%;.", ["C.", "B.", "C.", "A."]),
+            (b"%?%p1%tX%e%?%p2%tY%eZ%;%;.", ["Z.", "X.", "Y.", "X."]),
+            (
+                b"%?%p1%p2%+%{0}%=%tnone%e%p1%p2%&%tboth%e%p1%tfirst%esecond%;.",
+                ["none.", "first.", "second.", "both."],
+            ),
+            (b"%?%p1%tA%e%p2%tB%;.", [".", "A.", "B.", "A."]),
+            (b"%?%?%p1%t%{1}%e%{0}%;%tT%eF%;.", ["F.", "T.", "F.", "T."]),
+            (b"%?%p1%t%e%;%?%p2%t%;.", [".", ".", ".", "."]),
+        ];
+
+        for &(src, expected) in cases.iter() {
+            let program = Program::compile(src).unwrap();
+            for (&(a, b), expected) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter().zip(expected.iter()) {
+                assert_eq!(
+                    &Executor::new(src).arg(a).arg(b).string().unwrap(),
+                    expected
+                );
+                assert_eq!(
+                    program.vec(&[a.into(), b.into()]).unwrap(),
+                    expected.as_bytes()
+                );
+            }
+        }
+
+        for src in [&b"%?%p1%tA"[..], b"%?%p1"].iter() {
+            assert_eq!(
+                Executor::new(src).vec().unwrap_err().kind(),
+                &ErrorKind::UnexpectedEof
+            );
+        }
+        for src in [&b"a%;"[..], b"%e", b"%?%p1%tA%eB%eC%;", b"%?%p1%tA%tB%;"].iter() {
+            assert_eq!(
+                Executor::new(src).vec().unwrap_err().kind(),
+                &ErrorKind::UnbalancedConditional
+            );
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let mut exe = Executor::new(b"%p1%c");
+        exe.set_args(&['a'.into()]);
+        assert_eq!(exe.to_string().unwrap(), "a");
+
+        let mut s = String::from(">");
+        assert_eq!(exe.write_fmt(&mut s).unwrap(), 1);
+        assert_eq!(s, ">a");
+
+        exe.set_args(&[0xff.into()]);
+        assert_eq!(
+            exe.to_string().unwrap_err().kind(),
+            &ErrorKind::InvalidUtf8Output
+        );
+        assert_eq!(
+            exe.write_fmt(&mut s).unwrap_err().kind(),
+            &ErrorKind::InvalidUtf8Output
+        );
+        assert_eq!(s, ">a");
+    }
+
+    #[test]
+    fn char_constants() {
+        use terminfo::lang::parser::{Op, Parser};
+
+        fn parse(src: &[u8]) -> Vec<Op> {
+            Parser::new(src)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        }
+        let chars: [(&[u8], u8); 11] = [
+            (b"%'a'", b'a'),
+            (b"%'\\E'", 0x1b),
+            (b"%'\\e'", 0x1b),
+            (b"%'\\n'", b'\n'),
+            (b"%'\\s'", b' '),
+            (b"%'\\''", b'\''),
+            (b"%'\\0'", 0x80),
+            (b"%'\\177'", 0x7f),
+            (b"%'^M'", b'\r'),
+            (b"%'^['", 0x1b),
+            (b"%'^?'", 0x7f),
+        ];
+        for &(src, c) in chars.iter() {
+            assert_eq!(parse(src), vec![Op::Push(Argument::Char(c))], "{:?}", src);
+        }
+        assert_eq!(
+            parse(b"%'^M'%c%'x'"),
+            vec![
+                Op::Push(Argument::Char(b'\r')),
+                Op::Print(PrintfArgs {
+                    character: 'c',
+                    ..PrintfArgs::default()
+                }),
+                Op::Push(Argument::Char(b'x')),
+            ]
+        );
+
+        for src in [&b"%'\\q'"[..], b"%'ab'", b"%''", b"%'\\777'", b"%'^1'"].iter() {
+            assert!(Parser::new(src).next().unwrap().is_err(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn native() {
+        let sources: [(&[u8], bool); 8] = [
+            (b"\x1b[%i%p1%d;%p2%dH", true),
+            (b"\x1b[%p1%d;%p2%dH", true),
+            (b"\x1b[3%p1%dm", true),
+            (b"\x1b[m\x0f$<2>", true),
+            (b"\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m", true),
+            (b"\x1b[%?%p1%{8}%<%t4%p1%d%e%p1%{16}%<%t10%p1%{8}%-%d%e48;5;%p1%d%;m", true),
+            (b"%p1%d%i%p2%d", false),
+            (b"%p1%02d", false),
+        ];
+        let args: [&[Argument]; 8] = [
+            &[],
+            &[Argument::Integer(0), Argument::Integer(0)],
+            &[Argument::Integer(7), Argument::Integer(79)],
+            &[Argument::Integer(9)],
+            &[Argument::Integer(15), Argument::Integer(-3)],
+            &[Argument::Integer(255)],
+            &[Argument::Integer(-1)],
+            &[Argument::from("x"), Argument::Integer(2)],
+        ];
+
+        for &(src, native) in sources.iter() {
+            let program = Program::compile(src).unwrap();
+            assert_eq!(program.is_native(), native);
+
+            for &args in args.iter() {
+                let mut exe = Executor::new(src);
+                exe.set_args(args);
+
+                let mut buf = Vec::new();
+                match exe.vec() {
+                    Ok(expected) => {
+                        assert_eq!(program.exec(args, &mut buf).unwrap(), expected.len());
+                        assert_eq!(buf, expected);
+                    }
+                    Err(e) => assert_eq!(program.exec(args, &mut buf).unwrap_err().kind(), e.kind()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spans() {
+        use terminfo::lang::parser::{Op, Parser};
+
+        let src = b"ab%p1%:-3d%?%p2%tx%ey%;%%";
+        let spans = Parser::new(src)
+            .spanned()
+            .map(|r| r.map(|(_, span)| &src[span]))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            spans,
+            vec![
+                &b"ab"[..],
+                b"%p1",
+                b"%:-3d",
+                b"%p2",
+                b"%t",
+                b"x",
+                b"%e",
+                b"y",
+                b"%%",
+            ]
+        );
+
+        let ops = Parser::new(src).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(ops[4], Op::BranchFalse(2));
+        assert_eq!(ops[6], Op::Jump(1));
+
+        let mut parser = Parser::new(b"ab%p1%Q");
+        assert!(parser.next().unwrap().is_ok());
+        assert!(parser.next().unwrap().is_ok());
+        assert!(parser.next().unwrap().is_err());
+        assert_eq!(parser.offset(), 5);
+    }
+
+    #[test]
+    fn error_locations() {
+        let err = Executor::new(b"\x1b[%p1%Qm").vec().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::BadPrintfSpecifier);
+        assert_eq!(
+            err.location(),
+            Some(&Location {
+                offset: 5,
+                excerpt: "\\x1b[%p1%Qm".to_string(),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid printf format specifier (at byte 5, near \"\\x1b[%p1%Qm\")"
+        );
+
+        let err = Program::compile(b"%?%p1%tyes%eno").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedEof);
+        assert_eq!(err.location().unwrap().offset, 14);
+        assert_eq!(err.location().unwrap().excerpt, "tyes%eno");
+
+        let err = Executor::new(b"0123456789%p1%s")
+            .arg(1)
+            .vec()
+            .unwrap_err();
+        assert_eq!(
+            err.location(),
+            Some(&Location {
+                offset: 13,
+                excerpt: "56789%p1%s".to_string(),
+            })
+        );
+        assert_eq!(
+            Executor::new(b"%p1%d").arg(1).vec().map_err(|e| e.location().cloned()),
+            Ok(b"1".to_vec())
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let mut buffer = Vec::new();
+        Executor::new(b"%p1%Pa%ga%ga%+%d")
+            .arg(3)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"6");
+        buffer.clear();
+
+        Executor::new(b"%{5}%PZ%p1%Pz%gZ%d;%gz%d;%gb%d")
+            .arg(7)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"5;7;0");
+        buffer.clear();
+
+        Executor::new(b"%?%p1%t%{1}%Pc%e%{2}%Pc%;%gc%d")
+            .arg(0)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"2");
+
+        assert_eq!(
+            Executor::new(b"%P1").write(&mut buffer).unwrap_err().kind(),
+            &ErrorKind::InvalidVariableName
+        );
+
+        // static variables are shared by every run in an environment, dynamic variables aren't
+        let set = Program::compile(b"%p1%PA%p1%Pa").unwrap();
+        let get = Program::compile(b"%gA%d,%ga%d").unwrap();
+        let mut env = ExecutionEnvironment::new();
+        buffer.clear();
+        env.run(&set, &[4.into()], &mut buffer).unwrap();
+        env.run(&get, &[], &mut buffer).unwrap();
+        assert_eq!(&buffer, b"4,0");
+
+        buffer.clear();
+        env.reset();
+        env.run(&get, &[], &mut buffer).unwrap();
+        assert_eq!(&buffer, b"0,0");
+    }
+
+    #[test]
+    fn program() {
+        let sources: [&[u8]; 5] = [
+            b"%p1%p2%+%d",
+            b"%i%p1%d.%p2%d",
+            b"%?%p1%tyes!%eno!%;",
+            b"%?%p9%t\\E(0%e\\E(B%;\\E[0%?%p6%t;1%;%?%p2%t;4%;%?%p1%p3%|%t;7%;%?%p4%t;5%;%?%p7%t;8%;m",
+            b"\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m",
+        ];
+
+        for src in sources.iter() {
+            let program = Program::compile(src).unwrap();
+            for &(a, b) in [(0, 0), (1, 0), (3, 4), (12, 1)].iter() {
+                let expected = Executor::new(src).arg(a).arg(b).vec().unwrap();
+                assert_eq!(program.vec(&[a.into(), b.into()]).unwrap(), expected);
+            }
+        }
+
+        assert_eq!(
+            Program::compile(b"%p1%Pa%ga%ga%*%d")
+                .unwrap()
+                .vec(&[5.into()])
+                .unwrap(),
+            b"25"
+        );
+        assert!(Program::compile(b"%?%p1%t").is_err());
+    }
+
+    #[test]
+    fn analysis() {
+        use self::ParameterType::*;
+
+        let sgr = analyze(b"%?%p9%t\\E(0%e\\E(B%;\\E[0%?%p6%t;1%;%?%p2%t;4%;%?%p1%p3%|%t;7%;%?%p4%t;5%;%?%p7%t;8%;m").unwrap();
+        assert_eq!(
+            sgr.parameters,
+            vec![Integer, Any, Integer, Any, Unused, Any, Any, Unused, Any]
+        );
+        assert!(!sgr.increments);
+
+        let cup = analyze(b"\x1b[%i%p1%d;%p2%dH").unwrap();
+        assert_eq!(cup.parameters, vec![Integer, Integer]);
+        assert_eq!(cup.arity(), 2);
+        assert!(cup.increments);
+
+        assert_eq!(
+            analyze(b"%p1%Pa%p2%ga%l%d%c").unwrap().parameters,
+            vec![String, Integer]
+        );
+        assert_eq!(analyze(b"\x1b[H").unwrap().arity(), 0);
+        assert!(analyze(b"%p1%Q").is_err());
+    }
+
+    #[test]
+    fn lint() {
+        use self::LintKind::*;
+        let kinds = |src: &[u8]| -> Vec<LintKind> {
+            super::lint(src)
+                .unwrap()
+                .into_iter()
+                .map(|l| l.kind)
+                .collect()
+        };
+
+        assert_eq!(kinds(b"\x1b[%i%p1%d;%p2%dH"), vec![]);
+        assert_eq!(
+            kinds(b"\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m"),
+            vec![]
+        );
+        assert_eq!(kinds(b"%p1%p2%d"), vec![UnusedValue]);
+        assert_eq!(kinds(b"%d%p1%+%d"), vec![StackUnderflow, StackUnderflow]);
+        assert_eq!(kinds(b"%p1%d%p3%d"), vec![UnusedParameter(2)]);
+        assert_eq!(kinds(b"%?%{0}%tx%;"), vec![UnreachableBranch]);
+        assert_eq!(kinds(b"%?%{1}%tx%ey%;"), vec![UnreachableBranch]);
+        assert_eq!(kinds(b"%{2147483647}%{1}%+%d"), vec![Overflow]);
+        assert_eq!(kinds(b"%p1%p2%*%d"), vec![Overflow]);
+        assert_eq!(kinds(b"%p1%{2}%*%d"), vec![]);
+        assert_eq!(
+            kinds(b"%p1%{0}%/%d%p1%{0}%m%d"),
+            vec![DivisionByZero, DivisionByZero]
+        );
+
+        let sgr = kinds(b"%?%p9%t\\E(0%e\\E(B%;\\E[0%?%p6%t;1%;%?%p2%t;4%;%?%p1%p3%|%t;7%;%?%p4%t;5%;%?%p7%t;8%;m");
+        assert_eq!(sgr, vec![UnusedParameter(5), UnusedParameter(8)]);
+
+        let lints = super::lint(b"ab%p1%p2%d").unwrap();
+        assert_eq!(lints[0].span, 2..5);
+        assert_eq!(lints[0].to_string(), "2..5: value is pushed, but never used");
+        assert!(super::lint(b"%p1%Q").is_err());
+    }
+
+    #[test]
+    fn stack() {
+        use terminfo::lang::stack::Stack;
+
+        let mut stack = Stack::new();
+        for i in 0..12 {
+            stack.push(i.into());
+            assert_eq!(stack.spilled(), i >= 8);
+        }
+        assert_eq!(stack.len(), 12);
+        assert_eq!(stack[11], Argument::Integer(11));
+        assert_eq!(stack.pop(), Some(Argument::Integer(11)));
+        stack.clear();
+        assert!(stack.is_empty() && stack.spilled());
+
+        let mut inline = Stack::new();
+        inline.push("abc".into());
+        assert_eq!(&inline[..], &[Argument::String("abc".into())]);
+        assert_eq!(inline.pop(), Some("abc".into()));
+        assert_eq!(inline.pop(), None);
+
+        // deeper than the inline stack
+        let src = b"%{1}%{2}%{3}%{4}%{5}%{6}%{7}%{8}%{9}%{10}%d%d%d%d%d%d%d%d%d%d";
+        let mut exe = Executor::new(src);
+        assert_eq!(exe.to_string().unwrap(), "10987654321");
+    }
+
+    #[test]
+    fn render() {
+        use terminfo::lang::parser::{Op, Parser};
+        use terminfo::{StringField, TermInfo};
+
+        fn parse(src: &[u8]) -> Vec<Op> {
+            Parser::new(src)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        }
+
+        let sources: [&[u8]; 8] = [
+            b"\x1b[%i%p1%d;%p2%dH",
+            b"%?%p1%tA%eB%;.",
+            b"%?%p1%t%?%p2%tA%eB%;%eC%;",
+            b"%?%p1%tX%e%p2%tY%eZ%;",
+            b"%?%p1%tA%e%;",
+            b"%p1%?%tA%;%?%{1}%{2}%>%t%;",
+            b"100%%%'\\E'%'^M'%'\\''%Pa%gZ%:-+5.2x%03o%l%!%~",
+            b"%?%?%p1%t%{1}%e%{0}%;%tT%eF%;",
+        ];
+        for &src in sources.iter() {
+            let rendered = super::render(&parse(src)).unwrap();
+            assert_eq!(parse(&rendered), parse(src), "{:?}", String::from_utf8_lossy(&rendered));
+        }
+        assert_eq!(normalize(b"%?%p1%tA%e%p2%tB%;").unwrap(), b"%?%p1%tA%e%p2%tB%;".to_vec());
+        assert_eq!(normalize(b"%:2d%2d").unwrap(), b"%2d%2d".to_vec());
+        assert_eq!(normalize(b"%'\\033'").unwrap(), b"%'\\E'".to_vec());
+
+        let xterm = TermInfo::parse(include_bytes!("../../../test-data/xterm")).unwrap();
+        for &field in [
+            StringField::SetAttributes,
+            StringField::SetAForeground,
+            StringField::SetABackground,
+            StringField::CursorAddress,
+            StringField::ChangeScrollRegion,
+            StringField::SetForeground,
+            StringField::RepeatChar,
+        ].iter()
+        {
+            let src = xterm.string(field).unwrap().as_bytes();
+            assert_eq!(parse(&super::render(&parse(src)).unwrap()), parse(src));
+        }
+
+        assert!(super::render(&[Op::Jump(1)]).is_err());
+        assert!(super::render(&[Op::BranchFalse(2), Op::NoOp]).is_err());
+        assert!(super::render(&[Op::Push("a".into())]).is_err());
+    }
+
+    #[test]
+    fn reuse() {
+        let mut buffer = Vec::new();
+        let mut exe = Executor::new(b"%i%p1%d;%p2%d;%gA%d%p1%PA,");
+        for i in 0..3 {
+            exe.set_args(&[i.into(), (i * 2).into()]);
+            exe.write(&mut buffer).unwrap();
+        }
+        assert_eq!(&buffer, b"1;1;0,2;3;1,3;5;2,");
+        buffer.clear();
+
+        exe.reset();
+        exe.arg(7).write(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"8;0;0,");
+        buffer.clear();
+
+        let mut env = ExecutionEnvironment::new();
+        let program = Program::compile(b"%p1%d%p2%d").unwrap();
+        env.set_args(&[1.into(), 2.into()]);
+        program.exec_in(&mut env, &mut buffer).unwrap();
+        env.clear_args();
+        program.exec_in(&mut env, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"1200");
     }
 
     #[test]
@@ -106,7 +764,7 @@ mod tests {
             .arg(20)
             .write(&mut buffer)
             .unwrap();
-        assert_eq!(&buffer, b"/604");
+        assert_eq!(&buffer, b"/6040");
         buffer.clear();
 
         Executor::new(b"%i%p1%d.%p2%d")
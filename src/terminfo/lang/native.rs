@@ -0,0 +1,186 @@
+use failure::ResultExt;
+use std::io;
+use terminfo::errors::*;
+use terminfo::lang::parser::Op;
+use terminfo::lang::printf::PrintfArgs;
+use terminfo::lang::Argument;
+
+/// A piece of a `Native::Template`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Piece {
+    /// Bytes that are printed as-is.
+    Literal(Vec<u8>),
+
+    /// A parameter printed with a plain `%d`.
+    Decimal(usize),
+}
+
+/// A capability string in one of a few common forms, which can be written with native code instead of the interpreter.
+///
+/// Every form produces exactly the same output as running the string,
+/// when a form can't (e.g. a parameter is a string) `exec` returns `None` and the caller falls back to the interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Native {
+    /// A string made of literals and parameters printed with `%d`, possibly with a `%i` before the first parameter.
+    /// This covers most `cup`, `sgr0`, and 8 color `setaf` strings, e.g. `\E[%i%p1%d;%p2%dH`.
+    Template { pieces: Vec<Piece>, increment: bool },
+
+    /// xterm's 256 color `setaf` or `setab`, `base` is 3 for `setaf` and 4 for `setab`.
+    Color256 { base: u8 },
+}
+
+/// The source of xterm's 256 color `setaf` (`base` = 3) or `setab` (`base` = 4).
+fn xterm_256_color(base: u8) -> Vec<u8> {
+    let mut src = b"\x1b[%?%p1%{8}%<%t".to_vec();
+    src.push(b'0' + base);
+    src.extend_from_slice(b"%p1%d%e%p1%{16}%<%t");
+    src.extend_from_slice(if base == 3 { b"9" } else { b"10" });
+    src.extend_from_slice(b"%p1%{8}%-%d%e");
+    src.push(b'0' + base);
+    src.extend_from_slice(b"8;5;%p1%d%;m");
+    src
+}
+
+/// Write `n` in decimal, returns the number of bytes written.
+fn write_decimal<W: io::Write>(w: &mut W, n: i64) -> Result<usize> {
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    let mut x = n.unsigned_abs();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (x % 10) as u8;
+        x /= 10;
+        if x == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        w.write_all(b"-").context(ErrorKind::FailedToWriteOutput)?;
+    }
+    w.write_all(&buf[i..])
+        .context(ErrorKind::FailedToWriteOutput)?;
+    Ok(buf.len() - i + (n < 0) as usize)
+}
+
+/// Get parameter `i` as an integer, missing parameters are 0.
+fn integer(args: &[Argument], i: usize) -> Option<i64> {
+    match args.get(i) {
+        Some(&Argument::Integer(x)) => Some(x),
+        Some(_) => None,
+        None => Some(0),
+    }
+}
+
+impl Native {
+    /// Match the string `src`, which parsed to `ops`, against the known forms.
+    pub(crate) fn recognize(src: &[u8], ops: &[Op]) -> Option<Native> {
+        for &base in [3, 4].iter() {
+            if src == &xterm_256_color(base)[..] {
+                return Some(Native::Color256 { base });
+            }
+        }
+
+        let plain = PrintfArgs {
+            character: 'd',
+            ..PrintfArgs::default()
+        };
+        let mut pieces = Vec::new();
+        let mut increment = false;
+        let mut pushed = None;
+
+        for op in ops {
+            match (op, pushed) {
+                (&Op::PrintSlice(s), None) => match pieces.last_mut() {
+                    Some(Piece::Literal(lit)) => lit.extend_from_slice(s),
+                    _ => pieces.push(Piece::Literal(s.to_vec())),
+                },
+                (&Op::IncrementArgs, None) if !increment => {
+                    if pieces.iter().any(Piece::is_decimal) {
+                        return None;
+                    }
+                    increment = true;
+                }
+                (&Op::PushUserArg(i), None) => pushed = Some(i),
+                (Op::Print(p), Some(i)) if *p == plain => {
+                    pieces.push(Piece::Decimal(i));
+                    pushed = None;
+                }
+                _ => return None,
+            }
+        }
+
+        if pushed.is_some() {
+            return None;
+        }
+        Some(Native::Template { pieces, increment })
+    }
+
+    /// Write the string with the parameters `args`.
+    ///
+    /// Returns `None`, without writing anything, if the parameters can't be handled natively.
+    pub(crate) fn exec<W: io::Write>(&self, args: &[Argument], w: &mut W) -> Result<Option<usize>> {
+        match *self {
+            Native::Template {
+                ref pieces,
+                increment,
+            } => {
+                let supported = pieces.iter().all(|p| match *p {
+                    Piece::Decimal(i) => integer(args, i).is_some(),
+                    Piece::Literal(_) => true,
+                });
+                if !supported {
+                    return Ok(None);
+                }
+
+                let mut written = 0;
+                for piece in pieces {
+                    match *piece {
+                        Piece::Literal(ref lit) => {
+                            w.write_all(lit).context(ErrorKind::FailedToWriteOutput)?;
+                            written += lit.len();
+                        }
+                        Piece::Decimal(i) => {
+                            let mut n = integer(args, i).unwrap_or(0);
+                            // `%i` only changes parameters that were actually given
+                            if increment && i < 2 && i < args.len() {
+                                n = n.wrapping_add(1);
+                            }
+                            written += write_decimal(w, n)?;
+                        }
+                    }
+                }
+                Ok(Some(written))
+            }
+            Native::Color256 { base } => {
+                // negative colors are printed after the "3" or "4", e.g. "\x1b[3-1m"
+                let n = match integer(args, 0) {
+                    Some(n) if n >= 0 => n,
+                    _ => return Ok(None),
+                };
+
+                w.write_all(b"\x1b[").context(ErrorKind::FailedToWriteOutput)?;
+                let mut written = 2 + if n < 8 {
+                    write_decimal(w, i64::from(base) * 10 + n)?
+                } else if n < 16 {
+                    write_decimal(w, i64::from(base + 6) * 10 + n - 8)?
+                } else {
+                    write_decimal(w, i64::from(base) * 10 + 8)?;
+                    w.write_all(b";5;").context(ErrorKind::FailedToWriteOutput)?;
+                    5 + write_decimal(w, n)?
+                };
+                w.write_all(b"m").context(ErrorKind::FailedToWriteOutput)?;
+                written += 1;
+                Ok(Some(written))
+            }
+        }
+    }
+}
+
+impl Piece {
+    fn is_decimal(&self) -> bool {
+        match *self {
+            Piece::Decimal(_) => true,
+            Piece::Literal(_) => false,
+        }
+    }
+}
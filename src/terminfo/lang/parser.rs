@@ -1,14 +1,47 @@
+//! A parser for terminfo's parameterized string language.
+//!
+//! `Parser` turns a capability string into a flat list of `Op`s, the same ops the `Executor` runs.
+//! Conditionals are flattened into `BranchFalse` and `Jump` ops, so each op is a single step.
+//! `Parser::spanned` also gives the range of the source each op was parsed from, for tools like syntax highlighters.
 use failure::ResultExt;
 use std::collections::VecDeque;
+use std::ops::Range;
 use std::str;
-use std::str::FromStr;
 use terminfo::errors::*;
 use terminfo::lang::printf::PrintfArgs;
 use terminfo::lang::Argument;
 
+/// Parses a capability string into `Op`s, one at a time.
 pub struct Parser<'a> {
+    src: &'a [u8],
     slice: &'a [u8],
-    buffer: VecDeque<Op<'a>>,
+    buffer: VecDeque<(Op<'a>, Range<usize>)>,
+}
+
+/// An iterator over the ops in a string, along with the range of bytes each op was parsed from.
+///
+/// Ops that don't come from any source (like the jumps generated for a `%e`) have the range of the closest sequence, e.g. `%e`.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::parser::{Op, Parser};
+///
+/// let ops = Parser::new(b"\x1b[%p1%dm")
+///     .spanned()
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(ops[1], (Op::PushUserArg(0), 2..5));
+/// ```
+pub struct Spanned<'a>(Parser<'a>);
+
+/// A variable, set with `%P` and read with `%g`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Variable {
+    /// `a`-`z`, these are cleared every time a string is executed.
+    Dynamic(u8),
+
+    /// `A`-`Z`, these are kept as long as the `ExecutionEnvironment` lives.
+    Static(u8),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -19,19 +52,36 @@ pub enum Op<'a> {
     /// Push a static argument onto the stack
     Push(Argument),
 
+    /// Pop the stack and store the value in a variable
+    SetVariable(Variable),
+
+    /// Push a variable's value onto the stack, unset variables are 0
+    PushVariable(Variable),
+
+    /// Do nothing
     NoOp,
+
+    /// `%+`, `%-`, `%*`, `%/` and `%m`: pop `b` then `a`, and push `a + b`, `a - b`, etc.
     Add,
     Sub,
     Mul,
     Div,
     Mod,
+
+    /// `%&`, `%|` and `%^`: pop two integers and push the result of a bitwise operation
     BitAnd,
     BitOr,
     BitXor,
+
+    /// `%<`, `%>` and `%=`: pop `b` then `a`, and push 1 if `a < b`, `a > b` or `a = b`, otherwise 0
     Less,
     Greater,
     Equal,
+
+    /// `%~`: pop an integer and push its bitwise complement
     Invert,
+
+    /// `%!`: pop an integer and push 1 if it's 0, otherwise 0
     Not,
 
     /// increment the first two arguments
@@ -59,11 +109,41 @@ pub enum Op<'a> {
 impl<'a> Parser<'a> {
     pub fn new(src: &'a [u8]) -> Parser<'a> {
         Parser {
+            src,
             slice: src,
             buffer: VecDeque::with_capacity(4),
         }
     }
 
+    /// Get an iterator over the remaining ops and their locations in the source.
+    pub fn spanned(self) -> Spanned<'a> {
+        Spanned(self)
+    }
+
+    /// The offset of the first byte that hasn't been parsed yet.
+    ///
+    /// After an error this is the start of the sequence that couldn't be parsed, or the start of the conditional it's in.
+    pub fn offset(&self) -> usize {
+        self.src.len() - self.slice.len()
+    }
+
+    /// The string being parsed.
+    pub fn source(&self) -> &'a [u8] {
+        self.src
+    }
+
+    /// Parse the next op, and its location in the source.
+    ///
+    /// Errors include the location of the sequence that couldn't be parsed, see `Error::location`.
+    pub fn next_spanned(&mut self) -> Option<Result<(Op<'a>, Range<usize>)>> {
+        if self.buffer.is_empty() {
+            if let Err(e) = self.next_instruction() {
+                return Some(Err(e.at(self.src, self.offset())));
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+
     pub fn parse(&mut self) -> Result<()> {
         while self.slice.len() > 0 {
             self.next_instruction()?;
@@ -71,25 +151,14 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// Add an op that starts at the current position, its end is filled in by `next_instruction`.
     fn add_instruction(&mut self, op: Op<'a>) {
-        self.buffer.push_back(op)
+        let start = self.offset();
+        self.buffer.push_back((op, start..start))
     }
 
     fn parse_until(&mut self, stop: &[u8]) -> Result<()> {
-        if self.slice[0] == b'%' {
-            for &c in stop {
-                if c == self.slice[1] {
-                    break;
-                }
-            }
-        }
-
         while self.slice.len() >= 2 {
-            // println!(
-            //     "{} ? {}",
-            //     self.slice.iter().map(|&c| c as char).collect::<String>(),
-            //     stop.iter().map(|&c| c as char).collect::<String>()
-            // );
             if self.slice[0] == b'%' {
                 for &c in stop {
                     if c == self.slice[1] {
@@ -103,8 +172,71 @@ impl<'a> Parser<'a> {
         Err(ErrorKind::UnexpectedEof.into())
     }
 
+    /// Parse a conditional, starting after the `%?`, and leave the slice on the closing `%;`.
+    ///
+    /// A conditional is a list of conditions and branches, `%? c1 %t b1 %e c2 %t b2 %e b3 %;`,
+    /// every `%t` becomes a `BranchFalse` to the next condition, and every `%e` a `Jump` to the end of the conditional.
+    /// Nested conditionals are handled by `next_instruction` calling this function again.
+    fn parse_conditional(&mut self) -> Result<()> {
+        let mut end_jumps = Vec::new();
+
+        // the first condition
+        self.parse_until(b"te;")?;
+
+        while self.slice[1] == b't' {
+            let start = self.offset();
+            self.slice = &self.slice[2..];
+
+            // add a placeholder for the branch, it's filled in once the branch's length is known
+            let branch_idx = self.buffer.len();
+            self.buffer.push_back((Op::NoOp, start..start + 2));
+            self.parse_until(b"te;")?;
+            if self.slice[1] == b't' {
+                return Err(ErrorKind::UnbalancedConditional.into());
+            }
+
+            if self.slice[1] == b'e' {
+                let start = self.offset();
+                end_jumps.push(self.buffer.len());
+                self.buffer.push_back((Op::NoOp, start..start + 2));
+                self.buffer[branch_idx].0 = Op::BranchFalse(self.buffer.len() - 1 - branch_idx);
+
+                // either the else branch, or the next condition
+                self.slice = &self.slice[2..];
+                self.parse_until(b"te;")?;
+                if self.slice[1] == b'e' {
+                    return Err(ErrorKind::UnbalancedConditional.into());
+                }
+            } else {
+                self.buffer[branch_idx].0 = Op::BranchFalse(self.buffer.len() - 1 - branch_idx);
+            }
+        }
+
+        if self.slice[1] != b';' {
+            return Err(ErrorKind::UnbalancedConditional.into());
+        }
+
+        for j in end_jumps {
+            self.buffer[j].0 = Op::Jump(self.buffer.len() - j - 1);
+        }
+        Ok(())
+    }
+
     /// Read up to the next instruction store it & exit.
     fn next_instruction(&mut self) -> Result<()> {
+        let first = self.buffer.len();
+        self.parse_instruction()?;
+
+        let end = self.offset();
+        for &mut (_, ref mut span) in self.buffer.iter_mut().skip(first) {
+            if span.start == span.end {
+                span.end = end;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_instruction(&mut self) -> Result<()> {
         if self.slice.len() == 0 {
             // EOF
             return Ok(());
@@ -136,6 +268,19 @@ impl<'a> Parser<'a> {
                 };
                 read += 1;
             }
+            c @ b'P' | c @ b'g' => {
+                let var = match self.slice.get(2) {
+                    Some(&v @ b'a'..=b'z') => Variable::Dynamic(v - b'a'),
+                    Some(&v @ b'A'..=b'Z') => Variable::Static(v - b'A'),
+                    _ => return Err(ErrorKind::InvalidVariableName.into()),
+                };
+                self.add_instruction(if c == b'P' {
+                    Op::SetVariable(var)
+                } else {
+                    Op::PushVariable(var)
+                });
+                read += 1;
+            }
             b'{' => {
                 let numlen = self.slice
                     .iter()
@@ -152,17 +297,12 @@ impl<'a> Parser<'a> {
                 read += numlen - 1;
             }
             b'\'' => {
-                let charlen = self.slice
-                    .iter()
-                    .skip(2)
-                    .take_while(|&&c| c != b'\'')
-                    .count() + 2;
-                self.add_instruction(Op::Push(
-                    char::from_str(str::from_utf8(&self.slice[2..charlen]).context(ErrorKind::InvalidChar)?)
-                        .context(ErrorKind::InvalidChar)?
-                        .into(),
-                ));
-                read += charlen - 1;
+                let (c, len) = parse_char_constant(&self.slice[2..])?;
+                if self.slice.get(len + 2) != Some(&b'\'') {
+                    return Err(ErrorKind::InvalidChar.into());
+                }
+                self.add_instruction(Op::Push(Argument::Char(c)));
+                read += len + 1;
             }
 
             b'i' => self.add_instruction(Op::IncrementArgs),
@@ -181,43 +321,11 @@ impl<'a> Parser<'a> {
             b'~' => self.add_instruction(Op::Invert),
             b'!' => self.add_instruction(Op::Not),
             b'?' => {
-                // add a placeholder for branch instruction, we will update it later
                 self.slice = &self.slice[read..];
-                self.parse_until(&[b't'])?;
-                let mut end_jumps = Vec::new();
-
-                while self.slice.len() > 1 && self.slice[1] == b't' {
-                    self.slice = &self.slice[2..];
-
-                    let branch_idx = self.buffer.len();
-                    self.add_instruction(Op::NoOp);
-                    self.parse_until(&[b'e', b';'])?;
-
-                    if self.slice.len() < 2 {
-                        // missing end of if-statement
-                        return Err(ErrorKind::UnexpectedEof.into());
-                    }
-
-                    if self.slice[1] == b'e' {
-                        // add a placeholder jump instruction, we will update it later
-                        end_jumps.push(self.buffer.len());
-                        self.add_instruction(Op::NoOp);
-                        self.buffer[branch_idx] =
-                            Op::BranchFalse(self.buffer.len() - 1 - branch_idx);
-
-                        self.slice = &self.slice[2..];
-                        self.parse_until(&[b';', b't'])?;
-                    } else {
-                        // if the condition fails jump to the after the %;
-                        self.buffer[branch_idx] =
-                            Op::BranchFalse(self.buffer.len() - 1 - branch_idx);
-                    }
-                }
-                for j in end_jumps {
-                    self.buffer[j] = Op::Jump(self.buffer.len() - j - 1);
-                }
+                self.parse_conditional()?;
                 read = 2;
             }
+            b't' | b'e' | b';' => return Err(ErrorKind::UnbalancedConditional.into()),
             _ => {
                 self.add_instruction(Op::Print(PrintfArgs::parse(&self.slice[1..])?));
                 read += self.slice
@@ -235,19 +343,74 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Parse the character in a constant like `%'a'`, `%'\E'` or `%'^M'`, starting after the first quote.
+///
+/// Returns the character and the number of bytes it used, not including the closing quote.
+/// Escapes are the same as the ones in terminfo source files, so `\0` is 0x80, and `^?` is DEL.
+fn parse_char_constant(s: &[u8]) -> Result<(u8, usize)> {
+    match s.first() {
+        Some(b'\\') => {
+            let octal = s[1..]
+                .iter()
+                .take(3)
+                .take_while(|c| (b'0'..=b'7').contains(c))
+                .count();
+            if octal == 3 {
+                let n = s[1..4].iter().fold(0u32, |n, &c| n * 8 + (c - b'0') as u32);
+                return if n > 0xff {
+                    Err(ErrorKind::InvalidChar.into())
+                } else {
+                    Ok((n as u8, 4))
+                };
+            }
+
+            let c = match s.get(1) {
+                Some(b'E') | Some(b'e') => 0x1b,
+                Some(b'n') | Some(b'l') => b'\n',
+                Some(b'r') => b'\r',
+                Some(b't') => b'\t',
+                Some(b'b') => 0x08,
+                Some(b'f') => 0x0c,
+                Some(b's') => b' ',
+                Some(b'0') => 0x80,
+                Some(&c) if b"^\\,:'".contains(&c) => c,
+                _ => return Err(ErrorKind::InvalidChar.into()),
+            };
+            Ok((c, 2))
+        }
+        Some(b'^') => match s.get(1) {
+            Some(b'?') => Ok((0x7f, 2)),
+            Some(&c) if (b'@'..=b'_').contains(&c.to_ascii_uppercase()) => {
+                Ok((c.to_ascii_uppercase() & 0x1f, 2))
+            }
+            _ => Err(ErrorKind::InvalidChar.into()),
+        },
+        Some(b'\'') | None => Err(ErrorKind::InvalidChar.into()),
+        Some(_) => {
+            // a single, possibly multi-byte, character is truncated to a byte
+            let len = s.iter().take(4).take_while(|&&c| c != b'\'').count();
+            let c = (1..=len)
+                .filter_map(|n| str::from_utf8(&s[..n]).ok())
+                .next()
+                .and_then(|c| c.chars().next())
+                .ok_or(ErrorKind::InvalidChar)?;
+            Ok((c as u8, c.len_utf8()))
+        }
+    }
+}
+
 impl<'a> Iterator for Parser<'a> {
     type Item = Result<Op<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.buffer.pop_front() {
-            Some(v) => Some(Ok(v)),
-            None => match self.next_instruction() {
-                Ok(_) => Some(Ok(match self.buffer.pop_front() {
-                    Some(v) => v,
-                    None => return None,
-                })),
-                Err(e) => return Some(Err(e)),
-            },
-        }
+        self.next_spanned().map(|r| r.map(|(op, _)| op))
+    }
+}
+
+impl<'a> Iterator for Spanned<'a> {
+    type Item = Result<(Op<'a>, Range<usize>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_spanned()
     }
 }
@@ -18,6 +18,7 @@ pub struct PrintfArgs {
     pub show_sign: bool,
     pub pad_sign: bool,
     pub alt: bool,
+    pub zero_pad: bool,
     pub width: Option<usize>,
     pub prec: Option<usize>,
     pub character: char,
@@ -34,83 +35,88 @@ impl PrintfArgs {
         match src[0] {
             // flags are prefixed with a `:`
             b':' => spec.parse_flags(&src[1..])?,
-            b'0'..=b'9' | b'.' => spec.parse_width(src)?,
+            // a leading zero is the only flag that doesn't need a `:` (e.g. `%03d`)
+            b'0'..=b'9' | b'.' => spec.parse_flags(src)?,
             _ => spec.parse_specifier(src)?,
         }
 
         Ok(spec)
     }
 
-    fn pad<W: io::Write>(&self, w: &mut W, buf: &[u8]) -> Result<usize> {
+    /// Write `count` copies of `c`.
+    fn fill<W: io::Write>(w: &mut W, c: u8, count: usize) -> Result<usize> {
+        let chunk = [c; 32];
+        let mut left = count;
+        while left > 0 {
+            let n = left.min(chunk.len());
+            w.write_all(&chunk[..n])
+                .context(ErrorKind::FailedToWriteArgument)?;
+            left -= n;
+        }
+        Ok(count)
+    }
+
+    /// Write the pieces of a formatted value, padded with spaces to `width`.
+    fn pad_parts<W: io::Write>(&self, w: &mut W, parts: &[&[u8]], zeros: usize) -> Result<usize> {
+        let len = parts.iter().map(|p| p.len()).sum::<usize>() + zeros;
+        let padding = self.width.map(|width| width.saturating_sub(len)).unwrap_or(0);
         let mut written = 0;
-        if let Some(width) = self.width {
-            if buf.len() < width && !self.left_align {
-                for _ in buf.len()..width {
-                    w.write(&[b' ']).context(ErrorKind::FailedToWriteArgument)?;
-                }
-                written += width - buf.len()
-            }
+
+        if !self.left_align {
+            written += PrintfArgs::fill(w, b' ', padding)?;
         }
 
-        written += w.write(buf).context(ErrorKind::FailedToWriteArgument)?;
+        // zeros go between the sign or prefix and the digits
+        let (prefix, rest) = parts.split_at(parts.len().min(1));
+        for part in prefix {
+            w.write_all(part).context(ErrorKind::FailedToWriteArgument)?;
+        }
+        written += PrintfArgs::fill(w, b'0', zeros)?;
+        for part in rest {
+            w.write_all(part).context(ErrorKind::FailedToWriteArgument)?;
+        }
+        written += len - zeros;
 
-        if let Some(width) = self.width {
-            if buf.len() < width && self.left_align {
-                for _ in buf.len()..width {
-                    w.write(&[b' ']).context(ErrorKind::FailedToWriteArgument)?;
-                }
-                written += width - buf.len()
-            }
+        if self.left_align {
+            written += PrintfArgs::fill(w, b' ', padding)?;
         }
 
         Ok(written)
     }
+
+    fn pad<W: io::Write>(&self, w: &mut W, buf: &[u8]) -> Result<usize> {
+        self.pad_parts(w, &[b"", buf], 0)
+    }
+
     pub fn write_number<W: io::Write>(&self, w: &mut W, num: i64) -> Result<usize> {
         let (radix, uppercase) = match self.character {
             'x' => (16, false),
             'X' => (16, true),
             'o' => (8, false),
             'd' => (10, false),
+            'c' => return self.pad(w, &[num as u8]),
             's' => return Err(ErrorKind::UnexpectedArgumentType("string", "integer").into()),
-            'c' => return Err(ErrorKind::UnexpectedArgumentType("char", "integer").into()),
             _ => return Err(ErrorKind::UnexpectedArgumentType("", "integer").into()),
         };
-        let mut num_buf = [0u8; 22];
-
-        let mut wnum = num;
-        let mut num_buf_len = 0;
-        if wnum < 0 {
-            wnum = -wnum
-        }
 
-        if num < 0 {
-            num_buf[0] = b'-';
-            num_buf_len += 1;
-        } else if self.pad_sign {
-            num_buf[0] = b' ';
-            num_buf_len += 1;
-        } else if self.show_sign {
-            num_buf[0] = b'+';
-            num_buf_len += 1;
-        }
-
-        if self.alt {
-            if radix == 8 {
-                num_buf[num_buf_len] = b'0';
-                num_buf_len += 1;
-            } else if uppercase && radix == 16 {
-                num_buf[num_buf_len] = b'0';
-                num_buf[num_buf_len + 1] = b'X';
-                num_buf_len += 2;
-            } else if radix == 16 {
-                num_buf[num_buf_len] = b'0';
-                num_buf[num_buf_len + 1] = b'x';
-                num_buf_len += 2;
-            }
-        }
+        // terminfo parameters are C ints, so negative numbers are printed as 32 bit unsigned integers in hex and octal
+        let mut wnum = match radix {
+            10 => num.unsigned_abs(),
+            _ if num < 0 && num >= i64::from(i32::MIN) => u64::from(num as u32),
+            _ => num as u64,
+        };
 
-        let prefix_len = num_buf_len;
+        let prefix: &[u8] = match radix {
+            10 if num < 0 => b"-",
+            10 if self.show_sign => b"+",
+            10 if self.pad_sign => b" ",
+            16 if self.alt && wnum != 0 && uppercase => b"0X",
+            16 if self.alt && wnum != 0 => b"0x",
+            _ => b"",
+        };
 
+        let mut num_buf = [0u8; 22];
+        let mut num_buf_len = 0;
         while wnum > 0 {
             let c = wnum % radix;
             wnum /= radix;
@@ -121,15 +127,20 @@ impl PrintfArgs {
             }
             num_buf_len += 1;
         }
+        num_buf[..num_buf_len].reverse();
 
-        num_buf[prefix_len..num_buf_len].reverse();
-
-        if let Some(prec) = self.prec {
-            if num_buf_len - prefix_len > prec {
-                num_buf_len = prec + prefix_len;
+        // the precision is the minimum number of digits, by default zero is printed as "0"
+        let mut zeros = self.prec.unwrap_or(1).saturating_sub(num_buf_len);
+        if self.alt && radix == 8 && zeros == 0 {
+            zeros = 1;
+        }
+        if self.zero_pad && !self.left_align && self.prec.is_none() {
+            if let Some(width) = self.width {
+                zeros = zeros.max(width.saturating_sub(prefix.len() + num_buf_len));
             }
         }
-        self.pad(w, &num_buf[..num_buf_len])
+
+        self.pad_parts(w, &[prefix, &num_buf[..num_buf_len]], zeros)
     }
 
     pub fn write_string<W: io::Write>(&self, w: &mut W, s: &str) -> Result<usize> {
@@ -192,13 +203,14 @@ impl PrintfArgs {
 
     fn parse_flags(&mut self, src: &[u8]) -> Result<()> {
         let flags = src.iter()
-            .take_while(|&&c| c == b'+' || c == b'-' || c == b'#' || c == b' ')
+            .take_while(|&&c| c == b'+' || c == b'-' || c == b'#' || c == b' ' || c == b'0')
             .fold(0, |x, flag| {
                 match flag {
                     b'+' => self.show_sign = true,
                     b'-' => self.left_align = true,
                     b'#' => self.alt = true,
                     b' ' => self.pad_sign = true,
+                    b'0' => self.zero_pad = true,
                     _ => unreachable!(),
                 }
                 x + 1
@@ -239,12 +251,13 @@ impl PrintfArgs {
 
 fn parse_usize(s: &[u8]) -> Result<usize> {
     s.iter()
-        .try_fold((0_usize, 1_usize), |(num, pow), &c| {
+        .try_fold(0_usize, |num, &c| {
             if c >= b'0' && c <= b'9' {
-                Ok((num + (((c - b'0') as usize) * pow), pow * 10))
+                num.checked_mul(10)
+                    .and_then(|num| num.checked_add((c - b'0') as usize))
+                    .ok_or_else(|| ErrorKind::BadPrecisionSpecified.into())
             } else {
                 Err(ErrorKind::InvalidDigit(c).into())
             }
         })
-        .map(|(num, _)| num)
 }
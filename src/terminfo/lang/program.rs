@@ -0,0 +1,158 @@
+use std::io;
+use terminfo::errors::*;
+use terminfo::lang::executor::ExecutionEnvironment;
+use terminfo::lang::native::Native;
+use terminfo::lang::parser::{Op, Parser};
+use terminfo::lang::Argument;
+
+/// A single step in a `Program`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Instruction {
+    /// Any op that doesn't reference the source string, or change the control flow.
+    Op(Op<'static>),
+
+    /// Print `literals[start..end]`.
+    Literal(usize, usize),
+
+    /// Continue at the instruction at this index.
+    Jump(usize),
+
+    /// Pop the stack, if the value is false continue at the instruction at this index.
+    BranchFalse(usize),
+
+    /// Pop the stack, if the value is true continue at the instruction at this index.
+    BranchTrue(usize),
+}
+
+/// A parsed capability string.
+///
+/// Running an `Executor` parses its source every time it's written, a `Program` is parsed once, up front.
+/// This is useful for strings that are run often, like `cup` or `setaf`.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::Program;
+///
+/// let cup = Program::compile(b"\x1b[%i%p1%d;%p2%dH").unwrap();
+/// let mut buf = Vec::new();
+/// cup.exec(&[4.into(), 10.into()], &mut buf).unwrap();
+/// assert_eq!(buf, b"\x1b[5;11H");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    literals: Vec<u8>,
+    native: Option<Native>,
+}
+
+impl Program {
+    /// Parse a capability string.
+    pub fn compile(src: &[u8]) -> Result<Program> {
+        let ops = Parser::new(src).collect::<Result<Vec<Op>>>()?;
+        let mut program = Program {
+            instructions: Vec::with_capacity(ops.len()),
+            literals: Vec::new(),
+            native: Native::recognize(src, &ops),
+        };
+
+        for (i, op) in ops.into_iter().enumerate() {
+            // jumps in the parser's output are relative to the next op.
+            let instruction = match op {
+                Op::PrintSlice(slice) => {
+                    let start = program.literals.len();
+                    program.literals.extend_from_slice(slice);
+                    Instruction::Literal(start, program.literals.len())
+                }
+                Op::Jump(n) => Instruction::Jump(i + n + 1),
+                Op::BranchFalse(n) => Instruction::BranchFalse(i + n + 1),
+                Op::BranchTrue(n) => Instruction::BranchTrue(i + n + 1),
+                Op::PushUserArg(x) => Instruction::Op(Op::PushUserArg(x)),
+                Op::Push(x) => Instruction::Op(Op::Push(x)),
+                Op::SetVariable(x) => Instruction::Op(Op::SetVariable(x)),
+                Op::PushVariable(x) => Instruction::Op(Op::PushVariable(x)),
+                Op::NoOp => Instruction::Op(Op::NoOp),
+                Op::Add => Instruction::Op(Op::Add),
+                Op::Sub => Instruction::Op(Op::Sub),
+                Op::Mul => Instruction::Op(Op::Mul),
+                Op::Div => Instruction::Op(Op::Div),
+                Op::Mod => Instruction::Op(Op::Mod),
+                Op::BitAnd => Instruction::Op(Op::BitAnd),
+                Op::BitOr => Instruction::Op(Op::BitOr),
+                Op::BitXor => Instruction::Op(Op::BitXor),
+                Op::Less => Instruction::Op(Op::Less),
+                Op::Greater => Instruction::Op(Op::Greater),
+                Op::Equal => Instruction::Op(Op::Equal),
+                Op::Invert => Instruction::Op(Op::Invert),
+                Op::Not => Instruction::Op(Op::Not),
+                Op::IncrementArgs => Instruction::Op(Op::IncrementArgs),
+                Op::StrLen => Instruction::Op(Op::StrLen),
+                Op::Print(x) => Instruction::Op(Op::Print(x)),
+            };
+            program.instructions.push(instruction);
+        }
+
+        Ok(program)
+    }
+
+    /// Run the program with the parameters `args`, writing the output to `w`.
+    ///
+    /// Only the first 9 arguments are used, missing arguments are 0.
+    ///
+    /// Strings in a few common forms (like xterm's `cup` and `setaf`) are written directly, without running the program.
+    pub fn exec<W: io::Write>(&self, args: &[Argument], w: &mut W) -> Result<usize> {
+        if let Some(ref native) = self.native {
+            if let Some(written) = native.exec(args, w)? {
+                return Ok(written);
+            }
+        }
+        self.exec_in(&mut ExecutionEnvironment::with_args(args), w)
+    }
+
+    /// True if the program can usually be written without running it, see `exec`.
+    pub fn is_native(&self) -> bool {
+        self.native.is_some()
+    }
+
+    /// Run the program in an existing environment, writing the output to `w`.
+    ///
+    /// The environment's parameters and static variables are used as they are,
+    /// so a single environment can be reused for many runs without allocating.
+    pub fn exec_in<W: io::Write>(&self, env: &mut ExecutionEnvironment, w: &mut W) -> Result<usize> {
+        env.begin();
+        let mut written = 0;
+        let mut ip = 0;
+
+        while ip < self.instructions.len() {
+            ip = match self.instructions[ip] {
+                Instruction::Op(ref op) => {
+                    env.step(op, w, &mut written)?;
+                    ip + 1
+                }
+                Instruction::Literal(start, end) => {
+                    env.step(&Op::PrintSlice(&self.literals[start..end]), w, &mut written)?;
+                    ip + 1
+                }
+                Instruction::Jump(target) => target,
+                Instruction::BranchFalse(target) => {
+                    env.step(&Op::BranchFalse(0), w, &mut written)?
+                        .map(|_| target)
+                        .unwrap_or(ip + 1)
+                }
+                Instruction::BranchTrue(target) => {
+                    env.step(&Op::BranchTrue(0), w, &mut written)?
+                        .map(|_| target)
+                        .unwrap_or(ip + 1)
+                }
+            };
+        }
+
+        Ok(written)
+    }
+
+    /// Run the program, collecting the output in a vector.
+    pub fn vec(&self, args: &[Argument]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.exec(args, &mut buf)?;
+        Ok(buf)
+    }
+}
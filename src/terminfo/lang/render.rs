@@ -0,0 +1,259 @@
+use terminfo::errors::*;
+use terminfo::lang::parser::{Op, Parser, Variable};
+use terminfo::lang::printf::PrintfArgs;
+use terminfo::lang::Argument;
+
+/// Get the stack effect of an op, the number of values it pushes minus the number it pops.
+fn stack_effect(op: &Op) -> isize {
+    match *op {
+        Op::PushUserArg(_) | Op::Push(_) | Op::PushVariable(_) => 1,
+        Op::SetVariable(_) | Op::Print(_) | Op::BranchFalse(_) | Op::BranchTrue(_) => -1,
+        Op::Add
+        | Op::Sub
+        | Op::Mul
+        | Op::Div
+        | Op::Mod
+        | Op::BitAnd
+        | Op::BitOr
+        | Op::BitXor
+        | Op::Less
+        | Op::Greater
+        | Op::Equal => -1,
+        Op::Invert
+        | Op::Not
+        | Op::StrLen
+        | Op::IncrementArgs
+        | Op::NoOp
+        | Op::PrintSlice(_)
+        | Op::Jump(_) => 0,
+    }
+}
+
+/// Write a character constant's character, escaped the same way `%'\E'` or `%'^M'` is parsed.
+fn render_char(out: &mut Vec<u8>, c: u8) {
+    match c {
+        0x1b => out.extend_from_slice(b"\\E"),
+        0x80 => out.extend_from_slice(b"\\0"),
+        0x7f => out.extend_from_slice(b"^?"),
+        b'\'' | b'\\' | b'^' => out.extend_from_slice(&[b'\\', c]),
+        0..=0x1f => out.extend_from_slice(&[b'^', c + b'@']),
+        0x20..=0x7e => out.push(c),
+        _ => out.extend_from_slice(format!("\\{:03o}", c).as_bytes()),
+    }
+}
+
+fn render_variable(out: &mut Vec<u8>, var: Variable) {
+    out.push(match var {
+        Variable::Dynamic(i) => b'a' + i,
+        Variable::Static(i) => b'A' + i,
+    });
+}
+
+fn render_printf(out: &mut Vec<u8>, p: &PrintfArgs) {
+    out.push(b'%');
+    if p.left_align || p.show_sign || p.pad_sign || p.alt {
+        out.push(b':');
+        let flags = [
+            (p.left_align, b'-'),
+            (p.show_sign, b'+'),
+            (p.pad_sign, b' '),
+            (p.alt, b'#'),
+        ];
+        out.extend(flags.iter().filter(|f| f.0).map(|f| f.1));
+    }
+    if p.zero_pad {
+        out.push(b'0');
+    }
+    if let Some(width) = p.width {
+        out.extend_from_slice(width.to_string().as_bytes());
+    }
+    if let Some(prec) = p.prec {
+        out.push(b'.');
+        out.extend_from_slice(prec.to_string().as_bytes());
+    }
+    out.push(p.character as u8);
+}
+
+/// Write a single op, conditionals are handled by `render_range`.
+fn render_op(out: &mut Vec<u8>, op: &Op) -> Result<()> {
+    let seq: &[u8] = match *op {
+        Op::PushUserArg(i) if i < 9 => {
+            out.extend_from_slice(&[b'%', b'p', b'1' + i as u8]);
+            return Ok(());
+        }
+        Op::Push(Argument::Integer(x)) => {
+            out.extend_from_slice(format!("%{{{}}}", x).as_bytes());
+            return Ok(());
+        }
+        Op::Push(Argument::Char(c)) => {
+            out.extend_from_slice(b"%'");
+            render_char(out, c);
+            out.push(b'\'');
+            return Ok(());
+        }
+        Op::SetVariable(var) | Op::PushVariable(var) => {
+            out.extend_from_slice(if let Op::SetVariable(_) = *op { b"%P" } else { b"%g" });
+            render_variable(out, var);
+            return Ok(());
+        }
+        Op::Print(ref p) => {
+            render_printf(out, p);
+            return Ok(());
+        }
+        Op::PrintSlice(s) => {
+            for &c in s {
+                if c == b'%' {
+                    out.push(b'%');
+                }
+                out.push(c);
+            }
+            return Ok(());
+        }
+        Op::NoOp => b"",
+        Op::Add => b"%+",
+        Op::Sub => b"%-",
+        Op::Mul => b"%*",
+        Op::Div => b"%/",
+        Op::Mod => b"%m",
+        Op::BitAnd => b"%&",
+        Op::BitOr => b"%|",
+        Op::BitXor => b"%^",
+        Op::Less => b"%<",
+        Op::Greater => b"%>",
+        Op::Equal => b"%=",
+        Op::Invert => b"%~",
+        Op::Not => b"%!",
+        Op::IncrementArgs => b"%i",
+        Op::StrLen => b"%l",
+        Op::PushUserArg(_)
+        | Op::Push(Argument::String(_))
+        | Op::BranchFalse(_)
+        | Op::BranchTrue(_)
+        | Op::Jump(_) => return Err(ErrorKind::Unrenderable.into()),
+    };
+    out.extend_from_slice(seq);
+    Ok(())
+}
+
+/// A rendered range of ops.
+struct Rendered {
+    src: Vec<u8>,
+
+    /// True if the range is a single conditional, and nothing else.
+    conditional: bool,
+}
+
+/// Render `ops[lo..hi]`, jumps must stay inside the range.
+fn render_range(ops: &[Op], lo: usize, hi: usize) -> Result<Rendered> {
+    let mut src = Vec::new();
+    let mut offsets = Vec::with_capacity(hi - lo);
+    let mut conditionals = 0;
+    let mut whole = false;
+
+    // the first op that isn't part of an earlier conditional
+    let mut floor = lo;
+    let mut i = lo;
+    while i < hi {
+        offsets.push(src.len());
+        match ops[i] {
+            Op::BranchFalse(_) => {
+                // the condition is the shortest run of ops before the branch that pushes one value
+                let mut start = i;
+                let mut effect = 0;
+                for j in (floor..i).rev() {
+                    effect += stack_effect(&ops[j]);
+                    if effect == 1 {
+                        start = j;
+                        break;
+                    }
+                }
+
+                let (body, end) = render_conditional(ops, i, hi)?;
+                let at = offsets[start - lo];
+                src.splice(at..at, b"%?".iter().cloned());
+                src.extend_from_slice(&body);
+
+                conditionals += 1;
+                whole = start == lo && end == hi;
+                offsets.resize(end - lo, src.len());
+                floor = end;
+                i = end;
+            }
+            ref op => {
+                render_op(&mut src, op)?;
+                i += 1;
+            }
+        }
+    }
+
+    Ok(Rendered {
+        src,
+        conditional: conditionals == 1 && whole,
+    })
+}
+
+/// Render a conditional from the `BranchFalse` at `branch`, to the closing `%;`.
+/// Returns the source and the index of the first op after the conditional.
+fn render_conditional(ops: &[Op], branch: usize, hi: usize) -> Result<(Vec<u8>, usize)> {
+    let end = match ops[branch] {
+        Op::BranchFalse(n) => branch + 1 + n,
+        _ => unreachable!(),
+    };
+    if end > hi {
+        return Err(ErrorKind::Unrenderable.into());
+    }
+
+    // a jump at the end of the branch skips the `%e` part
+    if let Op::Jump(n) = ops[end - 1] {
+        let else_end = end + n;
+        if end - 1 > branch && else_end <= hi {
+            if let Ok(then) = render_range(ops, branch + 1, end - 1) {
+                let other = render_range(ops, end, else_end)?;
+                let mut src = b"%t".to_vec();
+                src.extend_from_slice(&then.src);
+                src.extend_from_slice(b"%e");
+                if other.conditional {
+                    // `%e %? c %t b %; %;` is written as `%e c %t b %;`
+                    src.extend_from_slice(&other.src[2..]);
+                } else {
+                    src.extend_from_slice(&other.src);
+                    src.extend_from_slice(b"%;");
+                }
+                return Ok((src, else_end));
+            }
+        }
+    }
+
+    let then = render_range(ops, branch + 1, end)?;
+    let mut src = b"%t".to_vec();
+    src.extend_from_slice(&then.src);
+    src.extend_from_slice(b"%;");
+    Ok((src, end))
+}
+
+/// Write parsed ops back out as a capability string.
+///
+/// The output is canonical, so two strings that parse to the same ops render the same way,
+/// e.g. flags are always written after a `:`, and conditions start as late as possible.
+/// An `Unrenderable` error is returned if the ops couldn't have come from the parser,
+/// like a jump out of a conditional, or a string constant.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::lang::parser::Parser;
+/// use nixterm::terminfo::lang::render;
+///
+/// let ops = Parser::new(b"%?%p1%t%p1%:-3d%e%{0}%c%;")
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(render(&ops).unwrap(), b"%?%p1%t%p1%:-3d%e%{0}%c%;".to_vec());
+/// ```
+pub fn render(ops: &[Op]) -> Result<Vec<u8>> {
+    render_range(ops, 0, ops.len()).map(|r| r.src)
+}
+
+/// Parse and render a capability string, so strings that do the same thing (e.g. `%2d` and `%:2d`) compare equal.
+pub fn normalize(src: &[u8]) -> Result<Vec<u8>> {
+    let ops = Parser::new(src).collect::<Result<Vec<_>>>()?;
+    render(&ops)
+}
@@ -0,0 +1,88 @@
+use std::mem;
+use std::ops::Deref;
+use terminfo::lang::Argument;
+
+/// The number of values a `Stack` holds before it moves to the heap.
+/// Capability strings rarely have more than a few values on the stack at once.
+const INLINE: usize = 8;
+
+/// Fills unused inline slots, so they don't hold on to strings.
+const EMPTY: Argument = Argument::Integer(0);
+
+/// The executor's stack, which keeps its first few values inline, and only allocates when it grows past that.
+pub(crate) enum Stack {
+    Inline([Argument; INLINE], usize),
+    Heap(Vec<Argument>),
+}
+
+impl Stack {
+    pub(crate) fn new() -> Stack {
+        Stack::Inline([EMPTY; INLINE], 0)
+    }
+
+    pub(crate) fn push(&mut self, arg: Argument) {
+        let spilled = match *self {
+            Stack::Inline(ref mut values, ref mut len) if *len < INLINE => {
+                values[*len] = arg;
+                *len += 1;
+                return;
+            }
+            Stack::Inline(ref mut values, _) => {
+                let mut heap = Vec::with_capacity(INLINE * 2);
+                heap.extend(values.iter_mut().map(|v| mem::replace(v, EMPTY)));
+                heap.push(arg);
+                heap
+            }
+            Stack::Heap(ref mut values) => {
+                values.push(arg);
+                return;
+            }
+        };
+        *self = Stack::Heap(spilled);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Argument> {
+        match *self {
+            Stack::Inline(_, 0) => None,
+            Stack::Inline(ref mut values, ref mut len) => {
+                *len -= 1;
+                Some(mem::replace(&mut values[*len], EMPTY))
+            }
+            Stack::Heap(ref mut values) => values.pop(),
+        }
+    }
+
+    /// Empty the stack, a stack that has moved to the heap keeps its memory.
+    pub(crate) fn clear(&mut self) {
+        match *self {
+            Stack::Inline(ref mut values, ref mut len) => {
+                for v in values[..*len].iter_mut() {
+                    *v = EMPTY;
+                }
+                *len = 0;
+            }
+            Stack::Heap(ref mut values) => values.clear(),
+        }
+    }
+
+    /// True if the stack has grown past its inline capacity.
+    #[cfg(test)]
+    pub(crate) fn spilled(&self) -> bool {
+        match *self {
+            Stack::Inline(..) => false,
+            Stack::Heap(_) => true,
+        }
+    }
+}
+
+impl Deref for Stack {
+    type Target = [Argument];
+
+    /// The values on the stack, the top of the stack is the last item.
+    fn deref(&self) -> &[Argument] {
+        match *self {
+            Stack::Inline(ref values, len) => &values[..len],
+            Stack::Heap(ref values) => values,
+        }
+    }
+}
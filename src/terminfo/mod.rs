@@ -1,12 +1,16 @@
 mod errors;
 mod fields;
 pub mod lang;
+mod padding;
+mod scan;
 mod strtab;
 mod terminfo;
 mod terminfobuf;
 
 pub use self::errors::*;
 pub use self::fields::*;
+pub use self::padding::*;
+pub use self::scan::*;
 pub use self::terminfo::*;
 pub use self::terminfobuf::*;
 
@@ -18,7 +22,7 @@ use failure::ResultExt;
 use std::env;
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Enumerate any know terminfo databases on the system.
 pub fn databases() -> Vec<PathBuf> {
@@ -44,35 +48,85 @@ pub fn databases() -> Vec<PathBuf> {
     dbs
 }
 
+/// Check that `name` can safely be used as a file name inside a terminfo database.
+///
+/// Names may not be empty, be "." or "..", or contain path separators or null bytes.
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') {
+        return Err(ErrorKind::InvalidTerminalName(name.to_string()).into());
+    }
+    Ok(())
+}
+
+/// List every file that may hold `name`'s entry in the database at `db`.
+///
+/// Most systems store entries in a directory named after the first character of the terminal name (e.g. `x/xterm`),
+/// but macOS, and other systems with case-insensitive file systems, use the first byte in hex (e.g. `78/xterm`).
+fn candidates(db: &Path, name: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(2);
+
+    if let Some(first) = name.chars().next() {
+        let mut buf = [0u8; 4];
+        paths.push(db.join(&*first.encode_utf8(&mut buf)).join(name));
+    }
+
+    paths.push(db.join(format!("{:02x}", name.as_bytes()[0])).join(name));
+    paths
+}
+
+/// Search `dbs`, in order, for the terminfo file describing `name`.
+fn search(dbs: &[PathBuf], name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+
+    let mut tried = Vec::new();
+    for db in dbs {
+        for candidate in candidates(db, name) {
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+            tried.push(candidate);
+        }
+    }
+
+    Err(ErrorKind::TermInfoNotFound(name.to_string(), tried).into())
+}
+
+/// Find the terminfo file for the terminal called `name` in any of the system's databases.
+///
+/// If no file could be found the error will be a `ErrorKind::TermInfoNotFound` listing every path that was checked.
+pub fn find<T: AsRef<str>>(name: T) -> Result<PathBuf> {
+    search(&databases(), name.as_ref())
+}
+
 /// Get a path to the terminfo file base on the `$TERM` environment variable.
 ///
 /// This function emulates the `curses` method for finding the compiled terminfo file.
 /// This method is explained in detail in `terminfo.5`.
+/// Use `find_from_env` to find out why the file couldn't be found.
 pub fn path() -> Option<PathBuf> {
-    let terminal_name = match env::var("TERM") {
-        Ok(v) => {
-            if v.is_empty() {
-                return None;
-            } else {
-                v
-            }
-        }
-        Err(_) => return None,
-    };
-
-    let suffix = PathBuf::from(&terminal_name[..1]).join(terminal_name);
-    databases()
-        .iter()
-        .find(|p| p.join(&suffix).exists())
-        .map(|p| p.join(suffix))
+    find_from_env().ok()
+}
+
+/// Find the terminfo file for the terminal named by `$TERM`, like `path`.
+///
+/// If `$TERM` isn't set the error is `ErrorKind::FailedToFindTermInfo`, otherwise it's the same as `find`.
+pub fn find_from_env() -> Result<PathBuf> {
+    match env::var("TERM") {
+        Ok(ref v) if !v.is_empty() => find(v),
+        _ => Err(ErrorKind::FailedToFindTermInfo.into()),
+    }
+}
+
+/// Load the terminfo entry for the terminal called `name`.
+pub fn from_name<T: AsRef<str>>(name: T) -> Result<TermInfoBuf> {
+    load(&find(name)?)
 }
 
 pub fn from_env() -> Result<TermInfoBuf> {
-    let path = match path() {
-        Some(v) => v,
-        None => return Err(ErrorKind::FailedToFindTermInfo.into()),
-    };
+    load(&find_from_env()?)
+}
 
+fn load(path: &Path) -> Result<TermInfoBuf> {
     let mut file = File::open(path).context(ErrorKind::FailedToParseFile)?;
     let mut data = Vec::new();
 
@@ -83,3 +137,45 @@ pub fn from_env() -> Result<TermInfoBuf> {
         .context(ErrorKind::FailedToParseFile)?
         .into())
 }
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use terminfo::*;
+
+    #[test]
+    fn search_paths() {
+        let db = env::temp_dir().join(format!("nixterm-terminfo-{}", ::std::process::id()));
+        fs::create_dir_all(db.join("x")).unwrap();
+        fs::create_dir_all(db.join("72")).unwrap();
+        fs::write(db.join("x").join("xterm"), b"").unwrap();
+        fs::write(db.join("72").join("rxvt"), b"").unwrap();
+
+        let dbs = vec![db.clone()];
+        assert_eq!(super::search(&dbs, "xterm").unwrap(), db.join("x/xterm"));
+        assert_eq!(super::search(&dbs, "rxvt").unwrap(), db.join("72/rxvt"));
+
+        match super::search(&dbs, "\u{e9}cran").unwrap_err().kind() {
+            ErrorKind::TermInfoNotFound(name, tried) => {
+                assert_eq!(name, "\u{e9}cran");
+                assert_eq!(
+                    tried,
+                    &vec![db.join("\u{e9}/\u{e9}cran"), db.join("c3/\u{e9}cran")]
+                );
+            }
+            k => panic!("unexpected error {:?}", k),
+        }
+
+        assert_eq!(
+            super::search(&dbs, "../x/xterm").unwrap_err().kind(),
+            &ErrorKind::InvalidTerminalName("../x/xterm".to_string())
+        );
+        assert_eq!(
+            super::search(&dbs, "").unwrap_err().kind(),
+            &ErrorKind::InvalidTerminalName("".to_string())
+        );
+
+        fs::remove_dir_all(db).unwrap();
+    }
+}
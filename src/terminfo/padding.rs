@@ -0,0 +1,256 @@
+use failure::ResultExt;
+use std::io;
+use std::thread;
+use std::time::Duration;
+use terminfo::errors::*;
+use terminfo::fields::*;
+use terminfo::TermInfoBuf;
+
+/// The longest delay `tputs` will honor, in tenths of a millisecond (one second).
+/// Longer delays, including proportional ones, are cut down to this.
+const MAX_DELAY: u64 = 10_000;
+
+/// The most pad characters `tputs` will write for one delay.
+const MAX_PADDING: usize = 4096;
+
+/// How `tputs` should handle the delays (e.g. `$<5>`) in a capability string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Padding {
+    /// The byte written to pad output.
+    pub pad_char: u8,
+
+    /// The line speed in bits per second, used to figure out how many pad characters make up a delay.
+    /// If it's 0, `tputs` sleeps instead.
+    pub baud_rate: u32,
+
+    /// The number of lines affected by the capability, proportional delays (e.g. `$<2*>`) are multiplied by this.
+    pub lines: usize,
+
+    /// Sleep for the length of the delay instead of writing pad characters.
+    pub sleep: bool,
+
+    /// Only honor mandatory delays (e.g. `$<5/>`), other delays are skipped.
+    pub mandatory_only: bool,
+}
+
+impl Padding {
+    /// Pad with NUL for a terminal at `baud_rate`.
+    pub fn new(baud_rate: u32) -> Padding {
+        Padding {
+            pad_char: 0,
+            baud_rate,
+            lines: 1,
+            sleep: false,
+            mandatory_only: false,
+        }
+    }
+
+    /// Pad the way `info` describes, for a terminal at `baud_rate`.
+    ///
+    /// This uses the `pad`, `npc`, `xon` and `pb` capabilities, the same ones curses uses.
+    pub fn from_terminfo(info: &TermInfoBuf, baud_rate: u32) -> Padding {
+        Padding {
+            pad_char: info
+                .string(StringField::PadChar)
+                .and_then(|s| s.bytes().next())
+                .unwrap_or(0),
+            baud_rate,
+            lines: 1,
+            sleep: info.boolean(BooleanField::NoPadChar),
+            mandatory_only: info.boolean(BooleanField::XonXoff)
+                || baud_rate < info.number(NumericField::PaddingBaudRate).unwrap_or(0),
+        }
+    }
+
+    /// Set the number of lines affected, for proportional delays.
+    pub fn lines(mut self, lines: usize) -> Padding {
+        self.lines = lines;
+        self
+    }
+
+    /// Wait for `delay`, returns the number of pad characters written.
+    fn delay<W: io::Write>(&self, w: &mut W, delay: Delay) -> Result<usize> {
+        if self.mandatory_only && !delay.mandatory {
+            return Ok(0);
+        }
+
+        let mut tenths = delay.tenths;
+        if delay.proportional {
+            tenths = tenths.saturating_mul(self.lines.max(1) as u64);
+        }
+        let tenths = tenths.min(MAX_DELAY);
+
+        if self.sleep || self.baud_rate == 0 {
+            w.flush().context(ErrorKind::FailedToWriteOutput)?;
+            thread::sleep(Duration::from_micros(tenths * 100));
+            return Ok(0);
+        }
+
+        // a byte takes 9 bits on the line, assuming one start bit and no stop bits (the same assumption curses makes)
+        let count = (tenths.saturating_mul(self.baud_rate as u64) / 90_000).min(MAX_PADDING as u64) as usize;
+        let chunk = [self.pad_char; 32];
+        let mut left = count;
+        while left > 0 {
+            let n = left.min(chunk.len());
+            w.write_all(&chunk[..n])
+                .context(ErrorKind::FailedToWriteOutput)?;
+            left -= n;
+        }
+        Ok(count)
+    }
+}
+
+/// A delay, in the form `$<tenths*/>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Delay {
+    /// The length of the delay in tenths of a millisecond.
+    tenths: u64,
+
+    /// The delay is multiplied by the number of lines affected (`*`).
+    proportional: bool,
+
+    /// The delay is required, even if the terminal has flow control (`/`).
+    mandatory: bool,
+}
+
+/// Parse a delay, starting after the `$<`. Returns the delay and the number of bytes it used, including the final `>`.
+fn parse_delay(s: &[u8]) -> Option<(Delay, usize)> {
+    let mut delay = Delay {
+        tenths: 0,
+        proportional: false,
+        mandatory: false,
+    };
+
+    let digits = s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let mut i = digits;
+    let mut decimal = 0;
+    if s.get(i) == Some(&b'.') {
+        i += 1;
+        if let Some(&c) = s.get(i).filter(|c| c.is_ascii_digit()) {
+            decimal = (c - b'0') as u64;
+            i += 1;
+        }
+    }
+    if digits == 0 && i == 0 {
+        return None;
+    }
+
+    delay.tenths = s[..digits]
+        .iter()
+        .fold(0u64, |n, &c| n.saturating_mul(10).saturating_add((c - b'0') as u64))
+        .saturating_mul(10)
+        .saturating_add(decimal);
+
+    loop {
+        match s.get(i) {
+            Some(b'*') => delay.proportional = true,
+            Some(b'/') => delay.mandatory = true,
+            Some(b'>') => return Some((delay, i + 1)),
+            _ => return None,
+        }
+        i += 1;
+    }
+}
+
+/// Write the expanded capability string `s` to `w`, interpreting delays like curses' `tputs`.
+///
+/// Delays (e.g. `$<5>`, or `$<2*/>`) are replaced with enough pad characters to take that long to send,
+/// or a call to `thread::sleep`, depending on `padding`. Anything that looks like a delay, but isn't a valid one, is written as-is.
+/// A single delay is capped at one second, or 4096 pad characters, so a bad entry can't stall the terminal.
+/// Returns the number of bytes written.
+///
+/// # Examples
+/// ```
+/// use nixterm::terminfo::{tputs, Padding};
+///
+/// let mut out = Vec::new();
+/// tputs(&mut out, b"\x1b[H\x1b[2J$<50>", &Padding::new(9600)).unwrap();
+/// assert_eq!(out.len(), 7 + 53);
+/// ```
+pub fn tputs<W: io::Write>(w: &mut W, s: &[u8], padding: &Padding) -> Result<usize> {
+    let mut written = 0;
+    let mut rest = s;
+
+    while let Some(pos) = rest.windows(2).position(|p| p == b"$<") {
+        let start = pos + 2;
+        match parse_delay(&rest[start..]) {
+            Some((delay, len)) => {
+                w.write_all(&rest[..pos])
+                    .context(ErrorKind::FailedToWriteOutput)?;
+                written += pos + padding.delay(w, delay)?;
+                rest = &rest[start + len..];
+            }
+            None => {
+                w.write_all(&rest[..start])
+                    .context(ErrorKind::FailedToWriteOutput)?;
+                written += start;
+                rest = &rest[start..];
+            }
+        }
+    }
+
+    w.write_all(rest).context(ErrorKind::FailedToWriteOutput)?;
+    Ok(written + rest.len())
+}
+
+#[cfg(test)]
+mod test {
+    use terminfo::*;
+
+    #[test]
+    fn delays() {
+        let tputs_vec = |s: &[u8], padding: &Padding| {
+            let mut out = Vec::new();
+            let written = tputs(&mut out, s, padding).unwrap();
+            assert_eq!(written, out.len());
+            out
+        };
+        let padding = Padding {
+            pad_char: b'.',
+            ..Padding::new(9000)
+        };
+
+        assert_eq!(tputs_vec(b"a$<5>b", &padding), b"a.....b");
+        assert_eq!(tputs_vec(b"a$<1.5>b", &padding), b"a.b");
+        assert_eq!(tputs_vec(b"$<2*>", &padding.clone().lines(3)), b"......");
+        assert_eq!(tputs_vec(b"$<2/>$<3>", &padding), b".....");
+        assert_eq!(tputs_vec(b"$<x>$<5$<>", &padding), b"$<x>$<5$<>");
+
+        let xon = Padding {
+            mandatory_only: true,
+            ..padding.clone()
+        };
+        assert_eq!(tputs_vec(b"a$<2/>$<3>b", &xon), b"a..b");
+
+        let sleep = Padding {
+            sleep: true,
+            ..padding.clone()
+        };
+        assert_eq!(tputs_vec(b"a$<1>b", &sleep), b"ab");
+
+        // huge delays are capped, rather than overflowing or padding forever
+        assert_eq!(tputs_vec(b"$<99999999999999999999.5>", &padding).len(), 1000);
+        assert_eq!(tputs_vec(b"$<1000*>", &padding.clone().lines(usize::max_value())).len(), 1000);
+        let fast = Padding {
+            baud_rate: u32::max_value(),
+            ..padding.clone()
+        };
+        assert_eq!(tputs_vec(b"$<99999999999999999999>", &fast).len(), 4096);
+    }
+
+    #[test]
+    fn from_terminfo() {
+        let mut info = TermInfoBuf::new();
+        info.set_string(PadChar, "*").unwrap();
+        info.set_number(PaddingBaudRate, 9600).unwrap();
+
+        let padding = Padding::from_terminfo(&info, 19200);
+        assert_eq!(padding.pad_char, b'*');
+        assert!(!padding.mandatory_only);
+        assert!(Padding::from_terminfo(&info, 2400).mandatory_only);
+
+        info.set_boolean(XonXoff, true).unwrap();
+        assert!(Padding::from_terminfo(&info, 19200).mandatory_only);
+        assert_eq!(Padding::from_terminfo(&TermInfoBuf::new(), 0).pad_char, 0);
+    }
+}
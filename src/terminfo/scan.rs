@@ -0,0 +1,164 @@
+use failure::ResultExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use terminfo::errors::*;
+use terminfo::TermInfo;
+
+/// A summary of a database scan.
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    /// The number of entries found.
+    pub entries: usize,
+
+    /// Every entry that couldn't be read or parsed.
+    pub failed: Vec<PathBuf>,
+
+    /// Directories that couldn't be read, entries inside these directories were skipped.
+    pub errors: Vec<(PathBuf, Error)>,
+}
+
+impl ScanStats {
+    /// The number of entries that were successfully parsed.
+    pub fn parsed(&self) -> usize {
+        self.entries - self.failed.len()
+    }
+}
+
+/// List the entries of a directory, recording an error in `stats` if it can't be read.
+fn read_dir(dir: &Path, stats: &mut ScanStats) -> Vec<PathBuf> {
+    match fs::read_dir(dir).context(ErrorKind::FailedToReadDatabase) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            stats.errors.push((dir.to_path_buf(), e.into()));
+            Vec::new()
+        }
+    }
+}
+
+/// Parse the entry at `path`, and pass it to `f`. Returns false if the entry couldn't be read or parsed.
+fn parse_file<F: Fn(&str, Result<TermInfo>)>(path: &Path, f: &F) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    match fs::read(path).context(ErrorKind::FailedToParseFile) {
+        Ok(data) => {
+            let info = TermInfo::parse(&data);
+            let ok = info.is_ok();
+            f(&name, info);
+            ok
+        }
+        Err(e) => {
+            f(&name, Err(e.into()));
+            false
+        }
+    }
+}
+
+/// Parse every entry in the terminfo databases `dbs`, spreading the work across all available cores.
+///
+/// `f` is called once for each entry with the entry's file name and the result of parsing it.
+/// It's called from the worker threads, so entries aren't visited in any particular order.
+/// Databases that don't exist are skipped.
+///
+/// # Examples
+/// ```no_run
+/// use nixterm::terminfo;
+///
+/// let stats = terminfo::scan(&terminfo::databases(), |name, info| {
+///     if let Ok(info) = info {
+///         println!("{}: {}", name, info.description().unwrap_or(""));
+///     }
+/// });
+/// println!("parsed {}/{} entries", stats.parsed(), stats.entries);
+/// ```
+pub fn scan<P, F>(dbs: &[P], f: F) -> ScanStats
+where
+    P: AsRef<Path>,
+    F: Fn(&str, Result<TermInfo>) + Sync,
+{
+    let mut stats = ScanStats::default();
+
+    let mut files = Vec::new();
+    for db in dbs.iter().map(|db| db.as_ref()).filter(|db| db.is_dir()) {
+        for dir in read_dir(db, &mut stats) {
+            if dir.is_dir() {
+                files.extend(read_dir(&dir, &mut stats).into_iter().filter(|p| p.is_file()));
+            }
+        }
+    }
+    stats.entries = files.len();
+
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len());
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                match files.get(i) {
+                    Some(path) => {
+                        if !parse_file(path, &f) {
+                            failed.lock().unwrap().push(path.clone());
+                        }
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+
+    stats.failed = failed.into_inner().unwrap();
+    stats
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::sync::Mutex;
+    use terminfo::*;
+
+    #[test]
+    fn scan_database() {
+        let db = env::temp_dir().join(format!("nixterm-scan-{}", ::std::process::id()));
+        fs::create_dir_all(db.join("r")).unwrap();
+        fs::create_dir_all(db.join("x")).unwrap();
+        fs::copy("test-data/rxvt", db.join("r").join("rxvt")).unwrap();
+        fs::copy("test-data/xterm", db.join("x").join("xterm")).unwrap();
+        fs::copy("test-data/fake-terminal", db.join("x").join("fake")).unwrap();
+
+        let names = Mutex::new(Vec::new());
+        let stats = scan(&[&db, &db.join("missing")], |name, info| {
+            names
+                .lock()
+                .unwrap()
+                .push((name.to_string(), info.map(|i| i.primary_name().to_string()).ok()));
+        });
+
+        let mut names = names.into_inner().unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                ("fake".to_string(), None),
+                ("rxvt".to_string(), Some("rxvt".to_string())),
+                ("xterm".to_string(), Some("xterm".to_string())),
+            ]
+        );
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.parsed(), 2);
+        assert_eq!(stats.failed, vec![db.join("x").join("fake")]);
+        assert!(stats.errors.is_empty());
+
+        fs::remove_dir_all(db).unwrap();
+    }
+}
@@ -2,7 +2,7 @@ use std::mem;
 use terminfo::errors::*;
 use terminfo::fields::*;
 use terminfo::strtab::{StrTable, StringTable};
-use util::{invalid, read_le_u16, read_le_u32};
+use util::{invalid, read_le_u16, read_le_u32, strlen};
 
 /// TermInfo is immutable terminfo data.
 ///
@@ -39,137 +39,170 @@ pub(crate) struct TermInfoExt<'a> {
     nametab_start: usize,
 }
 
-/// Split a terminfo file into the fields of a `terminfo` struct.
+/// Magic number for terminfo files which store numbers as 16-bit integers.
+const MAGIC_LEGACY: u16 = 0o432;
+
+/// Magic number for terminfo files which store numbers as 32-bit integers.
 ///
-/// This function hardly analyzes the data at all, it just finds each section
-/// and creates a new terminfo struct based on those sections.
-fn split_terminfo<'a>(bytes: &'a [u8]) -> Result<TermInfo<'a>> {
-    let file_size = bytes.len();
+/// ncurses uses this format for entries that are too large for the legacy format.
+const MAGIC_32BIT: u16 = 0o1036;
 
-    // Terminfo files start with a 12-byte header, made up of 6 16-bit fields.
-    if file_size < 12 {
-        return Err(ErrorKind::IncompleteTermInfoHeader.into());
+/// Read a number from a 16-bit terminfo file.
+///
+/// Numbers are signed, -1 marks a missing capability and -2 a cancelled one.
+fn read_number_u16(b: &[u8], i: usize) -> NumericValue {
+    NumericValue::from_signed(read_le_u16(b, i) as i16 as i32)
+}
+
+/// Read a number from a 32-bit terminfo file, negative values are interpreted like `read_number_u16`.
+fn read_number_u32(b: &[u8], i: usize) -> NumericValue {
+    NumericValue::from_signed(read_le_u32(b, i) as i32)
+}
+
+/// The value of a numeric capability, including whether it's missing or was cancelled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NumericValue {
+    Value(u32),
+
+    /// The entry doesn't include this capability.
+    Absent,
+
+    /// The capability was explicitly removed from the entry (e.g. `cols@`), usually to override a value it inherits.
+    Cancelled,
+}
+
+impl NumericValue {
+    /// Decode a number stored in a terminfo file, after it's been sign extended.
+    fn from_signed(n: i32) -> NumericValue {
+        match n {
+            -2 => NumericValue::Cancelled,
+            n if n < 0 => NumericValue::Absent,
+            n => NumericValue::Value(n as u32),
+        }
     }
 
-    let num_bytes = match read_le_u16(bytes, 0) {
-        0o542 | 542 => 4,
-        0o432 => 2,
-        _ => {
-            return Err(ErrorKind::InvalidMagicNumber.into());
+    /// Get the number, if it's present.
+    pub fn value(self) -> Option<u32> {
+        match self {
+            NumericValue::Value(n) => Some(n),
+            _ => None,
         }
-    };
+    }
+}
 
-    // following the magic there is a series of lengths for each section
-    let names_size = read_le_u16(bytes, 1) as usize;
-    let bools_count = read_le_u16(bytes, 2) as usize;
-    let numbers_count = read_le_u16(bytes, 3) as usize;
-    let strings_count = read_le_u16(bytes, 4) as usize;
-    let strtab_size = read_le_u16(bytes, 5) as usize;
+/// A cursor over the sections of a terminfo file.
+///
+/// Every offset is computed with checked arithmetic, so a corrupt header results in an error instead of a panic.
+struct SectionReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
 
-    let mut expected_filesize =
-        12 + bools_count + numbers_count * num_bytes + strings_count * 2 + strtab_size + names_size;
-    if bools_count + names_size % 2 != 0 {
-        expected_filesize += 1;
+impl<'a> SectionReader<'a> {
+    fn new(bytes: &'a [u8]) -> SectionReader<'a> {
+        SectionReader { bytes, pos: 0 }
     }
 
-    // make sure the file's length and the section lengths match up
-    // use <= here because they length might not match exactly, because of extensions
-    if expected_filesize > file_size {
-        return Err(ErrorKind::IncompleteTermInfo.into());
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
     }
 
-    let mut slice = &bytes[12..];
-
-    // subtract one so we ignore the null terminator
-    let names = &slice[..names_size - 1];
-    slice = &slice[names_size..];
+    /// Take the next `count` elements of `size` bytes, failing with `err` if the file is too short.
+    fn take(&mut self, count: usize, size: usize, err: ErrorKind) -> Result<&'a [u8]> {
+        let end = count
+            .checked_mul(size)
+            .and_then(|len| self.pos.checked_add(len))
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(err)?;
 
-    let bools = &slice[..bools_count];
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
 
-    // 2 byte align
-    slice = if (bools_count + names_size) % 2 != 0 {
-        &slice[bools_count + 1..]
-    } else {
-        &slice[bools_count..]
-    };
+    /// Read a single little endian u16 field.
+    fn u16(&mut self, err: ErrorKind) -> Result<usize> {
+        Ok(read_le_u16(self.take(1, 2, err)?, 0) as usize)
+    }
 
-    let numbers = &slice[..numbers_count * num_bytes];
-    slice = &slice[numbers_count * num_bytes..];
+    /// Skip a padding byte, if necessary, so the next section starts on an even offset.
+    fn align(&mut self) {
+        if self.pos & 1 != 0 && self.pos < self.bytes.len() {
+            self.pos += 1;
+        }
+    }
+}
 
-    let strings = &slice[..strings_count * 2];
-    slice = &slice[strings_count * 2..];
+/// Split a terminfo file into the fields of a `terminfo` struct.
+///
+/// This function hardly analyzes the data at all, it just finds each section
+/// and creates a new terminfo struct based on those sections.
+fn split_terminfo(bytes: &[u8]) -> Result<TermInfo<'_>> {
+    let mut reader = SectionReader::new(bytes);
 
-    let strtab = &slice[..strtab_size];
+    // Terminfo files start with a 12-byte header, made up of 6 16-bit fields.
+    let magic = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+    let num_bytes = match magic as u16 {
+        MAGIC_32BIT => 4,
+        MAGIC_LEGACY => 2,
+        _ => {
+            return Err(ErrorKind::InvalidMagicNumber.into());
+        }
+    };
 
-    let ext = if expected_filesize < file_size {
-        Some(split_terminfo_ext(
-            if strtab_size % 2 != 0 {
-                &slice[strtab_size + 1..]
-            } else {
-                &slice[strtab_size..]
-            },
-            num_bytes,
-        )?)
+    // following the magic there is a series of lengths for each section
+    let names_size = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+    let bools_count = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+    let numbers_count = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+    let strings_count = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+    let strtab_size = reader.u16(ErrorKind::IncompleteTermInfoHeader)?;
+
+    let names = reader.take(names_size, 1, ErrorKind::IncompleteTermInfo)?;
+    // ignore the null terminator
+    let names = &names[..strlen(names)];
+
+    let bools = reader.take(bools_count, 1, ErrorKind::IncompleteTermInfo)?;
+    reader.align();
+
+    let numbers = reader.take(numbers_count, num_bytes, ErrorKind::IncompleteTermInfo)?;
+    let strings = reader.take(strings_count, 2, ErrorKind::IncompleteTermInfo)?;
+    let strtab = reader.take(strtab_size, 1, ErrorKind::IncompleteTermInfo)?;
+    reader.align();
+
+    let ext = if reader.remaining() > 0 {
+        Some(split_terminfo_ext(&mut reader, num_bytes)?)
     } else {
         None
     };
 
     Ok(TermInfo {
         long: num_bytes > 2,
-        names: names,
-        bools: bools,
-        numbers: numbers,
-        strings: strings,
+        names,
+        bools,
+        numbers,
+        strings,
         strtab: StrTable::new(strtab),
-        ext: ext,
+        ext,
     })
 }
 
-fn split_terminfo_ext<'a>(bytes: &'a [u8], num_bytes: usize) -> Result<TermInfoExt<'a>> {
-    let file_size = bytes.len();
-
-    if file_size < 10 {
-        return Err(ErrorKind::IncompleteExtendedHeader.into());
-    }
-
-    let bools_count = read_le_u16(bytes, 0) as usize;
-    let numbers_count = read_le_u16(bytes, 1) as usize;
-    let strings_count = read_le_u16(bytes, 2) as usize;
-    let strtab_size = read_le_u16(bytes, 3) as usize;
-    let strtab_last_offset = read_le_u16(bytes, 4) as usize;
+fn split_terminfo_ext<'a>(reader: &mut SectionReader<'a>, num_bytes: usize) -> Result<TermInfoExt<'a>> {
+    let bools_count = reader.u16(ErrorKind::IncompleteExtendedHeader)?;
+    let numbers_count = reader.u16(ErrorKind::IncompleteExtendedHeader)?;
+    let strings_count = reader.u16(ErrorKind::IncompleteExtendedHeader)?;
+    // the number of entries in the string table, this is always equal to `strings_count` plus `names_count`.
+    let _strtab_count = reader.u16(ErrorKind::IncompleteExtendedHeader)?;
+    let strtab_last_offset = reader.u16(ErrorKind::IncompleteExtendedHeader)?;
 
     let names_count = strings_count + numbers_count + bools_count;
 
-    let mut expected_filesize = 10 + bools_count + numbers_count * num_bytes + strings_count * 2
-        + names_count * 2 + strtab_size;
-    if bools_count % 2 != 0 {
-        expected_filesize += 1;
-    }
-
-    if expected_filesize > file_size {
-        return Err(ErrorKind::IncompleteExtendedTermInfo.into());
-    }
+    let bools = reader.take(bools_count, 1, ErrorKind::IncompleteExtendedTermInfo)?;
+    reader.align();
 
-    let mut slice = &bytes[10..];
-    let bools = &slice[..bools_count];
-
-    // align the pointer to 2 bytes
-    slice = if bools_count % 2 != 0 {
-        &slice[bools_count + 1..]
-    } else {
-        &slice[bools_count..]
-    };
-
-    let numbers = &slice[..numbers_count * num_bytes];
-    slice = &slice[numbers_count * num_bytes..];
-
-    let strings = &slice[..strings_count * 2];
-    slice = &slice[strings_count * 2..];
-
-    let names = &slice[..names_count * 2];
-    slice = &slice[names_count * 2..];
-
-    let strtab = &slice[..strtab_last_offset];
+    let numbers = reader.take(numbers_count, num_bytes, ErrorKind::IncompleteExtendedTermInfo)?;
+    let strings = reader.take(strings_count, 2, ErrorKind::IncompleteExtendedTermInfo)?;
+    let names = reader.take(names_count, 2, ErrorKind::IncompleteExtendedTermInfo)?;
+    let strtab = reader.take(strtab_last_offset, 1, ErrorKind::IncompleteExtendedTermInfo)?;
 
     // This is comically slow. It accounts for like 95% of this functions runtime.
     // but I can't find another way to do it so whatever.
@@ -188,12 +221,12 @@ fn split_terminfo_ext<'a>(bytes: &'a [u8], num_bytes: usize) -> Result<TermInfoE
 
     Ok(TermInfoExt {
         long: num_bytes > 2,
-        bools: bools,
-        numbers: numbers,
-        strings: strings,
+        bools,
+        numbers,
+        strings,
         strtab: StrTable::new(strtab),
         nametab_start: nametab_offset,
-        names: names,
+        names,
     })
 }
 
@@ -202,14 +235,11 @@ impl<'a> TermInfoExt<'a> {
         self.strtab.split(self.nametab_start)
     }
 
-    pub(crate) fn get_numbers(&self) -> Vec<u32> {
+    pub(crate) fn get_numbers(&self) -> Vec<NumericValue> {
         if self.long {
-            self.numbers.chunks(4).map(|n| read_le_u32(n, 0)).collect()
+            self.numbers.chunks(4).map(|n| read_number_u32(n, 0)).collect()
         } else {
-            self.numbers
-                .chunks(2)
-                .map(|n| read_le_u16(n, 0) as u32)
-                .collect()
+            self.numbers.chunks(2).map(|n| read_number_u16(n, 0)).collect()
         }
     }
 
@@ -264,18 +294,39 @@ impl<'a> TermInfo<'a> {
             .map(|slice| unsafe { mem::transmute::<&[u8], &str>(slice) })
     }
 
+    /// Get the terminal's primary name, e.g. "xterm".
+    pub fn primary_name(&self) -> &str {
+        self.names().next().unwrap_or("")
+    }
+
+    /// Get an iterator over the terminal's aliases.
+    ///
+    /// These are all the names between the primary name and the description, for example "xterm-256color" or "kitty" (kitty's alias is "KovIdTTY").
+    pub fn aliases(&self) -> impl Iterator<Item = &str> {
+        let count = self.names().count();
+        self.names().skip(1).take(count.saturating_sub(2))
+    }
+
+    /// Get the terminal's description, e.g. "xterm terminal emulator (X Window System)".
+    ///
+    /// By convention the last name is a human readable description, unless the terminal only has one name.
+    pub fn description(&self) -> Option<&str> {
+        if self.names().count() > 1 {
+            self.names().last()
+        } else {
+            None
+        }
+    }
+
     pub(crate) fn get_strtab(&self) -> StringTable {
         self.strtab.to_string_table()
     }
 
-    pub(crate) fn get_numbers(&self) -> Vec<u32> {
+    pub(crate) fn get_numbers(&self) -> Vec<NumericValue> {
         if self.long {
-            self.numbers.chunks(4).map(|n| read_le_u32(n, 0)).collect()
+            self.numbers.chunks(4).map(|n| read_number_u32(n, 0)).collect()
         } else {
-            self.numbers
-                .chunks(2)
-                .map(|n| read_le_u16(n, 0) as u32)
-                .collect()
+            self.numbers.chunks(2).map(|n| read_number_u16(n, 0)).collect()
         }
     }
 
@@ -311,22 +362,22 @@ impl<'a> TermInfo<'a> {
     ///
     /// Not all terminals will include a value for every field enumerated in `NumericField`.
     pub fn number(&self, field: NumericField) -> Option<u32> {
-        let i = field as usize;
+        self.number_value(field).value()
+    }
 
-        if i * 2 < self.numbers.len() {
-            let number = if self.long {
-                read_le_u32(self.numbers, i)
-            } else {
-                read_le_u16(self.numbers, i) as u32
-            };
+    /// Get a numeric field, distinguishing between missing and cancelled capabilities.
+    pub fn number_value(&self, field: NumericField) -> NumericValue {
+        let i = field as usize;
+        let width = if self.long { 4 } else { 2 };
 
-            if number != invalid() {
-                Some(number)
+        if i * width < self.numbers.len() {
+            if self.long {
+                read_number_u32(self.numbers, i)
             } else {
-                None
+                read_number_u16(self.numbers, i)
             }
         } else {
-            None
+            NumericValue::Absent
         }
     }
 
@@ -358,6 +409,25 @@ impl<'a> TermInfo<'a> {
         None
     }
 
+    /// Get a boolean field by its two letter termcap code (e.g. "am" for `AutoRightMargin`).
+    ///
+    /// Returns false if the code isn't known.
+    pub fn boolean_by_termcap<T: AsRef<str>>(&self, code: T) -> bool {
+        BooleanField::from_termcap_name(code.as_ref())
+            .map(|f| self.boolean(f))
+            .unwrap_or(false)
+    }
+
+    /// Get a numeric field by its two letter termcap code (e.g. "co" for `Columns`).
+    pub fn number_by_termcap<T: AsRef<str>>(&self, code: T) -> Option<u32> {
+        NumericField::from_termcap_name(code.as_ref()).and_then(|f| self.number(f))
+    }
+
+    /// Get a string field by its two letter termcap code (e.g. "ce" for `ClrEol`).
+    pub fn string_by_termcap<T: AsRef<str>>(&self, code: T) -> Option<&str> {
+        StringField::from_termcap_name(code.as_ref()).and_then(|f| self.string(f))
+    }
+
     /// Check if the the terminfo file has an extensions section
     ///
     /// If this method returns false then the `TermInfo::ext_*` methods won't fail. However `TermInfo::ext_boolean`
@@ -385,14 +455,11 @@ impl<'a> TermInfo<'a> {
                 let idx_offset = ext.bools.len();
                 if idx >= idx_offset && idx - idx_offset < ext.numbers.len() {
                     let number = if self.long {
-                        read_le_u32(self.numbers, idx)
+                        read_number_u32(self.numbers, idx)
                     } else {
-                        read_le_u16(self.numbers, idx) as u32
+                        read_number_u16(self.numbers, idx)
                     };
-
-                    if number != invalid() {
-                        return Some(number);
-                    }
+                    return number.value();
                 }
             }
         }
@@ -424,9 +491,54 @@ mod test {
     const RXVT_INFO: &'static [u8] = include_bytes!("../../test-data/rxvt");
     const XTERM_INFO: &'static [u8] = include_bytes!("../../test-data/xterm");
     const LINUX_16COLOR_INFO: &'static [u8] = include_bytes!("../../test-data/linux-16color");
+    const INCOMPLETE_INFO: &'static [u8] = include_bytes!("../../test-data/incomplete-terminal");
+    const INCOMPLETE_INFO2: &'static [u8] = include_bytes!("../../test-data/incomplete-terminal2");
+    const FAKE_INFO: &'static [u8] = include_bytes!("../../test-data/fake-terminal");
 
     use terminfo::*;
 
+    fn push_u16(buf: &mut Vec<u8>, x: usize) {
+        buf.push(x as u8);
+        buf.push((x >> 8) as u8);
+    }
+
+    /// Build a 32-bit terminfo file, with an odd sized names section and a string table larger than 4096 bytes.
+    fn large_entry() -> Vec<u8> {
+        let names = b"huge|a very large terminal entry\0";
+        let bools = [1u8, 0, 1];
+        let numbers = [80u32, 8, 24, 0xFFFF_FFFF, 0x10000, 0xFFFF_FFFE, 0xFFFE, 0xFFFF];
+
+        let mut strtab = Vec::new();
+        let mut offsets = Vec::new();
+        for i in 0..PREDEFINED_STRINGS_COUNT {
+            offsets.push(strtab.len());
+            strtab.extend(format!("\x1b[{};{};{}~", i, i, i).bytes());
+            strtab.push(0);
+        }
+        assert!(strtab.len() > 4096);
+
+        let mut buf = Vec::new();
+        push_u16(&mut buf, 0o1036);
+        push_u16(&mut buf, names.len());
+        push_u16(&mut buf, bools.len());
+        push_u16(&mut buf, numbers.len());
+        push_u16(&mut buf, offsets.len());
+        push_u16(&mut buf, strtab.len());
+        buf.extend(names.iter());
+        buf.extend(bools.iter());
+        if buf.len() % 2 != 0 {
+            buf.push(0);
+        }
+        for n in numbers.iter() {
+            buf.extend([*n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8].iter());
+        }
+        for &o in offsets.iter() {
+            push_u16(&mut buf, o);
+        }
+        buf.extend(strtab);
+        buf
+    }
+
     #[test]
     fn names() {
         let rxvt = TermInfo::parse(RXVT_INFO).unwrap();
@@ -438,6 +550,93 @@ mod test {
         );
     }
 
+    #[test]
+    fn termcap_lookup() {
+        let rxvt = TermInfo::parse(RXVT_INFO).unwrap();
+
+        assert_eq!(rxvt.boolean_by_termcap("am"), rxvt.boolean(AutoRightMargin));
+        assert_eq!(rxvt.number_by_termcap("co"), rxvt.number(Columns));
+        assert_eq!(rxvt.string_by_termcap("ce"), Some("\x1b[K"));
+        assert_eq!(rxvt.string_by_termcap("zz"), None);
+        assert!(!rxvt.boolean_by_termcap("zz"));
+    }
+
+    #[test]
+    fn structured_names() {
+        let rxvt = TermInfo::parse(RXVT_INFO).unwrap();
+        let l16c = TermInfo::parse(LINUX_16COLOR_INFO).unwrap();
+
+        assert_eq!(rxvt.primary_name(), "rxvt");
+        assert_eq!(rxvt.aliases().count(), 0);
+        assert_eq!(
+            rxvt.description(),
+            Some("rxvt terminal emulator (X Window System)")
+        );
+
+        assert_eq!(l16c.primary_name(), "linux-16color");
+        assert_eq!(l16c.description(), Some("linux console with 16 colors"));
+    }
+
+    #[test]
+    fn large_entries() {
+        let data = large_entry();
+        let huge = TermInfo::parse(&data).unwrap();
+
+        assert_eq!(huge.primary_name(), "huge");
+        assert_eq!(huge.boolean(BooleanField::AutoLeftMargin), true);
+        assert_eq!(huge.boolean(BooleanField::NoEscCtlc), true);
+        assert_eq!(huge.number(NumericField::Columns), Some(80));
+        assert_eq!(huge.number(NumericField::InitTabs), Some(8));
+        assert_eq!(huge.number(NumericField::Lines), Some(24));
+        assert_eq!(huge.number(NumericField::LinesOfMemory), None);
+        assert_eq!(huge.number(NumericField::MagicCookieGlitch), Some(0x10000));
+        assert_eq!(huge.number(NumericField::PaddingBaudRate), None);
+        assert_eq!(
+            huge.number_value(NumericField::LinesOfMemory),
+            NumericValue::Absent
+        );
+        assert_eq!(
+            huge.number_value(NumericField::PaddingBaudRate),
+            NumericValue::Cancelled
+        );
+        // only negative numbers are missing or cancelled in 32-bit entries, not the 16-bit sentinels
+        assert_eq!(huge.number(NumericField::VirtualTerminal), Some(0xFFFE));
+        assert_eq!(huge.number(NumericField::WidthStatusLine), Some(0xFFFF));
+        assert_eq!(
+            huge.number_value(NumericField::NumLabels),
+            NumericValue::Absent
+        );
+        let buf = TermInfoBuf::from_terminfo(&huge);
+        assert_eq!(buf.number(NumericField::WidthStatusLine), Some(0xFFFF));
+        assert_eq!(
+            buf.number_value(NumericField::PaddingBaudRate),
+            NumericValue::Cancelled
+        );
+        assert_eq!(huge.string(StringField::BackTab), Some("\x1b[0;0;0~"));
+        assert_eq!(huge.string(StringField::BoxChars1), Some("\x1b[413;413;413~"));
+        assert_eq!(huge.has_ext(), false);
+    }
+
+    #[test]
+    fn incomplete() {
+        assert_eq!(
+            TermInfo::parse(INCOMPLETE_INFO).unwrap_err().kind(),
+            &ErrorKind::IncompleteTermInfo
+        );
+        assert_eq!(
+            TermInfo::parse(INCOMPLETE_INFO2).unwrap_err().kind(),
+            &ErrorKind::IncompleteTermInfo
+        );
+        assert_eq!(
+            TermInfo::parse(FAKE_INFO).unwrap_err().kind(),
+            &ErrorKind::InvalidMagicNumber
+        );
+        assert_eq!(
+            TermInfo::parse(&RXVT_INFO[..8]).unwrap_err().kind(),
+            &ErrorKind::IncompleteTermInfoHeader
+        );
+    }
+
     #[test]
     fn lookup_string() {
         let rxvt = TermInfo::parse(RXVT_INFO).unwrap();
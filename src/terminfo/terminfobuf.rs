@@ -1,31 +1,65 @@
+use failure::ResultExt;
+use std::hash::{Hash, Hasher};
+use std::io;
 use terminfo::errors::*;
 use terminfo::fields::*;
 use terminfo::strtab::StringTable;
-use terminfo::{lang, TermInfo};
+use terminfo::{lang, NumericValue, TermInfo};
 use util::invalid;
 
+/// Capabilities which are used often enough that they're compiled ahead of time, see `TermInfoBuf::program`.
+const HOT_CAPS: [StringField; 7] = [
+    StringField::CursorAddress,
+    StringField::ExitAttributeMode,
+    StringField::SetAForeground,
+    StringField::SetABackground,
+    StringField::SetForeground,
+    StringField::SetBackground,
+    StringField::SetAttributes,
+];
+
 /// The owning, mutable version of `TermInfo`
 #[derive(Debug, Clone)]
 pub struct TermInfoBuf {
     pub names: Vec<String>,
     bools: Vec<bool>,
-    numbers: Vec<u32>,
+    numbers: Vec<NumericValue>,
     strings: Vec<u16>,
     strtab: StringTable,
 
     ext: Option<TermInfoExtBuf>,
+
+    /// Compiled versions of `HOT_CAPS`, in the same order.
+    programs: Vec<Option<lang::Program>>,
 }
 
 #[derive(Debug, Clone)]
 struct TermInfoExtBuf {
     bools: Vec<bool>,
-    numbers: Vec<u32>,
+    numbers: Vec<NumericValue>,
     strings: Vec<u16>,
     names: Vec<u16>,
     strtab: StringTable,
     nametab: StringTable,
 }
 
+/// Identifies a capability when comparing entries.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Capability<'a> {
+    Boolean(usize),
+    Number(usize),
+    String(usize),
+    Ext(&'a str),
+}
+
+/// A capability's value when comparing entries.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Value<'a> {
+    Boolean(bool),
+    Number(NumericValue),
+    String(&'a str),
+}
+
 impl TermInfoExtBuf {
     fn new() -> TermInfoExtBuf {
         TermInfoExtBuf {
@@ -48,6 +82,7 @@ impl TermInfoBuf {
             strings: ti.get_string_offsets(),
             strtab: ti.get_strtab(),
             ext: None,
+            programs: Vec::new(),
         };
 
         if let Some(ext) = ti.get_ext() {
@@ -62,6 +97,7 @@ impl TermInfoBuf {
             });
         }
 
+        tib.programs = HOT_CAPS.iter().map(|&f| tib.compile(f)).collect();
         tib
     }
 
@@ -73,6 +109,27 @@ impl TermInfoBuf {
             strings: Vec::with_capacity(PREDEFINED_STRINGS_COUNT),
             strtab: StringTable::new(),
             ext: None,
+            programs: Vec::new(),
+        }
+    }
+
+    /// Get the terminal's primary name, e.g. "xterm".
+    pub fn primary_name(&self) -> &str {
+        self.names.first().map(|s| s.as_str()).unwrap_or("")
+    }
+
+    /// Get an iterator over the terminal's aliases, the names between the primary name and description.
+    pub fn aliases(&self) -> impl Iterator<Item = &str> {
+        let count = self.names.len().saturating_sub(2);
+        self.names.iter().skip(1).take(count).map(|s| s.as_str())
+    }
+
+    /// Get the terminal's description, the last name (unless the terminal only has one name).
+    pub fn description(&self) -> Option<&str> {
+        if self.names.len() > 1 {
+            self.names.last().map(|s| s.as_str())
+        } else {
+            None
         }
     }
 
@@ -80,6 +137,62 @@ impl TermInfoBuf {
         self.ext.is_some()
     }
 
+    /// List every capability present in this entry, independent of how the entry is laid out in memory.
+    ///
+    /// Absent capabilities (false booleans, missing numbers and strings) are skipped, and extended capabilities are sorted by name.
+    fn capabilities(&self) -> Vec<(Capability<'_>, Value<'_>)> {
+        let mut caps = Vec::new();
+
+        for (i, &b) in self.bools.iter().enumerate().filter(|&(_, &b)| b) {
+            caps.push((Capability::Boolean(i), Value::Boolean(b)));
+        }
+
+        for (i, &n) in self.numbers.iter().enumerate() {
+            if n != NumericValue::Absent {
+                caps.push((Capability::Number(i), Value::Number(n)));
+            }
+        }
+
+        for (i, &offset) in self.strings.iter().enumerate() {
+            if let Ok(s) = self.strtab.get(offset as usize) {
+                caps.push((Capability::String(i), Value::String(s)));
+            }
+        }
+
+        if let Some(ref ext) = self.ext {
+            let mut ext_caps = Vec::new();
+            for (i, &offset) in ext.names.iter().enumerate() {
+                let name = match ext.nametab.get(offset as usize) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+
+                let bools = ext.bools.len();
+                let numbers = bools + ext.numbers.len();
+                let value = if i < bools {
+                    Some(Value::Boolean(ext.bools[i])).filter(|_| ext.bools[i])
+                } else if i < numbers {
+                    Some(ext.numbers[i - bools])
+                        .filter(|&n| n != NumericValue::Absent)
+                        .map(Value::Number)
+                } else {
+                    ext.strings
+                        .get(i - numbers)
+                        .and_then(|&offset| ext.strtab.get(offset as usize).ok())
+                        .map(Value::String)
+                };
+
+                if let Some(value) = value {
+                    ext_caps.push((Capability::Ext(name), value));
+                }
+            }
+            ext_caps.sort();
+            caps.extend(ext_caps);
+        }
+
+        caps
+    }
+
     pub fn boolean(&self, field: BooleanField) -> bool {
         self.bools
             .iter()
@@ -89,17 +202,15 @@ impl TermInfoBuf {
     }
 
     pub fn number(&self, field: NumericField) -> Option<u32> {
-        let x = self.numbers
-            .iter()
-            .nth(field as usize)
-            .map(|x| *x)
-            .unwrap_or(invalid());
+        self.number_value(field).value()
+    }
 
-        if x == invalid() {
-            None
-        } else {
-            Some(x)
-        }
+    /// Get a numeric field, distinguishing between missing and cancelled capabilities.
+    pub fn number_value(&self, field: NumericField) -> NumericValue {
+        self.numbers
+            .get(field as usize)
+            .cloned()
+            .unwrap_or(NumericValue::Absent)
     }
 
     pub fn string(&self, field: StringField) -> Option<&str> {
@@ -116,6 +227,23 @@ impl TermInfoBuf {
         }
     }
 
+    /// Get a boolean field by its two letter termcap code, see `TermInfo::boolean_by_termcap`.
+    pub fn boolean_by_termcap<T: AsRef<str>>(&self, code: T) -> bool {
+        BooleanField::from_termcap_name(code.as_ref())
+            .map(|f| self.boolean(f))
+            .unwrap_or(false)
+    }
+
+    /// Get a numeric field by its two letter termcap code, see `TermInfo::number_by_termcap`.
+    pub fn number_by_termcap<T: AsRef<str>>(&self, code: T) -> Option<u32> {
+        NumericField::from_termcap_name(code.as_ref()).and_then(|f| self.number(f))
+    }
+
+    /// Get a string field by its two letter termcap code, see `TermInfo::string_by_termcap`.
+    pub fn string_by_termcap<T: AsRef<str>>(&self, code: T) -> Option<&str> {
+        StringField::from_termcap_name(code.as_ref()).and_then(|f| self.string(f))
+    }
+
     /// Execute a string
     pub fn exec<'a>(&'a self, field: StringField) -> Option<lang::Executor<'a>> {
         if let Ok(s) = self.strtab.get_slice(
@@ -131,6 +259,63 @@ impl TermInfoBuf {
         }
     }
 
+    /// Expand the string capability `field` with the parameters `args`, like ncurses' `tparm`.
+    ///
+    /// If the terminal doesn't have the capability a `MissingCapability` error is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use nixterm::terminfo::{StringField, TermInfoBuf};
+    ///
+    /// let mut info = TermInfoBuf::new();
+    /// info.set_string(StringField::CursorAddress, "\x1b[%i%p1%d;%p2%dH").unwrap();
+    /// assert_eq!(
+    ///     info.format(StringField::CursorAddress, &[4.into(), 10.into()]).unwrap(),
+    ///     b"\x1b[5;11H"
+    /// );
+    /// assert!(info.format(StringField::ClearScreen, &[]).is_err());
+    /// ```
+    pub fn format(&self, field: StringField, args: &[lang::Argument]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_to(field, args, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Expand the string capability `field` like `format`, writing the result to `w`.
+    pub fn write_to<W: io::Write>(
+        &self,
+        field: StringField,
+        args: &[lang::Argument],
+        w: &mut W,
+    ) -> Result<usize> {
+        let result = match self.program(field) {
+            Some(program) => program.exec(args, w),
+            None => {
+                let mut exe = self.exec(field)
+                    .ok_or(ErrorKind::MissingCapability(field))?;
+                exe.set_args(args).write(w)
+            }
+        };
+        Ok(result.context(ErrorKind::FailedToExpandCapability(field))?)
+    }
+
+    /// Get the compiled version of a string.
+    ///
+    /// Only a handful of frequently used capabilities (like `CursorAddress` and `SetAForeground`) are compiled,
+    /// for anything else, or if the capability is missing or invalid, this returns `None`.
+    pub fn program(&self, field: StringField) -> Option<&lang::Program> {
+        HOT_CAPS
+            .iter()
+            .position(|&f| f == field)
+            .and_then(|i| self.programs.get(i))
+            .and_then(|p| p.as_ref())
+    }
+
+    fn compile(&self, field: StringField) -> Option<lang::Program> {
+        self.string(field)
+            .and_then(|s| lang::Program::compile(s.as_bytes()).ok())
+    }
+
     pub fn ext_index<T: AsRef<str>>(&self, s: T) -> Option<usize> {
         match &self.ext {
             Some(e) => {
@@ -162,7 +347,7 @@ impl TermInfoBuf {
             if let Some(idx) = self.ext_index(field) {
                 let idx_offset = ext.bools.len();
                 if idx >= idx_offset && idx - idx_offset < ext.numbers.len() {
-                    return Some(ext.numbers[idx - idx_offset]);
+                    return ext.numbers[idx - idx_offset].value();
                 }
             }
         }
@@ -198,9 +383,14 @@ impl TermInfoBuf {
 
     #[inline]
     pub fn set_number(&mut self, field: NumericField, v: u32) -> Result<()> {
+        self.set_number_value(field, NumericValue::Value(v))
+    }
+
+    /// Set a numeric field, this can be used to cancel a capability, or remove it entirely.
+    pub fn set_number_value(&mut self, field: NumericField, v: NumericValue) -> Result<()> {
         let i = field as usize;
         while self.numbers.len() <= i {
-            self.numbers.push(invalid())
+            self.numbers.push(NumericValue::Absent)
         }
         self.numbers[i] = v;
 
@@ -220,6 +410,13 @@ impl TermInfoBuf {
 
         self.strings[i] = offset as u16;
 
+        if let Some(hot) = HOT_CAPS.iter().position(|&f| f == field) {
+            while self.programs.len() <= hot {
+                self.programs.push(None);
+            }
+            self.programs[hot] = self.compile(field);
+        }
+
         Ok(())
     }
 
@@ -232,15 +429,18 @@ impl TermInfoBuf {
             }
 
             if let Some(x) = idx {
-                ext.bools[x] = v;
+                if x < ext.bools.len() {
+                    ext.bools[x] = v;
+                }
             } else {
                 let offset = ext.nametab.add(field);
                 if offset >= invalid::<usize>() - 1 {
                     return Err(ErrorKind::MaxStrTabSizeReached.into());
                 }
 
+                // names are ordered booleans, then numbers, then strings
                 ext.bools.push(v);
-                ext.names.insert(ext.bools.len(), offset as u16)
+                ext.names.insert(ext.bools.len() - 1, offset as u16)
             }
             return Ok(());
         }
@@ -268,8 +468,8 @@ impl TermInfoBuf {
 
             if let Some(x) = idx {
                 let xoff = ext.bools.len();
-                if x > xoff && x < xoff + self.numbers.len() {
-                    self.numbers[x - xoff] = v;
+                if x >= xoff && x < xoff + ext.numbers.len() {
+                    ext.numbers[x - xoff] = NumericValue::Value(v);
                 }
             } else {
                 let offset = ext.nametab.add(field);
@@ -277,13 +477,14 @@ impl TermInfoBuf {
                     return Err(ErrorKind::MaxStrTabSizeReached.into());
                 }
 
-                ext.numbers.push(v);
-                ext.names.insert(ext.numbers.len(), offset as u16)
+                ext.numbers.push(NumericValue::Value(v));
+                ext.names
+                    .insert(ext.bools.len() + ext.numbers.len() - 1, offset as u16)
             }
             return Ok(());
         }
         let mut ext = TermInfoExtBuf::new();
-        ext.numbers.push(v);
+        ext.numbers.push(NumericValue::Value(v));
 
         let offset = ext.nametab.add(field);
         if offset >= invalid::<usize>() - 1 {
@@ -310,9 +511,9 @@ impl TermInfoBuf {
             }
 
             if let Some(x) = idx {
-                let xoff = ext.bools.len();
-                if x > xoff && x < xoff + self.strings.len() {
-                    self.strings[x - xoff] = strtab_ref;
+                let xoff = ext.bools.len() + ext.numbers.len();
+                if x >= xoff && x < xoff + ext.strings.len() {
+                    ext.strings[x - xoff] = strtab_ref;
                 }
             } else {
                 let offset = ext.nametab.add(field);
@@ -321,7 +522,7 @@ impl TermInfoBuf {
                 }
 
                 ext.strings.push(strtab_ref);
-                ext.names.insert(ext.strings.len(), offset as u16)
+                ext.names.push(offset as u16)
             }
             return Ok(());
         }
@@ -345,6 +546,22 @@ impl TermInfoBuf {
     }
 }
 
+/// Entries are equal if they have the same names and capabilities, regardless of how they're laid out.
+impl PartialEq for TermInfoBuf {
+    fn eq(&self, other: &TermInfoBuf) -> bool {
+        self.names == other.names && self.capabilities() == other.capabilities()
+    }
+}
+
+impl Eq for TermInfoBuf {}
+
+impl Hash for TermInfoBuf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.names.hash(state);
+        self.capabilities().hash(state);
+    }
+}
+
 impl<'a> From<TermInfo<'a>> for TermInfoBuf {
     fn from(src: TermInfo<'a>) -> TermInfoBuf {
         TermInfoBuf::from_terminfo(&src)
@@ -402,6 +619,153 @@ mod test {
         }
     }
 
+    #[test]
+    fn equality() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(t: &TermInfoBuf) -> u64 {
+            let mut h = DefaultHasher::new();
+            t.hash(&mut h);
+            h.finish()
+        }
+
+        let rxvt: TermInfoBuf = TermInfo::parse(RXVT_INFO).unwrap().into();
+        let xterm: TermInfoBuf = TermInfo::parse(XTERM_INFO).unwrap().into();
+        let mut edited = rxvt.clone();
+
+        assert_eq!(rxvt, edited);
+        assert_ne!(rxvt, xterm);
+
+        let clear = rxvt.string(StringField::ClearScreen).unwrap().to_string();
+        edited.set_string(StringField::ClearScreen, "\x1b[H").unwrap();
+        edited.set_number(NumericField::Columns, 132).unwrap();
+        assert_ne!(rxvt, edited);
+
+        // the string table now holds both strings, but the capabilities are the same.
+        edited.set_string(StringField::ClearScreen, clear).unwrap();
+        edited
+            .set_number(NumericField::Columns, rxvt.number(NumericField::Columns).unwrap())
+            .unwrap();
+        assert_eq!(rxvt, edited);
+        assert_eq!(hash(&rxvt), hash(&edited));
+
+        // trailing absent capabilities don't matter either
+        edited
+            .set_boolean(BooleanField::ReturnDoesClrEol, false)
+            .unwrap();
+        assert_eq!(rxvt, edited);
+    }
+
+    #[test]
+    fn cancelled_numbers() {
+        let mut rxvt: TermInfoBuf = TermInfo::parse(RXVT_INFO).unwrap().into();
+
+        assert_eq!(
+            rxvt.number_value(NumericField::Columns),
+            NumericValue::Value(80)
+        );
+        rxvt.set_number_value(NumericField::Columns, NumericValue::Cancelled)
+            .unwrap();
+        assert_eq!(rxvt.number(NumericField::Columns), None);
+        assert_eq!(
+            rxvt.number_value(NumericField::Columns),
+            NumericValue::Cancelled
+        );
+
+        rxvt.set_number_value(NumericField::Columns, NumericValue::Absent)
+            .unwrap();
+        assert_eq!(
+            rxvt.number_value(NumericField::Columns),
+            NumericValue::Absent
+        );
+        assert_eq!(
+            rxvt.number_value(NumericField::NumberOfFunctionKeys),
+            NumericValue::Absent
+        );
+    }
+
+    #[test]
+    fn format() {
+        let rxvt: TermInfoBuf = TermInfo::parse(RXVT_INFO).unwrap().into();
+
+        assert_eq!(
+            rxvt.format(StringField::CursorAddress, &[1.into(), 2.into()])
+                .unwrap(),
+            b"\x1b[2;3H"
+        );
+        assert_eq!(
+            rxvt.format(StringField::ParmDownCursor, &[3.into()]).unwrap(),
+            b"\x1b[3B"
+        );
+
+        let mut buf = Vec::new();
+        assert_eq!(
+            rxvt.write_to(StringField::ClearScreen, &[], &mut buf).unwrap(),
+            buf.len()
+        );
+        assert_eq!(
+            rxvt.format(StringField::ParmDch, &[]).unwrap_err().kind(),
+            &ErrorKind::MissingCapability(StringField::ParmDch)
+        );
+    }
+
+    #[test]
+    fn ext_setters() {
+        let mut info: TermInfoBuf = TermInfo::parse(XTERM_INFO).unwrap().into();
+        info.set_ext_string("Ss".into(), "a".into()).unwrap();
+        info.set_ext_number("Xn".into(), 3).unwrap();
+        info.set_ext_boolean("Xb".into(), true).unwrap();
+        info.set_ext_string("Se".into(), "b".into()).unwrap();
+        info.set_ext_string("Ss".into(), "c".into()).unwrap();
+        info.set_ext_number("Xn".into(), 4).unwrap();
+
+        assert_eq!(info.ext_string("Ss"), Some("c"));
+        assert_eq!(info.ext_string("Se"), Some("b"));
+        assert_eq!(info.ext_number("Xn"), Some(4));
+        assert!(info.ext_boolean("Xb"));
+        assert_eq!(info.ext_string("Xn"), None);
+        assert_eq!(info.ext_string("kUP7"), Some("\x1b[1;7A"));
+    }
+
+    #[test]
+    fn programs() {
+        let mut rxvt: TermInfoBuf = TermInfo::parse(RXVT_INFO).unwrap().into();
+
+        let cup = rxvt.program(StringField::CursorAddress).unwrap();
+        assert_eq!(cup.vec(&[1.into(), 2.into()]).unwrap(), b"\x1b[2;3H");
+        assert!(rxvt.program(StringField::ClearScreen).is_none());
+
+        rxvt.set_string(StringField::CursorAddress, "%p1%d,%p2%d")
+            .unwrap();
+        let cup = rxvt.program(StringField::CursorAddress).unwrap();
+        assert_eq!(cup.vec(&[1.into(), 2.into()]).unwrap(), b"1,2");
+
+        let mut new = TermInfoBuf::new();
+        assert!(new.program(StringField::SetAttributes).is_none());
+        new.set_string(StringField::SetAttributes, "%p1%d").unwrap();
+        assert!(new.program(StringField::SetAttributes).is_some());
+    }
+
+    #[test]
+    fn structured_names() {
+        let mut xterm: TermInfoBuf = TermInfo::parse(XTERM_INFO).unwrap().into();
+
+        assert_eq!(xterm.primary_name(), "xterm");
+        assert_eq!(xterm.aliases().count(), 0);
+        assert_eq!(
+            xterm.description(),
+            Some("xterm terminal emulator (X Window System)")
+        );
+
+        xterm.names = vec!["xterm".into(), "xterm-new".into(), "modern xterm".into()];
+        assert_eq!(xterm.aliases().collect::<Vec<&str>>(), vec!["xterm-new"]);
+        assert_eq!(xterm.description(), Some("modern xterm"));
+
+        xterm.names.truncate(1);
+        assert_eq!(xterm.description(), None);
+    }
+
     #[test]
     fn lookup_string() {
         let rxvt: TermInfoBuf = TermInfo::parse(RXVT_INFO).unwrap().into();
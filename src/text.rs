@@ -0,0 +1,119 @@
+//! Measuring, padding and truncating text by the number of columns it takes up on the terminal.
+//!
+//! Most characters take up one column, but CJK characters and many emoji take up two, and combining marks take up none,
+//! so the number of bytes or `char`s in a string is a poor guess for how wide it is.
+//! Control characters are treated as zero-width.
+use std::borrow::Cow;
+use term::Align;
+use unicode_width::UnicodeWidthChar;
+
+/// Get the number of columns a character takes up.
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Get the number of columns `s` takes up.
+///
+/// # Examples
+/// ```
+/// use nixterm::text::width;
+///
+/// assert_eq!(width("hello"), 5);
+/// assert_eq!(width("日本"), 4);
+/// assert_eq!(width("e\u{301}"), 1);
+/// ```
+pub fn width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Cut `s` down to at most `columns` wide.
+///
+/// Combining marks stay with the character before them, and a double-width character that would only half fit is left out.
+///
+/// # Examples
+/// ```
+/// use nixterm::text::truncate;
+///
+/// assert_eq!(truncate("hello", 3), "hel");
+/// assert_eq!(truncate("日本語", 3), "日");
+/// ```
+pub fn truncate(s: &str, columns: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in s.char_indices() {
+        used += char_width(c);
+        if used > columns {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+/// Pad `s` with spaces so it's `columns` wide, `s` is returned as-is if it's already at least that wide.
+///
+/// # Examples
+/// ```
+/// use nixterm::term::Align;
+/// use nixterm::text::pad;
+///
+/// assert_eq!(pad("日本", 6, Align::Left), "日本  ");
+/// assert_eq!(pad("ab", 5, Align::Center), " ab  ");
+/// ```
+pub fn pad<'a>(s: &'a str, columns: usize, align: Align) -> Cow<'a, str> {
+    let extra = match columns.checked_sub(width(s)) {
+        Some(0) | None => return Cow::Borrowed(s),
+        Some(extra) => extra,
+    };
+
+    let (left, right) = match align {
+        Align::Left => (0, extra),
+        Align::Right => (extra, 0),
+        Align::Center => (extra / 2, extra - extra / 2),
+    };
+    let mut padded = String::with_capacity(s.len() + extra);
+    padded.extend((0..left).map(|_| ' '));
+    padded.push_str(s);
+    padded.extend((0..right).map(|_| ' '));
+    Cow::Owned(padded)
+}
+
+/// Truncate or pad `s` so it's exactly `columns` wide.
+///
+/// A double-width character that's cut in half is replaced by a space.
+pub fn fit<'a>(s: &'a str, columns: usize, align: Align) -> Cow<'a, str> {
+    pad(truncate(s, columns), columns, align)
+}
+
+#[cfg(test)]
+mod test {
+    use term::Align;
+    use text::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(width(""), 0);
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("ｈｉ"), 4);
+        assert_eq!(width("a\u{300}\u{301}b"), 2);
+        assert_eq!(width("\x1b"), 0);
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate("abc", 0), "");
+        assert_eq!(truncate("abc", 5), "abc");
+        assert_eq!(truncate("a\u{301}b", 1), "a\u{301}");
+        assert_eq!(truncate("a日b", 2), "a");
+        assert_eq!(truncate("a日b", 3), "a日");
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(pad("abc", 2, Align::Left), "abc");
+        assert_eq!(pad("abc", 5, Align::Right), "  abc");
+        assert_eq!(pad("e\u{301}", 3, Align::Left), "e\u{301}  ");
+        assert_eq!(pad("日", 5, Align::Center), " 日  ");
+
+        assert_eq!(fit("日本語", 5, Align::Left), "日本 ");
+        assert_eq!(fit("ab", 4, Align::Right), "  ab");
+    }
+}
@@ -1,13 +1,21 @@
 //! Private utility functions and structures
 use errors::*;
-use failure::ResultExt;
+use failure::{Fail, ResultExt};
 use memchr::memchr;
+use nix::libc;
+use std::env;
 use std::io;
 use std::mem::{size_of, transmute};
 use std::slice::from_raw_parts;
+use std::thread;
+use std::time::Duration;
+use text;
 
 const DIGITS: [u8; 36] = *b"0123456789abcdefghijklmnopqrstuvwxyz";
 
+/// The number of times a transient I/O error is retried before giving up.
+const RETRY_ATTEMPTS: u32 = 5;
+
 /// Read a little endian u16 from a u8 slice.
 ///
 /// `i` is the u16's offset, in two-byte blocks (i.e. start reading at b[i * 2]).
@@ -51,6 +59,11 @@ pub fn strlen(s: &[u8]) -> usize {
     memchr(0, s).unwrap_or_else(|| s.len())
 }
 
+/// Find the first occurrence of `needle` in `haystack`.
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
 /// Write a u8 in a ansi-escape code compatible format
 #[inline]
 pub fn write_u8_ansi<W: io::Write>(w: &mut W, num: u8) -> Result<usize> {
@@ -77,3 +90,128 @@ pub fn write_u8_ansi<W: io::Write>(w: &mut W, num: u8) -> Result<usize> {
         .context(ErrorKind::FailedWriteToStdout)?;
     Ok(num_buf_len)
 }
+
+/// Check if `e` means the terminal has disappeared (e.g. the ssh connection dropped, or the emulator's tab was closed).
+pub fn is_terminal_gone(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(libc::EIO) | Some(libc::ENXIO) | Some(libc::ENODEV) => true,
+        _ => e.kind() == io::ErrorKind::BrokenPipe,
+    }
+}
+
+/// Wrap an I/O error, using `ErrorKind::TerminalGone` instead of `kind` if the terminal has disappeared.
+pub fn io_error(e: io::Error, kind: ErrorKind) -> Error {
+    if is_terminal_gone(&e) {
+        e.context(ErrorKind::TerminalGone).into()
+    } else {
+        e.context(kind).into()
+    }
+}
+
+/// Run `f` until it succeeds, or fails with an error that isn't transient.
+///
+/// Interrupted calls are retried immediately, `EAGAIN` and `EIO` are retried up to `RETRY_ATTEMPTS` times,
+/// doubling the delay between each attempt.
+pub fn retry<T, F: FnMut() -> io::Result<T>>(mut f: F) -> io::Result<T> {
+    let mut delay = Duration::from_millis(1);
+    let mut attempts = 0;
+
+    loop {
+        match f() {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(ref e)
+                if attempts < RETRY_ATTEMPTS
+                    && (e.kind() == io::ErrorKind::WouldBlock
+                        || e.raw_os_error() == Some(libc::EIO)) =>
+            {
+                thread::sleep(delay);
+                delay *= 2;
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Get the size of the terminal `fd` refers to as `(columns, rows)`, using the `TIOCGWINSZ` ioctl.
+///
+/// Returns `None` if `fd` isn't a terminal, or the terminal reports a size of 0.
+pub fn window_size(fd: libc::c_int) -> Option<(u16, u16)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        Some((size.ws_col, size.ws_row))
+    } else {
+        None
+    }
+}
+
+/// Check if the locale's character encoding is UTF-8, using the same variables as `setlocale` (`LC_ALL`, `LC_CTYPE`, then `LANG`).
+pub fn is_utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.is_empty())
+        .unwrap_or_default()
+        .to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// Get the locale's character encoding in lowercase (e.g. "utf-8" for `en_US.UTF-8`), from the same variables as
+/// `is_utf8_locale`. Returns `None` if the locale doesn't name one.
+pub fn locale_codeset() -> Option<String> {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.is_empty())?;
+    let codeset = locale.split('.').nth(1)?;
+    Some(codeset.split('@').next().unwrap_or(codeset).to_lowercase())
+}
+
+/// Soft-wrap `s` on spaces, so no line is wider than `width` columns, starting at `column`.
+///
+/// Words longer than `width` are split. If `auto_wrap` is set the terminal moves to the next line by itself
+/// once a line is full, so no newline is written after a full line.
+pub fn wrap(s: &str, mut column: usize, width: usize, auto_wrap: bool) -> String {
+    let width = width.max(1);
+    let mut out = String::with_capacity(s.len());
+    let newline = |out: &mut String, column: &mut usize| {
+        if !auto_wrap || *column < width {
+            out.push('\n');
+        }
+        *column = 0;
+    };
+
+    for (i, line) in s.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            column = 0;
+        }
+
+        for (j, word) in line.split(' ').enumerate() {
+            let len = text::width(word);
+            let sep = (j > 0) as usize;
+            if column > 0 && column + sep + len > width {
+                newline(&mut out, &mut column);
+            } else if sep > 0 {
+                out.push(' ');
+                column += 1;
+            }
+
+            for c in word.chars() {
+                let w = text::char_width(c);
+                if column + w > width {
+                    newline(&mut out, &mut column);
+                }
+                out.push(c);
+                column += w;
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,366 @@
+//! Larger pieces of output built on top of `TermWriter`.
+use std::io;
+use term::{Align, LineChar, Style, TermWriter};
+use text;
+use util;
+
+/// A cell in a `Table`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    pub style: Style,
+
+    /// How the text is aligned in the column, `None` uses the column's alignment.
+    pub align: Option<Align>,
+}
+
+impl Cell {
+    pub fn new<T: Into<String>>(text: T) -> Cell {
+        Cell {
+            text: text.into(),
+            ..Cell::default()
+        }
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = Some(align);
+        self
+    }
+}
+
+impl<'a> From<&'a str> for Cell {
+    fn from(s: &'a str) -> Cell {
+        Cell::new(s)
+    }
+}
+
+impl From<String> for Cell {
+    fn from(s: String) -> Cell {
+        Cell::new(s)
+    }
+}
+
+/// What happens to text that's wider than its column.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Overflow {
+    /// Cut the text off at the edge of the column.
+    Truncate,
+
+    /// Wrap the text onto more lines, making the row taller.
+    Wrap,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Column {
+    align: Align,
+    overflow: Overflow,
+}
+
+impl Default for Column {
+    fn default() -> Column {
+        Column {
+            align: Align::Left,
+            overflow: Overflow::Truncate,
+        }
+    }
+}
+
+/// Rows of cells, printed as aligned columns.
+///
+/// Columns are as wide as their widest cell, if the table doesn't fit in the terminal the widest columns are shrunk first.
+///
+/// # Examples
+/// ```no_run
+/// use nixterm::term::{Align, Style, Term};
+/// use nixterm::widgets::{Cell, Overflow, Table};
+///
+/// let term = Term::new().unwrap();
+/// let table = Table::new()
+///     .header(vec![Cell::new("name").style(Style::new().bold()), Cell::new("size").style(Style::new().bold())])
+///     .row(vec!["Cargo.toml", "512"])
+///     .row(vec!["README.md", "2048"])
+///     .align(1, Align::Right)
+///     .overflow(0, Overflow::Wrap)
+///     .borders(true);
+///
+/// table.write(term.writer()).done().unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    header: Option<Vec<Cell>>,
+    rows: Vec<Vec<Cell>>,
+    columns: Vec<Column>,
+    borders: bool,
+    max_width: Option<usize>,
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table::default()
+    }
+
+    /// Set the first row, which is separated from the rest of the table when borders are on.
+    pub fn header<I, T>(mut self, cells: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Cell>,
+    {
+        self.header = Some(cells.into_iter().map(T::into).collect());
+        self
+    }
+
+    pub fn row<I, T>(mut self, cells: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Cell>,
+    {
+        self.rows.push(cells.into_iter().map(T::into).collect());
+        self
+    }
+
+    /// Draw lines around and between the cells.
+    pub fn borders(mut self, borders: bool) -> Self {
+        self.borders = borders;
+        self
+    }
+
+    /// Set the alignment of every cell in `column` that doesn't have its own.
+    pub fn align(mut self, column: usize, align: Align) -> Self {
+        self.column(column).align = align;
+        self
+    }
+
+    /// Set what happens to text that doesn't fit in `column`, by default it's truncated.
+    pub fn overflow(mut self, column: usize, overflow: Overflow) -> Self {
+        self.column(column).overflow = overflow;
+        self
+    }
+
+    /// Limit the table's width, instead of using the width of the terminal.
+    pub fn max_width(mut self, columns: usize) -> Self {
+        self.max_width = Some(columns);
+        self
+    }
+
+    fn column(&mut self, i: usize) -> &mut Column {
+        if self.columns.len() <= i {
+            self.columns.resize(i + 1, Column::default());
+        }
+        &mut self.columns[i]
+    }
+
+    fn all_rows(&self) -> impl Iterator<Item = &Vec<Cell>> {
+        self.header.iter().chain(self.rows.iter())
+    }
+
+    /// Get the width of each column, shrinking the widest columns until the table fits in `max_width`.
+    fn widths(&self, max_width: Option<usize>) -> Vec<usize> {
+        let count = self.all_rows().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; count];
+        for row in self.all_rows() {
+            for (i, cell) in row.iter().enumerate() {
+                let width = cell.text.lines().map(text::width).max().unwrap_or(0);
+                widths[i] = widths[i].max(width);
+            }
+        }
+
+        let gaps = if self.borders {
+            3 * count + 1
+        } else {
+            2 * count.saturating_sub(1)
+        };
+        if let Some(max) = max_width {
+            let available = max.saturating_sub(gaps).max(count);
+            while widths.iter().sum::<usize>() > available {
+                let widest = (0..count).max_by_key(|&i| (widths[i], count - i)).unwrap();
+                widths[widest] -= 1;
+            }
+        }
+        widths
+    }
+
+    /// Split a cell into the lines it takes up in a column `width` wide.
+    fn lines(&self, cell: &Cell, i: usize, width: usize) -> Vec<String> {
+        let column = self.columns.get(i).cloned().unwrap_or_default();
+        let align = cell.align.unwrap_or(column.align);
+        match column.overflow {
+            Overflow::Truncate => cell
+                .text
+                .lines()
+                .map(|l| text::fit(l, width, align).into_owned())
+                .collect(),
+            Overflow::Wrap => util::wrap(&cell.text, 0, width, false)
+                .lines()
+                .map(|l| text::fit(l, width, align).into_owned())
+                .collect(),
+        }
+    }
+
+    fn write_rule<'a, O: io::Write>(
+        &self,
+        w: TermWriter<'a, O>,
+        widths: &[usize],
+        (left, middle, right): (LineChar, LineChar, LineChar),
+    ) -> TermWriter<'a, O> {
+        let mut chars = vec![left];
+        for (i, &width) in widths.iter().enumerate() {
+            if i > 0 {
+                chars.push(middle);
+            }
+            chars.extend((0..width + 2).map(|_| LineChar::Horizontal));
+        }
+        chars.push(right);
+        w.line(&chars).print("\n")
+    }
+
+    fn write_row<'a, O: io::Write>(
+        &self,
+        mut w: TermWriter<'a, O>,
+        widths: &[usize],
+        row: &[Cell],
+    ) -> TermWriter<'a, O> {
+        let empty = Cell::default();
+        let cells = (0..widths.len())
+            .map(|i| row.get(i).unwrap_or(&empty))
+            .collect::<Vec<_>>();
+        let lines = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| self.lines(cell, i, widths[i]))
+            .collect::<Vec<_>>();
+        let height = lines.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+        for line in 0..height {
+            for (i, &width) in widths.iter().enumerate() {
+                w = match (self.borders, i) {
+                    (true, _) => w.line(&[LineChar::Vertical]).print(" "),
+                    (false, 0) => w,
+                    (false, _) => w.print("  "),
+                };
+                w = match lines[i].get(line) {
+                    Some(text) => w.style(&cells[i].style).print(text),
+                    None => w.print(" ".repeat(width)),
+                };
+                if self.borders {
+                    w = w.print(" ");
+                }
+            }
+            if self.borders {
+                w = w.line(&[LineChar::Vertical]);
+            }
+            w = w.print("\n");
+        }
+        w
+    }
+
+    /// Print the table with `w`, starting at the beginning of the cursor's line.
+    ///
+    /// The table is sized to the terminal the writer was made for, unless `max_width` was set.
+    pub fn write<'a, O: io::Write>(&self, mut w: TermWriter<'a, O>) -> TermWriter<'a, O> {
+        let widths = self.widths(self.max_width.or_else(|| w.columns()));
+        if widths.is_empty() {
+            return w;
+        }
+
+        if self.borders {
+            let top = (LineChar::TopLeft, LineChar::TeeDown, LineChar::TopRight);
+            w = self.write_rule(w, &widths, top);
+        }
+        if let Some(ref header) = self.header {
+            w = self.write_row(w, &widths, header);
+            if self.borders {
+                let middle = (LineChar::TeeRight, LineChar::Cross, LineChar::TeeLeft);
+                w = self.write_rule(w, &widths, middle);
+            }
+        }
+        for row in &self.rows {
+            w = self.write_row(w, &widths, row);
+        }
+        if self.borders {
+            let bottom = (LineChar::BottomLeft, LineChar::TeeUp, LineChar::BottomRight);
+            w = self.write_rule(w, &widths, bottom);
+        }
+        w
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prompt::Shell;
+    use std::fs::File;
+    use term::{Align, Style, Term};
+    use terminfo;
+    use widgets::*;
+
+    const TERMINFO: &'static [u8] = include_bytes!("../test-data/rxvt");
+
+    /// Render a table, and strip out the escape sequences.
+    /// Line-drawing characters are mapped to ASCII, so the output is the same whichever character set the locale picks.
+    fn render(table: &Table) -> String {
+        let null = File::open("/dev/null").unwrap();
+        let term = Term::from_streams(
+            terminfo::TermInfo::parse(TERMINFO).unwrap().into(),
+            null.try_clone().unwrap(),
+            null,
+        );
+        let out = term
+            .render_prompt(Shell::Readline, |w| table.write(w).done())
+            .unwrap();
+
+        out.split('\x01')
+            .map(|part| part.splitn(2, '\x02').last().unwrap())
+            .collect::<String>()
+            .chars()
+            .map(|c| match c {
+                'q' | '─' => '-',
+                'x' | '│' => '|',
+                'l' | 'k' | 'm' | 'j' | 't' | 'u' | 'w' | 'v' | 'n' => '+',
+                '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' => '+',
+                c => c,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns() {
+        let table = Table::new()
+            .row(vec!["A", "BBB"])
+            .row(vec![Cell::new("CC").style(Style::new().bold()), "D".into()])
+            .align(1, Align::Right);
+        assert_eq!(render(&table), "A   BBB\nCC    D\n");
+
+        let table = Table::new()
+            .row(vec![Cell::new("A").align(Align::Center), "B".into()])
+            .row(vec!["CCC"]);
+        assert_eq!(render(&table), " A   B\nCCC   \n");
+    }
+
+    #[test]
+    fn sizing() {
+        let table = Table::new().row(vec!["AAAA", "BBBB"]).max_width(6);
+        assert_eq!(render(&table), "AA  BB\n");
+
+        let table = Table::new()
+            .row(vec!["AA AA", "B"])
+            .overflow(0, Overflow::Wrap)
+            .max_width(6);
+        assert_eq!(render(&table), "AA   B\nAA    \n");
+    }
+
+    #[test]
+    fn borders() {
+        let table = Table::new()
+            .header(vec!["H", "I"])
+            .row(vec!["1"])
+            .borders(true);
+        assert_eq!(
+            render(&table),
+            "+---+---+\n| H | I |\n+---+---+\n| 1 |   |\n+---+---+\n"
+        );
+    }
+}